@@ -4,7 +4,7 @@ use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
 
 use crate::error::WalletError;
-use crate::rpc::{RpcAuth, RpcConfig};
+use crate::rpc::{RateLimitConfig, RpcConfig};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -41,16 +41,7 @@ impl Config {
             network: NetworkConfig {
                 network: bitcoin::Network::Bitcoin,
             },
-            rpc: RpcConfig {
-                bind: "127.0.0.1".to_string(),
-                port: "8332".to_string(),
-                auth: RpcAuth {
-                    user: None,
-                    password: None,
-                    auth_pairs: Vec::new(),
-                },
-                allow_ips: vec!["127.0.0.1".to_string()],
-            },
+            rpc: RpcConfig::default(),
             wallet: WalletConfig {
                 keypool_size: 1000,
                 rescan: false,
@@ -129,6 +120,15 @@ impl Config {
             ("", "rpcport") | ("rpc", "port") => {
                 config.rpc.port = value.to_string();
             }
+            ("", "rpcwsport") | ("rpc", "wsport") => {
+                config.rpc.ws_port = Some(parse_port(value, "WebSocket")?);
+            }
+            ("", "restport") | ("rpc", "restport") => {
+                config.rpc.rest_port = Some(parse_port(value, "REST")?);
+            }
+            ("", "metricsport") | ("rpc", "metricsport") => {
+                config.rpc.metrics_port = Some(parse_port(value, "metrics")?);
+            }
             ("", "rpcuser") | ("rpc", "user") => {
                 config.rpc.auth.user = Some(value.to_string());
             }
@@ -141,6 +141,12 @@ impl Config {
             ("", "rpcallowip") | ("rpc", "allowip") => {
                 config.rpc.allow_ips.push(value.to_string());
             }
+            ("", "rpcratelimit") | ("rpc", "ratelimit") => {
+                Self::rate_limit(config).rate = parse_rate(value)?;
+            }
+            ("", "rpcratelimitburst") | ("rpc", "ratelimitburst") => {
+                Self::rate_limit(config).burst = parse_burst(value)?;
+            }
 
             // Wallet settings
             ("wallet", "keypool") => {
@@ -190,6 +196,15 @@ impl Config {
         if let Some(port) = matches.get_one::<String>("rpcport") {
             config.rpc.port = port.clone();
         }
+        if let Some(port) = matches.get_one::<String>("rpcwsport") {
+            config.rpc.ws_port = Some(parse_port(port, "WebSocket")?);
+        }
+        if let Some(port) = matches.get_one::<String>("restport") {
+            config.rpc.rest_port = Some(parse_port(port, "REST")?);
+        }
+        if let Some(port) = matches.get_one::<String>("metricsport") {
+            config.rpc.metrics_port = Some(parse_port(port, "metrics")?);
+        }
         if let Some(user) = matches.get_one::<String>("rpcuser") {
             config.rpc.auth.user = Some(user.clone());
         }
@@ -199,7 +214,42 @@ impl Config {
         if let Some(auth) = matches.get_one::<String>("rpcauth") {
             config.rpc.auth.auth_pairs.push(auth.clone());
         }
+        if let Some(rate) = matches.get_one::<String>("rpcratelimit") {
+            Self::rate_limit(config).rate = parse_rate(rate)?;
+        }
+        if let Some(burst) = matches.get_one::<String>("rpcratelimitburst") {
+            Self::rate_limit(config).burst = parse_burst(burst)?;
+        }
 
         Ok(())
     }
+
+    // Setting any rate limit option turns rate limiting on, with defaults for
+    // the others
+    fn rate_limit(config: &mut Config) -> &mut RateLimitConfig {
+        config.rpc.rate_limit.get_or_insert_with(Default::default)
+    }
+}
+
+// Parse the port a `server` server listens on
+fn parse_port(value: &str, server: &str) -> Result<u16, WalletError> {
+    value
+        .parse()
+        .map_err(|_| WalletError::ConfigError(format!("Invalid {} port: {}", server, value)))
+}
+
+fn parse_rate(value: &str) -> Result<f64, WalletError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+        .ok_or_else(|| WalletError::ConfigError(format!("Invalid RPC rate limit: {}", value)))
+}
+
+fn parse_burst(value: &str) -> Result<u32, WalletError> {
+    value
+        .parse::<u32>()
+        .ok()
+        .filter(|burst| *burst > 0)
+        .ok_or_else(|| WalletError::ConfigError(format!("Invalid RPC rate limit burst: {}", value)))
 }
@@ -1,4 +1,4 @@
-use bitcoin::Txid;
+use bitcoin::{BlockHash, Txid};
 use std::fmt;
 use thiserror::Error;
 
@@ -28,6 +28,27 @@ pub enum WalletError {
 
     #[error("Invalid descriptor: {0}")]
     InvalidDescriptor(String),
+
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Insufficient funds: {0}")]
+    InsufficientFunds(String),
+
+    #[error("Transaction rejected: {0}")]
+    TransactionRejected(String),
+
+    #[error("Block not found: {0}")]
+    BlockNotFound(BlockHash),
+
+    #[error("The wallet passphrase entered was incorrect")]
+    PassphraseIncorrect,
+
+    #[error("{0}")]
+    WalletNotFound(String),
+
+    #[error("Wallet \"{0}\" is already loaded.")]
+    WalletAlreadyLoaded(String),
     
     #[error("{0}")]
     Generic(String),
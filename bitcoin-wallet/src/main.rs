@@ -1,6 +1,7 @@
 use clap::Command;
 use env_logger;
 use log;
+use metrics_exporter_prometheus::PrometheusBuilder;
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::process;
@@ -8,6 +9,9 @@ use tokio::task::LocalSet;
 
 use bitcoin_wallet::{config::Config, rpc::RPCServer, wallet::WalletInterface};
 
+/// Name of the cookie file in the data directory, as in Bitcoin Core
+const COOKIE_FILE: &str = ".cookie";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
@@ -62,6 +66,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .default_value("8332")
                 .value_parser(clap::value_parser!(String)),
         )
+        .arg(
+            clap::Arg::new("rpcwsport")
+                .long("rpcwsport")
+                .value_name("PORT")
+                .help("Stream chain notifications to WebSocket clients on PORT")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            clap::Arg::new("restport")
+                .long("restport")
+                .value_name("PORT")
+                .help("Serve the REST interface on PORT")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            clap::Arg::new("metricsport")
+                .long("metricsport")
+                .value_name("PORT")
+                .help("Serve Prometheus metrics on PORT")
+                .value_parser(clap::value_parser!(String)),
+        )
         .arg(
             clap::Arg::new("rpcuser")
                 .long("rpcuser")
@@ -83,6 +108,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Username and HMAC-SHA-256 hashed password for JSON-RPC connections")
                 .value_parser(clap::value_parser!(String)),
         )
+        .arg(
+            clap::Arg::new("rpcratelimit")
+                .long("rpcratelimit")
+                .value_name("RATE")
+                .help("Limit each client to RATE JSON-RPC requests a second (default burst: 20)")
+                .value_parser(clap::value_parser!(String)),
+        )
+        .arg(
+            clap::Arg::new("rpcratelimitburst")
+                .long("rpcratelimitburst")
+                .value_name("N")
+                .help("Let each client make N JSON-RPC requests at once before being rate limited (default rate: 5)")
+                .value_parser(clap::value_parser!(String)),
+        )
         .arg(
             clap::Arg::new("regtest")
                 .long("regtest")
@@ -145,7 +184,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(|s| PathBuf::from(s))
         .unwrap_or_else(|| data_dir.join("bitcoin.conf"));
 
-    let config = match Config::load(&conf_path, matches.clone()) {
+    let mut config = match Config::load(&conf_path, matches.clone()) {
         Ok(config) => config,
         Err(err) => {
             eprintln!("Failed to load configuration: {}", err);
@@ -153,6 +192,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // Without configured credentials, clients authenticate with a cookie file
+    let cookie_path = if config.rpc.auth.is_empty() {
+        let cookie_path = data_dir.join(COOKIE_FILE);
+        let _ = std::fs::create_dir_all(&data_dir);
+        if let Err(e) = config.rpc.auth.generate_cookie(&cookie_path) {
+            eprintln!("Failed to generate RPC authentication cookie: {}", e);
+            process::exit(1);
+        }
+        Some(cookie_path)
+    } else {
+        None
+    };
+
     let rpc_addr = format!(
         "{}:{}",
         matches
@@ -184,6 +236,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 process::exit(1);
             });
 
+            if let Some(metrics_port) = config.rpc.metrics_port {
+                let metrics_addr = SocketAddr::new(rpc_addr.ip(), metrics_port);
+                log::info!("Serving metrics on {}", metrics_addr);
+                if let Err(e) = PrometheusBuilder::new()
+                    .with_http_listener(metrics_addr)
+                    .install()
+                {
+                    eprintln!("Failed to start metrics exporter: {}", e);
+                    process::exit(1);
+                }
+            }
+
             log::info!("Initializing wallet with network: {:?}", network);
             let wallet_path = wallet_dir.join(wallet_name);
             log::info!("Using wallet at: {}", wallet_path.display());
@@ -198,7 +262,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             log::info!("Starting RPC server on {}", rpc_addr);
             let mut rpc_server = RPCServer::new(wallet, &config.rpc);
-            if let Err(e) = rpc_server.start(rpc_addr).await {
+            if let Some(rest_port) = config.rpc.rest_port {
+                let rest_addr = SocketAddr::new(rpc_addr.ip(), rest_port);
+                if let Err(e) = rpc_server.start_rest(rest_addr) {
+                    eprintln!("Failed to start REST server: {}", e);
+                    process::exit(1);
+                }
+            }
+            let started = match config.rpc.ws_port {
+                Some(ws_port) => rpc_server.start_with_websocket(rpc_addr, ws_port).await,
+                None => rpc_server.start(rpc_addr).await,
+            };
+            if let Err(e) = started {
                 eprintln!("Failed to start RPC server: {}", e);
                 process::exit(1);
             }
@@ -208,6 +283,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             log::info!("Shutting down wallet");
             rpc_server.stop();
+            if let Some(cookie_path) = &cookie_path {
+                let _ = std::fs::remove_file(cookie_path);
+            }
 
             Ok(())
         })
@@ -0,0 +1,295 @@
+//! Access control shared by the JSON-RPC and REST servers: HTTP basic auth
+//! against `rpcuser`/`rpcpassword`, `rpcauth` or a cookie file, and the
+//! `rpcallowip` list
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use jsonrpc_http_server::hyper::header::{self, HeaderValue};
+use jsonrpc_http_server::hyper::{Body, Request, Response, StatusCode};
+use rand::Rng;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::Path;
+
+use super::config::{RpcAuth, RpcConfig};
+use crate::error::WalletError;
+
+/// Check a request from `remote` against the configured credentials and
+/// allowed IPs, returning the response to reject it with
+pub(super) fn check_access(
+    remote: IpAddr,
+    request: &Request<Body>,
+    config: &RpcConfig,
+) -> Result<(), Response<Body>> {
+    if !ip_allowed(remote, &config.allow_ips) {
+        log::warn!(
+            "Rejected request from {}, which is not in rpcallowip",
+            remote
+        );
+        return Err(empty_response(StatusCode::FORBIDDEN));
+    }
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !authorized(authorization, &config.auth) {
+        log::warn!(
+            "Rejected request from {} with incorrect credentials",
+            remote
+        );
+        let mut response = empty_response(StatusCode::UNAUTHORIZED);
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static("Basic realm=\"jsonrpc\""),
+        );
+        return Err(response);
+    }
+    Ok(())
+}
+
+/// Check every `rpcallowip` entry can be parsed
+pub(super) fn validate_allow_ips(allow_ips: &[String]) -> Result<(), WalletError> {
+    match allow_ips
+        .iter()
+        .find(|subnet| parse_subnet(subnet).is_none())
+    {
+        Some(subnet) => Err(WalletError::ConfigError(format!(
+            "Invalid rpcallowip subnet specification: {}",
+            subnet
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// User name of the credentials in the cookie file, as in Bitcoin Core
+const COOKIE_USER: &str = "__cookie__";
+
+impl RpcAuth {
+    /// Whether no credentials are configured, in which case nobody is let in
+    pub fn is_empty(&self) -> bool {
+        self.user.is_none() && self.password.is_none() && self.auth_pairs.is_empty()
+    }
+
+    /// Let in user `__cookie__` with a random password, written to `path` as
+    /// `__cookie__:PASSWORD` for local clients to read
+    /// This is how Bitcoin Core authenticates when no credentials are
+    /// configured. The file is only readable by its owner.
+    pub fn generate_cookie(&mut self, path: &Path) -> Result<(), WalletError> {
+        let password = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path).map_err(|e| {
+            WalletError::ConfigError(format!(
+                "Failed to write cookie file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        write!(file, "{}:{}", COOKIE_USER, password)?;
+
+        self.user = Some(COOKIE_USER.to_string());
+        self.password = Some(password);
+        log::info!("Generated RPC authentication cookie {}", path.display());
+        Ok(())
+    }
+}
+
+/// Whether an `Authorization` header holds credentials accepted by `auth`
+/// Nobody is let in when no credentials are configured.
+pub(super) fn authorized(authorization: Option<&str>, auth: &RpcAuth) -> bool {
+    let credentials = authorization
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let (user, password) = match credentials.as_deref().and_then(|c| c.split_once(':')) {
+        Some(credentials) => credentials,
+        None => return false,
+    };
+
+    if let (Some(expected_user), Some(expected_password)) = (&auth.user, &auth.password) {
+        if timing_resistant_eq(user.as_bytes(), expected_user.as_bytes())
+            & timing_resistant_eq(password.as_bytes(), expected_password.as_bytes())
+        {
+            return true;
+        }
+    }
+    auth.auth_pairs
+        .iter()
+        .any(|pair| rpcauth_matches(pair, user, password))
+}
+
+// Check credentials against an `rpcauth` entry, `USER:SALT$HASH` where HASH is
+// the hex HMAC-SHA256 of the password keyed with SALT
+fn rpcauth_matches(pair: &str, user: &str, password: &str) -> bool {
+    let (pair_user, salted_hash) = match pair.split_once(':') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let (salt, hash) = match salted_hash.split_once('$') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    if !timing_resistant_eq(pair_user.as_bytes(), user.as_bytes()) {
+        return false;
+    }
+
+    let mut engine = HmacEngine::<sha256::Hash>::new(salt.as_bytes());
+    engine.input(password.as_bytes());
+    let computed = Hmac::<sha256::Hash>::from_engine(engine).to_string();
+    timing_resistant_eq(computed.as_bytes(), hash.as_bytes())
+}
+
+// Compare without returning early, so timing doesn't reveal how much matched
+fn timing_resistant_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Whether `ip` is allowed by one of the `rpcallowip` entries
+/// Like Core, loopback addresses are always allowed.
+pub(super) fn ip_allowed(ip: IpAddr, allow_ips: &[String]) -> bool {
+    let ip = ip.to_canonical();
+    ip.is_loopback()
+        || allow_ips
+            .iter()
+            .filter_map(|subnet| parse_subnet(subnet))
+            .any(|subnet| subnet.contains(ip))
+}
+
+struct Subnet {
+    network: IpAddr,
+    // Mask over the address as a big endian integer
+    mask: u128,
+}
+
+impl Subnet {
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                u32::from(network) as u128 & self.mask == u32::from(ip) as u128 & self.mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                u128::from(network) & self.mask == u128::from(ip) & self.mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// Parse an `rpcallowip` entry: an address, `address/prefix` or `address/netmask`
+fn parse_subnet(subnet: &str) -> Option<Subnet> {
+    let (network, mask) = match subnet.split_once('/') {
+        Some((network, mask)) => (network, Some(mask)),
+        None => (subnet, None),
+    };
+    let network = network.parse::<IpAddr>().ok()?.to_canonical();
+    let bits = if network.is_ipv4() { 32 } else { 128 };
+
+    let mask = match mask {
+        None => prefix_mask(bits, bits),
+        Some(mask) => match (mask.parse::<u32>(), mask.parse::<IpAddr>()) {
+            (Ok(prefix), _) if prefix <= bits => prefix_mask(prefix, bits),
+            (_, Ok(IpAddr::V4(mask))) if bits == 32 => u32::from(mask) as u128,
+            (_, Ok(IpAddr::V6(mask))) if bits == 128 => u128::from(mask),
+            _ => return None,
+        },
+    };
+    Some(Subnet { network, mask })
+}
+
+// Mask of the first `prefix` bits of a `bits` wide address
+fn prefix_mask(prefix: u32, bits: u32) -> u128 {
+    let all = u128::MAX >> (128 - bits);
+    all & !all.checked_shr(prefix).unwrap_or(0)
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorized() {
+        let mut auth = RpcAuth {
+            user: None,
+            password: None,
+            auth_pairs: Vec::new(),
+        };
+        assert!(auth.is_empty());
+        assert!(!authorized(None, &auth));
+        // "alice:"
+        assert!(!authorized(Some("Basic YWxpY2U6"), &auth));
+
+        auth.user = Some("alice".to_string());
+        auth.password = Some("hunter2".to_string());
+        // "alice:hunter2" and "alice:hunter3"
+        assert!(authorized(Some("Basic YWxpY2U6aHVudGVyMg=="), &auth));
+        assert!(!authorized(Some("Basic YWxpY2U6aHVudGVyMw=="), &auth));
+        assert!(!authorized(None, &auth));
+
+        // rpcauth entry for bob with password "hunter3"
+        let mut engine = HmacEngine::<sha256::Hash>::new(b"c0ffee");
+        engine.input(b"hunter3");
+        let hash = Hmac::<sha256::Hash>::from_engine(engine);
+        auth.auth_pairs.push(format!("bob:c0ffee${}", hash));
+        assert!(authorized(Some("Basic Ym9iOmh1bnRlcjM="), &auth));
+        assert!(!authorized(Some("Basic Ym9iOmh1bnRlcjI="), &auth));
+    }
+
+    #[test]
+    fn test_generate_cookie() {
+        let path = std::env::temp_dir().join(format!("cookie-test-{}", std::process::id()));
+        let mut auth = RpcAuth {
+            user: None,
+            password: None,
+            auth_pairs: Vec::new(),
+        };
+        auth.generate_cookie(&path).unwrap();
+        assert!(!auth.is_empty());
+
+        let cookie = std::fs::read_to_string(&path).unwrap();
+        let (user, password) = cookie.split_once(':').unwrap();
+        assert_eq!(user, COOKIE_USER);
+        assert_eq!(password.len(), 64);
+        let header = format!("Basic {}", BASE64.encode(&cookie));
+        assert!(authorized(Some(&header), &auth));
+        assert!(!authorized(None, &auth));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_ip_allowed() {
+        let allow_ips = vec![
+            "10.0.0.5".to_string(),
+            "192.168.1.0/24".to_string(),
+            "172.16.0.0/255.255.0.0".to_string(),
+            "fd00::/8".to_string(),
+        ];
+        let allowed = |ip: &str| ip_allowed(ip.parse().unwrap(), &allow_ips);
+
+        assert!(allowed("127.0.0.1"));
+        assert!(allowed("::1"));
+        assert!(allowed("10.0.0.5"));
+        assert!(!allowed("10.0.0.6"));
+        assert!(allowed("192.168.1.77"));
+        assert!(!allowed("192.168.2.1"));
+        assert!(allowed("172.16.200.1"));
+        assert!(allowed("::ffff:192.168.1.1"));
+        assert!(allowed("fd12::1"));
+        assert!(!allowed("fe80::1"));
+
+        assert!(validate_allow_ips(&allow_ips).is_ok());
+        assert!(validate_allow_ips(&["10.0.0.0/33".to_string()]).is_err());
+        assert!(validate_allow_ips(&["localhost".to_string()]).is_err());
+    }
+}
@@ -2,8 +2,16 @@
 pub struct RpcConfig {
     pub bind: String,
     pub port: String,
+    /// Port of the WebSocket notification endpoint, which is off unless set
+    pub ws_port: Option<u16>,
+    /// Port of the REST interface, which is off unless set
+    pub rest_port: Option<u16>,
+    /// Port of the Prometheus metrics endpoint, which is off unless set
+    pub metrics_port: Option<u16>,
     pub auth: RpcAuth,
     pub allow_ips: Vec<String>,
+    /// Per-client request rate limit, which is off unless set
+    pub rate_limit: Option<RateLimitConfig>,
 }
 
 #[derive(Debug, Clone)]
@@ -12,3 +20,52 @@ pub struct RpcAuth {
     pub password: Option<String>,
     pub auth_pairs: Vec<String>,
 }
+
+/// Requests each client IP may make, refilled continuously like a token bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// Requests that may be made at once before being limited
+    pub burst: u32,
+    /// Requests a second allowed over time
+    pub rate: f64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 20,
+            rate: 5.0,
+        }
+    }
+}
+
+/// Local-only, with no credentials configured
+/// Requests are refused until some are, see `RpcAuth::generate_cookie`.
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: "8332".to_string(),
+            ws_port: None,
+            rest_port: None,
+            metrics_port: None,
+            auth: RpcAuth {
+                user: None,
+                password: None,
+                auth_pairs: Vec::new(),
+            },
+            allow_ips: vec!["127.0.0.1".to_string()],
+            rate_limit: None,
+        }
+    }
+}
+
+/// Local-only configuration for tests, letting in user alice with password
+/// hunter2
+#[cfg(test)]
+pub(crate) fn test_config() -> RpcConfig {
+    let mut config = RpcConfig::default();
+    config.auth.user = Some("alice".to_string());
+    config.auth.password = Some("hunter2".to_string());
+    config
+}
@@ -1,7 +1,43 @@
 use crate::error::WalletError;
 use jsonrpc_core::{Error as RpcError, ErrorCode};
 
+// Error codes used by Bitcoin Core's RPC interface (see rpc/protocol.h)
+pub const RPC_MISC_ERROR: i64 = -1;
+pub const RPC_WALLET_ERROR: i64 = -4;
+pub const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+pub const RPC_WALLET_INSUFFICIENT_FUNDS: i64 = -6;
+pub const RPC_INVALID_PARAMETER: i64 = -8;
+pub const RPC_WALLET_INVALID_LABEL_NAME: i64 = -11;
+pub const RPC_WALLET_PASSPHRASE_INCORRECT: i64 = -14;
+pub const RPC_WALLET_NOT_FOUND: i64 = -18;
+pub const RPC_DESERIALIZATION_ERROR: i64 = -22;
+pub const RPC_VERIFY_ERROR: i64 = -25;
+pub const RPC_VERIFY_REJECTED: i64 = -26;
+pub const RPC_WALLET_ALREADY_LOADED: i64 = -35;
+
+/// Create an RPC error with a Bitcoin Core error code
+pub fn rpc_error(code: i64, message: impl Into<String>) -> RpcError {
+    RpcError {
+        code: ErrorCode::ServerError(code),
+        message: message.into(),
+        data: None,
+    }
+}
+
 /// Convert wallet errors to RPC errors
 pub fn rpc_error_from_wallet_error(e: WalletError) -> RpcError {
-    RpcError::new(ErrorCode::InternalError)
+    let code = match &e {
+        WalletError::InvalidAddress(_)
+        | WalletError::TransactionNotFound(_)
+        | WalletError::BlockNotFound(_)
+        | WalletError::InvalidDescriptor(_) => RPC_INVALID_ADDRESS_OR_KEY,
+        WalletError::InsufficientFunds(_) => RPC_WALLET_INSUFFICIENT_FUNDS,
+        WalletError::TransactionRejected(_) => RPC_VERIFY_REJECTED,
+        WalletError::PassphraseIncorrect => RPC_WALLET_PASSPHRASE_INCORRECT,
+        WalletError::WalletNotFound(_) => RPC_WALLET_NOT_FOUND,
+        WalletError::WalletAlreadyLoaded(_) => RPC_WALLET_ALREADY_LOADED,
+        WalletError::BlocktalkError(_) => RPC_MISC_ERROR,
+        _ => RPC_WALLET_ERROR,
+    };
+    rpc_error(code, e.to_string())
 }
@@ -1,25 +1,99 @@
+use std::collections::{HashMap, HashSet};
 use std::future::Ready;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
 
-use bitcoin::{Address, Amount, Txid};
+use bitcoin::absolute::LockTime;
+use bitcoin::consensus::encode::{deserialize, serialize_hex};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::script::PushBytesBuf;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, BlockHash, FeeRate, Network, OutPoint, Script, ScriptBuf, Sequence,
+    SignedAmount, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use blocktalk::{ChainInfo, MempoolAcceptResult, MempoolEntry, PackageAcceptResult};
 use jsonrpc_core::{Error as RpcError, IoHandler, Params, Value};
 use serde_json::json;
 use tokio::task::{self, LocalSet};
 
-use super::error::rpc_error_from_wallet_error;
-use crate::wallet::{CreateWalletOptions, WalletInterface};
+use super::error::{
+    rpc_error, rpc_error_from_wallet_error, RPC_DESERIALIZATION_ERROR, RPC_INVALID_ADDRESS_OR_KEY,
+    RPC_INVALID_PARAMETER, RPC_VERIFY_ERROR, RPC_WALLET_ERROR, RPC_WALLET_INVALID_LABEL_NAME,
+};
+use super::types::{MempoolInfoResponse, NetworkInfoResponse};
+use crate::error::WalletError;
+use crate::wallet::{
+    AddressInfo, AddressType, BlockDetails, CoinControlOptions, CreateWalletOptions,
+    DescriptorEntry, FundingOptions, RawTransaction, ScanResult, TransactionCategory,
+    TransactionDetail, TxRecipient, UnspentOutput, WalletInterface, WalletManager,
+    WalletTransaction,
+};
+
+/// Largest confirmation target the node's fee estimator tracks
+const MAX_CONFIRMATION_TARGET: u64 = 1008;
+
+/// Confirmation target bumpfee estimates for, as Bitcoin Core's default -txconfirmtarget
+const DEFAULT_BUMP_CONFIRMATION_TARGET: u32 = 6;
+
+/// Default fee rate limit for sendrawtransaction, in BTC/kvB
+const DEFAULT_MAX_RAW_TX_FEE_RATE: Amount = Amount::from_sat(10_000_000);
+
+/// Most transactions a package may hold, as Bitcoin Core's MAX_PACKAGE_COUNT
+const MAX_PACKAGE_COUNT: usize = 25;
 
 pub fn register_wallet_methods(io: &mut IoHandler, wallet_interface: Arc<WalletInterface>) {
     register_createwallet(io, wallet_interface.clone());
-    register_loadwallet(io, wallet_interface.clone());
     register_getwalletinfo(io, wallet_interface.clone());
+    register_dumpmnemonic(io, wallet_interface.clone());
+    register_getxpub(io, wallet_interface.clone());
+    register_importxpub(io, wallet_interface.clone());
+    register_backupwallet(io, wallet_interface.clone());
     register_getnewaddress(io, wallet_interface.clone());
+    register_getaddressinfo(io, wallet_interface.clone());
+    register_setlabel(io, wallet_interface.clone());
+    register_getaddressesbylabel(io, wallet_interface.clone());
+    register_importdescriptors(io, wallet_interface.clone());
+    register_listdescriptors(io, wallet_interface.clone());
+    register_getdescriptorinfo(io, wallet_interface.clone());
+    register_deriveaddresses(io, wallet_interface.clone());
     register_getbalance(io, wallet_interface.clone());
     register_listunspent(io, wallet_interface.clone());
+    register_lockunspent(io, wallet_interface.clone());
+    register_listlockunspent(io, wallet_interface.clone());
+    register_listaddressgroupings(io, wallet_interface.clone());
     register_listtransactions(io, wallet_interface.clone());
     register_gettransaction(io, wallet_interface.clone());
+    register_abandontransaction(io, wallet_interface.clone());
     register_sendtoaddress(io, wallet_interface.clone());
+    register_bumpfee(io, wallet_interface.clone());
+    register_cpfpbumptx(io, wallet_interface.clone());
+    register_settxfee(io, wallet_interface.clone());
     register_rescanblockchain(io, wallet_interface.clone());
+    register_getrescanprogress(io, wallet_interface.clone());
+    register_scantxoutset(io, wallet_interface.clone());
+    register_createrawtransaction(io, wallet_interface.clone());
+    register_decoderawtransaction(io, wallet_interface.clone());
+    register_signrawtransactionwithwallet(io, wallet_interface.clone());
+    register_sendrawtransaction(io, wallet_interface.clone());
+    register_testmempoolaccept(io, wallet_interface.clone());
+    register_submitpackage(io, wallet_interface.clone());
+    register_testpackageaccept(io, wallet_interface.clone());
+    register_walletcreatefundedpsbt(io, wallet_interface.clone());
+    register_walletprocesspsbt(io, wallet_interface.clone());
+    register_finalizepsbt(io, wallet_interface.clone());
+    register_estimatesmartfee(io, wallet_interface.clone());
+    register_getmempoolinfo(io, wallet_interface.clone());
+    register_getrawmempool(io, wallet_interface.clone());
+    register_getnetworkinfo(io, wallet_interface.clone());
+    register_getblockchaininfo(io, wallet_interface.clone());
+    register_verifychain(io, wallet_interface.clone());
+    register_getblockcount(io, wallet_interface.clone());
+    register_getblockhash(io, wallet_interface.clone());
+    register_getblock(io, wallet_interface.clone());
+    register_getrawtransaction(io, wallet_interface.clone());
 }
 
 fn register_createwallet(io: &mut IoHandler, wallet_interface: Arc<WalletInterface>) {
@@ -47,11 +121,48 @@ fn register_createwallet(io: &mut IoHandler, wallet_interface: Arc<WalletInterfa
     });
 }
 
-fn register_loadwallet(io: &mut IoHandler, wallet_interface: Arc<WalletInterface>) {
+/// Register the methods that load and unload wallets
+/// `wallet_name` is the wallet the requests are sent to, which `unloadwallet`
+/// unloads when not given a name.
+pub fn register_wallet_manager_methods(
+    io: &mut IoHandler,
+    manager: Arc<tokio::sync::RwLock<WalletManager>>,
+    wallet_name: String,
+) {
+    register_listwallets(io, manager.clone());
+    register_listwalletdir(io, manager.clone());
+    register_loadwallet(io, manager.clone());
+    register_restorewallet(io, manager.clone());
+    register_unloadwallet(io, manager, wallet_name);
+}
+
+fn register_listwallets(io: &mut IoHandler, manager: Arc<tokio::sync::RwLock<WalletManager>>) {
+    io.add_sync_method("listwallets", move |_params| {
+        log::info!("=========================");
+        log::info!("Listing wallets...");
+        let wallets = block_on_local(async { manager.read().await.list_wallets() });
+        Ok(json!(wallets))
+    });
+}
+
+fn register_listwalletdir(io: &mut IoHandler, manager: Arc<tokio::sync::RwLock<WalletManager>>) {
+    io.add_sync_method("listwalletdir", move |_params| {
+        log::info!("=========================");
+        log::info!("Listing wallet directory...");
+        let wallets = block_on_local(async { manager.read().await.list_wallet_dir() })
+            .map_err(rpc_error_from_wallet_error)?;
+        let wallets: Vec<Value> = wallets
+            .into_iter()
+            .map(|name| json!({ "name": name }))
+            .collect();
+        Ok(json!({ "wallets": wallets }))
+    });
+}
+
+fn register_loadwallet(io: &mut IoHandler, manager: Arc<tokio::sync::RwLock<WalletManager>>) {
     io.add_sync_method("loadwallet", move |params: Params| {
         log::info!("=========================");
         log::info!("Loading wallet...");
-        let wallet_interface = wallet_interface.clone();
         let wallet_name = match params {
             Params::Array(arr) => arr
                 .get(0)
@@ -61,26 +172,7 @@ fn register_loadwallet(io: &mut IoHandler, wallet_interface: Arc<WalletInterface
             _ => return Err(RpcError::invalid_params("Invalid parameters")),
         };
 
-        // Use spawn_blocking to run the async operation in a separate thread
-        match task::block_in_place(|| {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-
-            let local = LocalSet::new();
-            rt.block_on(async {
-                local
-                    .run_until(async {
-                        log::debug!(
-                            "Inside async block in thread {:?}",
-                            std::thread::current().id()
-                        );
-                        wallet_interface.load_wallet(&wallet_name).await
-                    })
-                    .await
-            })
-        }) {
+        match block_on_local(WalletManager::load_wallet_by_name(&manager, &wallet_name)) {
             Ok(_) => Ok(json!({
                 "name": wallet_name,
                 "warning": ""
@@ -90,37 +182,214 @@ fn register_loadwallet(io: &mut IoHandler, wallet_interface: Arc<WalletInterface
     });
 }
 
+fn register_unloadwallet(
+    io: &mut IoHandler,
+    manager: Arc<tokio::sync::RwLock<WalletManager>>,
+    endpoint_wallet: String,
+) {
+    io.add_sync_method("unloadwallet", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Unloading wallet...");
+        let wallet_name = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(str::to_string),
+            Params::None => None,
+            _ => return Err(RpcError::invalid_params("Invalid parameters")),
+        };
+        let wallet_name = wallet_name.unwrap_or_else(|| endpoint_wallet.clone());
+
+        block_on_local(async { manager.write().await.unload_wallet(&wallet_name) })
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(json!({ "warning": "" }))
+    });
+}
+
 fn register_getwalletinfo(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
     io.add_sync_method("getwalletinfo", move |_params| {
         log::info!("=========================");
         log::info!("Getting wallet info…");
-        match wallet.get_balance() {
-            Ok(balance) => {
-                // Get transaction count
-                let tx_count = match wallet.list_transactions() {
-                    Ok(txs) => txs.len(),
-                    Err(_) => 0,
-                };
+        match wallet.get_wallet_info() {
+            Ok(info) => serde_json::to_value(info).map_err(|e| {
+                log::error!("Failed to serialize wallet info: {}", e);
+                RpcError::internal_error()
+            }),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
 
-                let result = json!({
-                    "walletname": "default",
-                    "walletversion": 169900,
-                    "format": "bdk",
-                    "balance": balance.confirmed.to_btc(),
-                    "unconfirmed_balance": balance.unconfirmed.to_btc(),
-                    "immature_balance": balance.immature.to_btc(),
-                    "txcount": tx_count,
-                    "keypoololdest": 0,
-                    "keypoolsize": 1000,
-                    "keypoolsize_hd_internal": 1000,
-                    "paytxfee": 0,
-                    "private_keys_enabled": true,
-                    "avoid_reuse": false,
-                    "scanning": false,
-                    "descriptors": true,
-                });
-                Ok(result)
-            }
+fn register_dumpmnemonic(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("dumpmnemonic", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Exporting wallet mnemonic…");
+
+        let mnemonic_passphrase = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(String::from),
+            Params::Map(map) => map
+                .get("mnemonic_passphrase")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            Params::None => None,
+        };
+
+        match wallet.export_mnemonic(mnemonic_passphrase.as_deref()) {
+            Ok(mnemonic) => Ok(Value::String(mnemonic)),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getxpub(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getxpub", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Deriving extended public key…");
+
+        let path = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(String::from),
+            Params::Map(map) => map.get("path").and_then(|v| v.as_str()).map(String::from),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing path parameter"))?;
+        let path = parse_derivation_path(&path)?;
+
+        let xpub = wallet
+            .get_xpub(&path)
+            .map_err(rpc_error_from_wallet_error)?;
+        let fingerprint = wallet
+            .get_master_fingerprint()
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(json!({
+            "xpub": xpub.to_string(),
+            "path": path.to_string(),
+            "fingerprint": hex::encode(fingerprint),
+        }))
+    });
+}
+
+fn register_importxpub(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("importxpub", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Importing extended public key…");
+
+        let (xpub, path) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).and_then(|v| v.as_str()).map(String::from),
+            ),
+            Params::Map(map) => (
+                map.get("xpub").and_then(|v| v.as_str()).map(String::from),
+                map.get("path").and_then(|v| v.as_str()).map(String::from),
+            ),
+            Params::None => (None, None),
+        };
+        let xpub = xpub.ok_or_else(|| RpcError::invalid_params("Missing xpub parameter"))?;
+        let xpub = bitcoin::bip32::Xpub::from_str(&xpub).map_err(|e| {
+            rpc_error(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                format!("Invalid extended public key: {}", e),
+            )
+        })?;
+        let path = path.ok_or_else(|| RpcError::invalid_params("Missing path parameter"))?;
+        let path = parse_derivation_path(&path)?;
+
+        match wallet.import_xpub(&xpub, &path) {
+            Ok(()) => Ok(json!({
+                "name": wallet.wallet_name(),
+                "warning": ""
+            })),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+// Parse a BIP32 derivation path like m/84'/1'/0'
+fn parse_derivation_path(path: &str) -> Result<bitcoin::bip32::DerivationPath, RpcError> {
+    bitcoin::bip32::DerivationPath::from_str(path).map_err(|e| {
+        rpc_error(
+            RPC_INVALID_PARAMETER,
+            format!("Invalid derivation path {}: {}", path, e),
+        )
+    })
+}
+
+fn register_backupwallet(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("backupwallet", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Backing up wallet…");
+
+        let destination = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(String::from),
+            Params::Map(map) => map
+                .get("destination")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing destination parameter"))?;
+
+        match wallet.export_wallet(Path::new(&destination)) {
+            Ok(()) => Ok(Value::Null),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_restorewallet(io: &mut IoHandler, manager: Arc<tokio::sync::RwLock<WalletManager>>) {
+    io.add_sync_method("restorewallet", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Restoring wallet from backup…");
+
+        let (wallet_name, backup_file) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).and_then(|v| v.as_str()).map(String::from),
+            ),
+            Params::Map(map) => (
+                map.get("wallet_name")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                map.get("backup_file")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+            ),
+            Params::None => (None, None),
+        };
+        let wallet_name =
+            wallet_name.ok_or_else(|| RpcError::invalid_params("Missing wallet_name parameter"))?;
+        let backup_file =
+            backup_file.ok_or_else(|| RpcError::invalid_params("Missing backup_file parameter"))?;
+
+        match block_on_local(WalletManager::restore_wallet(
+            &manager,
+            &wallet_name,
+            Path::new(&backup_file),
+        )) {
+            Ok(()) => Ok(json!({
+                "name": wallet_name,
+                "warning": ""
+            })),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_settxfee(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("settxfee", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Setting transaction fee…");
+
+        let amount = match params {
+            Params::Array(arr) => arr.get(0).cloned(),
+            Params::Map(map) => map.get("amount").cloned(),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing amount parameter"))?;
+
+        // The preference is given in BTC/kvB, zero clears it
+        let fee_per_kvb =
+            Some(parse_btc_amount(&amount, "amount")?).filter(|fee| *fee > Amount::ZERO);
+
+        match wallet.set_pay_tx_fee(fee_per_kvb) {
+            Ok(()) => Ok(Value::Bool(true)),
             Err(e) => Err(rpc_error_from_wallet_error(e)),
         }
     });
@@ -147,328 +416,3621 @@ fn register_getnewaddress(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
             _ => (None, None),
         };
 
-        if let Some(atype) = &address_type {
-            if !["legacy", "p2sh-segwit", "bech32"].contains(&atype.as_str()) {
-                return Err(RpcError::invalid_params("Invalid address type"));
-            }
+        let address_type = match address_type.as_deref() {
+            None => None,
+            Some(name) => match AddressType::from_core_name(name) {
+                Some(address_type) => Some(address_type),
+                None if ["legacy", "p2sh-segwit"].contains(&name) => {
+                    log::warn!(
+                        "Ignoring address_type={}, not supported by this wallet",
+                        name
+                    );
+                    None
+                }
+                None => return Err(RpcError::invalid_params("Invalid address type")),
+            },
+        };
 
-            if atype != "bech32" {
-                log::warn!("Ignoring address_type={}, always returning bech32", atype);
-            }
+        if let Some(label) = &label {
+            check_label(label)?;
         }
 
-        match wallet.get_new_address(label.as_deref()) {
+        let address = match address_type {
+            Some(address_type) => wallet.get_new_address_with_type(label.as_deref(), address_type),
+            None => wallet.get_new_address(label.as_deref()),
+        };
+        match address {
             Ok(address) => Ok(Value::String(address.to_string())),
             Err(e) => Err(rpc_error_from_wallet_error(e)),
         }
     });
 }
 
-fn register_getbalance(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
-    io.add_sync_method("getbalance", move |params: Params| {
-        let wallet = wallet.clone();
+fn register_setlabel(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("setlabel", move |params: Params| {
         log::info!("=========================");
-        log::info!("💰 Getting balance");
-        match wallet.get_balance() {
-            Ok(balance) => {
-                let amt = balance.confirmed.to_btc();
-                Ok(Value::Number(serde_json::Number::from_f64(amt).unwrap()))
-            }
-            Err(e) => Err(rpc_error_from_wallet_error(e)),
-        }
+        log::info!("Setting label…");
+        let (address, label) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).and_then(|v| v.as_str()).map(String::from),
+            ),
+            Params::Map(map) => (
+                map.get("address")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                map.get("label").and_then(|v| v.as_str()).map(String::from),
+            ),
+            Params::None => (None, None),
+        };
+        let address =
+            address.ok_or_else(|| RpcError::invalid_params("Missing address parameter"))?;
+        let label = label.ok_or_else(|| RpcError::invalid_params("Missing label parameter"))?;
+        let address = parse_address(&address, wallet.network())?;
+        check_label(&label)?;
+
+        wallet
+            .set_address_label(&address, &label)
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(Value::Null)
     });
 }
 
-fn register_listunspent(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
-    io.add_sync_method("listunspent", move |_params: Params| {
-        log::info!("💰 Listing unspent");
-        match wallet.list_unspent() {
-            Ok(unspent) => {
-                let result = json!({
-                    "result": unspent
-                });
-                Ok(result)
-            }
-            Err(e) => Err(rpc_error_from_wallet_error(e)),
+fn register_getaddressesbylabel(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getaddressesbylabel", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Getting addresses by label…");
+        let label = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(String::from),
+            Params::Map(map) => map.get("label").and_then(|v| v.as_str()).map(String::from),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing label parameter"))?;
+        check_label(&label)?;
+
+        let addresses = wallet
+            .get_addresses_by_label(&label)
+            .map_err(rpc_error_from_wallet_error)?;
+        if addresses.is_empty() {
+            return Err(rpc_error(
+                RPC_WALLET_INVALID_LABEL_NAME,
+                format!("No addresses with label {}", label),
+            ));
+        }
+
+        let mut result = serde_json::Map::new();
+        for (address, purpose) in addresses {
+            result.insert(address.to_string(), json!({ "purpose": purpose }));
         }
+        Ok(Value::Object(result))
     });
 }
 
-fn register_listtransactions(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
-    io.add_sync_method("listtransactions", move |params: Params| {
+// "*" is reserved by listtransactions to mean every label
+fn check_label(label: &str) -> Result<(), RpcError> {
+    if label == "*" {
+        return Err(rpc_error(
+            RPC_WALLET_INVALID_LABEL_NAME,
+            "Invalid label name",
+        ));
+    }
+    Ok(())
+}
+
+fn register_getaddressinfo(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getaddressinfo", move |params: Params| {
         log::info!("=========================");
-        log::info!("Listing transactions…");
+        log::info!("Getting address info…");
+        let address = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(String::from),
+            Params::Map(map) => map
+                .get("address")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing address parameter"))?;
+        let address = parse_address(&address, wallet.network())?;
 
-        match wallet.list_transactions() {
-            Ok(transactions) => {
-                let result = json!({
-                    "result": transactions
-                });
-                Ok(result)
-            }
+        match wallet.get_address_info(&address) {
+            Ok(info) => Ok(address_info_to_json(&info)),
             Err(e) => Err(rpc_error_from_wallet_error(e)),
         }
     });
 }
 
-fn register_rescanblockchain(io: &mut IoHandler, wallet_interface: Arc<WalletInterface>) {
-    io.add_sync_method("rescanblockchain", move |params: Params| {
-        log::info!("=========================");
-        log::info!("Rescanning blockchain...");
+// Render address info as `getaddressinfo` does. Descriptor wallets have no HD
+// seed, so like Bitcoin Core's descriptor wallets `hdseedid` is never reported.
+fn address_info_to_json(info: &AddressInfo) -> Value {
+    let mut result = json!({
+        "address": info.address.to_string(),
+        "scriptPubKey": info.script_pubkey.to_hex_string(),
+        "ismine": info.is_mine(),
+        // Every address of a descriptor wallet is spendable by its descriptors
+        "iswatchonly": false,
+        "isscript": info.script_pubkey.is_p2sh() || info.script_pubkey.is_p2wsh(),
+        "iswitness": info.script_pubkey.is_witness_program(),
+        "ischange": info.is_change(),
+        "labels": info.labels,
+    });
 
-        // Parse optional start_height and stop_height parameters
-        let (start_height, stop_height) = match params {
-            Params::Array(arr) => {
-                let start = arr.get(0).and_then(|v| v.as_i64()).unwrap_or(0);
-                let stop = arr.get(1).and_then(|v| v.as_i64());
-                (start, stop)
-            }
-            Params::Map(map) => {
-                let start = map
-                    .get("start_height")
-                    .and_then(|v| v.as_i64())
-                    .unwrap_or(0);
-                let stop = map.get("stop_height").and_then(|v| v.as_i64());
-                (start, stop)
-            }
-            _ => (0, None),
+    if let Some(program) = info.address.witness_program() {
+        result["witness_version"] = json!(program.version().to_num());
+        result["witness_program"] = json!(hex::encode(program.program().as_bytes()));
+    }
+    if let Some(pubkey) = info.pubkey {
+        result["pubkey"] = json!(pubkey.to_string());
+    }
+    if let Some(timestamp) = info.timestamp {
+        result["timestamp"] = json!(timestamp);
+    }
+    if let Some(path) = &info.hd_key_path {
+        let path: String = path
+            .into_iter()
+            .map(|child| format!("/{}", child))
+            .collect();
+        result["hdkeypath"] = json!(format!("m{}", path));
+    }
+    if let Some(fingerprint) = info.master_fingerprint {
+        result["hdmasterfingerprint"] = json!(fingerprint.to_string());
+    }
+
+    result
+}
+
+fn register_importdescriptors(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("importdescriptors", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Importing descriptors…");
+        let requests = match params {
+            Params::Array(arr) => arr.get(0).cloned(),
+            Params::Map(map) => map.get("requests").cloned(),
+            Params::None => None,
+        };
+        let requests = match requests {
+            Some(Value::Array(requests)) => requests,
+            _ => return Err(RpcError::invalid_params("Missing requests parameter")),
         };
 
-        // Validate parameters
-        if start_height < 0 {
-            return Err(RpcError::invalid_params("Start height cannot be negative"));
+        // A blank wallet is created when its receive descriptor is imported, so
+        // change descriptors go first to be included
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| !requests[i]["internal"].as_bool().unwrap_or(false));
+
+        let mut results = vec![Value::Null; requests.len()];
+        let mut rescan_from: Option<u64> = None;
+        for i in order {
+            let result = parse_import_request(&requests[i]).and_then(|import| {
+                wallet
+                    .import_descriptor(&import.desc, import.internal, import.timestamp)
+                    .map_err(rpc_error_from_wallet_error)?;
+                Ok(import.timestamp)
+            });
+            results[i] = match result {
+                Ok(timestamp) => {
+                    if let Some(timestamp) = timestamp {
+                        rescan_from = Some(rescan_from.map_or(timestamp, |t| t.min(timestamp)));
+                    }
+                    json!({ "success": true })
+                }
+                Err(e) => json!({
+                    "success": false,
+                    "error": { "code": e.code.code(), "message": e.message },
+                }),
+            };
         }
 
-        if let Some(stop) = stop_height {
-            if stop < start_height {
-                return Err(RpcError::invalid_params(
-                    "Stop height must be greater than or equal to start height",
-                ));
+        if let Some(timestamp) = rescan_from {
+            let start_height = block_on_local(wallet.rescan_start_height(timestamp))
+                .map_err(rpc_error_from_wallet_error)?;
+            if let Err(e) = wallet.start_rescan(start_height, None) {
+                log::warn!("Could not rescan for imported descriptors: {}", e);
             }
         }
 
-        // Use our dedicated rescan_blockchain method
-        match task::block_in_place(|| {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
+        Ok(Value::Array(results))
+    });
+}
 
-            let local = LocalSet::new();
-            rt.block_on(async {
-                local
-                    .run_until(async {
-                        log::debug!("Starting blockchain rescan from height {}", start_height);
-                        wallet_interface
-                            .rescan_blockchain(start_height as i32, stop_height.map(|h| h as i32))
-                            .await
-                    })
-                    .await
-            })
-        }) {
-            Ok((actual_start, actual_stop)) => Ok(json!({
-                "start_height": actual_start,
-                "stop_height": actual_stop
+struct ImportRequest {
+    desc: String,
+    internal: bool,
+    /// Time the descriptor's keys were created, or None for "now"
+    timestamp: Option<u64>,
+}
+
+fn parse_import_request(request: &Value) -> Result<ImportRequest, RpcError> {
+    let desc = request["desc"]
+        .as_str()
+        .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Descriptor not found."))?;
+    let timestamp = match &request["timestamp"] {
+        Value::String(now) if now == "now" => None,
+        Value::Number(timestamp) => Some(
+            timestamp
+                .as_u64()
+                .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Invalid timestamp"))?,
+        ),
+        _ => {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                "Missing required timestamp field for key",
+            ))
+        }
+    };
+
+    // BDK wallets only have active receive and change descriptors, whose address
+    // range is covered by the wallet's lookahead
+    if !request["active"].as_bool().unwrap_or(false) {
+        return Err(rpc_error(
+            RPC_INVALID_PARAMETER,
+            "Only active descriptors can be imported",
+        ));
+    }
+    if request.get("label").is_some() {
+        return Err(rpc_error(
+            RPC_INVALID_PARAMETER,
+            "Ranged descriptors should not have a label",
+        ));
+    }
+
+    Ok(ImportRequest {
+        desc: desc.to_string(),
+        internal: request["internal"].as_bool().unwrap_or(false),
+        timestamp,
+    })
+}
+
+fn register_listdescriptors(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("listdescriptors", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Listing descriptors...");
+        match wallet.list_descriptors() {
+            Ok(descriptors) => Ok(json!({
+                "wallet_name": wallet.wallet_name(),
+                "descriptors": descriptors
+                    .iter()
+                    .map(descriptor_entry_to_json)
+                    .collect::<Vec<_>>(),
             })),
             Err(e) => Err(rpc_error_from_wallet_error(e)),
         }
     });
 }
 
-fn register_gettransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
-    io.add_sync_method("gettransaction", move |params: Params| {
-        log::info!("Getting transaction…");
-        // Parse parameters
-        // let (txid_str, include_watchonly) = match params {
-        //     Params::Array(arr) => {
-        //         let txid = arr.get(0).and_then(|v| v.as_str()).ok_or_else(|| {
-        //             RpcError::invalid_params("Missing txid parameter")
-        //         })?;
-        //         let include_watchonly = arr.get(1).and_then(|v| v.as_bool()).unwrap_or(false);
-        //         (txid, include_watchonly)
-        //     }
-        //     Params::Map(map) => {
-        //         let txid = map.get("txid").and_then(|v| v.as_str()).ok_or_else(|| {
-        //             RpcError::invalid_params("Missing txid parameter")
-        //         })?;
-        //         let include_watchonly = map.get("include_watchonly").and_then(|v| v.as_bool()).unwrap_or(false);
-        //         (txid, include_watchonly)
-        //     }
-        //     _ => return Err(RpcError::invalid_params("Invalid parameters")),
-        // };
-
-        // Parse txid
-        // let txid = match Txid::from_str(txid_str) {
-        //     Ok(txid) => txid,
-        //     Err(_) => return Err(RpcError::invalid_params("Invalid txid")),
-        // };
-
-        // Get transaction
-        // match tokio::runtime::Handle::current().block_on(wallet.get_transaction(&txid)) {
-        //     Ok(tx) => {
-        //         let result = json!({
-        //             "amount": tx.amount.to_btc(),
-        //             "confirmations": tx.confirmations,
-        //             "blockhash": tx.blockhash.map(|h| h.to_string()).unwrap_or_default(),
-        //             "blockindex": 0, // Would need actual block index
-        //             "blocktime": tx.timestamp,
-        //             "txid": tx.txid.to_string(),
-        //             "time": tx.timestamp,
-        //             "timereceived": tx.timestamp,
-        //             "comment": tx.comment,
-        //             "details": [
-        //                 {
-        //                     "address": "", // Would need tx details
-        //                     "category": if tx.amount.is_negative() { "send" } else { "receive" },
-        //                     "amount": tx.amount.to_btc(),
-        //                     "label": tx.label,
-        //                     "vout": 0, // Would need tx details
-        //                 }
-        //             ],
-        //             "hex": "", // Would need serialized tx
-        //         });
-        //         Ok(result)
-        //     }
-        //     Err(e) => Err(rpc_error_from_wallet_error(e)),
-        // }
-        Ok(Value::String("txid".to_string()))
+fn descriptor_entry_to_json(entry: &DescriptorEntry) -> Value {
+    let mut json = json!({
+        "desc": entry.desc,
+        "timestamp": entry.timestamp,
+        "active": entry.active,
+        "internal": entry.internal,
+        "next_index": entry.next_index,
     });
+    if let Some(range) = entry.range {
+        json["range"] = json!(range);
+    }
+    json
 }
 
-fn register_sendtoaddress(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
-    io.add_sync_method("sendtoaddress", move |params: Params| {
-        log::info!("Sending to address…");
-        // Parse parameters
-        // let (address_str, amount, comment, comment_to, subtract_fee, avoid_reuse, fee_rate) = match params {
-        //     Params::Array(arr) => {
-        //         let address = arr.get(0).and_then(|v| v.as_str()).ok_or_else(|| {
-        //             RpcError::invalid_params("Missing address parameter")
-        //         })?;
-        //         let amount = arr.get(1).and_then(|v| v.as_f64()).ok_or_else(|| {
-        //             RpcError::invalid_params("Missing amount parameter")
-        //         })?;
-        //         let comment = arr.get(2).and_then(|v| v.as_str()).unwrap_or("");
-        //         let comment_to = arr.get(3).and_then(|v| v.as_str()).unwrap_or("");
-        //         let subtract_fee = arr.get(4).and_then(|v| v.as_bool()).unwrap_or(false);
-        //         let avoid_reuse = arr.get(5).and_then(|v| v.as_bool()).unwrap_or(false);
-        //         let fee_rate = arr.get(6).and_then(|v| v.as_f64());
-        //         (address, amount, comment, comment_to, subtract_fee, avoid_reuse, fee_rate)
-        //     }
-        //     Params::Map(map) => {
-        //         let address = map.get("address").and_then(|v| v.as_str()).ok_or_else(|| {
-        //             RpcError::invalid_params("Missing address parameter")
-        //         })?;
-        //         let amount = map.get("amount").and_then(|v| v.as_f64()).ok_or_else(|| {
-        //             RpcError::invalid_params("Missing amount parameter")
-        //         })?;
-        //         let comment = map.get("comment").and_then(|v| v.as_str()).unwrap_or("");
-        //         let comment_to = map.get("comment_to").and_then(|v| v.as_str()).unwrap_or("");
-        //         let subtract_fee = map.get("subtract_fee_from_amount").and_then(|v| v.as_bool()).unwrap_or(false);
-        //         let avoid_reuse = map.get("avoid_reuse").and_then(|v| v.as_bool()).unwrap_or(false);
-        //         let fee_rate = map.get("fee_rate").and_then(|v| v.as_f64());
-        //         (address, amount, comment, comment_to, subtract_fee, avoid_reuse, fee_rate)
-        //     }
-        //     _ => return Err(RpcError::invalid_params("Invalid parameters")),
-        // };
-
-        // Parse address
-        // let address = match Address::from_str(address_str) {
-        //     Ok(addr) => addr,
-        //     Err(_) => return Err(RpcError::invalid_params("Invalid address")),
-        // };
-
-        // // Create amount in satoshis
-        // let btc_amount = Amount::from_btc(amount).map_err(|_| {
-        //     RpcError::invalid_params("Invalid amount")
-        // })?;
-
-        // // Create transaction
-        // let recipient = TxRecipient {
-        //     script: address.script_pubkey(),
-        //     amount: btc_amount,
-        // };
-
-        // let subtract_indices = if subtract_fee { Some(vec![0]) } else { None };
-
-        // match wallet.create_transaction(&[recipient], fee_rate, subtract_indices) {
-        //     Ok(tx_details) => {
-        //         // Sign transaction
-        //         let mut tx = tx_details.transaction.clone();
-        //         if let Err(e) = wallet.sign_transaction(&mut tx) {
-        //             return Err(rpc_error_from_wallet_error(e));
-        //         }
-        //         // Send transaction
-        //         match tokio::runtime::Handle::current().block_on(wallet.send_transaction(&tx)) {
-        //             Ok(txid) => Ok(Value::String(txid.to_string())),
-        //             Err(e) => Err(rpc_error_from_wallet_error(e)),
-        //         }
-        //     }
-        //     Err(e) => Err(rpc_error_from_wallet_error(e)),
-        // }
-        Ok(Value::String("txid".to_string()))
+fn register_getdescriptorinfo(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getdescriptorinfo", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Getting descriptor info...");
+
+        let descriptor = match params {
+            Params::Array(arr) => arr.get(0).cloned(),
+            Params::Map(map) => map.get("descriptor").cloned(),
+            Params::None => None,
+        };
+        let descriptor = descriptor
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing descriptor parameter"))?;
+
+        let info = wallet
+            .parse_descriptor(descriptor)
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(json!({
+            "descriptor": info.descriptor,
+            "checksum": info.checksum,
+            "isrange": info.is_range,
+            "issolvable": info.is_solvable,
+            "hasprivatekeys": info.has_private_keys,
+        }))
     });
 }
 
-fn parse_create_wallet_options(params: Params) -> Result<CreateWalletOptions, RpcError> {
-    let mut options = CreateWalletOptions::default();
+fn register_deriveaddresses(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("deriveaddresses", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Deriving addresses...");
 
-    match params {
-        Params::Array(arr) => {
-            options.wallet_name = arr
-                .get(0)
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| RpcError::invalid_params("Missing wallet name parameter"))?
-                .to_string();
+        let (descriptor, range) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (map.get("descriptor").cloned(), map.get("range").cloned()),
+            Params::None => (None, None),
+        };
+        let descriptor = descriptor
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing descriptor parameter"))?;
+        let range = match range {
+            Some(Value::Null) | None => None,
+            Some(range) => Some(parse_descriptor_range(&range)?),
+        };
 
-            // Optional parameters
-            if let Some(v) = arr.get(1).and_then(|v| v.as_bool()) {
-                options.disable_private_keys = v;
-            }
-            if let Some(v) = arr.get(2).and_then(|v| v.as_bool()) {
-                options.blank = v;
-            }
-            if let Some(v) = arr.get(3).and_then(|v| v.as_str()) {
-                options.passphrase = Some(v.to_string());
-            }
-            if let Some(v) = arr.get(4).and_then(|v| v.as_bool()) {
-                options.avoid_reuse = v;
-            }
-            if let Some(v) = arr.get(5).and_then(|v| v.as_bool()) {
-                options.descriptors = v;
-            }
-            if let Some(v) = arr.get(6).and_then(|v| v.as_bool()) {
-                options.load_on_startup = v;
-            }
+        let info = wallet
+            .parse_descriptor(descriptor)
+            .map_err(rpc_error_from_wallet_error)?;
+        if info.is_range && range.is_none() {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                "Range must be specified for a ranged descriptor",
+            ));
+        }
+        if !info.is_range && range.is_some() {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                "Range should not be specified for an un-ranged descriptor",
+            ));
         }
-        Params::Map(map) => {
-            options.wallet_name = map
-                .get("wallet_name")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| RpcError::invalid_params("Missing wallet name parameter"))?
-                .to_string();
 
-            // Optional parameters
-            if let Some(v) = map.get("disable_private_keys").and_then(|v| v.as_bool()) {
-                options.disable_private_keys = v;
-            }
-            if let Some(v) = map.get("blank").and_then(|v| v.as_bool()) {
-                options.blank = v;
-            }
-            if let Some(v) = map.get("passphrase").and_then(|v| v.as_str()) {
-                options.passphrase = Some(v.to_string());
-            }
-            if let Some(v) = map.get("avoid_reuse").and_then(|v| v.as_bool()) {
-                options.avoid_reuse = v;
-            }
-            if let Some(v) = map.get("descriptors").and_then(|v| v.as_bool()) {
-                options.descriptors = v;
-            }
-            if let Some(v) = map.get("load_on_startup").and_then(|v| v.as_bool()) {
-                options.load_on_startup = v;
-            }
+        let addresses = wallet
+            .derive_addresses(descriptor, range)
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(json!(addresses
+            .iter()
+            .map(|address| address.to_string())
+            .collect::<Vec<_>>()))
+    });
+}
+
+// Parse a descriptor range, given as an end index or as `[begin, end]`, both inclusive
+fn parse_descriptor_range(range: &Value) -> Result<(u32, u32), RpcError> {
+    let (begin, end) = match range {
+        Value::Number(_) => (0, range.as_i64()),
+        Value::Array(arr) if arr.len() == 2 => (
+            arr[0]
+                .as_i64()
+                .ok_or_else(|| RpcError::invalid_params("Range begin must be a number"))?,
+            arr[1].as_i64(),
+        ),
+        _ => {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                "Range must be specified as end or as [begin,end]",
+            ))
         }
-        _ => return Err(RpcError::invalid_params("Invalid parameters")),
+    };
+    let end = end.ok_or_else(|| RpcError::invalid_params("Range end must be a number"))?;
+    if begin < 0 || end < 0 {
+        return Err(rpc_error(
+            RPC_INVALID_PARAMETER,
+            "Range should be greater or equal than 0",
+        ));
+    }
+    if end >> 31 != 0 {
+        return Err(rpc_error(RPC_INVALID_PARAMETER, "End of range is too high"));
+    }
+    if end < begin {
+        return Err(rpc_error(
+            RPC_INVALID_PARAMETER,
+            "Range specified as [begin,end] must not have begin after end",
+        ));
+    }
+    if end >= begin + 1_000_000 {
+        return Err(rpc_error(RPC_INVALID_PARAMETER, "Range is too large"));
+    }
+    Ok((begin as u32, end as u32))
+}
+
+fn register_getbalance(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getbalance", move |params: Params| {
+        let wallet = wallet.clone();
+        log::info!("=========================");
+        log::info!("💰 Getting balance");
+        match wallet.get_balance() {
+            Ok(balance) => {
+                let amt = balance.confirmed.to_btc();
+                Ok(Value::Number(serde_json::Number::from_f64(amt).unwrap()))
+            }
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_listunspent(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("listunspent", move |params: Params| {
+        log::info!("=========================");
+        log::info!("💰 Listing unspent");
+
+        let filter = parse_unspent_filter(params, wallet.network())?;
+        match block_on_local(wallet.list_unspent_details()) {
+            Ok(unspent) => Ok(Value::Array(
+                filter_unspent(unspent, &filter)
+                    .iter()
+                    .map(unspent_output_to_json)
+                    .collect(),
+            )),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_lockunspent(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("lockunspent", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Updating locked outputs…");
+
+        // Locks are always persisted, so the `persistent` argument is ignored
+        let (unlock, transactions) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (map.get("unlock").cloned(), map.get("transactions").cloned()),
+            Params::None => (None, None),
+        };
+        let unlock = unlock
+            .and_then(|v| v.as_bool())
+            .ok_or_else(|| RpcError::invalid_params("Missing unlock parameter"))?;
+        let outpoints = match transactions.filter(|v| !v.is_null()) {
+            Some(Value::Array(outputs)) => outputs
+                .iter()
+                .map(parse_outpoint)
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(_) => return Err(RpcError::invalid_params("transactions must be an array")),
+            None if unlock => {
+                wallet
+                    .unlock_all_outpoints()
+                    .map_err(rpc_error_from_wallet_error)?;
+                return Ok(Value::Bool(true));
+            }
+            None => Vec::new(),
+        };
+
+        for outpoint in outpoints {
+            let result = if unlock {
+                wallet.unlock_outpoint(outpoint)
+            } else {
+                wallet.lock_outpoint(outpoint)
+            };
+            result.map_err(rpc_error_from_wallet_error)?;
+        }
+        Ok(Value::Bool(true))
+    });
+}
+
+fn register_listlockunspent(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("listlockunspent", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Listing locked outputs…");
+
+        let outpoints = wallet
+            .list_locked_outpoints()
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(Value::Array(
+            outpoints
+                .iter()
+                .map(|outpoint| {
+                    json!({
+                        "txid": outpoint.txid.to_string(),
+                        "vout": outpoint.vout,
+                    })
+                })
+                .collect(),
+        ))
+    });
+}
+
+fn register_listaddressgroupings(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("listaddressgroupings", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Listing address groupings…");
+
+        let groupings = wallet
+            .list_address_groupings()
+            .map_err(rpc_error_from_wallet_error)?;
+        // Each address is listed as [address, amount] or [address, amount, label]
+        Ok(Value::Array(
+            groupings
+                .iter()
+                .map(|group| {
+                    Value::Array(
+                        group
+                            .iter()
+                            .map(|entry| {
+                                let mut fields = vec![
+                                    json!(entry.address.to_string()),
+                                    json!(entry.amount.to_btc()),
+                                ];
+                                if let Some(label) = &entry.label {
+                                    fields.push(json!(label));
+                                }
+                                Value::Array(fields)
+                            })
+                            .collect(),
+                    )
+                })
+                .collect(),
+        ))
+    });
+}
+
+/// Output selection criteria accepted by `listunspent`
+struct UnspentFilter {
+    min_conf: u32,
+    max_conf: u32,
+    addresses: Option<Vec<Address>>,
+    include_unsafe: bool,
+    minimum_amount: Amount,
+    maximum_amount: Option<Amount>,
+    maximum_count: Option<usize>,
+}
+
+impl Default for UnspentFilter {
+    fn default() -> Self {
+        Self {
+            min_conf: 1,
+            max_conf: 9_999_999,
+            addresses: None,
+            include_unsafe: true,
+            minimum_amount: Amount::ZERO,
+            maximum_amount: None,
+            maximum_count: None,
+        }
+    }
+}
+
+fn parse_unspent_filter(params: Params, network: Network) -> Result<UnspentFilter, RpcError> {
+    let (min_conf, max_conf, addresses, include_unsafe, query_options) = match params {
+        Params::Array(arr) => (
+            arr.get(0).cloned(),
+            arr.get(1).cloned(),
+            arr.get(2).cloned(),
+            arr.get(3).cloned(),
+            arr.get(4).cloned(),
+        ),
+        Params::Map(map) => (
+            map.get("minconf").cloned(),
+            map.get("maxconf").cloned(),
+            map.get("addresses").cloned(),
+            map.get("include_unsafe").cloned(),
+            map.get("query_options").cloned(),
+        ),
+        Params::None => (None, None, None, None, None),
+    };
+
+    let mut filter = UnspentFilter::default();
+    if let Some(min_conf) = min_conf.filter(|v| !v.is_null()) {
+        filter.min_conf = min_conf
+            .as_u64()
+            .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Invalid minconf"))?
+            as u32;
+    }
+    if let Some(max_conf) = max_conf.filter(|v| !v.is_null()) {
+        filter.max_conf = max_conf
+            .as_u64()
+            .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Invalid maxconf"))?
+            as u32;
+    }
+    if let Some(addresses) = addresses.filter(|v| !v.is_null()) {
+        let addresses = addresses
+            .as_array()
+            .ok_or_else(|| RpcError::invalid_params("addresses must be an array"))?;
+        let mut parsed: Vec<Address> = Vec::new();
+        for address in addresses {
+            let address = address
+                .as_str()
+                .ok_or_else(|| RpcError::invalid_params("addresses must be strings"))?;
+            let address = parse_address(address, network)?;
+            if parsed.contains(&address) {
+                return Err(rpc_error(
+                    RPC_INVALID_PARAMETER,
+                    format!("Invalid parameter, duplicated address: {}", address),
+                ));
+            }
+            parsed.push(address);
+        }
+        filter.addresses = Some(parsed);
+    }
+    if let Some(include_unsafe) = include_unsafe.and_then(|v| v.as_bool()) {
+        filter.include_unsafe = include_unsafe;
+    }
+    if let Some(options) = query_options.filter(|v| !v.is_null()) {
+        if let Some(amount) = options.get("minimumAmount") {
+            filter.minimum_amount = parse_btc_amount(amount, "minimumAmount")?;
+        }
+        if let Some(amount) = options.get("maximumAmount") {
+            filter.maximum_amount = Some(parse_btc_amount(amount, "maximumAmount")?);
+        }
+        if let Some(count) = options.get("maximumCount") {
+            filter.maximum_count = Some(
+                count
+                    .as_u64()
+                    .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Invalid maximumCount"))?
+                    as usize,
+            );
+        }
+    }
+
+    Ok(filter)
+}
+
+fn parse_btc_amount(value: &Value, name: &str) -> Result<Amount, RpcError> {
+    value
+        .as_f64()
+        .and_then(|btc| Amount::from_btc(btc).ok())
+        .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, format!("Invalid {}", name)))
+}
+
+fn filter_unspent(unspent: Vec<UnspentOutput>, filter: &UnspentFilter) -> Vec<UnspentOutput> {
+    let matching = unspent.into_iter().filter(|output| {
+        output.confirmations >= filter.min_conf
+            && output.confirmations <= filter.max_conf
+            && (filter.include_unsafe || output.safe)
+            && output.amount >= filter.minimum_amount
+            && filter
+                .maximum_amount
+                .map_or(true, |maximum| output.amount <= maximum)
+            && filter.addresses.as_ref().map_or(true, |addresses| {
+                output
+                    .address
+                    .as_ref()
+                    .map_or(false, |address| addresses.contains(address))
+            })
+    });
+
+    match filter.maximum_count {
+        Some(count) => matching.take(count).collect(),
+        None => matching.collect(),
+    }
+}
+
+fn unspent_output_to_json(output: &UnspentOutput) -> Value {
+    let mut entry = json!({
+        "txid": output.txid.to_string(),
+        "vout": output.vout,
+        "scriptPubKey": output.script_pubkey.to_hex_string(),
+        "amount": output.amount.to_btc(),
+        "confirmations": output.confirmations,
+        "spendable": true,
+        "solvable": true,
+        "safe": output.safe,
+    });
+
+    if let Some(address) = &output.address {
+        entry["address"] = json!(address.to_string());
+        entry["label"] = json!(output.label);
+    }
+    if let Some(descriptor) = &output.descriptor {
+        entry["desc"] = json!(descriptor);
+    }
+
+    entry
+}
+
+fn register_listtransactions(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("listtransactions", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Listing transactions…");
+
+        let (label, count, skip) = match params {
+            Params::Array(arr) => {
+                let label = arr.get(0).and_then(|v| v.as_str()).map(String::from);
+                let count = arr.get(1).and_then(|v| v.as_i64()).unwrap_or(10);
+                let skip = arr.get(2).and_then(|v| v.as_i64()).unwrap_or(0);
+                (label, count, skip)
+            }
+            Params::Map(map) => {
+                let label = map.get("label").and_then(|v| v.as_str()).map(String::from);
+                let count = map.get("count").and_then(|v| v.as_i64()).unwrap_or(10);
+                let skip = map.get("skip").and_then(|v| v.as_i64()).unwrap_or(0);
+                (label, count, skip)
+            }
+            _ => (None, 10, 0),
+        };
+
+        if count < 0 {
+            return Err(RpcError::invalid_params("Negative count"));
+        }
+        if skip < 0 {
+            return Err(RpcError::invalid_params("Negative from"));
+        }
+
+        match wallet.list_transaction_details() {
+            Ok(transactions) => {
+                let selected = select_transactions(
+                    transactions,
+                    label.as_deref(),
+                    count as usize,
+                    skip as usize,
+                );
+                Ok(Value::Array(
+                    selected.iter().map(wallet_transaction_to_json).collect(),
+                ))
+            }
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+// Apply listtransactions filtering: keep entries matching `label` ("*" matches
+// everything), then page backwards from the most recent, returning oldest first
+fn select_transactions(
+    transactions: Vec<WalletTransaction>,
+    label: Option<&str>,
+    count: usize,
+    skip: usize,
+) -> Vec<WalletTransaction> {
+    let matching: Vec<WalletTransaction> = transactions
+        .into_iter()
+        .filter(|tx| match label {
+            None | Some("*") => true,
+            Some(label) => tx.label == label,
+        })
+        .collect();
+
+    let end = matching.len().saturating_sub(skip);
+    let start = end.saturating_sub(count);
+    matching[start..end].to_vec()
+}
+
+fn wallet_transaction_to_json(tx: &WalletTransaction) -> Value {
+    let mut entry = json!({
+        "address": tx.address.as_ref().map(|address| address.to_string()),
+        "category": tx.category.as_str(),
+        "amount": tx.amount.to_btc(),
+        "label": tx.label,
+        "vout": tx.vout,
+        "confirmations": tx.confirmations,
+        "txid": tx.txid.to_string(),
+        "time": tx.block_time.unwrap_or(tx.time_received),
+        "timereceived": tx.time_received,
+    });
+
+    if let Some(fee) = tx.fee {
+        entry["fee"] = json!(-fee.to_btc());
+        entry["abandoned"] = json!(tx.abandoned);
+    }
+    if let (Some(hash), Some(height), Some(time)) = (tx.block_hash, tx.block_height, tx.block_time)
+    {
+        entry["blockhash"] = json!(hash.to_string());
+        entry["blockheight"] = json!(height);
+        entry["blocktime"] = json!(time);
+    }
+
+    entry
+}
+
+fn register_rescanblockchain(io: &mut IoHandler, wallet_interface: Arc<WalletInterface>) {
+    io.add_sync_method("rescanblockchain", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Rescanning blockchain...");
+
+        // Parse optional start_height and stop_height parameters
+        let (start_height, stop_height) = match params {
+            Params::Array(arr) => {
+                let start = arr.get(0).and_then(|v| v.as_i64()).unwrap_or(0);
+                let stop = arr.get(1).and_then(|v| v.as_i64());
+                (start, stop)
+            }
+            Params::Map(map) => {
+                let start = map
+                    .get("start_height")
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or(0);
+                let stop = map.get("stop_height").and_then(|v| v.as_i64());
+                (start, stop)
+            }
+            _ => (0, None),
+        };
+
+        // Validate parameters
+        if start_height < 0 {
+            return Err(RpcError::invalid_params("Start height cannot be negative"));
+        }
+
+        if let Some(stop) = stop_height {
+            if stop < start_height {
+                return Err(RpcError::invalid_params(
+                    "Stop height must be greater than or equal to start height",
+                ));
+            }
+        }
+
+        // Rescanning can take a long time, so it runs in the background and
+        // its progress is reported by getrescanprogress
+        log::debug!("Starting blockchain rescan from height {}", start_height);
+        match wallet_interface.start_rescan(start_height as i32, stop_height.map(|h| h as i32)) {
+            Ok(()) => Ok(json!({
+                "start_height": start_height,
+                "stop_height": Value::Null
+            })),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getrescanprogress(io: &mut IoHandler, wallet_interface: Arc<WalletInterface>) {
+    io.add_sync_method("getrescanprogress", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Getting rescan progress...");
+        match wallet_interface.rescan_progress() {
+            Some(progress) => Ok(json!({
+                "progress": progress,
+                "current_height": wallet_interface.rescan_height(),
+            })),
+            None => Ok(Value::Null),
+        }
+    });
+}
+
+fn register_scantxoutset(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("scantxoutset", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Scanning UTXO set…");
+
+        let (action, scanobjects) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (map.get("action").cloned(), map.get("scanobjects").cloned()),
+            Params::None => (None, None),
+        };
+        let action = action
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing action parameter"))?;
+
+        match action {
+            "start" => {
+                let descriptors = parse_scan_objects(scanobjects.as_ref())?;
+                match block_on_local(wallet.scan_txout_set(&descriptors)) {
+                    Ok(result) => Ok(scan_result_to_json(&result)),
+                    Err(e) => Err(rpc_error_from_wallet_error(e)),
+                }
+            }
+            "abort" => Ok(Value::Bool(wallet.abort_scan_txout_set())),
+            "status" => Ok(match wallet.scan_txout_set_progress() {
+                Some(progress) => json!({ "progress": progress * 100.0 }),
+                None => Value::Null,
+            }),
+            other => Err(RpcError::invalid_params(format!(
+                "Invalid action '{}'",
+                other
+            ))),
+        }
+    });
+}
+
+// Descriptors to scan for, given as strings or as objects with a `desc`
+fn parse_scan_objects(scanobjects: Option<&Value>) -> Result<Vec<String>, RpcError> {
+    let scanobjects = scanobjects.and_then(|v| v.as_array()).ok_or_else(|| {
+        RpcError::invalid_params("scanobjects argument is required for the start action")
+    })?;
+    scanobjects
+        .iter()
+        .map(|object| match object {
+            Value::String(desc) => Ok(desc.clone()),
+            Value::Object(object) => {
+                if object.contains_key("range") {
+                    return Err(RpcError::invalid_params(
+                        "range is not supported, ranged descriptors are scanned over their first 1000 indices",
+                    ));
+                }
+                object
+                    .get("desc")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+                    .ok_or_else(|| RpcError::invalid_params("Scan object is missing desc"))
+            }
+            _ => Err(RpcError::invalid_params(
+                "Scan objects must be descriptor strings or objects",
+            )),
+        })
+        .collect()
+}
+
+fn scan_result_to_json(result: &ScanResult) -> Value {
+    json!({
+        "success": true,
+        "height": result.height,
+        "bestblock": result.best_block.to_string(),
+        "unspents": result
+            .unspents
+            .iter()
+            .map(|unspent| {
+                json!({
+                    "txid": unspent.txid.to_string(),
+                    "vout": unspent.vout,
+                    "scriptPubKey": unspent.script_pubkey.to_hex_string(),
+                    "desc": unspent.desc,
+                    "amount": unspent.amount.to_btc(),
+                    "height": unspent.height,
+                })
+            })
+            .collect::<Vec<_>>(),
+        "total_amount": result.total_amount.to_btc(),
+    })
+}
+
+fn register_abandontransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("abandontransaction", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Abandoning transaction…");
+
+        let txid_str = match params {
+            Params::Array(arr) => arr
+                .get(0)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?
+                .to_string(),
+            Params::Map(map) => map
+                .get("txid")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?
+                .to_string(),
+            _ => return Err(RpcError::invalid_params("Invalid parameters")),
+        };
+
+        let txid = Txid::from_str(&txid_str).map_err(|_| {
+            rpc_error(
+                RPC_INVALID_PARAMETER,
+                "txid must be a 64 character hex string",
+            )
+        })?;
+
+        match wallet.abandon_transaction(&txid) {
+            Ok(()) => Ok(Value::Null),
+            Err(WalletError::TransactionNotFound(_)) => Err(rpc_error(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "Invalid or non-wallet transaction id",
+            )),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_gettransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("gettransaction", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Getting transaction…");
+
+        let txid_str = match params {
+            Params::Array(arr) => arr
+                .get(0)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?
+                .to_string(),
+            Params::Map(map) => map
+                .get("txid")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?
+                .to_string(),
+            _ => return Err(RpcError::invalid_params("Invalid parameters")),
+        };
+
+        let txid = Txid::from_str(&txid_str).map_err(|_| {
+            rpc_error(
+                RPC_INVALID_PARAMETER,
+                "txid must be a 64 character hex string",
+            )
+        })?;
+
+        match block_on_local(wallet.get_transaction_detail(&txid)) {
+            Ok(Some(detail)) => Ok(transaction_detail_to_json(&detail)),
+            Ok(None) => Err(rpc_error(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "Invalid or non-wallet transaction id",
+            )),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn transaction_detail_to_json(tx: &TransactionDetail) -> Value {
+    let details: Vec<Value> = tx
+        .details
+        .iter()
+        .map(|detail| {
+            let mut entry = json!({
+                "address": detail.address.as_ref().map(|address| address.to_string()),
+                "category": detail.category.as_str(),
+                "amount": detail.amount.to_btc(),
+                "label": tx.label,
+                "vout": detail.vout,
+            });
+            if let (TransactionCategory::Send, Some(fee)) = (detail.category, tx.fee) {
+                entry["fee"] = json!(-fee.to_btc());
+                entry["abandoned"] = json!(tx.abandoned);
+            }
+            entry
+        })
+        .collect();
+
+    let mut result = json!({
+        "amount": tx.amount.to_btc(),
+        "confirmations": tx.confirmations,
+        "txid": tx.txid.to_string(),
+        "time": tx.block_time.unwrap_or(tx.time_received),
+        "timereceived": tx.time_received,
+        "details": details,
+        "hex": serialize_hex(&tx.transaction),
+    });
+
+    if let Some(fee) = tx.fee {
+        result["fee"] = json!(-fee.to_btc());
+    }
+    if let (Some(hash), Some(height), Some(time)) = (tx.block_hash, tx.block_height, tx.block_time)
+    {
+        result["blockhash"] = json!(hash.to_string());
+        result["blockheight"] = json!(height);
+        result["blocktime"] = json!(time);
+        if let Some(index) = tx.block_index {
+            result["blockindex"] = json!(index);
+        }
+    }
+    if !tx.comment.is_empty() {
+        result["comment"] = json!(tx.comment);
+    }
+
+    result
+}
+
+fn register_createrawtransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("createrawtransaction", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Creating raw transaction…");
+
+        let (inputs, outputs, locktime, replaceable) = match params {
+            Params::Array(arr) => (
+                arr.get(0).cloned(),
+                arr.get(1).cloned(),
+                arr.get(2).cloned(),
+                arr.get(3).cloned(),
+            ),
+            Params::Map(map) => (
+                map.get("inputs").cloned(),
+                map.get("outputs").cloned(),
+                map.get("locktime").cloned(),
+                map.get("replaceable").cloned(),
+            ),
+            Params::None => (None, None, None, None),
+        };
+
+        let inputs = inputs.ok_or_else(|| RpcError::invalid_params("Missing inputs parameter"))?;
+        let outputs =
+            outputs.ok_or_else(|| RpcError::invalid_params("Missing outputs parameter"))?;
+        let locktime = match locktime.filter(|v| !v.is_null()) {
+            Some(locktime) => locktime
+                .as_u64()
+                .filter(|&locktime| locktime <= u32::MAX as u64)
+                .ok_or_else(|| {
+                    rpc_error(
+                        RPC_INVALID_PARAMETER,
+                        "Invalid parameter, locktime out of range",
+                    )
+                })? as u32,
+            None => 0,
+        };
+        let replaceable = replaceable.and_then(|v| v.as_bool()).unwrap_or(false);
+
+        let tx = build_raw_transaction(&inputs, &outputs, locktime, replaceable, wallet.network())?;
+        Ok(Value::String(serialize_hex(&tx)))
+    });
+}
+
+fn register_decoderawtransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("decoderawtransaction", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Decoding raw transaction…");
+
+        let hexstring = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_str()).map(String::from),
+            Params::Map(map) => map
+                .get("hexstring")
+                .and_then(|v| v.as_str())
+                .map(String::from),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing hexstring parameter"))?;
+
+        let tx = parse_raw_transaction(&hexstring)?;
+        Ok(decode_raw_transaction(&tx, wallet.network()))
+    });
+}
+
+fn register_signrawtransactionwithwallet(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("signrawtransactionwithwallet", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Signing raw transaction…");
+
+        let (hexstring, sighashtype) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(2).and_then(|v| v.as_str()).map(String::from),
+            ),
+            Params::Map(map) => (
+                map.get("hexstring")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                map.get("sighashtype")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+            ),
+            Params::None => (None, None),
+        };
+        let hexstring =
+            hexstring.ok_or_else(|| RpcError::invalid_params("Missing hexstring parameter"))?;
+        let sighash_type = sighashtype.as_deref().map(parse_sighash_type).transpose()?;
+
+        let mut tx = parse_raw_transaction(&hexstring)?;
+        let complete = wallet
+            .sign_transaction(&mut tx, sighash_type)
+            .map_err(rpc_error_from_wallet_error)?;
+
+        let errors: Vec<Value> = tx
+            .input
+            .iter()
+            .filter(|input| input.script_sig.is_empty() && input.witness.is_empty())
+            .map(|input| {
+                json!({
+                    "txid": input.previous_output.txid.to_string(),
+                    "vout": input.previous_output.vout,
+                    "witness": [],
+                    "scriptSig": "",
+                    "sequence": input.sequence.0,
+                    "error": "Unable to sign input, input not found in wallet or missing key",
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "hex": serialize_hex(&tx),
+            "complete": complete,
+            "errors": errors,
+        }))
+    });
+}
+
+fn register_sendrawtransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("sendrawtransaction", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Sending raw transaction…");
+
+        let (hexstring, maxfeerate) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).cloned(),
+            ),
+            Params::Map(map) => (
+                map.get("hexstring")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                map.get("maxfeerate").cloned(),
+            ),
+            Params::None => (None, None),
+        };
+        let hexstring =
+            hexstring.ok_or_else(|| RpcError::invalid_params("Missing hexstring parameter"))?;
+        let max_fee_rate = match maxfeerate.filter(|v| !v.is_null()) {
+            Some(rate) => parse_btc_amount(&rate, "maxfeerate")?,
+            None => DEFAULT_MAX_RAW_TX_FEE_RATE,
+        };
+
+        let tx = parse_raw_transaction(&hexstring)?;
+        let max_tx_fee = max_tx_fee(&tx, max_fee_rate);
+        match block_on_local(wallet.broadcast_transaction_with_max_fee(&tx, max_tx_fee)) {
+            Ok(txid) => Ok(Value::String(txid.to_string())),
+            Err(WalletError::TransactionRejected(reason)) => {
+                Err(rpc_error(RPC_VERIFY_ERROR, reason))
+            }
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_testmempoolaccept(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("testmempoolaccept", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Testing mempool acceptance…");
+
+        let (rawtxs, maxfeerate) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (map.get("rawtxs").cloned(), map.get("maxfeerate").cloned()),
+            Params::None => (None, None),
+        };
+        let rawtxs = rawtxs
+            .as_ref()
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| RpcError::invalid_params("Missing rawtxs parameter"))?;
+        let max_fee_rate = match maxfeerate.filter(|v| !v.is_null()) {
+            Some(rate) => parse_btc_amount(&rate, "maxfeerate")?,
+            None => DEFAULT_MAX_RAW_TX_FEE_RATE,
+        };
+
+        let txs = rawtxs
+            .iter()
+            .map(|rawtx| {
+                rawtx
+                    .as_str()
+                    .ok_or_else(|| RpcError::invalid_params("rawtxs must be hex strings"))
+                    .and_then(parse_raw_transaction)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match block_on_local(wallet.test_mempool_accept(&txs)) {
+            Ok(results) => Ok(Value::Array(
+                txs.iter()
+                    .zip(&results)
+                    .map(|(tx, result)| mempool_accept_to_json(tx, result, max_fee_rate))
+                    .collect(),
+            )),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+// Render a mempool acceptance result like `testmempoolaccept`, rejecting
+// transactions paying more than `max_fee_rate` as the node would
+fn mempool_accept_to_json(
+    tx: &Transaction,
+    result: &MempoolAcceptResult,
+    max_fee_rate: Amount,
+) -> Value {
+    let max_fee_exceeded = max_fee_rate > Amount::ZERO
+        && result
+            .fee
+            .is_some_and(|fee| fee > max_tx_fee(tx, max_fee_rate));
+    let mut json = json!({
+        "txid": result.txid.to_string(),
+        "wtxid": tx.compute_wtxid().to_string(),
+    });
+    if max_fee_exceeded {
+        json["allowed"] = json!(false);
+        json["reject-reason"] = json!("max-fee-exceeded");
+    } else if result.allowed {
+        json["allowed"] = json!(true);
+        json["vsize"] = json!(tx.vsize());
+        json["fees"] = json!({ "base": result.fee.unwrap_or(Amount::ZERO).to_btc() });
+    } else {
+        json["allowed"] = json!(false);
+        json["reject-reason"] = json!(result.reject_reason);
+    }
+    json
+}
+
+fn register_submitpackage(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("submitpackage", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Submitting package…");
+
+        let maxfeerate = match &params {
+            Params::Array(arr) => arr.get(1).cloned(),
+            Params::Map(map) => map.get("maxfeerate").cloned(),
+            Params::None => None,
+        };
+        let max_fee_rate = match maxfeerate.filter(|v| !v.is_null()) {
+            Some(rate) => parse_btc_amount(&rate, "maxfeerate")?,
+            None => DEFAULT_MAX_RAW_TX_FEE_RATE,
+        };
+        let txs = parse_package(params)?;
+        // From sat/kvB to sat/kwu
+        let max_fee_rate = FeeRate::from_sat_per_kwu(max_fee_rate.to_sat() / 4);
+        match block_on_local(wallet.submit_package(&txs, max_fee_rate)) {
+            Ok(results) => {
+                let package_msg = if results.iter().all(|result| result.allowed) {
+                    "success"
+                } else {
+                    "transaction failed"
+                };
+                let tx_results: serde_json::Map<String, Value> = txs
+                    .iter()
+                    .zip(&results)
+                    .map(|(tx, result)| {
+                        // Keyed by wtxid instead
+                        let mut json = package_accept_to_json(tx, result);
+                        if let Some(json) = json.as_object_mut() {
+                            json.remove("wtxid");
+                        }
+                        (tx.compute_wtxid().to_string(), json)
+                    })
+                    .collect();
+                Ok(json!({
+                    "package_msg": package_msg,
+                    "tx-results": tx_results,
+                }))
+            }
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_testpackageaccept(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("testpackageaccept", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Testing package acceptance…");
+
+        let txs = parse_package(params)?;
+        match block_on_local(wallet.test_package_accept(&txs)) {
+            Ok(results) => Ok(Value::Array(
+                txs.iter()
+                    .zip(&results)
+                    .map(|(tx, result)| package_accept_to_json(tx, result))
+                    .collect(),
+            )),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+// Read the `package` parameter of submitpackage and testpackageaccept, raw
+// transactions ordered parents first
+fn parse_package(params: Params) -> Result<Vec<Transaction>, RpcError> {
+    let package = match params {
+        Params::Array(arr) => arr.get(0).cloned(),
+        Params::Map(map) => map.get("package").cloned(),
+        Params::None => None,
+    };
+    let package = package
+        .as_ref()
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| RpcError::invalid_params("Missing package parameter"))?;
+    if package.is_empty() || package.len() > MAX_PACKAGE_COUNT {
+        return Err(rpc_error(
+            RPC_INVALID_PARAMETER,
+            format!(
+                "Array must contain between 1 and {} transactions.",
+                MAX_PACKAGE_COUNT
+            ),
+        ));
+    }
+    package
+        .iter()
+        .map(|rawtx| {
+            rawtx
+                .as_str()
+                .ok_or_else(|| RpcError::invalid_params("package must be hex strings"))
+                .and_then(parse_raw_transaction)
+        })
+        .collect()
+}
+
+// Render a package acceptance result, with the package's fee rate for
+// transactions it adds to the mempool
+fn package_accept_to_json(tx: &Transaction, result: &PackageAcceptResult) -> Value {
+    let mut json = json!({
+        "txid": result.txid.to_string(),
+        "wtxid": tx.compute_wtxid().to_string(),
+        "allowed": result.allowed,
+        "vsize": tx.vsize(),
+    });
+    if let Some(fee_rate) = result.effective_fee_rate {
+        json["fees"] = json!({ "effective-feerate": fee_rate });
+    }
+    if let Some(error) = &result.error {
+        json["error"] = json!(error);
+    }
+    json
+}
+
+fn register_walletcreatefundedpsbt(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("walletcreatefundedpsbt", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Creating funded PSBT…");
+
+        let (inputs, outputs, locktime, options) = match params {
+            Params::Array(arr) => (
+                arr.get(0).cloned(),
+                arr.get(1).cloned(),
+                arr.get(2).cloned(),
+                arr.get(3).cloned(),
+            ),
+            Params::Map(map) => (
+                map.get("inputs").cloned(),
+                map.get("outputs").cloned(),
+                map.get("locktime").cloned(),
+                map.get("options").cloned(),
+            ),
+            Params::None => (None, None, None, None),
+        };
+
+        let inputs = inputs.filter(|v| !v.is_null()).unwrap_or_else(|| json!([]));
+        let outputs =
+            outputs.ok_or_else(|| RpcError::invalid_params("Missing outputs parameter"))?;
+        let locktime = match locktime.filter(|v| !v.is_null()) {
+            Some(locktime) => locktime
+                .as_u64()
+                .filter(|&locktime| locktime <= u32::MAX as u64)
+                .ok_or_else(|| {
+                    rpc_error(
+                        RPC_INVALID_PARAMETER,
+                        "Invalid parameter, locktime out of range",
+                    )
+                })? as u32,
+            None => 0,
+        };
+        let options = parse_psbt_options(&options.unwrap_or_else(|| json!({})), wallet.network())?;
+
+        // Reuse createrawtransaction's parsing for the inputs and outputs
+        let template = build_raw_transaction(&inputs, &outputs, locktime, false, wallet.network())?;
+        let recipients: Vec<TxRecipient> = template
+            .output
+            .iter()
+            .map(|output| TxRecipient {
+                script: output.script_pubkey.clone(),
+                amount: output.value,
+            })
+            .collect();
+        let funding = FundingOptions {
+            inputs: template
+                .input
+                .iter()
+                .map(|input| input.previous_output)
+                .collect(),
+            lock_time: (locktime != 0).then_some(template.lock_time),
+            change_script: options.change_script,
+            coin_control: options.coin_control,
+        };
+
+        let (psbt, fee, change_position) = block_on_local(wallet.create_funded_psbt(
+            &recipients,
+            options.fee_rate,
+            options.subtract_fee_from,
+            &funding,
+        ))
+        .map_err(rpc_error_from_wallet_error)?;
+
+        // The unsigned transaction is smaller than the signed one, so this
+        // errs on the side of rejecting
+        if fee > max_tx_fee(&psbt.unsigned_tx, options.max_fee_rate) {
+            return Err(rpc_error(
+                RPC_WALLET_ERROR,
+                "Fee exceeds maximum configured by user (e.g. -maxtxfee, maxfeerate)",
+            ));
+        }
+
+        Ok(json!({
+            "psbt": psbt.to_string(),
+            "fee": fee.to_btc(),
+            "changepos": change_position,
+        }))
+    });
+}
+
+fn register_walletprocesspsbt(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("walletprocesspsbt", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Processing PSBT…");
+
+        let (psbt, sign, sighashtype, bip32derivs) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).and_then(|v| v.as_bool()),
+                arr.get(2).and_then(|v| v.as_str()).map(String::from),
+                arr.get(3).and_then(|v| v.as_bool()),
+            ),
+            Params::Map(map) => (
+                map.get("psbt").and_then(|v| v.as_str()).map(String::from),
+                map.get("sign").and_then(|v| v.as_bool()),
+                map.get("sighashtype")
+                    .and_then(|v| v.as_str())
+                    .map(String::from),
+                map.get("bip32derivs").and_then(|v| v.as_bool()),
+            ),
+            Params::None => (None, None, None, None),
+        };
+        let psbt = psbt.ok_or_else(|| RpcError::invalid_params("Missing psbt parameter"))?;
+        let sighash_type = sighashtype.as_deref().map(parse_sighash_type).transpose()?;
+
+        let mut psbt = parse_psbt(&psbt)?;
+        wallet
+            .update_psbt(&mut psbt, sighash_type)
+            .map_err(rpc_error_from_wallet_error)?;
+        let complete = if sign.unwrap_or(true) {
+            wallet
+                .sign_psbt(&mut psbt)
+                .map_err(rpc_error_from_wallet_error)?
+        } else {
+            psbt.inputs.iter().all(|input| {
+                input.final_script_sig.is_some() || input.final_script_witness.is_some()
+            })
+        };
+        if !bip32derivs.unwrap_or(true) {
+            strip_key_origins(&mut psbt);
+        }
+
+        Ok(json!({
+            "psbt": psbt.to_string(),
+            "complete": complete,
+        }))
+    });
+}
+
+fn register_finalizepsbt(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("finalizepsbt", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Finalizing PSBT…");
+
+        let (psbt, extract) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).and_then(|v| v.as_bool()),
+            ),
+            Params::Map(map) => (
+                map.get("psbt").and_then(|v| v.as_str()).map(String::from),
+                map.get("extract").and_then(|v| v.as_bool()),
+            ),
+            Params::None => (None, None),
+        };
+        let psbt = psbt.ok_or_else(|| RpcError::invalid_params("Missing psbt parameter"))?;
+
+        let mut psbt = parse_psbt(&psbt)?;
+        let tx = wallet
+            .finalize_psbt(&mut psbt)
+            .map_err(rpc_error_from_wallet_error)?;
+
+        Ok(match tx {
+            Some(tx) if extract.unwrap_or(true) => json!({
+                "hex": serialize_hex(&tx),
+                "complete": true,
+            }),
+            tx => json!({
+                "psbt": psbt.to_string(),
+                "complete": tx.is_some(),
+            }),
+        })
+    });
+}
+
+fn parse_psbt(psbt: &str) -> Result<Psbt, RpcError> {
+    Psbt::from_str(psbt)
+        .map_err(|e| rpc_error(RPC_DESERIALIZATION_ERROR, format!("TX decode failed {}", e)))
+}
+
+// Remove the BIP32 derivation paths the wallet adds to inputs and outputs
+fn strip_key_origins(psbt: &mut Psbt) {
+    for input in psbt.inputs.iter_mut() {
+        input.bip32_derivation.clear();
+        input.tap_key_origins.clear();
+    }
+    for output in psbt.outputs.iter_mut() {
+        output.bip32_derivation.clear();
+        output.tap_key_origins.clear();
+    }
+}
+
+struct PsbtOptions {
+    fee_rate: Option<FeeRate>,
+    subtract_fee_from: Vec<usize>,
+    change_script: Option<ScriptBuf>,
+    /// Fee rate limit in BTC/kvB
+    max_fee_rate: Amount,
+    coin_control: CoinControlOptions,
+}
+
+fn parse_psbt_options(options: &Value, network: Network) -> Result<PsbtOptions, RpcError> {
+    let change_script = match options.get("changeAddress").and_then(|v| v.as_str()) {
+        Some(address) => Some(parse_address(address, network)?.script_pubkey()),
+        None => None,
+    };
+    let subtract_fee_from = match options.get("subtractFeeFromOutputs") {
+        Some(Value::Array(indices)) => indices
+            .iter()
+            .map(|index| {
+                index.as_u64().map(|index| index as usize).ok_or_else(|| {
+                    rpc_error(
+                        RPC_INVALID_PARAMETER,
+                        "Invalid parameter, subtractFeeFromOutputs must contain output indices",
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                "subtractFeeFromOutputs must be an array",
+            ))
+        }
+        None => Vec::new(),
+    };
+    let max_fee_rate = match options.get("max_fee_rate").filter(|v| !v.is_null()) {
+        Some(rate) => parse_btc_amount(rate, "max_fee_rate")?,
+        None => DEFAULT_MAX_RAW_TX_FEE_RATE,
+    };
+    let coin_control = match options.get("coin_control").filter(|v| !v.is_null()) {
+        Some(coin_control) => parse_coin_control(coin_control)?,
+        None => CoinControlOptions::default(),
+    };
+
+    Ok(PsbtOptions {
+        fee_rate: parse_fee_rate(options.get("fee_rate").and_then(|v| v.as_f64()))?,
+        subtract_fee_from,
+        change_script,
+        max_fee_rate,
+        coin_control,
+    })
+}
+
+fn register_estimatesmartfee(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("estimatesmartfee", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Estimating smart fee…");
+
+        let (conf_target, estimate_mode) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (
+                map.get("conf_target").cloned(),
+                map.get("estimate_mode").cloned(),
+            ),
+            Params::None => (None, None),
+        };
+        let conf_target = conf_target
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| RpcError::invalid_params("Missing conf_target parameter"))?;
+        if !(1..=MAX_CONFIRMATION_TARGET).contains(&conf_target) {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                format!(
+                    "Invalid conf_target, must be between 1 and {}",
+                    MAX_CONFIRMATION_TARGET
+                ),
+            ));
+        }
+        let conservative = parse_estimate_mode(estimate_mode.as_ref().and_then(|v| v.as_str()))?;
+
+        match block_on_local(wallet.estimate_smart_fee(conf_target as u32, conservative)) {
+            Ok(fee_rate) => Ok(fee_estimate_to_json(fee_rate, conf_target as u32)),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getmempoolinfo(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getmempoolinfo", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Getting mempool info…");
+        let result = block_on_local(async {
+            Ok::<_, WalletError>((
+                wallet.get_mempool_info().await?,
+                wallet.get_fee_histogram().await?,
+            ))
+        });
+        match result {
+            Ok((info, fee_histogram)) => {
+                serde_json::to_value(MempoolInfoResponse::new(info, fee_histogram)).map_err(|e| {
+                    log::error!("Failed to serialize mempool info: {}", e);
+                    RpcError::internal_error()
+                })
+            }
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getnetworkinfo(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getnetworkinfo", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Getting network info…");
+        match block_on_local(wallet.get_network_info()) {
+            Ok(info) => serde_json::to_value(NetworkInfoResponse::from(info)).map_err(|e| {
+                log::error!("Failed to serialize network info: {}", e);
+                RpcError::internal_error()
+            }),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getrawmempool(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getrawmempool", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Listing mempool transactions…");
+
+        let verbose = match params {
+            Params::Array(arr) => arr.get(0).and_then(|v| v.as_bool()),
+            Params::Map(map) => map.get("verbose").and_then(|v| v.as_bool()),
+            Params::None => None,
+        }
+        .unwrap_or(false);
+
+        if verbose {
+            match block_on_local(wallet.get_mempool_entries()) {
+                Ok(entries) => Ok(mempool_entries_to_json(&entries)),
+                Err(e) => Err(rpc_error_from_wallet_error(e)),
+            }
+        } else {
+            match block_on_local(wallet.get_raw_mempool()) {
+                Ok(txids) => Ok(json!(txids
+                    .iter()
+                    .map(|txid| txid.to_string())
+                    .collect::<Vec<_>>())),
+                Err(e) => Err(rpc_error_from_wallet_error(e)),
+            }
+        }
+    });
+}
+
+// Render mempool entries as verbose `getrawmempool` does. The node doesn't expose
+// when or at what height a transaction entered the mempool, so `time` and `height`
+// are omitted, and without fee prioritisation `modifiedfee` is the plain fee.
+pub(super) fn mempool_entries_to_json(entries: &[MempoolEntry]) -> Value {
+    let index: HashMap<Txid, &MempoolEntry> = entries
+        .iter()
+        .map(|entry| (entry.transaction.compute_txid(), entry))
+        .collect();
+
+    let mut result = serde_json::Map::new();
+    for entry in entries {
+        let txid = entry.transaction.compute_txid();
+
+        // Descendant totals include the transaction itself
+        let mut descendants = HashSet::from([txid]);
+        let mut pending = entry.spent_by.clone();
+        while let Some(child) = pending.pop() {
+            if let Some(child_entry) = index.get(&child) {
+                if descendants.insert(child) {
+                    pending.extend(child_entry.spent_by.iter().copied());
+                }
+            }
+        }
+        let descendant_size: u64 = descendants.iter().map(|txid| index[txid].vsize).sum();
+        let descendant_fees: Option<i64> = descendants.iter().map(|txid| index[txid].fee).sum();
+
+        let mut json = json!({
+            "vsize": entry.vsize,
+            "weight": entry.transaction.weight().to_wu(),
+            "descendantcount": entry.ancestry.descendants,
+            "descendantsize": descendant_size,
+            "ancestorcount": entry.ancestry.ancestors,
+            "ancestorsize": entry.ancestry.ancestor_size,
+            "ancestorfees": entry.ancestry.ancestor_fees,
+            "depends": entry.depends.iter().map(|txid| txid.to_string()).collect::<Vec<_>>(),
+            "spentby": entry.spent_by.iter().map(|txid| txid.to_string()).collect::<Vec<_>>(),
+        });
+        if let Some(fee) = entry.fee {
+            let fee = SignedAmount::from_sat(fee).to_btc();
+            json["fee"] = json!(fee);
+            json["modifiedfee"] = json!(fee);
+        }
+        if let Some(descendant_fees) = descendant_fees {
+            json["descendantfees"] = json!(descendant_fees);
+        }
+
+        result.insert(txid.to_string(), json);
+    }
+
+    Value::Object(result)
+}
+
+fn register_getblockchaininfo(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getblockchaininfo", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Getting blockchain info…");
+        match block_on_local(wallet.get_chain_info()) {
+            Ok(info) => Ok(chain_info_to_json(&info, wallet.network())),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+// Render chain info with `getblockchaininfo`'s field names
+pub(super) fn chain_info_to_json(info: &ChainInfo, network: Network) -> Value {
+    json!({
+        "chain": network.to_core_arg(),
+        "blocks": info.height,
+        "bestblockhash": info.best_block_hash.to_string(),
+        "time": info.tip_time,
+        "initialblockdownload": info.ibd,
+    })
+}
+
+fn register_verifychain(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("verifychain", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Verifying chain…");
+
+        let (checklevel, nblocks) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (map.get("checklevel").cloned(), map.get("nblocks").cloned()),
+            Params::None => (None, None),
+        };
+        // Core's defaults, checking the last 6 blocks at level 3
+        let check_level = match checklevel {
+            None | Some(Value::Null) => 3,
+            Some(v) => v
+                .as_u64()
+                .filter(|level| *level <= 4)
+                .ok_or_else(|| RpcError::invalid_params("checklevel must be between 0 and 4"))?
+                as u8,
+        };
+        let num_blocks = match nblocks {
+            None | Some(Value::Null) => 6,
+            Some(v) => v
+                .as_u64()
+                .and_then(|n| u32::try_from(n).ok())
+                .ok_or_else(|| {
+                    RpcError::invalid_params("nblocks must be a non-negative integer")
+                })?,
+        };
+
+        match block_on_local(wallet.verify_chain(check_level, num_blocks)) {
+            Ok(valid) => Ok(Value::Bool(valid)),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getblockcount(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getblockcount", move |_params: Params| {
+        log::info!("=========================");
+        log::info!("Getting block count…");
+        match block_on_local(wallet.get_tip()) {
+            Ok((height, _)) => Ok(json!(height)),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getblockhash(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getblockhash", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Getting block hash…");
+
+        let height = match params {
+            Params::Array(arr) => arr.get(0).cloned(),
+            Params::Map(map) => map.get("height").cloned(),
+            Params::None => None,
+        }
+        .ok_or_else(|| RpcError::invalid_params("Missing height parameter"))?;
+        let height = parse_block_height(&height)?;
+
+        match block_on_local(wallet.get_block_hash(height)) {
+            Ok(Some(hash)) => Ok(Value::String(hash.to_string())),
+            Ok(None) => Err(block_height_out_of_range()),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getblock(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getblock", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Getting block…");
+
+        let (blockhash, verbosity) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned()),
+            Params::Map(map) => (map.get("blockhash").cloned(), map.get("verbosity").cloned()),
+            Params::None => (None, None),
+        };
+        let block_hash = blockhash
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing blockhash parameter"))?;
+        let block_hash = BlockHash::from_str(block_hash).map_err(|_| {
+            rpc_error(
+                RPC_INVALID_PARAMETER,
+                "blockhash must be a 64 character hex string",
+            )
+        })?;
+        // Like Core, booleans are accepted for backwards compatibility
+        let verbosity = match verbosity {
+            None | Some(Value::Null) => 1,
+            Some(Value::Bool(verbose)) => verbose as u64,
+            Some(v) => v
+                .as_u64()
+                .ok_or_else(|| RpcError::invalid_params("verbosity must be an integer"))?,
+        };
+
+        match block_on_local(wallet.get_block_details(&block_hash)) {
+            Ok(Some(details)) => Ok(block_to_json(&details, verbosity, wallet.network())),
+            Ok(None) => Err(rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "Block not found")),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_getrawtransaction(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("getrawtransaction", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Getting raw transaction…");
+
+        let (txid, verbose, blockhash) = match params {
+            Params::Array(arr) => (arr.get(0).cloned(), arr.get(1).cloned(), arr.get(2).cloned()),
+            Params::Map(map) => (
+                map.get("txid").cloned(),
+                map.get("verbose").cloned(),
+                map.get("blockhash").cloned(),
+            ),
+            Params::None => (None, None, None),
+        };
+        let txid = txid
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?;
+        let txid = Txid::from_str(txid).map_err(|_| {
+            rpc_error(RPC_INVALID_PARAMETER, "txid must be a 64 character hex string")
+        })?;
+        // Core also accepts a verbosity integer here
+        let verbose = match verbose {
+            Some(Value::Bool(verbose)) => verbose,
+            Some(Value::Number(verbosity)) => verbosity.as_u64().unwrap_or(0) > 0,
+            _ => false,
+        };
+        let block_hash = match blockhash.as_ref().and_then(|v| v.as_str()) {
+            Some(hash) => Some(BlockHash::from_str(hash).map_err(|_| {
+                rpc_error(RPC_INVALID_PARAMETER, "blockhash must be a 64 character hex string")
+            })?),
+            None => None,
+        };
+
+        match block_on_local(wallet.get_raw_transaction(&txid, block_hash.as_ref())) {
+            Ok(Some(tx)) if verbose => Ok(raw_transaction_to_json(&tx, wallet.network())),
+            Ok(Some(tx)) => Ok(Value::String(serialize_hex(&tx.transaction))),
+            Ok(None) if block_hash.is_some() => Err(rpc_error(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "No such transaction found in the provided block",
+            )),
+            Ok(None) => Err(rpc_error(
+                RPC_INVALID_ADDRESS_OR_KEY,
+                "No such mempool transaction. Blockchain transactions can only be found by providing the blockhash",
+            )),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+pub(super) fn raw_transaction_to_json(tx: &RawTransaction, network: Network) -> Value {
+    let mut result = decode_raw_transaction(&tx.transaction, network);
+    result["hex"] = json!(serialize_hex(&tx.transaction));
+
+    if let Some(block_hash) = tx.block_hash {
+        result["in_active_chain"] = json!(tx.confirmations > 0);
+        result["blockhash"] = json!(block_hash.to_string());
+        result["confirmations"] = json!(tx.confirmations.max(0));
+        if let Some(block_time) = tx.block_time {
+            result["time"] = json!(block_time);
+            result["blocktime"] = json!(block_time);
+        }
+    } else {
+        result["confirmations"] = json!(0);
+    }
+
+    result
+}
+
+// Render a block as `getblock` does at the given verbosity: 0 for raw hex,
+// 1 for a summary with txids, 2 or more to also decode each transaction
+pub(super) fn block_to_json(details: &BlockDetails, verbosity: u64, network: Network) -> Value {
+    let block = &details.block;
+    if verbosity == 0 {
+        return Value::String(serialize_hex(block));
+    }
+
+    let tx: Vec<Value> = block
+        .txdata
+        .iter()
+        .map(|tx| {
+            if verbosity >= 2 {
+                decode_raw_transaction(tx, network)
+            } else {
+                json!(tx.compute_txid().to_string())
+            }
+        })
+        .collect();
+
+    let header = &block.header;
+    let mut result = json!({
+        "hash": block.block_hash().to_string(),
+        "confirmations": details.confirmations,
+        "size": block.total_size(),
+        "strippedsize": block.base_size(),
+        "weight": block.weight().to_wu(),
+        "version": header.version.to_consensus(),
+        "versionHex": format!("{:08x}", header.version.to_consensus()),
+        "merkleroot": header.merkle_root.to_string(),
+        "tx": tx,
+        "time": header.time,
+        "mediantime": details.median_time,
+        "nonce": header.nonce,
+        "bits": format!("{:08x}", header.bits.to_consensus()),
+        "difficulty": header.target().difficulty_float(),
+        "nTx": block.txdata.len(),
+    });
+
+    if let Some(height) = details.height {
+        result["height"] = json!(height);
+    }
+    if header.prev_blockhash != BlockHash::all_zeros() {
+        result["previousblockhash"] = json!(header.prev_blockhash.to_string());
+    }
+    if let Some(next_block_hash) = details.next_block_hash {
+        result["nextblockhash"] = json!(next_block_hash.to_string());
+    }
+
+    result
+}
+
+fn parse_block_height(height: &Value) -> Result<i32, RpcError> {
+    let height = height
+        .as_i64()
+        .ok_or_else(|| RpcError::invalid_params("height must be an integer"))?;
+    if height < 0 || height > i32::MAX as i64 {
+        return Err(block_height_out_of_range());
+    }
+    Ok(height as i32)
+}
+
+fn block_height_out_of_range() -> RpcError {
+    rpc_error(RPC_INVALID_PARAMETER, "Block height out of range")
+}
+
+// Whether an estimate_mode asks for a conservative estimate
+fn parse_estimate_mode(estimate_mode: Option<&str>) -> Result<bool, RpcError> {
+    match estimate_mode.map(str::to_ascii_uppercase).as_deref() {
+        None | Some("UNSET") | Some("ECONOMICAL") => Ok(false),
+        Some("CONSERVATIVE") => Ok(true),
+        Some(_) => Err(rpc_error(
+            RPC_INVALID_PARAMETER,
+            r#"Invalid estimate_mode parameter, must be one of: "unset", "economical", "conservative""#,
+        )),
+    }
+}
+
+// The node reports the estimate but not the target it was found at, so the
+// requested target is returned as `blocks`
+fn fee_estimate_to_json(fee_rate: Option<FeeRate>, blocks: u32) -> Value {
+    match fee_rate {
+        Some(fee_rate) => json!({
+            "feerate": fee_rate_to_btc_per_kvb(fee_rate),
+            "blocks": blocks,
+        }),
+        None => json!({
+            "errors": ["Insufficient data or no feerate found"],
+            "blocks": blocks,
+        }),
+    }
+}
+
+fn fee_rate_to_btc_per_kvb(fee_rate: FeeRate) -> f64 {
+    Amount::from_sat(fee_rate.to_sat_per_kwu() * 4).to_btc()
+}
+
+// Absolute fee limit for a transaction given a limit in BTC/kvB, which the node
+// checks against the fee computed from the spent coins
+fn max_tx_fee(tx: &Transaction, max_fee_rate: Amount) -> Amount {
+    Amount::from_sat(max_fee_rate.to_sat() * tx.vsize() as u64 / 1000)
+}
+
+fn parse_sighash_type(sighashtype: &str) -> Result<PsbtSighashType, RpcError> {
+    let value = match sighashtype {
+        "DEFAULT" => 0x00,
+        "ALL" => 0x01,
+        "NONE" => 0x02,
+        "SINGLE" => 0x03,
+        "ALL|ANYONECANPAY" => 0x81,
+        "NONE|ANYONECANPAY" => 0x82,
+        "SINGLE|ANYONECANPAY" => 0x83,
+        _ => {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                format!("'{}' is not a valid sighash parameter.", sighashtype),
+            ))
+        }
+    };
+    Ok(PsbtSighashType::from_u32(value))
+}
+
+// Build an unsigned transaction from `createrawtransaction` style inputs and outputs
+fn build_raw_transaction(
+    inputs: &Value,
+    outputs: &Value,
+    locktime: u32,
+    replaceable: bool,
+    network: Network,
+) -> Result<Transaction, RpcError> {
+    let inputs = inputs
+        .as_array()
+        .ok_or_else(|| RpcError::invalid_params("inputs must be an array"))?;
+
+    // Signal RBF if asked to, otherwise only enable the locktime if one is set
+    let default_sequence = if replaceable {
+        Sequence::ENABLE_RBF_NO_LOCKTIME
+    } else if locktime != 0 {
+        Sequence::ENABLE_LOCKTIME_NO_RBF
+    } else {
+        Sequence::MAX
+    };
+
+    let mut tx_inputs = Vec::new();
+    for input in inputs {
+        let previous_output = parse_outpoint(input)?;
+        let sequence = match input.get("sequence") {
+            Some(sequence) => sequence
+                .as_u64()
+                .filter(|&sequence| sequence <= u32::MAX as u64)
+                .map(|sequence| Sequence(sequence as u32))
+                .ok_or_else(|| {
+                    rpc_error(
+                        RPC_INVALID_PARAMETER,
+                        "Invalid parameter, sequence number is out of range",
+                    )
+                })?,
+            None => default_sequence,
+        };
+
+        tx_inputs.push(TxIn {
+            previous_output,
+            script_sig: ScriptBuf::new(),
+            sequence,
+            witness: Witness::new(),
+        });
+    }
+
+    // Outputs may be given as a single object or an array of single-key objects
+    let output_entries: Vec<(&String, &Value)> = match outputs {
+        Value::Object(map) => map.iter().collect(),
+        Value::Array(arr) => arr
+            .iter()
+            .filter_map(|output| output.as_object())
+            .flat_map(|map| map.iter())
+            .collect(),
+        _ => {
+            return Err(RpcError::invalid_params(
+                "outputs must be an object or an array",
+            ))
+        }
+    };
+
+    let mut tx_outputs = Vec::new();
+    let mut seen_addresses: Vec<Address> = Vec::new();
+    for (key, value) in output_entries {
+        if key == "data" {
+            let data = value
+                .as_str()
+                .and_then(|data| hex::decode(data).ok())
+                .ok_or_else(|| {
+                    rpc_error(RPC_INVALID_PARAMETER, "Data must be hexadecimal string")
+                })?;
+            let data = PushBytesBuf::try_from(data)
+                .map_err(|_| rpc_error(RPC_INVALID_PARAMETER, "Data too large"))?;
+            tx_outputs.push(TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new_op_return(data),
+            });
+            continue;
+        }
+
+        let address = parse_address(key, network)?;
+        if seen_addresses.contains(&address) {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                format!("Invalid parameter, duplicated address: {}", key),
+            ));
+        }
+        tx_outputs.push(TxOut {
+            value: parse_btc_amount(value, "amount")?,
+            script_pubkey: address.script_pubkey(),
+        });
+        seen_addresses.push(address);
+    }
+
+    Ok(Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::from_consensus(locktime),
+        input: tx_inputs,
+        output: tx_outputs,
+    })
+}
+
+// Parse a `{"txid": ..., "vout": ...}` object
+fn parse_outpoint(outpoint: &Value) -> Result<OutPoint, RpcError> {
+    let txid = outpoint
+        .get("txid")
+        .and_then(|v| v.as_str())
+        .and_then(|txid| Txid::from_str(txid).ok())
+        .ok_or_else(|| {
+            rpc_error(
+                RPC_INVALID_PARAMETER,
+                "txid must be a 64 character hex string",
+            )
+        })?;
+    let vout = outpoint
+        .get("vout")
+        .and_then(|v| v.as_u64())
+        .filter(|&vout| vout <= u32::MAX as u64)
+        .ok_or_else(|| rpc_error(RPC_INVALID_PARAMETER, "Invalid parameter, missing vout key"))?
+        as u32;
+    Ok(OutPoint { txid, vout })
+}
+
+// Parse a `{"must_use": [...], "must_not_use": [...]}` coin control object
+fn parse_coin_control(coin_control: &Value) -> Result<CoinControlOptions, RpcError> {
+    if !coin_control.is_object() {
+        return Err(RpcError::invalid_params("coin_control must be an object"));
+    }
+    let outpoints = |name: &str| match coin_control.get(name) {
+        Some(Value::Array(outpoints)) => outpoints.iter().map(parse_outpoint).collect(),
+        Some(Value::Null) | None => Ok(Vec::new()),
+        Some(_) => Err(RpcError::invalid_params(format!(
+            "coin_control.{} must be an array",
+            name
+        ))),
+    };
+    Ok(CoinControlOptions {
+        must_use: outpoints("must_use")?,
+        must_not_use: outpoints("must_not_use")?,
+    })
+}
+
+fn parse_raw_transaction(hexstring: &str) -> Result<Transaction, RpcError> {
+    hex::decode(hexstring)
+        .ok()
+        .and_then(|bytes| deserialize::<Transaction>(&bytes).ok())
+        .ok_or_else(|| rpc_error(RPC_DESERIALIZATION_ERROR, "TX decode failed"))
+}
+
+// Break a transaction down the way Bitcoin Core's `decoderawtransaction` does
+fn decode_raw_transaction(tx: &Transaction, network: Network) -> Value {
+    let vin: Vec<Value> = tx
+        .input
+        .iter()
+        .map(|input| {
+            let mut entry = if tx.is_coinbase() {
+                json!({ "coinbase": input.script_sig.to_hex_string() })
+            } else {
+                json!({
+                    "txid": input.previous_output.txid.to_string(),
+                    "vout": input.previous_output.vout,
+                    "scriptSig": {
+                        "asm": input.script_sig.to_asm_string(),
+                        "hex": input.script_sig.to_hex_string(),
+                    },
+                })
+            };
+            if !input.witness.is_empty() {
+                entry["txinwitness"] =
+                    json!(input.witness.iter().map(hex::encode).collect::<Vec<_>>());
+            }
+            entry["sequence"] = json!(input.sequence.0);
+            entry
+        })
+        .collect();
+
+    let vout: Vec<Value> = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(n, output)| {
+            json!({
+                "value": output.value.to_btc(),
+                "n": n,
+                "scriptPubKey": script_pubkey_to_json(&output.script_pubkey, network),
+            })
+        })
+        .collect();
+
+    json!({
+        "txid": tx.compute_txid().to_string(),
+        "hash": tx.compute_wtxid().to_string(),
+        "version": tx.version.0,
+        "size": tx.total_size(),
+        "vsize": tx.vsize(),
+        "weight": tx.weight().to_wu(),
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+    })
+}
+
+fn script_pubkey_to_json(script: &Script, network: Network) -> Value {
+    let mut entry = json!({
+        "asm": script.to_asm_string(),
+        "hex": script.to_hex_string(),
+        "type": script_type(script),
+    });
+    if let Ok(address) = Address::from_script(script, network) {
+        entry["address"] = json!(address.to_string());
+    }
+    entry
+}
+
+// Script type names used by Bitcoin Core
+fn script_type(script: &Script) -> &'static str {
+    if script.is_p2pk() {
+        "pubkey"
+    } else if script.is_p2pkh() {
+        "pubkeyhash"
+    } else if script.is_p2sh() {
+        "scripthash"
+    } else if script.is_multisig() {
+        "multisig"
+    } else if script.is_op_return() {
+        "nulldata"
+    } else if script.is_p2wpkh() {
+        "witness_v0_keyhash"
+    } else if script.is_p2wsh() {
+        "witness_v0_scripthash"
+    } else if script.is_p2tr() {
+        "witness_v1_taproot"
+    } else if script.is_witness_program() {
+        "witness_unknown"
+    } else {
+        "nonstandard"
+    }
+}
+
+fn register_sendtoaddress(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("sendtoaddress", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Sending to address…");
+
+        let (address_str, amount, comment, subtract_fee, fee_rate, coin_control) = match params {
+            Params::Array(arr) => {
+                let address = arr
+                    .get(0)
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing address parameter"))?
+                    .to_string();
+                let amount = arr
+                    .get(1)
+                    .and_then(|v| v.as_f64())
+                    .ok_or_else(|| RpcError::invalid_params("Missing amount parameter"))?;
+                let comment = arr
+                    .get(2)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                let subtract_fee = arr.get(4).and_then(|v| v.as_bool()).unwrap_or(false);
+                let fee_rate = arr.get(8).and_then(|v| v.as_f64());
+                let coin_control = arr.get(9).cloned();
+                (
+                    address,
+                    amount,
+                    comment,
+                    subtract_fee,
+                    fee_rate,
+                    coin_control,
+                )
+            }
+            Params::Map(map) => {
+                let address = map
+                    .get("address")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| RpcError::invalid_params("Missing address parameter"))?
+                    .to_string();
+                let amount = map
+                    .get("amount")
+                    .and_then(|v| v.as_f64())
+                    .ok_or_else(|| RpcError::invalid_params("Missing amount parameter"))?;
+                let comment = map
+                    .get("comment")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+                let subtract_fee = map
+                    .get("subtractfeefromamount")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let fee_rate = map.get("fee_rate").and_then(|v| v.as_f64());
+                let coin_control = map.get("coin_control").cloned();
+                (
+                    address,
+                    amount,
+                    comment,
+                    subtract_fee,
+                    fee_rate,
+                    coin_control,
+                )
+            }
+            _ => return Err(RpcError::invalid_params("Invalid parameters")),
+        };
+
+        let address = parse_address(&address_str, wallet.network())?;
+        let amount = match Amount::from_btc(amount) {
+            Ok(amount) if amount > Amount::ZERO => amount,
+            _ => return Err(rpc_error(RPC_INVALID_PARAMETER, "Invalid amount for send")),
+        };
+        let fee_rate = parse_fee_rate(fee_rate)?;
+        let coin_control = match coin_control.filter(|v| !v.is_null()) {
+            Some(coin_control) => parse_coin_control(&coin_control)?,
+            None => CoinControlOptions::default(),
+        };
+
+        match block_on_local(wallet.send_to_address(
+            &address,
+            amount,
+            subtract_fee,
+            fee_rate,
+            &comment,
+            &coin_control,
+        )) {
+            Ok(txid) => Ok(Value::String(txid.to_string())),
+            Err(e) => Err(rpc_error_from_wallet_error(e)),
+        }
+    });
+}
+
+fn register_bumpfee(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("bumpfee", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Bumping fee…");
+
+        let (txid, options) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).cloned(),
+            ),
+            Params::Map(map) => (
+                map.get("txid").and_then(|v| v.as_str()).map(String::from),
+                map.get("options").cloned(),
+            ),
+            Params::None => (None, None),
+        };
+        let txid = txid.ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?;
+        let txid = Txid::from_str(&txid).map_err(|_| {
+            rpc_error(
+                RPC_INVALID_PARAMETER,
+                "txid must be a 64 character hex string",
+            )
+        })?;
+        let options = options
+            .filter(|v| !v.is_null())
+            .unwrap_or_else(|| json!({}));
+
+        let fee_rate = parse_fee_rate(options.get("fee_rate").and_then(|v| v.as_f64()))?;
+        let conf_target = options
+            .get("conf_target")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_BUMP_CONFIRMATION_TARGET as u64);
+        if !(1..=MAX_CONFIRMATION_TARGET).contains(&conf_target) {
+            return Err(rpc_error(
+                RPC_INVALID_PARAMETER,
+                format!(
+                    "Invalid conf_target, must be between 1 and {}",
+                    MAX_CONFIRMATION_TARGET
+                ),
+            ));
+        }
+        let conservative =
+            parse_estimate_mode(options.get("estimate_mode").and_then(|v| v.as_str()))?;
+        if options.get("replaceable").and_then(|v| v.as_bool()) == Some(false) {
+            log::warn!("Ignoring replaceable=false, the replacement signals RBF");
+        }
+
+        let original_fee = block_on_local(wallet.get_transaction_detail(&txid))
+            .map_err(rpc_error_from_wallet_error)?
+            .ok_or_else(|| {
+                rpc_error(
+                    RPC_INVALID_ADDRESS_OR_KEY,
+                    "Invalid or non-wallet transaction id",
+                )
+            })?
+            .fee;
+        let min_fee_rate = wallet
+            .min_bump_fee_rate(&txid)
+            .map_err(rpc_error_from_wallet_error)?;
+        // An estimate below what replacement needs is raised to the minimum,
+        // whereas an explicit fee rate that is too low is an error
+        let fee_rate = match fee_rate {
+            Some(fee_rate) => fee_rate,
+            None => {
+                let estimate =
+                    block_on_local(wallet.estimate_smart_fee(conf_target as u32, conservative))
+                        .map_err(rpc_error_from_wallet_error)?;
+                estimate.map_or(min_fee_rate, |estimate| estimate.max(min_fee_rate))
+            }
+        };
+
+        let new_txid = block_on_local(wallet.bump_fee(&txid, fee_rate))
+            .map_err(rpc_error_from_wallet_error)?;
+        let new_fee = block_on_local(wallet.get_transaction_detail(&new_txid))
+            .map_err(rpc_error_from_wallet_error)?
+            .and_then(|detail| detail.fee);
+
+        Ok(json!({
+            "txid": new_txid.to_string(),
+            "origfee": original_fee.map(|fee| fee.to_btc()),
+            "fee": new_fee.map(|fee| fee.to_btc()),
+            "errors": [],
+        }))
+    });
+}
+
+fn register_cpfpbumptx(io: &mut IoHandler, wallet: Arc<WalletInterface>) {
+    io.add_sync_method("cpfpbumptx", move |params: Params| {
+        log::info!("=========================");
+        log::info!("Bumping fee with a child transaction…");
+
+        let (txid, vout, fee_rate) = match params {
+            Params::Array(arr) => (
+                arr.get(0).and_then(|v| v.as_str()).map(String::from),
+                arr.get(1).and_then(|v| v.as_u64()),
+                arr.get(2).and_then(|v| v.as_f64()),
+            ),
+            Params::Map(map) => (
+                map.get("txid").and_then(|v| v.as_str()).map(String::from),
+                map.get("vout").and_then(|v| v.as_u64()),
+                map.get("fee_rate").and_then(|v| v.as_f64()),
+            ),
+            Params::None => (None, None, None),
+        };
+        let txid = txid.ok_or_else(|| RpcError::invalid_params("Missing txid parameter"))?;
+        let txid = Txid::from_str(&txid).map_err(|_| {
+            rpc_error(
+                RPC_INVALID_PARAMETER,
+                "txid must be a 64 character hex string",
+            )
+        })?;
+        let vout = vout
+            .and_then(|vout| u32::try_from(vout).ok())
+            .ok_or_else(|| RpcError::invalid_params("Missing vout parameter"))?;
+        let fee_rate = parse_fee_rate(fee_rate)?
+            .ok_or_else(|| RpcError::invalid_params("Missing fee_rate parameter"))?;
+
+        let child_txid = block_on_local(wallet.create_cpfp(&txid, vout, fee_rate))
+            .map_err(rpc_error_from_wallet_error)?;
+        Ok(json!({ "txid": child_txid.to_string() }))
+    });
+}
+
+// Parse an address and check it belongs to the wallet's network
+fn parse_address(address: &str, network: Network) -> Result<Address, RpcError> {
+    let address = Address::from_str(address)
+        .map_err(|_| rpc_error(RPC_INVALID_ADDRESS_OR_KEY, "Invalid Bitcoin address"))?;
+    address.require_network(network).map_err(|_| {
+        rpc_error(
+            RPC_INVALID_ADDRESS_OR_KEY,
+            format!("Address is not valid for network {}", network),
+        )
+    })
+}
+
+// Explicit fee rates are given in sat/vB
+fn parse_fee_rate(fee_rate: Option<f64>) -> Result<Option<FeeRate>, RpcError> {
+    match fee_rate {
+        Some(rate) if rate.is_finite() && rate > 0.0 => Ok(Some(FeeRate::from_sat_per_kwu(
+            (rate * 250.0).round() as u64,
+        ))),
+        Some(_) => Err(rpc_error(RPC_INVALID_PARAMETER, "Invalid fee_rate")),
+        None => Ok(None),
+    }
+}
+
+// Run an async wallet operation to completion from a synchronous RPC handler
+pub(super) fn block_on_local<F: std::future::Future>(future: F) -> F::Output {
+    task::block_in_place(|| {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let local = LocalSet::new();
+        rt.block_on(local.run_until(future))
+    })
+}
+
+fn parse_create_wallet_options(params: Params) -> Result<CreateWalletOptions, RpcError> {
+    let mut options = CreateWalletOptions::default();
+
+    match params {
+        Params::Array(arr) => {
+            options.wallet_name = arr
+                .get(0)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| RpcError::invalid_params("Missing wallet name parameter"))?
+                .to_string();
+
+            // Optional parameters
+            if let Some(v) = arr.get(1).and_then(|v| v.as_bool()) {
+                options.disable_private_keys = v;
+            }
+            if let Some(v) = arr.get(2).and_then(|v| v.as_bool()) {
+                options.blank = v;
+            }
+            reject_wallet_encryption(arr.get(3))?;
+            if let Some(v) = arr.get(4).and_then(|v| v.as_bool()) {
+                options.avoid_reuse = v;
+            }
+            if let Some(v) = arr.get(5).and_then(|v| v.as_bool()) {
+                options.descriptors = v;
+            }
+            if let Some(v) = arr.get(6).and_then(|v| v.as_bool()) {
+                options.load_on_startup = v;
+            }
+        }
+        Params::Map(map) => {
+            options.wallet_name = map
+                .get("wallet_name")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| RpcError::invalid_params("Missing wallet name parameter"))?
+                .to_string();
+
+            // Optional parameters
+            if let Some(v) = map.get("disable_private_keys").and_then(|v| v.as_bool()) {
+                options.disable_private_keys = v;
+            }
+            if let Some(v) = map.get("blank").and_then(|v| v.as_bool()) {
+                options.blank = v;
+            }
+            reject_wallet_encryption(map.get("passphrase"))?;
+            if let Some(v) = map.get("mnemonic_passphrase").and_then(|v| v.as_str()) {
+                options.mnemonic_passphrase = Some(v.to_string());
+            }
+            if let Some(v) = map.get("avoid_reuse").and_then(|v| v.as_bool()) {
+                options.avoid_reuse = v;
+            }
+            if let Some(v) = map.get("descriptors").and_then(|v| v.as_bool()) {
+                options.descriptors = v;
+            }
+            if let Some(v) = map.get("load_on_startup").and_then(|v| v.as_bool()) {
+                options.load_on_startup = v;
+            }
+            if let Some(v) = map.get("external_descriptor").and_then(|v| v.as_str()) {
+                options.external_descriptor = Some(v.to_string());
+            }
+            if let Some(v) = map.get("internal_descriptor").and_then(|v| v.as_str()) {
+                options.internal_descriptor = Some(v.to_string());
+            }
+            if let Some(v) = map.get("mnemonic").and_then(|v| v.as_str()) {
+                options.mnemonic = Some(v.to_string());
+            }
+            if let Some(v) = map.get("address_type").and_then(|v| v.as_str()) {
+                options.address_type = AddressType::from_core_name(v)
+                    .ok_or_else(|| RpcError::invalid_params("Invalid address type"))?;
+            }
+        }
+        _ => return Err(RpcError::invalid_params("Invalid parameters")),
     };
 
     Ok(options)
 }
+
+// Core's `passphrase` encrypts the whole wallet, which isn't supported here, so
+// refuse it rather than leave the keys unprotected behind the user's back
+fn reject_wallet_encryption(passphrase: Option<&Value>) -> Result<(), RpcError> {
+    match passphrase.and_then(|v| v.as_str()) {
+        Some(passphrase) if !passphrase.is_empty() => Err(RpcError::invalid_params(
+            "Wallet encryption is not supported; use mnemonic_passphrase to encrypt only the stored mnemonic",
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::TransactionOutputDetail;
+    use blocktalk::{FeeHistogramBucket, MempoolInfo, TransactionAncestry};
+    use jsonrpc_core::ErrorCode;
+
+    fn wallet_tx(n: u8, label: &str, amount: i64) -> WalletTransaction {
+        WalletTransaction {
+            txid: Txid::from_byte_array([n; 32]),
+            category: if amount < 0 {
+                TransactionCategory::Send
+            } else {
+                TransactionCategory::Receive
+            },
+            address: None,
+            vout: Some(0),
+            amount: SignedAmount::from_sat(amount),
+            fee: None,
+            label: label.to_string(),
+            confirmations: 0,
+            block_hash: None,
+            block_height: None,
+            block_time: None,
+            time_received: n as u64,
+            abandoned: false,
+        }
+    }
+
+    fn txids(transactions: &[WalletTransaction]) -> Vec<Txid> {
+        transactions.iter().map(|tx| tx.txid).collect()
+    }
+
+    #[test]
+    fn test_select_transactions_pagination() {
+        let transactions: Vec<_> = (1..=5).map(|n| wallet_tx(n, "", 1000)).collect();
+
+        let selected = select_transactions(transactions.clone(), None, 2, 0);
+        assert_eq!(txids(&selected), txids(&transactions[3..5]));
+
+        let selected = select_transactions(transactions.clone(), None, 2, 1);
+        assert_eq!(txids(&selected), txids(&transactions[2..4]));
+
+        let selected = select_transactions(transactions.clone(), None, 10, 4);
+        assert_eq!(txids(&selected), txids(&transactions[0..1]));
+
+        assert!(select_transactions(transactions, None, 10, 5).is_empty());
+    }
+
+    #[test]
+    fn test_select_transactions_label_filter() {
+        let transactions = vec![
+            wallet_tx(1, "rent", 1000),
+            wallet_tx(2, "salary", 1000),
+            wallet_tx(3, "rent", 1000),
+        ];
+
+        let selected = select_transactions(transactions.clone(), Some("rent"), 10, 0);
+        assert_eq!(
+            txids(&selected),
+            vec![transactions[0].txid, transactions[2].txid]
+        );
+
+        let selected = select_transactions(transactions.clone(), Some("*"), 10, 0);
+        assert_eq!(selected.len(), 3);
+    }
+
+    #[test]
+    fn test_wallet_transaction_category_json() {
+        let sent = wallet_transaction_to_json(&wallet_tx(1, "", -1000));
+        assert_eq!(sent["category"], "send");
+        assert_eq!(sent["amount"], json!(-0.00001));
+
+        let received = wallet_transaction_to_json(&wallet_tx(2, "", 1000));
+        assert_eq!(received["category"], "receive");
+        assert!(received.get("blockhash").is_none());
+    }
+
+    #[test]
+    fn test_parse_address_checks_network() {
+        let address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        assert!(parse_address(address, Network::Bitcoin).is_ok());
+
+        let err = parse_address(address, Network::Regtest).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_ADDRESS_OR_KEY));
+
+        let err = parse_address("not an address", Network::Regtest).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_ADDRESS_OR_KEY));
+    }
+
+    #[test]
+    fn test_parse_fee_rate() {
+        assert_eq!(parse_fee_rate(None).unwrap(), None);
+        assert_eq!(
+            parse_fee_rate(Some(2.0)).unwrap(),
+            Some(FeeRate::from_sat_per_vb_unchecked(2))
+        );
+        assert!(parse_fee_rate(Some(0.0)).is_err());
+        assert!(parse_fee_rate(Some(-1.0)).is_err());
+    }
+
+    #[test]
+    fn test_transaction_detail_json() {
+        let sent = wallet_tx(1, "rent", -50_000);
+        let detail = TransactionDetail {
+            txid: sent.txid,
+            amount: sent.amount,
+            fee: Some(Amount::from_sat(1_000)),
+            confirmations: 0,
+            block_hash: None,
+            block_height: None,
+            block_index: None,
+            block_time: None,
+            time_received: 1_700_000_000,
+            comment: "monthly".to_string(),
+            label: sent.label,
+            abandoned: true,
+            details: vec![TransactionOutputDetail {
+                category: TransactionCategory::Send,
+                address: None,
+                amount: sent.amount,
+                vout: 0,
+            }],
+            transaction: Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![],
+                output: vec![],
+            },
+        };
+
+        let json = transaction_detail_to_json(&detail);
+        assert_eq!(json["amount"], json!(-0.0005));
+        assert_eq!(json["fee"], json!(-0.00001));
+        assert_eq!(json["comment"], "monthly");
+        assert_eq!(json["timereceived"], 1_700_000_000);
+        assert_eq!(json["hex"], "02000000000000000000");
+        assert_eq!(json["details"][0]["category"], "send");
+        assert_eq!(json["details"][0]["label"], "rent");
+        assert_eq!(json["details"][0]["abandoned"], true);
+        assert!(json.get("blockhash").is_none());
+    }
+
+    fn unspent(n: u8, address: &str, sats: u64, confirmations: u32) -> UnspentOutput {
+        let address = Address::from_str(address).unwrap().assume_checked();
+        UnspentOutput {
+            txid: Txid::from_byte_array([n; 32]),
+            vout: 0,
+            script_pubkey: address.script_pubkey(),
+            address: Some(address),
+            label: String::new(),
+            amount: Amount::from_sat(sats),
+            confirmations,
+            descriptor: None,
+            safe: confirmations > 0,
+        }
+    }
+
+    const ADDRESS_A: &str = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+    const ADDRESS_B: &str = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+
+    fn unspent_txids(unspent: &[UnspentOutput]) -> Vec<Txid> {
+        unspent.iter().map(|output| output.txid).collect()
+    }
+
+    #[test]
+    fn test_filter_unspent_by_minconf() {
+        let outputs = vec![
+            unspent(1, ADDRESS_A, 10_000, 0),
+            unspent(2, ADDRESS_A, 10_000, 1),
+            unspent(3, ADDRESS_B, 10_000, 6),
+        ];
+
+        let filter = parse_unspent_filter(Params::None, Network::Bitcoin).unwrap();
+        let selected = filter_unspent(outputs.clone(), &filter);
+        assert_eq!(
+            unspent_txids(&selected),
+            vec![outputs[1].txid, outputs[2].txid]
+        );
+
+        let filter =
+            parse_unspent_filter(Params::Array(vec![json!(0), json!(1)]), Network::Bitcoin)
+                .unwrap();
+        let selected = filter_unspent(outputs.clone(), &filter);
+        assert_eq!(
+            unspent_txids(&selected),
+            vec![outputs[0].txid, outputs[1].txid]
+        );
+    }
+
+    #[test]
+    fn test_filter_unspent_by_address() {
+        let outputs = vec![
+            unspent(1, ADDRESS_A, 10_000, 3),
+            unspent(2, ADDRESS_B, 10_000, 3),
+        ];
+
+        let params = Params::Array(vec![json!(1), json!(9_999_999), json!([ADDRESS_B])]);
+        let filter = parse_unspent_filter(params, Network::Bitcoin).unwrap();
+        let selected = filter_unspent(outputs.clone(), &filter);
+        assert_eq!(unspent_txids(&selected), vec![outputs[1].txid]);
+
+        let params = Params::Array(vec![
+            json!(1),
+            json!(9_999_999),
+            json!([ADDRESS_A, ADDRESS_A]),
+        ]);
+        let err = parse_unspent_filter(params, Network::Bitcoin)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_PARAMETER));
+    }
+
+    #[test]
+    fn test_filter_unspent_by_minimum_amount() {
+        let outputs = vec![
+            unspent(1, ADDRESS_A, 5_000, 3),
+            unspent(2, ADDRESS_A, 50_000, 3),
+        ];
+
+        let mut map = serde_json::Map::new();
+        map.insert(
+            "query_options".to_string(),
+            json!({ "minimumAmount": 0.0001 }),
+        );
+        let filter = parse_unspent_filter(Params::Map(map), Network::Bitcoin).unwrap();
+        let selected = filter_unspent(outputs.clone(), &filter);
+        assert_eq!(unspent_txids(&selected), vec![outputs[1].txid]);
+    }
+
+    #[test]
+    fn test_decode_coinbase_transaction() {
+        let genesis = bitcoin::constants::genesis_block(Network::Bitcoin);
+        let coinbase = &genesis.txdata[0];
+        let hex = serialize_hex(coinbase);
+
+        let decoded =
+            decode_raw_transaction(&parse_raw_transaction(&hex).unwrap(), Network::Bitcoin);
+        assert_eq!(
+            decoded["txid"],
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+        assert!(decoded["vin"][0]["coinbase"].is_string());
+        assert!(decoded["vin"][0].get("txid").is_none());
+        assert_eq!(decoded["vout"][0]["value"], json!(50.0));
+        assert_eq!(decoded["vout"][0]["scriptPubKey"]["type"], "pubkey");
+        assert_eq!(decoded["size"], 204);
+    }
+
+    #[test]
+    fn test_create_and_decode_segwit_output() {
+        let inputs = json!([{ "txid": "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b", "vout": 0 }]);
+        let outputs = json!({ ADDRESS_A: 0.5 });
+        let tx = build_raw_transaction(&inputs, &outputs, 0, false, Network::Bitcoin).unwrap();
+        assert_eq!(tx.input[0].sequence, Sequence::MAX);
+
+        let decoded = decode_raw_transaction(
+            &parse_raw_transaction(&serialize_hex(&tx)).unwrap(),
+            Network::Bitcoin,
+        );
+        assert_eq!(decoded["vin"][0]["vout"], 0);
+        assert_eq!(decoded["vout"][0]["value"], json!(0.5));
+        assert_eq!(
+            decoded["vout"][0]["scriptPubKey"]["type"],
+            "witness_v0_keyhash"
+        );
+        assert_eq!(decoded["vout"][0]["scriptPubKey"]["address"], ADDRESS_A);
+    }
+
+    #[test]
+    fn test_create_op_return_output() {
+        let outputs = json!([{ "data": "deadbeef" }, { ADDRESS_B: 0.1 }]);
+        let tx = build_raw_transaction(&json!([]), &outputs, 100, true, Network::Bitcoin).unwrap();
+        assert_eq!(tx.lock_time.to_consensus_u32(), 100);
+
+        let decoded = decode_raw_transaction(&tx, Network::Bitcoin);
+        assert_eq!(decoded["vout"][0]["value"], json!(0.0));
+        assert_eq!(decoded["vout"][0]["scriptPubKey"]["type"], "nulldata");
+        assert_eq!(decoded["vout"][0]["scriptPubKey"]["hex"], "6a04deadbeef");
+        assert_eq!(decoded["vout"][1]["scriptPubKey"]["type"], "pubkeyhash");
+    }
+
+    #[test]
+    fn test_create_raw_transaction_rejects_duplicates() {
+        let outputs = json!([{ ADDRESS_A: 0.1 }, { ADDRESS_A: 0.2 }]);
+        let err = build_raw_transaction(&json!([]), &outputs, 0, false, Network::Bitcoin)
+            .err()
+            .unwrap();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_PARAMETER));
+
+        let err = parse_raw_transaction("00zz").err().unwrap();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_DESERIALIZATION_ERROR));
+    }
+
+    #[test]
+    fn test_parse_sighash_type() {
+        assert_eq!(
+            parse_sighash_type("ALL").unwrap(),
+            PsbtSighashType::from_u32(0x01)
+        );
+        assert_eq!(
+            parse_sighash_type("SINGLE|ANYONECANPAY").unwrap().to_u32(),
+            0x83
+        );
+        let err = parse_sighash_type("EVERYTHING").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_PARAMETER));
+    }
+
+    #[test]
+    fn test_max_tx_fee() {
+        let tx = bitcoin::constants::genesis_block(Network::Bitcoin).txdata[0].clone();
+        assert_eq!(tx.vsize(), 204);
+        assert_eq!(
+            max_tx_fee(&tx, DEFAULT_MAX_RAW_TX_FEE_RATE),
+            Amount::from_sat(2_040_000)
+        );
+        assert_eq!(max_tx_fee(&tx, Amount::ZERO), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_parse_estimate_mode() {
+        assert!(!parse_estimate_mode(None).unwrap());
+        assert!(!parse_estimate_mode(Some("economical")).unwrap());
+        assert!(parse_estimate_mode(Some("CONSERVATIVE")).unwrap());
+        assert!(parse_estimate_mode(Some("fast")).is_err());
+    }
+
+    #[test]
+    fn test_fee_estimate_json() {
+        let estimate = fee_estimate_to_json(Some(FeeRate::from_sat_per_vb_unchecked(5)), 6);
+        assert_eq!(estimate, json!({ "feerate": 0.00005, "blocks": 6 }));
+
+        let estimate = fee_estimate_to_json(None, 2);
+        assert_eq!(estimate["blocks"], 2);
+        assert!(estimate.get("feerate").is_none());
+        assert_eq!(estimate["errors"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_chain_info_json() {
+        let genesis = bitcoin::constants::genesis_block(Network::Regtest);
+        let info = ChainInfo {
+            height: 0,
+            best_block_hash: genesis.block_hash(),
+            tip_time: genesis.header.time,
+            is_synced: false,
+            ibd: true,
+        };
+        assert_eq!(
+            chain_info_to_json(&info, Network::Regtest),
+            json!({
+                "chain": "regtest",
+                "blocks": 0,
+                "bestblockhash": genesis.block_hash().to_string(),
+                "time": 1296688602,
+                "initialblockdownload": true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_mempool_info_json() {
+        let info = MempoolInfo {
+            size: 2,
+            bytes: 300,
+            usage: 400,
+            mempoolminfee: 0.00001,
+            minrelaytxfee: 0.00001,
+        };
+        let fee_histogram = vec![
+            FeeHistogramBucket {
+                min_feerate: 1.0,
+                max_feerate: 2.0,
+                vsize: 200,
+            },
+            FeeHistogramBucket {
+                min_feerate: 10.0,
+                max_feerate: 12.0,
+                vsize: 100,
+            },
+        ];
+
+        let json = serde_json::to_value(MempoolInfoResponse::new(info, fee_histogram)).unwrap();
+        assert_eq!(json["size"], 2);
+        assert_eq!(
+            json["fee_histogram"],
+            json!([
+                { "min_feerate": 1.0, "max_feerate": 2.0, "vsize": 200 },
+                { "min_feerate": 10.0, "max_feerate": 12.0, "vsize": 100 },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_mempool_accept_json() {
+        let tx = bitcoin::constants::genesis_block(Network::Regtest).txdata[0].clone();
+        let mut result = MempoolAcceptResult {
+            txid: tx.compute_txid(),
+            allowed: true,
+            reject_reason: None,
+            fee: Some(Amount::from_sat(1_000)),
+        };
+        let json = mempool_accept_to_json(&tx, &result, DEFAULT_MAX_RAW_TX_FEE_RATE);
+        assert_eq!(json["allowed"], json!(true));
+        assert_eq!(json["vsize"], json!(204));
+        assert_eq!(json["fees"]["base"], json!(0.00001));
+        assert!(json.get("reject-reason").is_none());
+
+        // At 4 sat/kvB a 204 vB transaction may pay no more than 0 sat
+        let json = mempool_accept_to_json(&tx, &result, Amount::from_sat(4));
+        assert_eq!(json["allowed"], json!(false));
+        assert_eq!(json["reject-reason"], json!("max-fee-exceeded"));
+        // Zero disables the limit
+        let json = mempool_accept_to_json(&tx, &result, Amount::ZERO);
+        assert_eq!(json["allowed"], json!(true));
+
+        result.allowed = false;
+        result.reject_reason = Some("txn-mempool-conflict".to_string());
+        result.fee = None;
+        let json = mempool_accept_to_json(&tx, &result, DEFAULT_MAX_RAW_TX_FEE_RATE);
+        assert_eq!(json["allowed"], json!(false));
+        assert_eq!(json["reject-reason"], json!("txn-mempool-conflict"));
+        assert!(json.get("fees").is_none());
+    }
+
+    #[test]
+    fn test_package_accept_json() {
+        let tx = bitcoin::constants::genesis_block(Network::Regtest).txdata[0].clone();
+        let mut result = PackageAcceptResult {
+            txid: tx.compute_txid(),
+            allowed: true,
+            error: None,
+            effective_fee_rate: Some(0.00001333),
+        };
+        let json = package_accept_to_json(&tx, &result);
+        assert_eq!(json["allowed"], json!(true));
+        assert_eq!(json["vsize"], json!(204));
+        assert_eq!(json["fees"]["effective-feerate"], json!(0.00001333));
+        assert!(json.get("error").is_none());
+
+        result.allowed = false;
+        result.error = Some("mempool min fee not met".to_string());
+        result.effective_fee_rate = None;
+        let json = package_accept_to_json(&tx, &result);
+        assert_eq!(json["allowed"], json!(false));
+        assert_eq!(json["error"], json!("mempool min fee not met"));
+        assert!(json.get("fees").is_none());
+    }
+
+    #[test]
+    fn test_parse_package() {
+        let tx = bitcoin::constants::genesis_block(Network::Regtest).txdata[0].clone();
+        let rawtx = json!(serialize_hex(&tx));
+        let txs = parse_package(Params::Array(vec![json!([rawtx.clone()])])).unwrap();
+        assert_eq!(txs, vec![tx]);
+
+        assert!(parse_package(Params::Array(vec![json!([])])).is_err());
+        let too_many = vec![rawtx; MAX_PACKAGE_COUNT + 1];
+        assert!(parse_package(Params::Array(vec![json!(too_many)])).is_err());
+        assert!(parse_package(Params::None).is_err());
+    }
+
+    #[test]
+    fn test_parse_scan_objects() {
+        let objects = json!(["addr(bcrt1qexample)", { "desc": "raw(51)" }]);
+        assert_eq!(
+            parse_scan_objects(Some(&objects)).unwrap(),
+            vec!["addr(bcrt1qexample)".to_string(), "raw(51)".to_string()]
+        );
+
+        assert!(parse_scan_objects(None).is_err());
+        assert!(parse_scan_objects(Some(&json!([{ "range": 10 }]))).is_err());
+        let ranged = json!([{ "desc": "wpkh(xpub/0/*)", "range": [0, 10] }]);
+        assert!(parse_scan_objects(Some(&ranged)).is_err());
+        assert!(parse_scan_objects(Some(&json!([1]))).is_err());
+    }
+
+    #[test]
+    fn test_descriptor_entry_json() {
+        let mut entry = DescriptorEntry {
+            desc: "wpkh(tpub/0/*)#checksum".to_string(),
+            timestamp: 1_700_000_000,
+            active: true,
+            internal: false,
+            range: Some([0, 24]),
+            next_index: 3,
+        };
+        assert_eq!(
+            descriptor_entry_to_json(&entry),
+            json!({
+                "desc": "wpkh(tpub/0/*)#checksum",
+                "timestamp": 1_700_000_000,
+                "active": true,
+                "internal": false,
+                "range": [0, 24],
+                "next_index": 3,
+            })
+        );
+
+        entry.range = None;
+        assert!(descriptor_entry_to_json(&entry).get("range").is_none());
+    }
+
+    #[test]
+    fn test_parse_descriptor_range() {
+        assert_eq!(parse_descriptor_range(&json!(5)).unwrap(), (0, 5));
+        assert_eq!(parse_descriptor_range(&json!([2, 7])).unwrap(), (2, 7));
+        assert_eq!(parse_descriptor_range(&json!([3, 3])).unwrap(), (3, 3));
+
+        for range in [
+            json!(-1),
+            json!([5, 2]),
+            json!([0, 1u64 << 31]),
+            json!([0, 1_000_000]),
+            json!([1, 2, 3]),
+            json!("5"),
+        ] {
+            let err = parse_descriptor_range(&range).unwrap_err();
+            assert_eq!(
+                err.code,
+                ErrorCode::ServerError(RPC_INVALID_PARAMETER),
+                "{}",
+                range
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_block_height() {
+        assert_eq!(parse_block_height(&json!(0)).unwrap(), 0);
+        assert_eq!(parse_block_height(&json!(840_000)).unwrap(), 840_000);
+
+        let err = parse_block_height(&json!(-1)).unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_PARAMETER));
+        assert!(parse_block_height(&json!("tip")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_settxfee_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("settxfee-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+        let mut io = IoHandler::new();
+        register_settxfee(&mut io, wallet.clone());
+        register_getwalletinfo(&mut io, wallet.clone());
+        let call = |method: &str, params: Value| {
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            let reply = io.handle_request_sync(&request.to_string()).unwrap();
+            serde_json::from_str::<Value>(&reply).unwrap()["result"].clone()
+        };
+
+        // Not a whole number of sat/kwu
+        assert_eq!(call("settxfee", json!([0.00001001])), true);
+        assert_eq!(call("getwalletinfo", json!([]))["paytxfee"], 0.00001001);
+        assert_eq!(
+            wallet.get_pay_tx_fee().unwrap(),
+            Some(FeeRate::from_sat_per_kwu(251))
+        );
+
+        assert_eq!(call("settxfee", json!([0])), true);
+        assert_eq!(call("getwalletinfo", json!([]))["paytxfee"], 0.0);
+        assert_eq!(wallet.get_pay_tx_fee().unwrap(), None);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn genesis_details() -> BlockDetails {
+        BlockDetails {
+            block: bitcoin::constants::genesis_block(Network::Regtest),
+            height: Some(0),
+            confirmations: 3,
+            median_time: 1296688602,
+            next_block_hash: Some(BlockHash::all_zeros()),
+        }
+    }
+
+    #[test]
+    fn test_getblock_verbosity_0() {
+        let details = genesis_details();
+        let raw = block_to_json(&details, 0, Network::Regtest);
+        assert_eq!(raw, Value::String(serialize_hex(&details.block)));
+    }
+
+    #[test]
+    fn test_getblock_verbosity_1() {
+        let json = block_to_json(&genesis_details(), 1, Network::Regtest);
+        assert_eq!(
+            json["hash"],
+            "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206"
+        );
+        assert_eq!(json["height"], 0);
+        assert_eq!(json["confirmations"], 3);
+        assert_eq!(json["nTx"], 1);
+        assert_eq!(json["bits"], "207fffff");
+        assert_eq!(json["versionHex"], "00000001");
+        assert!(json["tx"][0].is_string());
+        assert!(json.get("previousblockhash").is_none());
+        assert!(json["nextblockhash"].is_string());
+        // Working out chain work can take a request per block back to genesis
+        assert!(json.get("chainwork").is_none());
+    }
+
+    #[test]
+    fn test_getblock_verbosity_2() {
+        let json = block_to_json(&genesis_details(), 2, Network::Regtest);
+        assert_eq!(
+            json["tx"][0]["txid"],
+            "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+        );
+        assert!(json["tx"][0]["vin"][0]["coinbase"].is_string());
+    }
+
+    #[test]
+    fn test_raw_transaction_json_from_mempool() {
+        let tx = RawTransaction {
+            transaction: genesis_details().block.txdata[0].clone(),
+            block_hash: None,
+            confirmations: 0,
+            block_time: None,
+        };
+
+        let json = raw_transaction_to_json(&tx, Network::Regtest);
+        assert_eq!(json["confirmations"], 0);
+        assert_eq!(json["hex"], serialize_hex(&tx.transaction));
+        assert!(json.get("blockhash").is_none());
+    }
+
+    #[test]
+    fn test_raw_transaction_json_from_block() {
+        let block = genesis_details().block;
+        let tx = RawTransaction {
+            transaction: block.txdata[0].clone(),
+            block_hash: Some(block.block_hash()),
+            confirmations: 5,
+            block_time: Some(block.header.time),
+        };
+
+        let json = raw_transaction_to_json(&tx, Network::Regtest);
+        assert_eq!(json["confirmations"], 5);
+        assert_eq!(json["in_active_chain"], true);
+        assert_eq!(json["blockhash"], block.block_hash().to_string());
+        assert_eq!(json["blocktime"], block.header.time);
+    }
+
+    fn mempool_entry(
+        transaction: Transaction,
+        ancestors: u64,
+        descendants: u64,
+        fee: i64,
+        ancestor_fees: i64,
+    ) -> MempoolEntry {
+        MempoolEntry {
+            vsize: transaction.vsize() as u64,
+            ancestry: TransactionAncestry {
+                ancestors,
+                descendants,
+                ancestor_size: transaction.vsize() as u64 * ancestors,
+                ancestor_fees,
+            },
+            fee: Some(fee),
+            transaction,
+            depends: Vec::new(),
+            spent_by: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_mempool_entries_json_chained() {
+        let confirmed = genesis_details().block.txdata[0].clone();
+        let parent = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(confirmed.compute_txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+        let child = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(parent.compute_txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        };
+
+        let mut parent_entry = mempool_entry(parent.clone(), 1, 2, 300, 300);
+        parent_entry.spent_by = vec![child.compute_txid()];
+        let mut child_entry = mempool_entry(child.clone(), 2, 1, 200, 500);
+        child_entry.depends = vec![parent.compute_txid()];
+
+        let json = mempool_entries_to_json(&[parent_entry, child_entry]);
+        let parent_json = &json[parent.compute_txid().to_string()];
+        let child_json = &json[child.compute_txid().to_string()];
+
+        assert_eq!(parent_json["depends"], json!([]));
+        assert_eq!(
+            parent_json["spentby"],
+            json!([child.compute_txid().to_string()])
+        );
+        assert_eq!(parent_json["descendantfees"], 500);
+        assert_eq!(
+            parent_json["descendantsize"],
+            parent.vsize() as u64 + child.vsize() as u64
+        );
+        assert_eq!(
+            child_json["depends"],
+            json!([parent.compute_txid().to_string()])
+        );
+        assert_eq!(child_json["fee"], 0.000002);
+        assert_eq!(child_json["ancestorfees"], 500);
+    }
+
+    #[test]
+    fn test_address_info_json_for_unrelated_address() {
+        let address = Address::from_str(ADDRESS_A).unwrap().assume_checked();
+        let info = AddressInfo {
+            script_pubkey: address.script_pubkey(),
+            address,
+            derivation: None,
+            pubkey: None,
+            hd_key_path: None,
+            master_fingerprint: None,
+            timestamp: None,
+            labels: Vec::new(),
+        };
+
+        let json = address_info_to_json(&info);
+        assert_eq!(json["ismine"], false);
+        assert_eq!(json["iswitness"], true);
+        assert_eq!(json["witness_version"], 0);
+        assert_eq!(
+            json["witness_program"],
+            "751e76e8199196d454941c45d1b3a323f1433bd6"
+        );
+        assert!(json.get("hdkeypath").is_none());
+    }
+
+    #[test]
+    fn test_parse_import_request() {
+        let import = parse_import_request(&json!({
+            "desc": "wpkh(tpub/0/*)",
+            "timestamp": "now",
+            "active": true,
+            "internal": true,
+        }))
+        .unwrap();
+        assert!(import.internal);
+        assert_eq!(import.timestamp, None);
+
+        let import = parse_import_request(&json!({
+            "desc": "wpkh(tpub/0/*)",
+            "timestamp": 1_700_000_000,
+            "active": true,
+        }))
+        .unwrap();
+        assert!(!import.internal);
+        assert_eq!(import.timestamp, Some(1_700_000_000));
+
+        let missing_timestamp = parse_import_request(&json!({
+            "desc": "wpkh(tpub/0/*)",
+            "active": true,
+        }));
+        assert!(missing_timestamp.is_err());
+
+        let inactive = parse_import_request(&json!({
+            "desc": "wpkh(tpub/0/*)",
+            "timestamp": "now",
+        }));
+        assert_eq!(
+            inactive.unwrap_err().code,
+            ErrorCode::ServerError(RPC_INVALID_PARAMETER)
+        );
+    }
+
+    #[test]
+    fn test_parse_create_wallet_descriptors() {
+        let params = json!({
+            "wallet_name": "watcher",
+            "disable_private_keys": true,
+            "external_descriptor": "wpkh(tpub/0/*)",
+            "internal_descriptor": "wpkh(tpub/1/*)",
+        });
+        let options =
+            parse_create_wallet_options(Params::Map(params.as_object().unwrap().clone())).unwrap();
+        assert_eq!(options.wallet_name, "watcher");
+        assert!(options.disable_private_keys);
+        assert_eq!(
+            options.external_descriptor.as_deref(),
+            Some("wpkh(tpub/0/*)")
+        );
+        assert_eq!(
+            options.internal_descriptor.as_deref(),
+            Some("wpkh(tpub/1/*)")
+        );
+
+        let options = parse_create_wallet_options(Params::Array(vec![json!("plain")])).unwrap();
+        assert_eq!(options.external_descriptor, None);
+        assert_eq!(options.internal_descriptor, None);
+        assert_eq!(options.mnemonic, None);
+
+        assert_eq!(options.address_type, AddressType::P2WPKH);
+
+        let params = json!({
+            "wallet_name": "restored",
+            "mnemonic": "abandon about",
+            "address_type": "bech32m",
+        });
+        let options =
+            parse_create_wallet_options(Params::Map(params.as_object().unwrap().clone())).unwrap();
+        assert_eq!(options.mnemonic.as_deref(), Some("abandon about"));
+        assert_eq!(options.address_type, AddressType::P2TR);
+
+        let params = json!({"wallet_name": "old", "address_type": "legacy"});
+        assert!(
+            parse_create_wallet_options(Params::Map(params.as_object().unwrap().clone())).is_err()
+        );
+
+        let params = json!({"wallet_name": "secret", "mnemonic_passphrase": "hunter2"});
+        let options =
+            parse_create_wallet_options(Params::Map(params.as_object().unwrap().clone())).unwrap();
+        assert_eq!(options.mnemonic_passphrase.as_deref(), Some("hunter2"));
+
+        // Wallet encryption isn't supported, so Core's passphrase is refused
+        let params = json!({"wallet_name": "secret", "passphrase": "hunter2"});
+        assert!(
+            parse_create_wallet_options(Params::Map(params.as_object().unwrap().clone())).is_err()
+        );
+        assert!(parse_create_wallet_options(Params::Array(vec![
+            json!("secret"),
+            json!(false),
+            json!(false),
+            json!("hunter2"),
+        ]))
+        .is_err());
+        assert!(parse_create_wallet_options(Params::Array(vec![
+            json!("plain"),
+            json!(false),
+            json!(false),
+            json!(""),
+        ]))
+        .is_ok());
+    }
+
+    #[test]
+    fn test_parse_psbt_options() {
+        let options = parse_psbt_options(
+            &json!({
+                "fee_rate": 2.5,
+                "changeAddress": ADDRESS_B,
+                "subtractFeeFromOutputs": [0, 2],
+                "max_fee_rate": 0.01,
+            }),
+            Network::Bitcoin,
+        )
+        .unwrap();
+        assert_eq!(options.fee_rate, Some(FeeRate::from_sat_per_kwu(625)));
+        assert_eq!(options.subtract_fee_from, vec![0, 2]);
+        assert_eq!(
+            options.change_script,
+            Some(
+                Address::from_str(ADDRESS_B)
+                    .unwrap()
+                    .assume_checked()
+                    .script_pubkey()
+            )
+        );
+        assert_eq!(options.max_fee_rate, Amount::from_sat(1_000_000));
+
+        let defaults = parse_psbt_options(&json!({}), Network::Bitcoin).unwrap();
+        assert_eq!(defaults.fee_rate, None);
+        assert!(defaults.subtract_fee_from.is_empty());
+        assert_eq!(defaults.max_fee_rate, DEFAULT_MAX_RAW_TX_FEE_RATE);
+
+        let err = parse_psbt_options(
+            &json!({ "subtractFeeFromOutputs": ["a"] }),
+            Network::Bitcoin,
+        )
+        .unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_INVALID_PARAMETER));
+    }
+
+    #[test]
+    fn test_parse_psbt() {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: Vec::new(),
+        };
+        let psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        assert_eq!(parse_psbt(&psbt.to_string()).unwrap(), psbt);
+
+        let err = parse_psbt("not a psbt").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ServerError(RPC_DESERIALIZATION_ERROR));
+    }
+
+    #[test]
+    fn test_parse_coin_control() {
+        let txid = Txid::from_byte_array([1; 32]);
+        let coin_control = parse_coin_control(&json!({
+            "must_use": [{"txid": txid.to_string(), "vout": 0}],
+            "must_not_use": [{"txid": txid.to_string(), "vout": 1}],
+        }))
+        .unwrap();
+        assert_eq!(coin_control.must_use, vec![OutPoint::new(txid, 0)]);
+        assert_eq!(coin_control.must_not_use, vec![OutPoint::new(txid, 1)]);
+
+        let coin_control = parse_coin_control(&json!({})).unwrap();
+        assert!(coin_control.must_use.is_empty());
+        assert!(coin_control.must_not_use.is_empty());
+
+        assert!(parse_coin_control(&json!({"must_use": [{"txid": "00", "vout": 0}]})).is_err());
+        assert!(parse_coin_control(&json!({"must_not_use": "all"})).is_err());
+        assert!(parse_coin_control(&json!([])).is_err());
+    }
+
+    #[test]
+    fn test_check_label() {
+        assert!(check_label("savings").is_ok());
+        assert!(check_label("").is_ok());
+        let err = check_label("*").unwrap_err();
+        assert_eq!(
+            err.code,
+            ErrorCode::ServerError(RPC_WALLET_INVALID_LABEL_NAME)
+        );
+    }
+}
@@ -1,10 +1,14 @@
+mod auth;
 mod config;
 mod error;
 mod handlers;
+mod rate_limit;
+mod rest;
 mod server;
 mod types;
+mod websocket;
 
-pub use config::{RpcAuth, RpcConfig};
-pub use error::rpc_error_from_wallet_error;
+pub use config::{RateLimitConfig, RpcAuth, RpcConfig};
+pub use error::{rpc_error, rpc_error_from_wallet_error};
 pub use server::RPCServer;
 pub use types::*;
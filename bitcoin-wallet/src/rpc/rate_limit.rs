@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use super::config::RateLimitConfig;
+
+/// How long a client's bucket is kept after its last request
+const STALE_BUCKET_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// How often stale buckets are looked for
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Limits how often each client IP may make requests, by token bucket
+///
+/// Every client starts with `burst` tokens and each request takes one. Tokens
+/// come back at `rate` per second, up to `burst`, so a client can make short
+/// bursts of requests but no more than `rate` a second for long.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Take a token for a request from `client`, or say how long until one
+    /// is available
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let burst = self.config.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client).or_insert(TokenBucket {
+            tokens: burst,
+            updated: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.config.rate).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        // A tiny rate can put the next token further off than a Duration goes
+        Err(
+            Duration::try_from_secs_f64((1.0 - bucket.tokens) / self.config.rate)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+
+    /// Forget clients that have made no request for `max_age`
+    /// Their buckets would have filled up again, so they lose nothing.
+    pub fn prune(&self, max_age: Duration) {
+        self.prune_at(max_age, Instant::now());
+    }
+
+    fn prune_at(&self, max_age: Duration, now: Instant) {
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, bucket| now.saturating_duration_since(bucket.updated) < max_age);
+    }
+
+    /// Prune stale buckets in the background for as long as the limiter is
+    /// in use
+    pub fn spawn_pruner(limiter: &Arc<Self>) {
+        let limiter: Weak<Self> = Arc::downgrade(limiter);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                match limiter.upgrade() {
+                    Some(limiter) => limiter.prune(STALE_BUCKET_AGE),
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(burst: u32, rate: f64) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { burst, rate })
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = limiter(3, 2.0);
+        let client = IpAddr::from([127, 0, 0, 1]);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(client, start).is_ok());
+        }
+        assert_eq!(
+            limiter.check_at(client, start),
+            Err(Duration::from_millis(500))
+        );
+        // Other clients have their own buckets
+        assert!(limiter.check_at(IpAddr::from([10, 0, 0, 1]), start).is_ok());
+
+        // Half a second brings back one token
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_at(client, later).is_ok());
+        assert!(limiter.check_at(client, later).is_err());
+
+        // Tokens never build up beyond the burst
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.check_at(client, much_later).is_ok());
+        }
+        assert!(limiter.check_at(client, much_later).is_err());
+    }
+
+    #[test]
+    fn test_tiny_rate() {
+        let limiter = limiter(1, 1e-300);
+        let client = IpAddr::from([127, 0, 0, 1]);
+        let start = Instant::now();
+
+        assert!(limiter.check_at(client, start).is_ok());
+        assert_eq!(limiter.check_at(client, start), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn test_prune_stale_buckets() {
+        let limiter = limiter(20, 5.0);
+        let start = Instant::now();
+        limiter
+            .check_at(IpAddr::from([127, 0, 0, 1]), start)
+            .unwrap();
+        limiter
+            .check_at(IpAddr::from([10, 0, 0, 1]), start + STALE_BUCKET_AGE / 2)
+            .unwrap();
+
+        limiter.prune_at(STALE_BUCKET_AGE, start + STALE_BUCKET_AGE);
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+        limiter.prune_at(STALE_BUCKET_AGE, start + STALE_BUCKET_AGE * 2);
+        assert!(limiter.buckets.lock().unwrap().is_empty());
+    }
+}
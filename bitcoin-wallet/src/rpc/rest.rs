@@ -0,0 +1,307 @@
+//! REST interface modelled on Bitcoin Core's, served next to the JSON-RPC server
+//!
+//! Resources are read with `GET /rest/<resource>.<format>`:
+//! - `block/<hash>` as json, bin or hex
+//! - `tx/<txid>` as json, bin or hex, for transactions in the mempool
+//! - `chaininfo` as json
+//! - `mempool/contents` as json
+//!
+//! Requests are checked against the same credentials and allowed IPs as
+//! JSON-RPC ones.
+
+use bitcoin::consensus::encode::{serialize, serialize_hex};
+use bitcoin::{BlockHash, Txid};
+use jsonrpc_http_server::hyper::header::{self, HeaderValue};
+use jsonrpc_http_server::hyper::server::conn::AddrStream;
+use jsonrpc_http_server::hyper::service::{make_service_fn, service_fn};
+use jsonrpc_http_server::hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+use super::auth::check_access;
+use super::config::RpcConfig;
+use super::handlers::{
+    block_on_local, block_to_json, chain_info_to_json, mempool_entries_to_json,
+    raw_transaction_to_json,
+};
+use crate::error::WalletError;
+use crate::wallet::WalletInterface;
+
+const REST_PREFIX: &str = "/rest/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Binary,
+    Hex,
+    Json,
+}
+
+/// Serve REST requests on `address` until `shutdown` fires or is dropped
+pub(crate) fn serve(
+    address: SocketAddr,
+    wallet: Arc<WalletInterface>,
+    config: RpcConfig,
+    shutdown: oneshot::Receiver<()>,
+) -> Result<(), WalletError> {
+    let builder = Server::try_bind(&address)
+        .map_err(|e| WalletError::RPCError(format!("Failed to start REST server: {}", e)))?;
+    let config = Arc::new(config);
+
+    let server = builder
+        .serve(make_service_fn(move |connection: &AddrStream| {
+            let remote = connection.remote_addr().ip();
+            let wallet = wallet.clone();
+            let config = config.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = handle_request(remote, &request, &wallet, &config);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        }))
+        .with_graceful_shutdown(async {
+            let _ = shutdown.await;
+        });
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("REST server failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+fn handle_request(
+    remote: IpAddr,
+    request: &Request<Body>,
+    wallet: &WalletInterface,
+    config: &RpcConfig,
+) -> Response<Body> {
+    if let Err(response) = check_access(remote, request, config) {
+        return response;
+    }
+    if request.method() != Method::GET {
+        return text_response(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+    }
+
+    let resource = match request.uri().path().strip_prefix(REST_PREFIX) {
+        Some(resource) => resource,
+        None => return text_response(StatusCode::NOT_FOUND, "Not found"),
+    };
+    // Like Core, the format is everything after the last dot
+    let (resource, format) = match resource.rsplit_once('.') {
+        Some((resource, "bin")) => (resource, Format::Binary),
+        Some((resource, "hex")) => (resource, Format::Hex),
+        Some((resource, "json")) => (resource, Format::Json),
+        _ => {
+            return text_response(
+                StatusCode::NOT_FOUND,
+                "output format not found (available: json, bin, hex)",
+            )
+        }
+    };
+
+    let result = match resource.split_once('/') {
+        Some(("block", hash)) => rest_block(wallet, hash, format),
+        Some(("tx", txid)) => rest_tx(wallet, txid, format),
+        Some(("mempool", "contents")) => json_only(format).and_then(|_| rest_mempool(wallet)),
+        None if resource == "chaininfo" => json_only(format).and_then(|_| rest_chaininfo(wallet)),
+        _ => Err(text_response(StatusCode::NOT_FOUND, "Not found")),
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+fn rest_block(
+    wallet: &WalletInterface,
+    hash: &str,
+    format: Format,
+) -> Result<Response<Body>, Response<Body>> {
+    let block_hash = BlockHash::from_str(hash)
+        .map_err(|_| text_response(StatusCode::BAD_REQUEST, &format!("Invalid hash: {}", hash)))?;
+    let details = block_on_local(wallet.get_block_details(&block_hash))
+        .map_err(wallet_error_response)?
+        .ok_or_else(|| text_response(StatusCode::NOT_FOUND, &format!("{} not found", hash)))?;
+
+    Ok(match format {
+        Format::Binary => binary_response(serialize(&details.block)),
+        Format::Hex => text_response(StatusCode::OK, &serialize_hex(&details.block)),
+        Format::Json => json_response(&block_to_json(&details, 2, wallet.network())),
+    })
+}
+
+fn rest_tx(
+    wallet: &WalletInterface,
+    txid: &str,
+    format: Format,
+) -> Result<Response<Body>, Response<Body>> {
+    let parsed = Txid::from_str(txid)
+        .map_err(|_| text_response(StatusCode::BAD_REQUEST, &format!("Invalid hash: {}", txid)))?;
+    // Without a transaction index only mempool transactions can be found
+    let tx = block_on_local(wallet.get_raw_transaction(&parsed, None))
+        .map_err(wallet_error_response)?
+        .ok_or_else(|| text_response(StatusCode::NOT_FOUND, &format!("{} not found", txid)))?;
+
+    Ok(match format {
+        Format::Binary => binary_response(serialize(&tx.transaction)),
+        Format::Hex => text_response(StatusCode::OK, &serialize_hex(&tx.transaction)),
+        Format::Json => json_response(&raw_transaction_to_json(&tx, wallet.network())),
+    })
+}
+
+fn rest_chaininfo(wallet: &WalletInterface) -> Result<Response<Body>, Response<Body>> {
+    let info = block_on_local(wallet.get_chain_info()).map_err(wallet_error_response)?;
+    Ok(json_response(&chain_info_to_json(&info, wallet.network())))
+}
+
+fn rest_mempool(wallet: &WalletInterface) -> Result<Response<Body>, Response<Body>> {
+    let entries = block_on_local(wallet.get_mempool_entries()).map_err(wallet_error_response)?;
+    Ok(json_response(&mempool_entries_to_json(&entries)))
+}
+
+fn json_only(format: Format) -> Result<(), Response<Body>> {
+    match format {
+        Format::Json => Ok(()),
+        _ => Err(text_response(
+            StatusCode::NOT_FOUND,
+            "output format not found (available: json)",
+        )),
+    }
+}
+
+// Node failures mean the data is unavailable for now rather than missing
+fn wallet_error_response(e: WalletError) -> Response<Body> {
+    let status = match &e {
+        WalletError::BlocktalkError(_) => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    text_response(status, &e.to_string())
+}
+
+fn text_response(status: StatusCode, text: &str) -> Response<Body> {
+    response(status, "text/plain", format!("{}\r\n", text).into())
+}
+
+fn json_response(value: &Value) -> Response<Body> {
+    response(
+        StatusCode::OK,
+        "application/json",
+        format!("{}\n", value).into(),
+    )
+}
+
+fn binary_response(bytes: Vec<u8>) -> Response<Body> {
+    response(StatusCode::OK, "application/octet-stream", bytes.into())
+}
+
+fn response(status: StatusCode, content_type: &'static str, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::config::test_config;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Network;
+    use reqwest::StatusCode as HttpStatus;
+
+    // REST server on a free port for a wallet whose node is unreachable
+    async fn start_server(config: RpcConfig) -> (String, oneshot::Sender<()>) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = SocketAddr::from(([127, 0, 0, 1], port));
+        let path = std::env::temp_dir().join(format!("rest-test-{}.sqlite", port));
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+
+        let (shutdown, receiver) = oneshot::channel();
+        serve(address, wallet, config, receiver).unwrap();
+        (format!("http://{}{}", address, REST_PREFIX), shutdown)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rest_requires_credentials() {
+        let (url, _shutdown) = start_server(test_config()).await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("{}chaininfo.json", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::UNAUTHORIZED);
+        assert!(response.headers().contains_key("www-authenticate"));
+
+        // Credentials are checked before the node is asked anything
+        let response = client
+            .get(format!("{}block/00.json", url))
+            .basic_auth("alice", Some("hunter2"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_rest_endpoints() {
+        let (url, _shutdown) = start_server(test_config()).await;
+        let client = reqwest::Client::new();
+        let get = |path: String| {
+            client
+                .get(format!("{}{}", url, path))
+                .basic_auth("alice", Some("hunter2"))
+                .send()
+        };
+
+        let hash = BlockHash::all_zeros();
+        let txid = Txid::all_zeros();
+        // Without a node every resource is unavailable
+        for path in [
+            format!("block/{}.json", hash),
+            format!("block/{}.bin", hash),
+            format!("tx/{}.json", txid),
+            "chaininfo.json".to_string(),
+            "mempool/contents.json".to_string(),
+        ] {
+            let response = get(path.clone()).await.unwrap();
+            assert_eq!(
+                response.status(),
+                HttpStatus::SERVICE_UNAVAILABLE,
+                "{}",
+                path
+            );
+        }
+
+        for (path, status) in [
+            ("block/xyz.json", HttpStatus::BAD_REQUEST),
+            ("tx/xyz.hex", HttpStatus::BAD_REQUEST),
+            ("chaininfo.bin", HttpStatus::NOT_FOUND),
+            ("mempool/contents.xml", HttpStatus::NOT_FOUND),
+            ("headers/5.json", HttpStatus::NOT_FOUND),
+        ] {
+            let response = get(path.to_string()).await.unwrap();
+            assert_eq!(response.status(), status, "{}", path);
+        }
+
+        let response = client
+            .post(format!("{}chaininfo.json", url))
+            .basic_auth("alice", Some("hunter2"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::METHOD_NOT_ALLOWED);
+    }
+}
@@ -1,43 +1,88 @@
-use std::net::SocketAddr;
-use std::sync::Arc;
+use blocktalk::Blockchain;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, RwLock};
 use tokio::task::LocalSet;
 
-use jsonrpc_core::IoHandler;
-use jsonrpc_http_server::{Server, ServerBuilder};
+use jsonrpc_core::{IoHandler, Value};
+use jsonrpc_http_server::hyper::body::HttpBody;
+use jsonrpc_http_server::hyper::header::{self, HeaderValue};
+use jsonrpc_http_server::hyper::server::conn::AddrStream;
+use jsonrpc_http_server::hyper::service::{make_service_fn, service_fn};
+use jsonrpc_http_server::hyper::{Body, Method, Request, Response, Server, StatusCode};
 
+use super::auth::{self, check_access};
 use super::config::RpcConfig;
+use super::error::RPC_WALLET_NOT_FOUND;
 use super::handlers;
+use super::rate_limit::RateLimiter;
+use super::rest;
+use super::websocket::{self, WEBSOCKET_PATH};
 use crate::error::WalletError;
-use crate::wallet::WalletInterface;
+use crate::wallet::{WalletInterface, WalletManager};
+
+/// Largest request body the JSON-RPC server reads, matching jsonrpc-http-server's default
+const MAX_REQUEST_BODY_SIZE: usize = 5 * 1024 * 1024;
+
+/// URL path prefix of requests for a named wallet, as used by bitcoin-cli's -rpcwallet
+const WALLET_PATH_PREFIX: &str = "/wallet/";
+
+/// Header naming the wallet a request is for, when the URL doesn't
+const WALLET_HEADER: &str = "x-wallet";
 
 pub struct RPCServer {
     wallet: Arc<WalletInterface>,
-    server: Option<Server>,
+    manager: Arc<RwLock<WalletManager>>,
+    server_shutdown: Option<oneshot::Sender<()>>,
+    // Keeps the node sending the notifications streamed over WebSocket
+    chain: Option<Blockchain>,
+    rest_shutdown: Option<oneshot::Sender<()>>,
     config: RpcConfig,
 }
 
 impl RPCServer {
     pub fn new(wallet: Arc<WalletInterface>, config: &RpcConfig) -> Self {
+        let manager = WalletManager::with_wallet(wallet.clone());
         Self {
             wallet,
-            server: None,
+            manager: Arc::new(RwLock::new(manager)),
+            server_shutdown: None,
+            chain: None,
+            rest_shutdown: None,
             config: config.clone(),
         }
     }
 
-    pub async fn start(&mut self, bind_address: SocketAddr) -> Result<(), WalletError> {
-        let wallet = self.wallet.clone();
-        let mut io = IoHandler::new();
+    /// The wallets served, which requests are routed between by name
+    pub fn wallet_manager(&self) -> &Arc<RwLock<WalletManager>> {
+        &self.manager
+    }
 
-        handlers::register_wallet_methods(&mut io, wallet.clone());
+    pub async fn start(&mut self, bind_address: SocketAddr) -> Result<(), WalletError> {
+        let manager = self.manager.clone();
+        let routes = WalletRoutes::new(
+            self.manager.clone(),
+            self.wallet.clone(),
+            move |wallet, wallet_name| {
+                let mut io = IoHandler::new();
+                let wallet_name = wallet_name
+                    .map(str::to_string)
+                    .unwrap_or_else(|| wallet.wallet_name());
+                handlers::register_wallet_methods(&mut io, wallet);
+                handlers::register_wallet_manager_methods(&mut io, manager.clone(), wallet_name);
+                io
+            },
+        );
 
         log::info!("Starting RPC server on {}", bind_address);
-        let server = ServerBuilder::new(io)
-            .threads(1) // Force single thread
-            .start_http(&bind_address)
-            .map_err(|e| WalletError::RPCError(format!("Failed to start RPC server: {}", e)))?;
+        auth::validate_allow_ips(&self.config.allow_ips)?;
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        serve(bind_address, routes, self.config.clone(), shutdown_receiver)?;
 
-        self.server = Some(server);
+        self.server_shutdown = Some(shutdown);
         log::info!("RPC server started");
         let local = LocalSet::new();
         local
@@ -51,11 +96,521 @@ impl RPCServer {
         Ok(())
     }
 
+    /// Start the RPC server like `start`, also streaming chain notifications
+    /// to WebSocket clients connecting to `ws_port` on the same address
+    pub async fn start_with_websocket(
+        &mut self,
+        bind_address: SocketAddr,
+        ws_port: u16,
+    ) -> Result<(), WalletError> {
+        let ws_address = SocketAddr::new(bind_address.ip(), ws_port);
+        let listener = TcpListener::bind(ws_address).await.map_err(|e| {
+            WalletError::RPCError(format!("Failed to start WebSocket server: {}", e))
+        })?;
+
+        let chain = self.wallet.begin_chain_updates().await?;
+        tokio::spawn(websocket::serve(
+            listener,
+            chain.subscribe(),
+            Arc::new(self.config.clone()),
+        ));
+        self.chain = Some(chain);
+        log::info!(
+            "Streaming notifications on ws://{}{}",
+            ws_address,
+            WEBSOCKET_PATH
+        );
+
+        self.start(bind_address).await
+    }
+
+    /// Serve the REST interface on `address` in the background, checking
+    /// requests against the RPC credentials and allowed IPs
+    pub fn start_rest(&mut self, address: SocketAddr) -> Result<(), WalletError> {
+        auth::validate_allow_ips(&self.config.allow_ips)?;
+        let (shutdown, shutdown_receiver) = oneshot::channel();
+        rest::serve(
+            address,
+            self.wallet.clone(),
+            self.config.clone(),
+            shutdown_receiver,
+        )?;
+        self.rest_shutdown = Some(shutdown);
+        log::info!("REST server started on {}", address);
+        Ok(())
+    }
+
     pub fn stop(&mut self) {
-        if let Some(server) = self.server.take() {
+        if let Some(shutdown) = self.server_shutdown.take() {
             log::info!("Stopping RPC server");
-            server.close();
+            let _ = shutdown.send(());
             log::info!("RPC server stopped");
         }
+        if let Some(shutdown) = self.rest_shutdown.take() {
+            let _ = shutdown.send(());
+            log::info!("REST server stopped");
+        }
+        self.chain = None;
+    }
+}
+
+// Builds the handler for a wallet's requests, given the name they were sent
+// to, if any
+type HandlerBuilder = dyn Fn(Arc<WalletInterface>, Option<&str>) -> IoHandler + Send + Sync;
+
+// Picks the handler for each request from the wallet it names
+// Handlers are built the first time a wallet is asked for and kept until the
+// name refers to another wallet, as it does after the wallet is reloaded.
+struct WalletRoutes {
+    manager: Arc<RwLock<WalletManager>>,
+    // Serves requests naming no wallet while more or less than one is loaded
+    default_wallet: Arc<WalletInterface>,
+    build: Box<HandlerBuilder>,
+    handlers: Mutex<HashMap<Option<String>, (Arc<WalletInterface>, Arc<IoHandler>)>>,
+}
+
+impl WalletRoutes {
+    fn new(
+        manager: Arc<RwLock<WalletManager>>,
+        default_wallet: Arc<WalletInterface>,
+        build: impl Fn(Arc<WalletInterface>, Option<&str>) -> IoHandler + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            manager,
+            default_wallet,
+            build: Box::new(build),
+            handlers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The handler for requests to `wallet_name`, or `None` if no wallet by
+    // that name is loaded
+    async fn handler(&self, wallet_name: Option<&str>) -> Option<Arc<IoHandler>> {
+        let wallet = {
+            let manager = self.manager.read().await;
+            match wallet_name {
+                Some(name) => manager.get_wallet(name)?.clone(),
+                None => manager
+                    .only_wallet()
+                    .cloned()
+                    .unwrap_or_else(|| self.default_wallet.clone()),
+            }
+        };
+
+        let key = wallet_name.map(str::to_string);
+        let mut handlers = self.handlers.lock().unwrap();
+        if let Some((cached, io)) = handlers.get(&key) {
+            if Arc::ptr_eq(cached, &wallet) {
+                return Some(io.clone());
+            }
+        }
+        let io = Arc::new((self.build)(wallet.clone(), wallet_name));
+        handlers.insert(key, (wallet, io.clone()));
+        Some(io)
+    }
+}
+
+// Serve JSON-RPC over HTTP in the background until `shutdown` fires or is dropped
+fn serve(
+    address: SocketAddr,
+    routes: WalletRoutes,
+    config: RpcConfig,
+    shutdown: oneshot::Receiver<()>,
+) -> Result<(), WalletError> {
+    let builder = Server::try_bind(&address)
+        .map_err(|e| WalletError::RPCError(format!("Failed to start RPC server: {}", e)))?;
+    let routes = Arc::new(routes);
+    let rate_limiter = config
+        .rate_limit
+        .clone()
+        .map(|rate_limit| Arc::new(RateLimiter::new(rate_limit)));
+    if let Some(rate_limiter) = &rate_limiter {
+        RateLimiter::spawn_pruner(rate_limiter);
+    }
+    let config = Arc::new(config);
+
+    let server = builder
+        .serve(make_service_fn(move |connection: &AddrStream| {
+            let remote = connection.remote_addr().ip();
+            let routes = routes.clone();
+            let config = config.clone();
+            let rate_limiter = rate_limiter.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle_request(
+                        remote,
+                        request,
+                        routes.clone(),
+                        config.clone(),
+                        rate_limiter.clone(),
+                    )
+                }))
+            }
+        }))
+        .with_graceful_shutdown(async {
+            let _ = shutdown.await;
+        });
+
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("RPC server failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn handle_request(
+    remote: IpAddr,
+    request: Request<Body>,
+    routes: Arc<WalletRoutes>,
+    config: Arc<RpcConfig>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+) -> Result<Response<Body>, Infallible> {
+    // Checked first so credentials can't be guessed at any faster either
+    if let Some(Err(retry_after)) = rate_limiter.map(|limiter| limiter.check(remote)) {
+        log::warn!("Rate limited request from {}", remote);
+        let mut response = response(StatusCode::TOO_MANY_REQUESTS, Body::empty());
+        let seconds = retry_after
+            .as_secs()
+            .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(seconds.max(1)));
+        return Ok(response);
+    }
+    if let Err(response) = check_access(remote, &request, &config) {
+        return Ok(response);
+    }
+    if request.method() != Method::POST {
+        return Ok(response(StatusCode::METHOD_NOT_ALLOWED, Body::empty()));
+    }
+    let wallet_name = match requested_wallet(&request) {
+        Ok(wallet_name) => wallet_name,
+        Err(()) => return Ok(response(StatusCode::NOT_FOUND, Body::empty())),
+    };
+
+    let mut body = request.into_body();
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(_) => return Ok(response(StatusCode::BAD_REQUEST, Body::empty())),
+        };
+        if bytes.len() + chunk.len() > MAX_REQUEST_BODY_SIZE {
+            return Ok(response(StatusCode::PAYLOAD_TOO_LARGE, Body::empty()));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let body = match String::from_utf8(bytes) {
+        Ok(body) => body,
+        Err(_) => return Ok(response(StatusCode::BAD_REQUEST, Body::empty())),
+    };
+
+    let Some(io) = routes.handler(wallet_name.as_deref()).await else {
+        let reply = wallet_not_found(&body);
+        return Ok(response(StatusCode::NOT_FOUND, Body::from(reply)));
+    };
+
+    // Notifications get no reply
+    let reply = io.handle_request(&body).await.unwrap_or_default();
+    Ok(response(StatusCode::OK, Body::from(reply)))
+}
+
+// The wallet a request names, by its `/wallet/<name>` path or else its
+// `X-Wallet` header, failing for any other path
+fn requested_wallet(request: &Request<Body>) -> Result<Option<String>, ()> {
+    let path = request.uri().path();
+    if let Some(name) = path.strip_prefix(WALLET_PATH_PREFIX) {
+        return percent_decode(name).map(Some);
+    }
+    if path != "/" {
+        return Err(());
+    }
+    Ok(request
+        .headers()
+        .get(WALLET_HEADER)
+        .and_then(|name| name.to_str().ok())
+        .map(str::to_string))
+}
+
+// Decode the `%XX` escapes in a URL path segment
+fn percent_decode(segment: &str) -> Result<String, ()> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+        let hex = tail.get(..2).ok_or(())?;
+        let hex = std::str::from_utf8(hex).map_err(|_| ())?;
+        bytes.push(u8::from_str_radix(hex, 16).map_err(|_| ())?);
+        rest = &tail[2..];
+    }
+    String::from_utf8(bytes).map_err(|_| ())
+}
+
+// Error reply to a request for a wallet that isn't loaded
+fn wallet_not_found(body: &str) -> String {
+    let id = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|request| request.get("id").cloned())
+        .unwrap_or(Value::Null);
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": {
+            "code": RPC_WALLET_NOT_FOUND,
+            "message": "Requested wallet does not exist or is not loaded",
+        },
+        "id": id,
+    })
+    .to_string()
+}
+
+fn response(status: StatusCode, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::config::{test_config, RateLimitConfig};
+    use crate::wallet::CreateWalletOptions;
+    use bitcoin::Network;
+    use reqwest::StatusCode as HttpStatus;
+    use std::path::{Path, PathBuf};
+
+    const NODE_SOCKET: &str = "/nonexistent/node.sock";
+
+    // Wallet database created at `dir`/`name`.dat
+    async fn create_wallet(dir: &Path, name: &str) -> Arc<WalletInterface> {
+        let wallet = WalletInterface::new(
+            &dir.join(format!("{}.dat", name)),
+            NODE_SOCKET,
+            Network::Regtest,
+        )
+        .await
+        .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+        wallet
+    }
+
+    // Empty directory unique to `name`
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Serve stub methods, with `getwalletname` naming the wallet routed to
+    async fn start_server(
+        config: RpcConfig,
+        manager: Arc<RwLock<WalletManager>>,
+        default_wallet: Arc<WalletInterface>,
+    ) -> (String, oneshot::Sender<()>) {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let address = SocketAddr::from(([127, 0, 0, 1], port));
+
+        let routes = WalletRoutes::new(manager, default_wallet, |wallet, _| {
+            let mut io = IoHandler::new();
+            io.add_sync_method("getblockcount", |_| Ok(Value::from(101)));
+            io.add_sync_method("getwalletname", move |_| {
+                Ok(Value::from(wallet.wallet_name()))
+            });
+            io
+        });
+        let (shutdown, receiver) = oneshot::channel();
+        serve(address, routes, config, receiver).unwrap();
+        (format!("http://{}", address), shutdown)
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_credentials() {
+        let config = test_config();
+        let dir = test_dir("server-credentials-test");
+        let wallet = WalletInterface::new(&dir.join("wallet.dat"), NODE_SOCKET, Network::Regtest)
+            .await
+            .unwrap();
+        let manager = Arc::new(RwLock::new(WalletManager::with_wallet(wallet.clone())));
+        let (url, _shutdown) = start_server(config, manager, wallet).await;
+        let url = format!("{}/", url);
+        let client = reqwest::Client::new();
+        let request = r#"{"jsonrpc": "2.0", "id": 1, "method": "getblockcount"}"#;
+
+        let response = client.post(&url).body(request).send().await.unwrap();
+        assert_eq!(response.status(), HttpStatus::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()["www-authenticate"],
+            "Basic realm=\"jsonrpc\""
+        );
+
+        let response = client
+            .post(&url)
+            .basic_auth("alice", Some("hunter3"))
+            .body(request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::UNAUTHORIZED);
+
+        let response = client
+            .post(&url)
+            .basic_auth("alice", Some("hunter2"))
+            .body(request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::OK);
+        let reply: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+        assert_eq!(reply["result"], 101);
+
+        let response = client
+            .get(&url)
+            .basic_auth("alice", Some("hunter2"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_no_credentials_configured() {
+        // With nothing configured nobody gets in, rather than everybody
+        let dir = test_dir("server-no-credentials-test");
+        let wallet = WalletInterface::new(&dir.join("wallet.dat"), NODE_SOCKET, Network::Regtest)
+            .await
+            .unwrap();
+        let manager = Arc::new(RwLock::new(WalletManager::with_wallet(wallet.clone())));
+        let (url, _shutdown) = start_server(RpcConfig::default(), manager, wallet).await;
+        let request = r#"{"jsonrpc": "2.0", "id": 1, "method": "getblockcount"}"#;
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/", url))
+            .body(request)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), HttpStatus::UNAUTHORIZED);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_requests_rate_limited() {
+        let mut config = test_config();
+        // One request back every 40 seconds
+        config.rate_limit = Some(RateLimitConfig {
+            burst: 3,
+            rate: 0.025,
+        });
+        let dir = test_dir("server-rate-limit-test");
+        let wallet = WalletInterface::new(&dir.join("wallet.dat"), NODE_SOCKET, Network::Regtest)
+            .await
+            .unwrap();
+        let manager = Arc::new(RwLock::new(WalletManager::with_wallet(wallet.clone())));
+        let (url, _shutdown) = start_server(config, manager, wallet).await;
+        let url = format!("{}/", url);
+        let client = reqwest::Client::new();
+        let request = r#"{"jsonrpc": "2.0", "id": 1, "method": "getblockcount"}"#;
+
+        let post = || {
+            client
+                .post(&url)
+                .basic_auth("alice", Some("hunter2"))
+                .body(request)
+                .send()
+        };
+        for _ in 0..3 {
+            let response = post().await.unwrap();
+            assert_eq!(response.status(), HttpStatus::OK);
+        }
+        let response = post().await.unwrap();
+        assert_eq!(response.status(), HttpStatus::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()["retry-after"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((39..=40).contains(&retry_after));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_requests_routed_by_wallet() {
+        let dir = test_dir("server-routing-test");
+        let alice = create_wallet(&dir, "alice").await;
+        create_wallet(&dir, "bob").await;
+        let manager = Arc::new(RwLock::new(WalletManager::new(
+            &dir,
+            NODE_SOCKET,
+            Network::Regtest,
+        )));
+        WalletManager::load_wallet_by_name(&manager, "alice")
+            .await
+            .unwrap();
+        WalletManager::load_wallet_by_name(&manager, "bob")
+            .await
+            .unwrap();
+        let (url, _shutdown) = start_server(test_config(), manager.clone(), alice).await;
+        let client = reqwest::Client::new();
+        let request = r#"{"jsonrpc": "2.0", "id": 7, "method": "getwalletname"}"#;
+
+        let post = |path: &str, wallet: Option<&str>| {
+            let mut builder = client
+                .post(format!("{}{}", url, path))
+                .basic_auth("alice", Some("hunter2"))
+                .body(request);
+            if let Some(wallet) = wallet {
+                builder = builder.header("X-Wallet", wallet);
+            }
+            async move {
+                let response = builder.send().await.unwrap();
+                let status = response.status();
+                let text = response.text().await.unwrap();
+                (
+                    status,
+                    serde_json::from_str::<Value>(&text).unwrap_or_default(),
+                )
+            }
+        };
+
+        let (status, reply) = post("/wallet/bob", None).await;
+        assert_eq!(status, HttpStatus::OK);
+        assert_eq!(reply["result"], "bob");
+        let (_, reply) = post("/wallet/b%6Fb", Some("alice")).await;
+        assert_eq!(reply["result"], "bob");
+        let (_, reply) = post("/", Some("bob")).await;
+        assert_eq!(reply["result"], "bob");
+        let (_, reply) = post("/", None).await;
+        assert_eq!(reply["result"], "alice");
+
+        let (status, reply) = post("/wallet/carol", None).await;
+        assert_eq!(status, HttpStatus::NOT_FOUND);
+        assert_eq!(reply["error"]["code"], RPC_WALLET_NOT_FOUND);
+        assert_eq!(reply["id"], 7);
+        let (status, _) = post("/wallets/bob", None).await;
+        assert_eq!(status, HttpStatus::NOT_FOUND);
+
+        // With only bob left, requests naming no wallet go to bob
+        manager.write().await.unload_wallet("alice").unwrap();
+        let (_, reply) = post("/", Some("alice")).await;
+        assert_eq!(reply["error"]["code"], RPC_WALLET_NOT_FOUND);
+        let (_, reply) = post("/", None).await;
+        assert_eq!(reply["result"], "bob");
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
@@ -1,3 +1,4 @@
+use blocktalk::{FeeHistogramBucket, MempoolInfo, NetworkInfo};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -17,3 +18,66 @@ pub struct WalletInfoResponse {
     pub scanning: bool,
     pub descriptors: bool,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MempoolInfoResponse {
+    pub loaded: bool,
+    pub size: u64,
+    pub bytes: u64,
+    pub usage: u64,
+    pub mempoolminfee: f64,
+    pub minrelaytxfee: f64,
+    /// Virtual size of the mempool paying each range of fee rates, lowest first
+    pub fee_histogram: Vec<FeeHistogramBucketResponse>,
+}
+
+impl MempoolInfoResponse {
+    pub fn new(info: MempoolInfo, fee_histogram: Vec<FeeHistogramBucket>) -> Self {
+        Self {
+            loaded: true,
+            size: info.size,
+            bytes: info.bytes,
+            usage: info.usage,
+            mempoolminfee: info.mempoolminfee,
+            minrelaytxfee: info.minrelaytxfee,
+            fee_histogram: fee_histogram
+                .into_iter()
+                .map(FeeHistogramBucketResponse::from)
+                .collect(),
+        }
+    }
+}
+
+/// Fee rates in sat/vB
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeeHistogramBucketResponse {
+    pub min_feerate: f64,
+    pub max_feerate: f64,
+    pub vsize: u64,
+}
+
+impl From<FeeHistogramBucket> for FeeHistogramBucketResponse {
+    fn from(bucket: FeeHistogramBucket) -> Self {
+        Self {
+            min_feerate: bucket.min_feerate,
+            max_feerate: bucket.max_feerate,
+            vsize: bucket.vsize,
+        }
+    }
+}
+
+/// The parts of Bitcoin Core's `getnetworkinfo` the node exposes over IPC
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetworkInfoResponse {
+    pub relayfee: f64,
+    pub incrementalfee: f64,
+}
+
+impl From<NetworkInfo> for NetworkInfoResponse {
+    fn from(info: NetworkInfo) -> Self {
+        Self {
+            relayfee: info.relay_fee,
+            incrementalfee: info.incremental_fee,
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! WebSocket endpoint streaming chain notifications to clients
+//!
+//! Every notification is sent as a JSON object such as
+//! `{"type": "blockconnected", "data": {"hash": "...", "height": 101, "hex": "..."}}`,
+//! where `hex` is the consensus-encoded block or transaction.
+//! Clients receive every type of notification until they send a subscribe
+//! message, `{"subscribe": ["blockconnected", "blockdisconnected"]}`, which
+//! replaces the types they receive.
+//!
+//! Clients are checked against the same credentials and allowed IPs as
+//! JSON-RPC ones when they connect.
+
+use blocktalk::{ChainNotification, NotificationFilter};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::{self, Message};
+
+use super::auth::{authorized, ip_allowed};
+use super::config::RpcConfig;
+
+/// Path WebSocket clients connect to
+pub const WEBSOCKET_PATH: &str = "/ws";
+
+// Names of the notification types in events and subscribe messages
+const NOTIFICATION_TYPES: [(&str, NotificationFilter); 6] = [
+    ("blockconnected", NotificationFilter::BLOCK_CONNECTED),
+    ("blockdisconnected", NotificationFilter::BLOCK_DISCONNECTED),
+    ("transactionaddedtomempool", NotificationFilter::MEMPOOL_ADD),
+    (
+        "transactionremovedfrommempool",
+        NotificationFilter::MEMPOOL_REMOVE,
+    ),
+    ("updatedblocktip", NotificationFilter::TIP_UPDATED),
+    ("chainstateflushed", NotificationFilter::CHAIN_STATE_FLUSHED),
+];
+
+/// Accept WebSocket clients on `listener`, streaming each of them the
+/// notifications sent to `notifications` after it connects
+pub(crate) async fn serve(
+    listener: TcpListener,
+    notifications: broadcast::Receiver<ChainNotification>,
+    config: Arc<RpcConfig>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let notifications = notifications.resubscribe();
+                let config = config.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_client(stream, peer, notifications, &config).await {
+                        log::debug!("WebSocket client {} disconnected: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => log::error!("Failed to accept WebSocket connection: {}", e),
+        }
+    }
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    peer: SocketAddr,
+    mut notifications: broadcast::Receiver<ChainNotification>,
+    config: &RpcConfig,
+) -> Result<(), tungstenite::Error> {
+    let mut ws = tokio_tungstenite::accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let authorization = request
+                .headers()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok());
+            let status = if !ip_allowed(peer.ip(), &config.allow_ips) {
+                StatusCode::FORBIDDEN
+            } else if !authorized(authorization, &config.auth) {
+                StatusCode::UNAUTHORIZED
+            } else if request.uri().path() != WEBSOCKET_PATH {
+                StatusCode::NOT_FOUND
+            } else {
+                return Ok(response);
+            };
+            let mut rejection = ErrorResponse::new(None);
+            *rejection.status_mut() = status;
+            Err(rejection)
+        },
+    )
+    .await?;
+
+    let mut filter = NotificationFilter::ALL;
+    loop {
+        tokio::select! {
+            message = ws.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match parse_subscribe(&text) {
+                        Ok((names, new_filter)) => {
+                            filter = new_filter;
+                            json!({ "subscribed": names })
+                        }
+                        Err(e) => json!({ "error": e }),
+                    };
+                    ws.send(Message::Text(reply.to_string())).await?;
+                }
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                // Pings are answered by tungstenite itself
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+            notification = notifications.recv() => match notification {
+                Ok(notification) if filter.matches(&notification) => {
+                    let event = notification_to_json(&notification);
+                    ws.send(Message::Text(event.to_string())).await?;
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!(
+                        "WebSocket client fell behind, {} notifications dropped",
+                        missed
+                    );
+                }
+                Err(RecvError::Closed) => return ws.close(None).await,
+            },
+        }
+    }
+}
+
+// The notification types named by a subscribe message, and the filter they make up
+fn parse_subscribe(text: &str) -> Result<(Vec<String>, NotificationFilter), String> {
+    let message: Value =
+        serde_json::from_str(text).map_err(|e| format!("Invalid message: {}", e))?;
+    let names = message
+        .get("subscribe")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "Expected a subscribe message".to_string())?;
+
+    let mut subscribed = Vec::new();
+    let mut filter = NotificationFilter::NONE;
+    for name in names {
+        let name = name.as_str().unwrap_or_default();
+        let (name, kind) = NOTIFICATION_TYPES
+            .iter()
+            .find(|(type_name, _)| *type_name == name)
+            .ok_or_else(|| format!("Unknown notification type: {}", name))?;
+        subscribed.push(name.to_string());
+        filter = filter | *kind;
+    }
+    Ok((subscribed, filter))
+}
+
+// Render a notification as blocktalk serializes it, adding the hash and
+// height of connected blocks and the txid of mempool additions
+fn notification_to_json(notification: &ChainNotification) -> Value {
+    let mut event = serde_json::to_value(notification).unwrap_or_else(|_| json!({}));
+    match notification {
+        ChainNotification::BlockConnected(block) => {
+            event["data"]["hash"] = json!(block.block_hash().to_string());
+            // Blocks carry their height in the coinbase since BIP34
+            event["data"]["height"] = json!(block.bip34_block_height().ok());
+        }
+        ChainNotification::TransactionAddedToMempool(tx) => {
+            event["data"]["txid"] = json!(tx.compute_txid().to_string());
+        }
+        _ => {}
+    }
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::config::test_config;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Block, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, Witness};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    // Block at `height` on top of the regtest genesis block
+    fn mined_block(height: i64) -> Block {
+        let mut block = genesis_block(Network::Regtest);
+        block.header.prev_blockhash = block.block_hash();
+        block.header.version = bitcoin::block::Version::TWO;
+        block.txdata[0] = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::builder().push_int(height).into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: block.txdata[0].output.clone(),
+        };
+        block
+    }
+
+    async fn start_server() -> (String, broadcast::Sender<ChainNotification>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}{}", listener.local_addr().unwrap(), WEBSOCKET_PATH);
+        let (sender, receiver) = broadcast::channel(16);
+        tokio::spawn(serve(listener, receiver, Arc::new(test_config())));
+        (url, sender)
+    }
+
+    // Connect as alice, the user `test_config` lets in
+    async fn connect(
+        url: &str,
+    ) -> Result<
+        tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>,
+        tungstenite::Error,
+    > {
+        let mut request = url.into_client_request()?;
+        // "alice:hunter2"
+        request.headers_mut().insert(
+            "authorization",
+            "Basic YWxpY2U6aHVudGVyMg==".parse().unwrap(),
+        );
+        let (ws, _) = tokio_tungstenite::connect_async(request).await?;
+        Ok(ws)
+    }
+
+    async fn next_json<S>(ws: &mut S) -> Value
+    where
+        S: futures::Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+    {
+        match ws.next().await.unwrap().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("Expected a text message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_receives_block_notifications() {
+        let (url, sender) = start_server().await;
+        let mut ws = connect(&url).await.unwrap();
+
+        let block = mined_block(101);
+        sender
+            .send(ChainNotification::BlockConnected(block.clone()))
+            .unwrap();
+        assert_eq!(
+            next_json(&mut ws).await,
+            json!({
+                "type": "blockconnected",
+                "data": {
+                    "hash": block.block_hash().to_string(),
+                    "height": 101,
+                    "hex": serialize_hex(&block),
+                },
+            })
+        );
+
+        sender
+            .send(ChainNotification::BlockDisconnected(block.block_hash()))
+            .unwrap();
+        assert_eq!(
+            next_json(&mut ws).await,
+            json!({
+                "type": "blockdisconnected",
+                "data": { "hash": block.block_hash().to_string() },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_filters_notifications() {
+        let (url, sender) = start_server().await;
+        let mut ws = connect(&url).await.unwrap();
+
+        ws.send(Message::Text(
+            json!({ "subscribe": ["transactionaddedtomempool"] }).to_string(),
+        ))
+        .await
+        .unwrap();
+        assert_eq!(
+            next_json(&mut ws).await,
+            json!({ "subscribed": ["transactionaddedtomempool"] })
+        );
+
+        let block = mined_block(102);
+        let tx = block.txdata[0].clone();
+        sender
+            .send(ChainNotification::BlockConnected(block))
+            .unwrap();
+        sender
+            .send(ChainNotification::TransactionAddedToMempool(tx.clone()))
+            .unwrap();
+        assert_eq!(
+            next_json(&mut ws).await,
+            json!({
+                "type": "transactionaddedtomempool",
+                "data": {
+                    "txid": tx.compute_txid().to_string(),
+                    "hex": serialize_hex(&tx),
+                },
+            })
+        );
+
+        ws.send(Message::Text(
+            json!({ "subscribe": ["blocks"] }).to_string(),
+        ))
+        .await
+        .unwrap();
+        assert_eq!(
+            next_json(&mut ws).await,
+            json!({ "error": "Unknown notification type: blocks" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_other_paths_are_rejected() {
+        let (url, _sender) = start_server().await;
+        let url = url.replace(WEBSOCKET_PATH, "/other");
+        assert!(connect(&url).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_are_required() {
+        let (url, _sender) = start_server().await;
+        assert!(tokio_tungstenite::connect_async(&url).await.is_err());
+        assert!(connect(&url).await.is_ok());
+    }
+}
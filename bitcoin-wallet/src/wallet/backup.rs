@@ -0,0 +1,176 @@
+//! Wallet backups, written as JSON files that can restore a wallet into a new database
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::WalletError;
+
+/// Version of the backup format written by `write_backup`
+pub(crate) const BACKUP_VERSION: u32 = 1;
+
+// Key of the checksum HMAC. The checksum catches corrupted or hand-edited
+// backups, but anyone can recompute it, so it says nothing about who made one.
+const CHECKSUM_KEY: &[u8] = b"bitcoin-wallet backup";
+
+/// Everything needed to rebuild a wallet and its metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct WalletBackup {
+    pub version: u32,
+    pub network: String,
+    pub external_descriptor: String,
+    pub internal_descriptor: Option<String>,
+    /// Highest derivation index handed out from each descriptor
+    pub last_external_index: Option<u32>,
+    pub last_internal_index: Option<u32>,
+    pub settings: BTreeMap<String, String>,
+    pub labels: Vec<LabelBackup>,
+    pub transactions: Vec<TransactionBackup>,
+    /// Locked outputs as `txid:vout`
+    pub locked_outputs: Vec<String>,
+    pub secrets: Vec<SecretBackup>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LabelBackup {
+    pub address: String,
+    pub label: String,
+    pub purpose: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TransactionBackup {
+    pub txid: String,
+    pub time_received: u64,
+    pub block_height: Option<u32>,
+    pub fee: Option<u64>,
+    pub comment: String,
+    pub label: String,
+    // Left out when false, so backups written before the field existed keep
+    // their checksum
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub abandoned: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// A stored secret, hex encoded and still encrypted if it was in the wallet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SecretBackup {
+    pub name: String,
+    pub secret: String,
+    pub encrypted: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupFile {
+    #[serde(flatten)]
+    backup: WalletBackup,
+    checksum: String,
+}
+
+/// Write a backup to `path` with its checksum
+pub(crate) fn write_backup(path: &Path, backup: &WalletBackup) -> Result<(), WalletError> {
+    let file = BackupFile {
+        backup: backup.clone(),
+        checksum: checksum(backup)?,
+    };
+    let json = serde_json::to_string_pretty(&file)
+        .map_err(|e| WalletError::Generic(format!("Failed to serialize backup: {}", e)))?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Read a backup from `path`, checking its checksum and version
+pub(crate) fn read_backup(path: &Path) -> Result<WalletBackup, WalletError> {
+    let json = std::fs::read_to_string(path)?;
+    let file: BackupFile = serde_json::from_str(&json)
+        .map_err(|e| WalletError::Generic(format!("Invalid wallet backup: {}", e)))?;
+
+    if file.backup.version > BACKUP_VERSION {
+        return Err(WalletError::Generic(format!(
+            "Wallet backup version {} is not supported",
+            file.backup.version
+        )));
+    }
+    if checksum(&file.backup)? != file.checksum {
+        return Err(WalletError::Generic(
+            "Wallet backup checksum does not match, the file is corrupted".to_string(),
+        ));
+    }
+    Ok(file.backup)
+}
+
+// HMAC-SHA256 of the backup's JSON, which serializes the same way every time
+fn checksum(backup: &WalletBackup) -> Result<String, WalletError> {
+    let json = serde_json::to_vec(backup)
+        .map_err(|e| WalletError::Generic(format!("Failed to serialize backup: {}", e)))?;
+    let mut engine = HmacEngine::<sha256::Hash>::new(CHECKSUM_KEY);
+    engine.input(&json);
+    Ok(Hmac::<sha256::Hash>::from_engine(engine).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup() -> WalletBackup {
+        WalletBackup {
+            version: BACKUP_VERSION,
+            network: "regtest".to_string(),
+            external_descriptor: "wpkh(tpub/0/*)".to_string(),
+            internal_descriptor: None,
+            last_external_index: Some(2),
+            last_internal_index: None,
+            settings: BTreeMap::from([("avoid_reuse".to_string(), "false".to_string())]),
+            labels: vec![],
+            transactions: vec![],
+            locked_outputs: vec![],
+            secrets: vec![],
+        }
+    }
+
+    #[test]
+    fn test_backup_checksum_detects_changes() {
+        let path =
+            std::env::temp_dir().join(format!("backup-checksum-{}.json", std::process::id()));
+        write_backup(&path, &backup()).unwrap();
+        assert_eq!(read_backup(&path).unwrap(), backup());
+
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, json.replace("wpkh(tpub/0/*)", "wpkh(tpub/1/*)")).unwrap();
+        assert!(matches!(read_backup(&path), Err(WalletError::Generic(_))));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_abandoned_only_written_when_set() {
+        let path =
+            std::env::temp_dir().join(format!("backup-abandoned-{}.json", std::process::id()));
+        let transaction = |abandoned| TransactionBackup {
+            txid: "00".repeat(32),
+            time_received: 1_700_000_000,
+            block_height: None,
+            fee: None,
+            comment: String::new(),
+            label: String::new(),
+            abandoned,
+        };
+        let mut wallet = backup();
+        wallet.transactions = vec![transaction(false)];
+        write_backup(&path, &wallet).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(!json.contains("abandoned"));
+
+        wallet.transactions = vec![transaction(true)];
+        write_backup(&path, &wallet).unwrap();
+        assert_eq!(read_backup(&path).unwrap(), wallet);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,74 @@
+//! Coin selection honouring coin control
+
+use bdk_wallet::coin_selection::{
+    CoinSelectionAlgorithm, CoinSelectionResult, DefaultCoinSelectionAlgorithm, InsufficientFunds,
+};
+use bdk_wallet::WeightedUtxo;
+use bitcoin::{Amount, FeeRate, Script};
+use rand::RngCore;
+
+use super::types::CoinControlOptions;
+
+/// BDK's default coin selection run over the UTXOs coin control allows
+///
+/// Outputs in `must_not_use` are dropped from the candidates and outputs in
+/// `must_use` are moved to the required set before branch-and-bound picks
+/// whatever else is needed to cover the target.
+#[derive(Debug, Default)]
+pub struct CoinControlSelection {
+    coin_control: CoinControlOptions,
+    inner: DefaultCoinSelectionAlgorithm,
+}
+
+impl CoinControlSelection {
+    pub fn new(coin_control: CoinControlOptions) -> Self {
+        Self {
+            coin_control,
+            inner: DefaultCoinSelectionAlgorithm::default(),
+        }
+    }
+}
+
+impl CoinSelectionAlgorithm for CoinControlSelection {
+    fn coin_select<R: RngCore>(
+        &self,
+        required_utxos: Vec<WeightedUtxo>,
+        optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        target_amount: Amount,
+        drain_script: &Script,
+        rand: &mut R,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        let (required_utxos, optional_utxos) =
+            apply_coin_control(&self.coin_control, required_utxos, optional_utxos);
+        self.inner.coin_select(
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script,
+            rand,
+        )
+    }
+}
+
+// Split the candidate UTXOs into those that must be spent and those that may be
+fn apply_coin_control(
+    coin_control: &CoinControlOptions,
+    mut required_utxos: Vec<WeightedUtxo>,
+    optional_utxos: Vec<WeightedUtxo>,
+) -> (Vec<WeightedUtxo>, Vec<WeightedUtxo>) {
+    let mut remaining = Vec::with_capacity(optional_utxos.len());
+    for utxo in optional_utxos {
+        let outpoint = utxo.utxo.outpoint();
+        if coin_control.must_not_use.contains(&outpoint) {
+            continue;
+        }
+        if coin_control.must_use.contains(&outpoint) {
+            required_utxos.push(utxo);
+        } else {
+            remaining.push(utxo);
+        }
+    }
+    (required_utxos, remaining)
+}
@@ -1,15 +1,144 @@
-use bdk_wallet::rusqlite;
+use bdk_wallet::rusqlite::{self, params, OptionalExtension};
 use bdk_wallet::KeychainKind;
 use bdk_wallet::{PersistedWallet, Wallet};
-use bitcoin::Network;
+use bitcoin::{Amount, Network, OutPoint, Txid};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Mutex;
 
+use super::types::TransactionMetadata;
 use crate::error::WalletError;
 
+// Descriptors of wallets created before generated descriptors were stored
 const EXTERNAL_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdJuLWWArdBsWjqDA3W5WoREnfdgKEcCQB1FMKfSoaFz9JHZU71HwXAqTsjHripkLM62kUQar14SDD8brsmhFKqVUPXGrZLc/86'/1'/0'/0/*)#fv8tutn2";
 const INTERNAL_DESCRIPTOR: &str = "tr(tprv8ZgxMBicQKsPdJuLWWArdBsWjqDA3W5WoREnfdgKEcCQB1FMKfSoaFz9JHZU71HwXAqTsjHripkLM62kUQar14SDD8brsmhFKqVUPXGrZLc/86'/1'/0'/1/*)#ccz2p7rj";
 
+// Wallet-specific data BDK does not track, kept alongside BDK's own tables
+const CREATE_TX_METADATA_TABLE: &str = "CREATE TABLE IF NOT EXISTS wallet_tx_metadata (
+    txid TEXT PRIMARY KEY NOT NULL,
+    time_received INTEGER NOT NULL,
+    block_height INTEGER,
+    fee INTEGER,
+    comment TEXT NOT NULL DEFAULT '',
+    label TEXT NOT NULL DEFAULT ''
+)";
+
+// Per-wallet preferences, stored as name/value pairs
+const CREATE_SETTINGS_TABLE: &str = "CREATE TABLE IF NOT EXISTS wallet_settings (
+    name TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+)";
+
+// Descriptors imported into blank wallets. BDK only persists the public
+// descriptors, so any private keys are kept here to sign with after loading.
+const CREATE_DESCRIPTORS_TABLE: &str = "CREATE TABLE IF NOT EXISTS wallet_descriptors (
+    keychain TEXT PRIMARY KEY NOT NULL,
+    descriptor TEXT NOT NULL,
+    timestamp INTEGER
+)";
+
+// Outputs excluded from coin selection by `lockunspent`
+const CREATE_LOCKED_UTXOS_TABLE: &str = "CREATE TABLE IF NOT EXISTS locked_utxos (
+    txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    PRIMARY KEY (txid, vout)
+)";
+
+// Address labels, with Bitcoin Core's purpose of "receive" for our own
+// addresses and "send" for anyone else's
+const CREATE_LABELS_TABLE: &str = "CREATE TABLE IF NOT EXISTS wallet_labels (
+    address TEXT PRIMARY KEY NOT NULL,
+    label TEXT NOT NULL,
+    purpose TEXT NOT NULL
+)";
+
+// Secrets such as the wallet's mnemonic, encrypted when created with a mnemonic passphrase.
+// The private descriptors in `wallet_descriptors` are never encrypted.
+const CREATE_SECRETS_TABLE: &str = "CREATE TABLE IF NOT EXISTS wallet_secrets (
+    name TEXT PRIMARY KEY NOT NULL,
+    secret BLOB NOT NULL,
+    encrypted INTEGER NOT NULL
+)";
+
+// A schema change, named for the error message if it fails. Migrations must be
+// idempotent, since databases created before versioning already have some of
+// their tables.
+type Migration = (
+    &'static str,
+    fn(&rusqlite::Connection) -> rusqlite::Result<()>,
+);
+
+// Schema changes in the order they were made. A database's `user_version` is
+// the number of them it has had applied, so new ones go at the end and existing
+// ones are never changed.
+const MIGRATIONS: &[Migration] = &[
+    ("create wallet_tx_metadata", |conn| {
+        conn.execute(CREATE_TX_METADATA_TABLE, []).map(drop)
+    }),
+    ("create wallet_settings", |conn| {
+        conn.execute(CREATE_SETTINGS_TABLE, []).map(drop)
+    }),
+    ("create wallet_descriptors", |conn| {
+        conn.execute(CREATE_DESCRIPTORS_TABLE, []).map(drop)
+    }),
+    ("create locked_utxos", |conn| {
+        conn.execute(CREATE_LOCKED_UTXOS_TABLE, []).map(drop)
+    }),
+    ("create wallet_labels", |conn| {
+        conn.execute(CREATE_LABELS_TABLE, []).map(drop)
+    }),
+    ("create wallet_secrets", |conn| {
+        conn.execute(CREATE_SECRETS_TABLE, []).map(drop)
+    }),
+    ("add abandoned to wallet_tx_metadata", |conn| {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('wallet_tx_metadata')
+                WHERE name = 'abandoned'",
+            [],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            conn.execute(
+                "ALTER TABLE wallet_tx_metadata
+                    ADD COLUMN abandoned INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        Ok(())
+    }),
+];
+
+/// Bring the wallet's own tables up to date, recording the schema version in
+/// `PRAGMA user_version`
+///
+/// Each migration is applied in a transaction together with the version it
+/// brings the database to. Databases from a newer version of the wallet are
+/// refused rather than opened with a schema we do not know.
+pub fn migrate(conn: &rusqlite::Connection) -> Result<(), WalletError> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to read schema version: {}", e)))?;
+    if version > MIGRATIONS.len() {
+        return Err(WalletError::DatabaseError(format!(
+            "Database schema version {} is newer than the latest supported version {}",
+            version,
+            MIGRATIONS.len()
+        )));
+    }
+
+    for (index, (name, apply)) in MIGRATIONS.iter().enumerate().skip(version) {
+        let migration_error =
+            |e: rusqlite::Error| WalletError::DatabaseError(format!("Failed to {}: {}", name, e));
+        let tx = conn.unchecked_transaction().map_err(migration_error)?;
+        apply(&tx).map_err(migration_error)?;
+        tx.pragma_update(None, "user_version", index + 1)
+            .map_err(migration_error)?;
+        tx.commit().map_err(migration_error)?;
+        log::debug!("Applied database migration {}: {}", index + 1, name);
+    }
+    Ok(())
+}
+
 // Define ThreadSafeWallet as a Mutex-wrapped PersistedWallet
 pub type ThreadSafeWallet = Mutex<PersistedWallet<rusqlite::Connection>>;
 
@@ -27,8 +156,10 @@ impl WalletDatabase {
     }
 
     pub fn open_connection(&self) -> Result<rusqlite::Connection, WalletError> {
-        rusqlite::Connection::open(&self.db_path)
-            .map_err(|e| WalletError::DatabaseError(format!("Failed to open database: {}", e)))
+        let conn = rusqlite::Connection::open(&self.db_path)
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to open database: {}", e)))?;
+        migrate(&conn)?;
+        Ok(conn)
     }
 
     pub fn exists(&self) -> bool {
@@ -36,11 +167,12 @@ impl WalletDatabase {
     }
 
     pub fn load_wallet(&self, network: Network) -> Result<ThreadSafeWallet, WalletError> {
+        let (external, internal) = self.wallet_descriptors()?;
         let mut conn = self.open_connection()?;
 
         let persisted = Wallet::load()
-            .descriptor(KeychainKind::External, Some(EXTERNAL_DESCRIPTOR))
-            .descriptor(KeychainKind::Internal, Some(INTERNAL_DESCRIPTOR))
+            .descriptor(KeychainKind::External, Some(external))
+            .descriptor(KeychainKind::Internal, internal)
             .extract_keys()
             .check_network(network)
             .load_wallet(&mut conn)
@@ -61,11 +193,493 @@ impl WalletDatabase {
         network: Network,
     ) -> Result<ThreadSafeWallet, WalletError> {
         let mut conn = self.open_connection()?;
-        let persisted = Wallet::create(EXTERNAL_DESCRIPTOR, INTERNAL_DESCRIPTOR)
+        let persisted = Wallet::create(external_descriptor, internal_descriptor)
+            .network(network)
+            .create_wallet(&mut conn)
+            .map_err(|e| WalletError::Generic(format!("Failed to create wallet: {}", e)))?;
+
+        Ok(Mutex::new(persisted))
+    }
+
+    /// Create a wallet from imported descriptors, with or without a change descriptor
+    pub fn create_wallet_from_descriptors(
+        &self,
+        external_descriptor: String,
+        internal_descriptor: Option<String>,
+        network: Network,
+    ) -> Result<ThreadSafeWallet, WalletError> {
+        if let Some(internal_descriptor) = internal_descriptor {
+            return self.create_wallet(external_descriptor, internal_descriptor, network);
+        }
+        let mut conn = self.open_connection()?;
+        let persisted = Wallet::create_single(external_descriptor)
             .network(network)
             .create_wallet(&mut conn)
             .map_err(|e| WalletError::Generic(format!("Failed to create wallet: {}", e)))?;
 
         Ok(Mutex::new(persisted))
     }
+
+    /// Write the wallet's unsaved chain and transaction changes to the database
+    pub fn persist_wallet(
+        &self,
+        wallet: &mut PersistedWallet<rusqlite::Connection>,
+    ) -> Result<(), WalletError> {
+        let mut conn = self.open_connection()?;
+        wallet
+            .persist(&mut conn)
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to persist wallet: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn store_descriptor(
+        &self,
+        keychain: KeychainKind,
+        descriptor: &str,
+        timestamp: Option<u64>,
+    ) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO wallet_descriptors (keychain, descriptor, timestamp)
+                VALUES (?1, ?2, ?3)",
+            params![
+                keychain_name(keychain),
+                descriptor,
+                timestamp.map(|t| t as i64)
+            ],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to store descriptor: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn get_descriptor(&self, keychain: KeychainKind) -> Result<Option<String>, WalletError> {
+        let conn = self.open_connection()?;
+        conn.query_row(
+            "SELECT descriptor FROM wallet_descriptors WHERE keychain = ?1",
+            params![keychain_name(keychain)],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to read descriptor: {}", e)))
+    }
+
+    /// Every stored descriptor with its keychain and import timestamp, receive
+    /// descriptor first
+    pub fn list_descriptors(
+        &self,
+    ) -> Result<Vec<(KeychainKind, String, Option<u64>)>, WalletError> {
+        let conn = self.open_connection()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT keychain, descriptor, timestamp FROM wallet_descriptors ORDER BY keychain",
+            )
+            .map_err(|e| {
+                WalletError::DatabaseError(format!("Failed to list descriptors: {}", e))
+            })?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                ))
+            })
+            .map_err(|e| {
+                WalletError::DatabaseError(format!("Failed to list descriptors: {}", e))
+            })?;
+
+        let mut descriptors = Vec::new();
+        for row in rows {
+            let (keychain, descriptor, timestamp) = row.map_err(|e| {
+                WalletError::DatabaseError(format!("Failed to read descriptor: {}", e))
+            })?;
+            let keychain = match keychain.as_str() {
+                "external" => KeychainKind::External,
+                "internal" => KeychainKind::Internal,
+                other => {
+                    return Err(WalletError::DatabaseError(format!(
+                        "Unknown keychain {}",
+                        other
+                    )))
+                }
+            };
+            descriptors.push((keychain, descriptor, timestamp.map(|t| t as u64)));
+        }
+        Ok(descriptors)
+    }
+
+    /// The wallet's receive and change descriptors, with any private keys
+    /// Wallets built from imported descriptors may have no change descriptor.
+    pub fn wallet_descriptors(&self) -> Result<(String, Option<String>), WalletError> {
+        match self.get_descriptor(KeychainKind::External)? {
+            Some(external) => Ok((external, self.get_descriptor(KeychainKind::Internal)?)),
+            None => Ok((
+                EXTERNAL_DESCRIPTOR.to_string(),
+                Some(INTERNAL_DESCRIPTOR.to_string()),
+            )),
+        }
+    }
+
+    pub fn store_tx_metadata(
+        &self,
+        txid: &Txid,
+        metadata: &TransactionMetadata,
+    ) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO wallet_tx_metadata
+                (txid, time_received, block_height, fee, comment, label, abandoned)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                txid.to_string(),
+                metadata.timestamp as i64,
+                metadata.block_height,
+                metadata.fee.map(|fee| fee.to_sat() as i64),
+                metadata.comment,
+                metadata.label,
+                metadata.abandoned,
+            ],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to store metadata: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn get_tx_metadata(&self, txid: &Txid) -> Result<Option<TransactionMetadata>, WalletError> {
+        let conn = self.open_connection()?;
+        conn.query_row(
+            "SELECT time_received, block_height, fee, comment, label, abandoned
+                FROM wallet_tx_metadata WHERE txid = ?1",
+            params![txid.to_string()],
+            |row| {
+                Ok(TransactionMetadata {
+                    timestamp: row.get::<_, i64>(0)? as u64,
+                    block_height: row.get(1)?,
+                    fee: row
+                        .get::<_, Option<i64>>(2)?
+                        .map(|fee| Amount::from_sat(fee as u64)),
+                    comment: row.get(3)?,
+                    label: row.get(4)?,
+                    abandoned: row.get(5)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to read metadata: {}", e)))
+    }
+
+    /// Metadata of every transaction the wallet has recorded
+    pub fn list_tx_metadata(&self) -> Result<Vec<(Txid, TransactionMetadata)>, WalletError> {
+        let conn = self.open_connection()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT txid, time_received, block_height, fee, comment, label, abandoned
+                    FROM wallet_tx_metadata ORDER BY time_received, txid",
+            )
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read metadata: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    TransactionMetadata {
+                        timestamp: row.get::<_, i64>(1)? as u64,
+                        block_height: row.get(2)?,
+                        fee: row
+                            .get::<_, Option<i64>>(3)?
+                            .map(|fee| Amount::from_sat(fee as u64)),
+                        comment: row.get(4)?,
+                        label: row.get(5)?,
+                        abandoned: row.get(6)?,
+                    },
+                ))
+            })
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read metadata: {}", e)))?;
+
+        let mut metadata = Vec::new();
+        for row in rows {
+            let (txid, tx_metadata) = row.map_err(|e| {
+                WalletError::DatabaseError(format!("Failed to read metadata: {}", e))
+            })?;
+            let txid = Txid::from_str(&txid).map_err(|e| {
+                WalletError::DatabaseError(format!("Invalid txid in metadata: {}", e))
+            })?;
+            metadata.push((txid, tx_metadata));
+        }
+        Ok(metadata)
+    }
+
+    /// Clear the confirmation height of transactions confirmed at or above
+    /// `height`, returning how many there were
+    pub fn mark_unconfirmed_from(&self, height: u32) -> Result<usize, WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "UPDATE wallet_tx_metadata SET block_height = NULL WHERE block_height >= ?1",
+            params![height],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to update metadata: {}", e)))
+    }
+
+    pub fn store_setting(&self, name: &str, value: &str) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO wallet_settings (name, value) VALUES (?1, ?2)",
+            params![name, value],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to store setting: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn get_setting(&self, name: &str) -> Result<Option<String>, WalletError> {
+        let conn = self.open_connection()?;
+        conn.query_row(
+            "SELECT value FROM wallet_settings WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to read setting: {}", e)))
+    }
+
+    /// All settings as (name, value)
+    pub fn list_settings(&self) -> Result<Vec<(String, String)>, WalletError> {
+        let conn = self.open_connection()?;
+        let mut stmt = conn
+            .prepare("SELECT name, value FROM wallet_settings ORDER BY name")
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read settings: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read settings: {}", e)))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read setting: {}", e)))
+    }
+
+    pub fn store_secret(
+        &self,
+        name: &str,
+        secret: &[u8],
+        encrypted: bool,
+    ) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO wallet_secrets (name, secret, encrypted) VALUES (?1, ?2, ?3)",
+            params![name, secret, encrypted],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to store secret: {}", e)))?;
+        Ok(())
+    }
+
+    /// A stored secret and whether it is encrypted
+    pub fn get_secret(&self, name: &str) -> Result<Option<(Vec<u8>, bool)>, WalletError> {
+        let conn = self.open_connection()?;
+        conn.query_row(
+            "SELECT secret, encrypted FROM wallet_secrets WHERE name = ?1",
+            params![name],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to read secret: {}", e)))
+    }
+
+    /// All stored secrets as (name, secret, encrypted)
+    pub fn list_secrets(&self) -> Result<Vec<(String, Vec<u8>, bool)>, WalletError> {
+        let conn = self.open_connection()?;
+        let mut stmt = conn
+            .prepare("SELECT name, secret, encrypted FROM wallet_secrets ORDER BY name")
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read secrets: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read secrets: {}", e)))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read secret: {}", e)))
+    }
+
+    pub fn store_address_label(
+        &self,
+        address: &str,
+        label: &str,
+        purpose: &str,
+    ) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO wallet_labels (address, label, purpose) VALUES (?1, ?2, ?3)",
+            params![address, label, purpose],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to store label: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn get_address_label(&self, address: &str) -> Result<Option<String>, WalletError> {
+        let conn = self.open_connection()?;
+        conn.query_row(
+            "SELECT label FROM wallet_labels WHERE address = ?1",
+            params![address],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to read label: {}", e)))
+    }
+
+    /// All labelled addresses as (address, label, purpose)
+    pub fn list_address_labels(&self) -> Result<Vec<(String, String, String)>, WalletError> {
+        let conn = self.open_connection()?;
+        let mut stmt = conn
+            .prepare("SELECT address, label, purpose FROM wallet_labels ORDER BY address")
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read labels: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read labels: {}", e)))?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read label: {}", e)))
+    }
+
+    pub fn lock_outpoint(&self, outpoint: &OutPoint) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO locked_utxos (txid, vout) VALUES (?1, ?2)",
+            params![outpoint.txid.to_string(), outpoint.vout],
+        )
+        .map_err(|e| WalletError::DatabaseError(format!("Failed to lock output: {}", e)))?;
+        Ok(())
+    }
+
+    /// Unlock an output, returning whether it was locked
+    pub fn unlock_outpoint(&self, outpoint: &OutPoint) -> Result<bool, WalletError> {
+        let conn = self.open_connection()?;
+        let removed = conn
+            .execute(
+                "DELETE FROM locked_utxos WHERE txid = ?1 AND vout = ?2",
+                params![outpoint.txid.to_string(), outpoint.vout],
+            )
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to unlock output: {}", e)))?;
+        Ok(removed > 0)
+    }
+
+    pub fn unlock_all_outpoints(&self) -> Result<(), WalletError> {
+        let conn = self.open_connection()?;
+        conn.execute("DELETE FROM locked_utxos", [])
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to unlock outputs: {}", e)))?;
+        Ok(())
+    }
+
+    pub fn list_locked_outpoints(&self) -> Result<Vec<OutPoint>, WalletError> {
+        let conn = self.open_connection()?;
+        let mut stmt = conn
+            .prepare("SELECT txid, vout FROM locked_utxos ORDER BY txid, vout")
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read locks: {}", e)))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+            })
+            .map_err(|e| WalletError::DatabaseError(format!("Failed to read locks: {}", e)))?;
+
+        let mut outpoints = Vec::new();
+        for row in rows {
+            let (txid, vout) =
+                row.map_err(|e| WalletError::DatabaseError(format!("Failed to read lock: {}", e)))?;
+            let txid = Txid::from_str(&txid).map_err(|e| {
+                WalletError::DatabaseError(format!("Invalid txid in locked outputs: {}", e))
+            })?;
+            outpoints.push(OutPoint { txid, vout });
+        }
+        Ok(outpoints)
+    }
+}
+
+fn keychain_name(keychain: KeychainKind) -> &'static str {
+    match keychain {
+        KeychainKind::External => "external",
+        KeychainKind::Internal => "internal",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_survive_reopen() {
+        let path = std::env::temp_dir().join(format!("labels-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let database = WalletDatabase::new(path.clone());
+        database
+            .store_address_label("bcrt1qexample", "savings", "receive")
+            .unwrap();
+        database
+            .store_address_label("bcrt1qexample", "rent", "receive")
+            .unwrap();
+        drop(database);
+
+        let database = WalletDatabase::new(path.clone());
+        assert_eq!(
+            database.get_address_label("bcrt1qexample").unwrap(),
+            Some("rent".to_string())
+        );
+        assert_eq!(database.get_address_label("bcrt1qother").unwrap(), None);
+        assert_eq!(
+            database.list_address_labels().unwrap(),
+            vec![(
+                "bcrt1qexample".to_string(),
+                "rent".to_string(),
+                "receive".to_string()
+            )]
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn schema_version(conn: &rusqlite::Connection) -> usize {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrations_rerun_after_downgrade() {
+        let path =
+            std::env::temp_dir().join(format!("migrations-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let database = WalletDatabase::new(path.clone());
+        let conn = database.open_connection().unwrap();
+        assert_eq!(schema_version(&conn), MIGRATIONS.len());
+        database.store_setting("avoidreuse", "true").unwrap();
+
+        // Lose a table and go back to before it was created
+        let secrets_version = MIGRATIONS
+            .iter()
+            .position(|(name, _)| *name == "create wallet_secrets")
+            .unwrap();
+        conn.execute("DROP TABLE wallet_secrets", []).unwrap();
+        conn.pragma_update(None, "user_version", secrets_version)
+            .unwrap();
+        drop(conn);
+
+        let conn = database.open_connection().unwrap();
+        assert_eq!(schema_version(&conn), MIGRATIONS.len());
+        database.store_secret("mnemonic", b"words", false).unwrap();
+        assert_eq!(
+            database.get_secret("mnemonic").unwrap(),
+            Some((b"words".to_vec(), false))
+        );
+
+        // Rerunning every migration leaves existing tables and their rows alone
+        conn.pragma_update(None, "user_version", 0).unwrap();
+        drop(conn);
+        let conn = database.open_connection().unwrap();
+        assert_eq!(schema_version(&conn), MIGRATIONS.len());
+        assert_eq!(
+            database.get_setting("avoidreuse").unwrap(),
+            Some("true".to_string())
+        );
+
+        // A database from a newer wallet is refused
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        drop(conn);
+        assert!(matches!(
+            database.open_connection(),
+            Err(WalletError::DatabaseError(_))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
 }
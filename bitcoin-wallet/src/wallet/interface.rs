@@ -1,22 +1,94 @@
-use bdk_wallet::{KeychainKind, LocalOutput};
-use bitcoin::{Address, Network, Transaction};
+use bdk_wallet::chain::{ChainPosition, ConfirmationBlockTime};
+use bdk_wallet::descriptor::calc_checksum;
+use bdk_wallet::error::CreateTxError;
+use bdk_wallet::miniscript::descriptor::{DescriptorSecretKey, KeyMap};
+use bdk_wallet::miniscript::{Descriptor, DescriptorPublicKey, ForEachKey};
+use bdk_wallet::{KeychainKind, LocalOutput, SignOptions, Wallet};
+use bip39::Mnemonic;
+use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+use bitcoin::policy::MAX_STANDARD_TX_WEIGHT;
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::{
+    Address, Amount, Block, BlockHash, FeeRate, Network, OutPoint, Script, ScriptBuf, SignedAmount,
+    Transaction, TxOut, Txid, Witness,
+};
+use futures::stream::{self, Stream, StreamExt};
 use rand::{self, Rng};
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use tokio::sync::mpsc;
+use tokio::task::LocalSet;
 
+use super::backup::{
+    read_backup, write_backup, LabelBackup, SecretBackup, TransactionBackup, WalletBackup,
+    BACKUP_VERSION,
+};
+use super::coin_selection::CoinControlSelection;
 use super::database::WalletDatabase;
+use super::mnemonic::{
+    decrypt_secret, encrypt_secret, generate_mnemonic, mnemonic_descriptors, parse_mnemonic,
+};
 use super::notification::NotificationProcessor;
 use crate::error::WalletError;
-use blocktalk::BlockTalk;
+use crate::rpc::WalletInfoResponse;
+use blocktalk::{
+    BlockTalk, BlockTalkError, Blockchain, ChainInfo, ChainInterface, FeeHistogramBucket,
+    MempoolAcceptResult, MempoolEntry, MempoolInfo, NetworkInfo, PackageAcceptResult,
+};
 // use super::transaction::{TransactionBuilder, TransactionBroadcaster};
 use super::database::ThreadSafeWallet;
-use super::types::{CreateWalletOptions, TransactionMetadata, TxRecipient, WalletBalance};
+use super::types::{
+    AddressGroupEntry, AddressInfo, AddressType, BlockDetails, CoinControlOptions,
+    CreateWalletOptions, DescriptorEntry, DescriptorInfo, FundingOptions, RawTransaction,
+    ScanResult, ScanUnspent, TransactionCategory, TransactionDetail, TransactionMetadata,
+    TransactionOutputDetail, TxRecipient, UnspentOutput, WalletBalance, WalletTransaction,
+};
+
+/// Confirmation target used when estimating fees for new transactions
+const DEFAULT_CONFIRMATION_TARGET: u32 = 2;
+
+/// Wallet version reported by `getwalletinfo`, Bitcoin Core's first descriptor wallet version
+const WALLET_VERSION: u32 = 169900;
+
+/// Maximum fee accepted when broadcasting, matching Bitcoin Core's default -maxtxfee
+const DEFAULT_MAX_TX_FEE: Amount = Amount::from_sat(10_000_000);
+
+/// Minimum fee rate increase of a replacement, Bitcoin Core's default -incrementalrelayfee
+const INCREMENTAL_RELAY_FEE: FeeRate = FeeRate::from_sat_per_vb_unchecked(1);
+
+/// Scale of the rescan progress counter, which holds the completed fraction in millionths
+const RESCAN_PROGRESS_SCALE: u32 = 1_000_000;
+
+/// Number of indices ranged descriptors are scanned over by `scantxoutset`,
+/// Bitcoin Core's default range
+const DEFAULT_SCAN_RANGE: u32 = 1000;
+
+/// Most scripts a wallet may watch for `sync_wallet` to skip blocks using the
+/// node's block filters, past which nearly every block matches anyway
+const FILTER_SYNC_MAX_SCRIPTS: usize = 1000;
+
+/// Name the wallet's mnemonic is stored under among its secrets
+const MNEMONIC_SECRET: &str = "mnemonic";
+
+/// Leeway for block timestamps when rescanning from a key's birth time, as in Bitcoin Core
+const TIMESTAMP_WINDOW: u64 = 2 * 60 * 60;
 
 pub struct WalletInterface {
     wallet: Arc<RwLock<Option<Arc<ThreadSafeWallet>>>>,
     database: WalletDatabase,
     node_socket: String,
     network: Network,
+    rescan_progress: Arc<AtomicU32>,
+    rescan_height: Arc<AtomicI32>,
+    rescan_handle: Mutex<Option<JoinHandle<()>>>,
+    scan_running: AtomicBool,
+    scan_abort: AtomicBool,
+    scan_progress: AtomicU32,
+    derived_addresses: Mutex<HashMap<(String, u32), Address>>,
 }
 
 impl WalletInterface {
@@ -40,26 +112,82 @@ impl WalletInterface {
             database,
             node_socket: node_socket.to_string(),
             network,
+            rescan_progress: Arc::new(AtomicU32::new(0)),
+            rescan_height: Arc::new(AtomicI32::new(-1)),
+            rescan_handle: Mutex::new(None),
+            scan_running: AtomicBool::new(false),
+            scan_abort: AtomicBool::new(false),
+            scan_progress: AtomicU32::new(0),
+            derived_addresses: Mutex::new(HashMap::new()),
         });
 
         Ok(wallet_interface)
     }
 
-    pub fn create_wallet(&self, options: CreateWalletOptions) -> Result<(), WalletError> {
-        let (external_descriptor, internal_descriptor) = if options.blank {
-            ("wpkh()".to_string(), "wpkh()".to_string())
-        } else {
-            generate_descriptors(self.network)?
-        };
+    pub fn create_wallet(&self, mut options: CreateWalletOptions) -> Result<(), WalletError> {
+        let mnemonic = options
+            .mnemonic
+            .take()
+            .map(|mnemonic| parse_mnemonic(&mnemonic))
+            .transpose()?;
+        if let Some(mnemonic) = &mnemonic {
+            if options.external_descriptor.is_some() || options.internal_descriptor.is_some() {
+                return Err(WalletError::InvalidDescriptor(
+                    "A wallet cannot be created from both a mnemonic and descriptors".to_string(),
+                ));
+            }
+            let (external_descriptor, internal_descriptor) =
+                mnemonic_descriptors(mnemonic, self.network, options.address_type)?;
+            options.external_descriptor = Some(external_descriptor);
+            options.internal_descriptor = Some(internal_descriptor);
+        }
 
-        let persisted_wallet =
-            self.database
-                .create_wallet(external_descriptor, internal_descriptor, self.network)?;
+        let private_keys_enabled = match options.external_descriptor.as_deref() {
+            Some(_) if options.blank => {
+                return Err(WalletError::InvalidDescriptor(
+                    "A blank wallet cannot be created with descriptors".to_string(),
+                ));
+            }
+            Some(external_descriptor) => self.create_wallet_with_descriptors(
+                external_descriptor,
+                options.internal_descriptor.as_deref(),
+                options.disable_private_keys,
+            )?,
+            None if options.internal_descriptor.is_some() => {
+                return Err(WalletError::InvalidDescriptor(
+                    "A change descriptor needs a receive descriptor".to_string(),
+                ));
+            }
+            None => {
+                // A BDK wallet can't exist without descriptors, so a blank wallet is
+                // only created once its descriptors are imported
+                if !options.blank {
+                    let (external_descriptor, internal_descriptor) =
+                        generate_descriptors(self.network, options.address_type)?;
+                    self.create_wallet_with_descriptors(
+                        &external_descriptor,
+                        Some(&internal_descriptor),
+                        false,
+                    )?;
+                }
+                !(options.blank || options.disable_private_keys)
+            }
+        };
 
-        let wallet = Arc::new(persisted_wallet); // Wrap in Arc directly
-        {
-            let mut current_wallet = self.wallet.write().unwrap();
-            *current_wallet = Some(wallet);
+        self.database
+            .store_setting("private_keys_enabled", &private_keys_enabled.to_string())?;
+        self.database
+            .store_setting("blank", &options.blank.to_string())?;
+        self.database.store_setting(
+            "disable_private_keys",
+            &options.disable_private_keys.to_string(),
+        )?;
+        self.database
+            .store_setting("avoid_reuse", &options.avoid_reuse.to_string())?;
+        self.database
+            .store_setting("created_at", &chrono::Utc::now().timestamp().to_string())?;
+        if let Some(mnemonic) = mnemonic {
+            self.store_mnemonic(&mnemonic, options.mnemonic_passphrase.as_deref())?;
         }
 
         log::info!("Created wallet");
@@ -67,6 +195,12 @@ impl WalletInterface {
     }
 
     pub async fn load_wallet(&self, _wallet_name: &str) -> Result<(), WalletError> {
+        self.open_wallet()?;
+        self.sync_wallet().await
+    }
+
+    /// Load the wallet from its database without syncing it with the node
+    pub fn open_wallet(&self) -> Result<(), WalletError> {
         let persisted_wallet = self.database.load_wallet(self.network)?;
         let wallet = Arc::new(persisted_wallet); // Wrap in Arc directly
 
@@ -76,7 +210,512 @@ impl WalletInterface {
         }
 
         log::info!("Loaded wallet from database");
-        self.sync_wallet().await
+        Ok(())
+    }
+
+    /// Drop the loaded wallet, leaving its database as it is
+    pub fn close_wallet(&self) {
+        *self.wallet.write().unwrap() = None;
+    }
+
+    /// Generate a new 12 word BIP39 mnemonic
+    pub fn generate_mnemonic() -> Result<String, WalletError> {
+        Ok(generate_mnemonic()?.to_string())
+    }
+
+    /// Create a wallet with BIP84 descriptors derived from a BIP39 mnemonic
+    /// The mnemonic is kept so it can be exported, encrypted with
+    /// `mnemonic_passphrase` if one is given. That passphrase only protects the
+    /// stored mnemonic: the private descriptors derived from it are stored
+    /// unencrypted, so it does not protect the wallet's funds.
+    pub fn create_wallet_from_mnemonic(
+        &self,
+        mnemonic: &str,
+        mnemonic_passphrase: Option<&str>,
+    ) -> Result<(), WalletError> {
+        self.create_wallet(CreateWalletOptions {
+            mnemonic: Some(mnemonic.to_string()),
+            mnemonic_passphrase: mnemonic_passphrase.map(str::to_string),
+            ..Default::default()
+        })
+    }
+
+    /// The mnemonic the wallet was created from
+    /// `mnemonic_passphrase` is needed if the wallet was created with one.
+    pub fn export_mnemonic(
+        &self,
+        mnemonic_passphrase: Option<&str>,
+    ) -> Result<String, WalletError> {
+        let (secret, encrypted) = self.database.get_secret(MNEMONIC_SECRET)?.ok_or_else(|| {
+            WalletError::Generic("Wallet was not created from a mnemonic".to_string())
+        })?;
+        let phrase = match (encrypted, mnemonic_passphrase) {
+            (false, _) => secret,
+            (true, Some(passphrase)) => decrypt_secret(&secret, passphrase)?,
+            (true, None) => {
+                return Err(WalletError::Generic(
+                    "The mnemonic passphrase is needed to export the mnemonic".to_string(),
+                ))
+            }
+        };
+        String::from_utf8(phrase)
+            .map_err(|_| WalletError::DatabaseError("Stored mnemonic is not valid".to_string()))
+    }
+
+    // Keep the wallet's mnemonic for export, encrypted if there is a mnemonic passphrase
+    fn store_mnemonic(
+        &self,
+        mnemonic: &Mnemonic,
+        mnemonic_passphrase: Option<&str>,
+    ) -> Result<(), WalletError> {
+        let phrase = mnemonic.to_string();
+        match mnemonic_passphrase.filter(|passphrase| !passphrase.is_empty()) {
+            Some(passphrase) => self.database.store_secret(
+                MNEMONIC_SECRET,
+                &encrypt_secret(phrase.as_bytes(), passphrase)?,
+                true,
+            ),
+            None => self
+                .database
+                .store_secret(MNEMONIC_SECRET, phrase.as_bytes(), false),
+        }
+    }
+
+    /// Create a wallet that watches a public descriptor's addresses without
+    /// being able to sign for them
+    pub fn create_watch_only_wallet(&self, external_desc: &str) -> Result<(), WalletError> {
+        self.create_wallet(CreateWalletOptions {
+            disable_private_keys: true,
+            external_descriptor: Some(external_desc.to_string()),
+            ..Default::default()
+        })
+    }
+
+    /// Extended public key at `derivation_path` from the wallet's master key
+    pub fn get_xpub(&self, derivation_path: &DerivationPath) -> Result<Xpub, WalletError> {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = self
+            .master_xprv()?
+            .derive_priv(&secp, derivation_path)
+            .map_err(|e| WalletError::Generic(format!("Failed to derive key: {}", e)))?;
+        Ok(Xpub::from_priv(&secp, &xprv))
+    }
+
+    /// Create a watch-only wallet from the account xpub at `derivation_path`
+    /// The address type follows the path's BIP44 purpose, 84' or 86'. An xpub
+    /// doesn't record its master fingerprint, so the descriptors carry no key
+    /// origin.
+    pub fn import_xpub(
+        &self,
+        xpub: &Xpub,
+        derivation_path: &DerivationPath,
+    ) -> Result<(), WalletError> {
+        if xpub.network != self.network.into() {
+            return Err(WalletError::InvalidDescriptor(format!(
+                "Extended key is not for {}",
+                self.network
+            )));
+        }
+        if usize::from(xpub.depth) != derivation_path.len() {
+            return Err(WalletError::InvalidDescriptor(format!(
+                "Extended key at depth {} cannot be at {}",
+                xpub.depth, derivation_path
+            )));
+        }
+        let address_type = AddressType::from_account_path(derivation_path).ok_or_else(|| {
+            WalletError::InvalidDescriptor(format!(
+                "Unsupported derivation path {}, expected BIP84 or BIP86",
+                derivation_path
+            ))
+        })?;
+
+        let (external_descriptor, internal_descriptor) = address_type.xpub_descriptors(xpub);
+        self.create_wallet(CreateWalletOptions {
+            disable_private_keys: true,
+            address_type,
+            external_descriptor: Some(external_descriptor),
+            internal_descriptor: Some(internal_descriptor),
+            ..Default::default()
+        })
+    }
+
+    /// Fingerprint of the master key the wallet's receive descriptor derives from
+    pub fn get_master_fingerprint(&self) -> Result<[u8; 4], WalletError> {
+        let descriptor = self.receive_descriptor()?;
+        let (descriptor, _) = parse_active_descriptor(&descriptor)?;
+        descriptor
+            .iter_pk()
+            .next()
+            .map(|key| key.master_fingerprint().to_bytes())
+            .ok_or_else(|| WalletError::InvalidDescriptor("Descriptor has no keys".to_string()))
+    }
+
+    // The receive descriptor as it was created or imported, with any private keys
+    fn receive_descriptor(&self) -> Result<String, WalletError> {
+        self.database
+            .get_descriptor(KeychainKind::External)?
+            .ok_or_else(|| WalletError::Generic("Wallet has no descriptors".to_string()))
+    }
+
+    // The master private key of the wallet's receive descriptor
+    fn master_xprv(&self) -> Result<Xpriv, WalletError> {
+        let (_, keymap) = parse_active_descriptor(&self.receive_descriptor()?)?;
+        keymap
+            .values()
+            .find_map(|key| match key {
+                DescriptorSecretKey::XPrv(xkey)
+                    if xkey.origin.is_none() && xkey.xkey.depth == 0 =>
+                {
+                    Some(xkey.xkey)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| WalletError::Generic("Wallet has no master private key".to_string()))
+    }
+
+    // Create the wallet from the user's own descriptors, returning whether they
+    // hold private keys
+    fn create_wallet_with_descriptors(
+        &self,
+        external_descriptor: &str,
+        internal_descriptor: Option<&str>,
+        disable_private_keys: bool,
+    ) -> Result<bool, WalletError> {
+        let mut has_private_keys = false;
+        for desc in std::iter::once(external_descriptor).chain(internal_descriptor) {
+            let (_, keymap) = parse_active_descriptor(desc)?;
+            has_private_keys |= !keymap.is_empty();
+        }
+        if has_private_keys && disable_private_keys {
+            return Err(WalletError::InvalidDescriptor(
+                "Cannot import private keys to a wallet with private keys disabled".to_string(),
+            ));
+        }
+
+        let persisted_wallet = self.database.create_wallet_from_descriptors(
+            external_descriptor.to_string(),
+            internal_descriptor.map(str::to_string),
+            self.network,
+        )?;
+        // Kept so the wallet can be loaded, and sign, with the same descriptors
+        self.database
+            .store_descriptor(KeychainKind::External, external_descriptor, None)?;
+        if let Some(internal_descriptor) = internal_descriptor {
+            self.database
+                .store_descriptor(KeychainKind::Internal, internal_descriptor, None)?;
+        }
+        {
+            let mut current_wallet = self.wallet.write().unwrap();
+            *current_wallet = Some(Arc::new(persisted_wallet));
+        }
+
+        log::info!("Created wallet from supplied descriptors");
+        Ok(has_private_keys)
+    }
+
+    /// Import a receive or change descriptor
+    ///
+    /// BDK wallets track exactly one receive and one change descriptor, fixed when
+    /// the wallet is created. Descriptors can therefore only be imported into a
+    /// blank wallet, which is created once its receive descriptor arrives; import
+    /// the change descriptor first to have both. Re-importing a descriptor the
+    /// wallet already has succeeds without changes.
+    pub fn import_descriptor(
+        &self,
+        desc: &str,
+        internal: bool,
+        timestamp: Option<u64>,
+    ) -> Result<(), WalletError> {
+        let (descriptor, keymap) = parse_active_descriptor(desc)?;
+        let keychain = if internal {
+            KeychainKind::Internal
+        } else {
+            KeychainKind::External
+        };
+
+        if let Ok(wallet) = self.get_current_wallet() {
+            let wallet_guard = wallet.lock().unwrap();
+            if wallet_guard.public_descriptor(keychain).to_string() == descriptor.to_string() {
+                log::debug!("Descriptor {} is already in the wallet", descriptor);
+                return Ok(());
+            }
+            return Err(WalletError::Generic(format!(
+                "The wallet already has a {} descriptor; descriptors can only be imported into a blank wallet",
+                if internal { "change" } else { "receive" }
+            )));
+        }
+
+        if !self.setting_or("blank", false)? {
+            return Err(WalletError::Generic("No wallet loaded".to_string()));
+        }
+        if !keymap.is_empty() {
+            if self.setting_or("disable_private_keys", false)? {
+                return Err(WalletError::InvalidDescriptor(
+                    "Cannot import private keys to a wallet with private keys disabled".to_string(),
+                ));
+            }
+            self.database
+                .store_setting("private_keys_enabled", "true")?;
+        }
+
+        self.database.store_descriptor(keychain, desc, timestamp)?;
+        if internal {
+            log::info!("Stored change descriptor, waiting for a receive descriptor");
+            return Ok(());
+        }
+
+        let internal_descriptor = self.database.get_descriptor(KeychainKind::Internal)?;
+        let persisted_wallet = self.database.create_wallet_from_descriptors(
+            desc.to_string(),
+            internal_descriptor,
+            self.network,
+        )?;
+        {
+            let mut current_wallet = self.wallet.write().unwrap();
+            *current_wallet = Some(Arc::new(persisted_wallet));
+        }
+
+        log::info!("Created wallet from imported descriptors");
+        Ok(())
+    }
+
+    /// The wallet's descriptors, in public form with their checksums
+    ///
+    /// A BDK wallet has one receive and one change descriptor, both active. A
+    /// change descriptor imported into a blank wallet is listed as inactive
+    /// until the receive descriptor arrives and the wallet is created.
+    pub fn list_descriptors(&self) -> Result<Vec<DescriptorEntry>, WalletError> {
+        let wallet = self.get_current_wallet().ok();
+        let mut stored = self.database.list_descriptors()?;
+        if stored.is_empty() && wallet.is_some() {
+            // Wallets created before their descriptors were stored
+            let (external, internal) = self.database.wallet_descriptors()?;
+            stored.push((KeychainKind::External, external, None));
+            stored.extend(internal.map(|desc| (KeychainKind::Internal, desc, None)));
+        }
+        let created_at = self.setting_or("created_at", 0)?;
+
+        let wallet_guard = wallet.as_ref().map(|wallet| wallet.lock().unwrap());
+        stored
+            .into_iter()
+            .map(|(keychain, desc, timestamp)| {
+                let (descriptor, _) = parse_active_descriptor(&desc)?;
+                let (next_index, lookahead) = match &wallet_guard {
+                    Some(wallet) => (
+                        wallet.next_derivation_index(keychain),
+                        wallet.spk_index().lookahead(),
+                    ),
+                    None => (0, 0),
+                };
+                Ok(DescriptorEntry {
+                    desc: descriptor.to_string(),
+                    timestamp: timestamp.unwrap_or(created_at),
+                    active: wallet_guard.is_some(),
+                    internal: keychain == KeychainKind::Internal,
+                    range: descriptor
+                        .has_wildcard()
+                        .then(|| [0, (next_index + lookahead).saturating_sub(1)]),
+                    next_index,
+                })
+            })
+            .collect()
+    }
+
+    /// Write a backup of the wallet to `output_path`
+    /// The backup holds the wallet's descriptors, including any private keys,
+    /// along with its settings, labels, transaction metadata and locked outputs.
+    pub fn export_wallet(&self, output_path: &Path) -> Result<(), WalletError> {
+        let (last_external_index, last_internal_index) = {
+            let wallet = self.get_current_wallet()?;
+            let wallet_guard = wallet.lock().unwrap();
+            (
+                wallet_guard.derivation_index(KeychainKind::External),
+                wallet_guard.derivation_index(KeychainKind::Internal),
+            )
+        };
+        let (external_descriptor, internal_descriptor) = self.database.wallet_descriptors()?;
+
+        let backup = WalletBackup {
+            version: BACKUP_VERSION,
+            network: self.network.to_core_arg().to_string(),
+            last_internal_index: internal_descriptor.as_ref().and(last_internal_index),
+            external_descriptor,
+            internal_descriptor,
+            last_external_index,
+            settings: self.database.list_settings()?.into_iter().collect(),
+            labels: self
+                .database
+                .list_address_labels()?
+                .into_iter()
+                .map(|(address, label, purpose)| LabelBackup {
+                    address,
+                    label,
+                    purpose,
+                })
+                .collect(),
+            transactions: self
+                .database
+                .list_tx_metadata()?
+                .into_iter()
+                .map(|(txid, metadata)| TransactionBackup {
+                    txid: txid.to_string(),
+                    time_received: metadata.timestamp,
+                    block_height: metadata.block_height,
+                    fee: metadata.fee.map(Amount::to_sat),
+                    comment: metadata.comment,
+                    label: metadata.label,
+                    abandoned: metadata.abandoned,
+                })
+                .collect(),
+            locked_outputs: self
+                .database
+                .list_locked_outpoints()?
+                .iter()
+                .map(OutPoint::to_string)
+                .collect(),
+            secrets: self
+                .database
+                .list_secrets()?
+                .into_iter()
+                .map(|(name, secret, encrypted)| SecretBackup {
+                    name,
+                    secret: hex::encode(secret),
+                    encrypted,
+                })
+                .collect(),
+        };
+
+        write_backup(output_path, &backup)?;
+        log::info!("Backed up wallet to {}", output_path.display());
+        Ok(())
+    }
+
+    /// Restore a backup written by `export_wallet` into a new wallet database
+    /// at `wallet_path`
+    pub async fn import_wallet(
+        input_path: &Path,
+        wallet_path: &Path,
+        node_socket: &str,
+        network: Network,
+    ) -> Result<Arc<Self>, WalletError> {
+        if wallet_path.exists() {
+            return Err(WalletError::Generic(format!(
+                "Wallet database {} already exists",
+                wallet_path.display()
+            )));
+        }
+        let wallet_interface = Self::new(wallet_path, node_socket, network).await?;
+        wallet_interface.restore_wallet(input_path).await?;
+        Ok(wallet_interface)
+    }
+
+    /// Restore a backup written by `export_wallet`, when no wallet has been
+    /// created or loaded yet
+    /// The wallet's transactions are found again by a background rescan from
+    /// its earliest transaction, started if the node can be reached.
+    pub async fn restore_wallet(self: &Arc<Self>, input_path: &Path) -> Result<(), WalletError> {
+        if self.get_current_wallet().is_ok() {
+            return Err(WalletError::Generic(
+                "Cannot restore a backup over a loaded wallet".to_string(),
+            ));
+        }
+        let backup = read_backup(input_path)?;
+        if backup.network != self.network.to_core_arg() {
+            return Err(WalletError::Generic(format!(
+                "Wallet backup is for {}, not {}",
+                backup.network,
+                self.network.to_core_arg()
+            )));
+        }
+
+        let disable_private_keys = backup
+            .settings
+            .get("disable_private_keys")
+            .is_some_and(|value| value == "true");
+        self.create_wallet_with_descriptors(
+            &backup.external_descriptor,
+            backup.internal_descriptor.as_deref(),
+            disable_private_keys,
+        )?;
+        {
+            let wallet = self.get_current_wallet()?;
+            let mut wallet_guard = wallet.lock().unwrap();
+            if let Some(index) = backup.last_external_index {
+                let _ = wallet_guard.reveal_addresses_to(KeychainKind::External, index);
+            }
+            if let Some(index) = backup.last_internal_index {
+                let _ = wallet_guard.reveal_addresses_to(KeychainKind::Internal, index);
+            }
+        }
+
+        for (name, value) in &backup.settings {
+            self.database.store_setting(name, value)?;
+        }
+        for label in &backup.labels {
+            self.database
+                .store_address_label(&label.address, &label.label, &label.purpose)?;
+        }
+        for tx in &backup.transactions {
+            let txid = Txid::from_str(&tx.txid).map_err(|e| {
+                WalletError::Generic(format!("Invalid txid in wallet backup: {}", e))
+            })?;
+            let metadata = TransactionMetadata {
+                timestamp: tx.time_received,
+                block_height: tx.block_height,
+                fee: tx.fee.map(Amount::from_sat),
+                comment: tx.comment.clone(),
+                label: tx.label.clone(),
+                abandoned: tx.abandoned,
+            };
+            self.database.store_tx_metadata(&txid, &metadata)?;
+        }
+        for outpoint in &backup.locked_outputs {
+            let outpoint = OutPoint::from_str(outpoint).map_err(|e| {
+                WalletError::Generic(format!("Invalid locked output in wallet backup: {}", e))
+            })?;
+            self.database.lock_outpoint(&outpoint)?;
+        }
+        for secret in &backup.secrets {
+            let bytes = hex::decode(&secret.secret).map_err(|e| {
+                WalletError::Generic(format!("Invalid secret in wallet backup: {}", e))
+            })?;
+            self.database
+                .store_secret(&secret.name, &bytes, secret.encrypted)?;
+        }
+        log::info!("Restored wallet from {}", input_path.display());
+
+        let earliest = backup
+            .transactions
+            .iter()
+            .map(|tx| tx.time_received)
+            .min()
+            .or_else(|| backup.settings.get("created_at")?.parse().ok())
+            .unwrap_or(0);
+        match self.rescan_start_height(earliest).await {
+            Ok(start_height) => self.start_rescan(start_height, None)?,
+            Err(e) => log::warn!(
+                "Not rescanning the restored wallet, rescan once the node is reachable: {}",
+                e
+            ),
+        }
+        Ok(())
+    }
+
+    /// Height to start rescanning from to find transactions after `timestamp`
+    pub async fn rescan_start_height(&self, timestamp: u64) -> Result<i32, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        // Block times are only roughly increasing, which the window allows for
+        let target = timestamp.saturating_sub(TIMESTAMP_WINDOW);
+        Ok(blocktalk.chain().find_block_at_timestamp(target, 0).await?)
+    }
+
+    /// Connect to the node and start receiving its chain notifications
+    /// Notifications stop when the returned chain is dropped.
+    pub async fn begin_chain_updates(&self) -> Result<Blockchain, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        let chain = Blockchain::new(blocktalk.connection().clone());
+        chain.begin_chain_updates().await?;
+        Ok(chain)
     }
 
     async fn get_blocktalk(&self) -> Result<BlockTalk, WalletError> {
@@ -103,12 +742,7 @@ impl WalletInterface {
         let wallet = self.get_current_wallet()?;
         let wallet_guard = wallet.lock().unwrap();
 
-        let is_relevant = tx
-            .output
-            .iter()
-            .any(|output| wallet_guard.is_mine(output.script_pubkey.clone()));
-
-        if is_relevant {
+        if is_relevant(&wallet_guard, tx) {
             log::info!("Found relevant transaction: {}", txid);
 
             if let Some(height) = block_height {
@@ -119,19 +753,127 @@ impl WalletInterface {
 
             // wallet_guard.persist(wallet_guard.connection())?;
 
-            let timestamp = chrono::Utc::now().timestamp() as u64;
-            let metadata = TransactionMetadata {
-                timestamp,
-                block_height: block_height.map(|h| h as u32),
-                fee: None,
-                comment: String::new(),
-                label: String::new(),
-            };
+            let mut metadata = self.get_or_create_tx_metadata(&txid)?;
+            metadata.block_height = block_height.map(|h| h as u32);
+            if metadata.fee.is_none() {
+                metadata.fee = transaction_fee(tx, |outpoint| {
+                    wallet_guard.tx_graph().get_txout(outpoint).cloned()
+                });
+            }
+            self.database.store_tx_metadata(&txid, &metadata)?;
+        }
+
+        Ok(())
+    }
 
-            // TODO: Store metadata somewhere
-            // self.db.store_tx_metadata(&txid, &metadata)?;
+    /// Apply a block announced by the node, recording the wallet's transactions
+    /// in it as confirmed
+    /// A block building on an earlier block than the wallet's tip is handled as
+    /// a reorg, even if the node's disconnect notifications were missed. A block
+    /// whose parent the wallet hasn't seen, such as after missed notifications,
+    /// is picked up by syncing with the node instead.
+    pub async fn process_block(&self, block: &Block) -> Result<(), WalletError> {
+        let block_hash = block.block_hash();
+        let wallet = self.get_current_wallet()?;
+        let mut parent = None;
+        let mut displaced = Vec::new();
+        for checkpoint in wallet.lock().unwrap().latest_checkpoint().iter() {
+            if checkpoint.hash() == block.header.prev_blockhash {
+                parent = Some(checkpoint);
+                break;
+            }
+            displaced.push(checkpoint.hash());
+        }
+        let Some(parent) = parent else {
+            log::info!(
+                "Block {} does not extend the wallet's chain, syncing",
+                block_hash
+            );
+            return self.sync_wallet().await;
+        };
+
+        if !displaced.is_empty() {
+            log::info!(
+                "Block {} replaces {} blocks at the wallet's tip",
+                block_hash,
+                displaced.len()
+            );
+        }
+        let height = parent.height() as i32 + 1;
+        self.handle_reorg(&displaced, &[(height, block.clone())])
+            .await
+    }
+
+    /// Undo `disconnected` blocks and apply `connected` ones after a chain
+    /// reorganization, then save the wallet's new chain
+    /// Connected blocks must be in height order, starting right above the fork
+    /// point. The disconnected blocks' transactions are marked unconfirmed
+    /// straight away, but BDK only drops the blocks themselves once a block
+    /// connecting to the fork point replaces them.
+    pub async fn handle_reorg(
+        &self,
+        disconnected: &[BlockHash],
+        connected: &[(i32, Block)],
+    ) -> Result<(), WalletError> {
+        for block_hash in disconnected {
+            self.process_disconnected_block(block_hash)?;
+        }
+
+        let wallet = self.get_current_wallet()?;
+        for (height, block) in connected {
+            for tx in &block.txdata {
+                self.process_transaction(tx, Some(*height)).await?;
+            }
+            wallet
+                .lock()
+                .unwrap()
+                .apply_block(block, *height as u32)
+                .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
+            log::info!("Applied block {} at height {}", block.block_hash(), height);
         }
 
+        self.database.persist_wallet(&mut wallet.lock().unwrap())
+    }
+
+    /// Mark the wallet's transactions in a disconnected block, and in any block
+    /// after it, as unconfirmed
+    pub fn process_disconnected_block(&self, block_hash: &BlockHash) -> Result<(), WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let checkpoint = wallet
+            .lock()
+            .unwrap()
+            .latest_checkpoint()
+            .iter()
+            .find(|checkpoint| checkpoint.hash() == *block_hash);
+        let Some(checkpoint) = checkpoint else {
+            log::debug!(
+                "Disconnected block {} is not in the wallet's chain",
+                block_hash
+            );
+            return Ok(());
+        };
+
+        let count = self.database.mark_unconfirmed_from(checkpoint.height())?;
+        log::info!(
+            "Block {} at height {} disconnected, {} wallet transactions now unconfirmed",
+            block_hash,
+            checkpoint.height(),
+            count
+        );
+        Ok(())
+    }
+
+    /// Record a transaction that entered the node's mempool if it pays to or
+    /// spends from the wallet
+    pub async fn process_mempool_transaction(&self, tx: &Transaction) -> Result<(), WalletError> {
+        self.process_transaction(tx, None).await?;
+
+        let wallet = self.get_current_wallet()?;
+        let mut wallet_guard = wallet.lock().unwrap();
+        if is_relevant(&wallet_guard, tx) {
+            wallet_guard
+                .apply_unconfirmed_txs([(tx.clone(), chrono::Utc::now().timestamp() as u64)]);
+        }
         Ok(())
     }
 
@@ -158,8 +900,39 @@ impl WalletInterface {
         let start_height = wallet_tip.height() as i32 + 1;
 
         log::info!("🔄 Syncing wallet with blockchain");
-        for height in start_height..=tip_height {
-            if let Ok(block) = blocktalk.chain().get_block(&tip_hash, height).await {
+        // Blocks are fetched ahead while earlier ones are being applied
+        let chain = Blockchain::new(blocktalk.connection().clone());
+        // BDK watches the revealed scripts and `lookahead` more per keychain
+        let scripts: Vec<ScriptBuf> = wallet_guard
+            .spk_index()
+            .inner()
+            .all_spks()
+            .values()
+            .cloned()
+            .collect();
+        if scripts.len() <= FILTER_SYNC_MAX_SCRIPTS {
+            // Only blocks that may pay to or spend from the wallet are fetched,
+            // each connected to the last block applied
+            let mut blocks = chain.matching_block_stream(start_height, tip_height, scripts);
+            while let Some(result) = blocks.next().await {
+                let (height, block) = result?;
+                let connected_to = wallet_guard.latest_checkpoint().block_id();
+                wallet_guard
+                    .apply_block_connected_to(&block, height as u32, connected_to)
+                    .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
+            }
+            // The wallet's tip still has to reach the node's
+            if (wallet_guard.latest_checkpoint().height() as i32) < tip_height {
+                let block = chain.get_block(&tip_hash, tip_height).await?;
+                let connected_to = wallet_guard.latest_checkpoint().block_id();
+                wallet_guard
+                    .apply_block_connected_to(&block, tip_height as u32, connected_to)
+                    .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
+            }
+        } else {
+            let mut blocks = chain.block_stream(start_height, tip_height);
+            while let Some(result) = blocks.next().await {
+                let (height, block) = result?;
                 wallet_guard
                     .apply_block(&block, height as u32)
                     .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
@@ -176,6 +949,46 @@ impl WalletInterface {
         Ok(())
     }
 
+    /// Sync the wallet like `sync_wallet`, fetching up to `concurrency` blocks
+    /// from the node at once
+    /// Blocks still have to be applied in height order, so any that arrive
+    /// early are held until the blocks before them have been applied.
+    pub async fn sync_wallet_parallel(&self, concurrency: usize) -> Result<(), WalletError> {
+        log::info!(
+            "Syncing wallet with blockchain, fetching up to {} blocks at once",
+            concurrency
+        );
+
+        let blocktalk = self.get_blocktalk().await?;
+        let (tip_height, tip_hash) = blocktalk.chain().get_tip().await?;
+        log::info!(
+            "Current blockchain tip is at height {} with hash {}",
+            tip_height,
+            tip_hash
+        );
+
+        let wallet = self.get_current_wallet()?;
+        let mut wallet_guard = wallet.lock().unwrap();
+        let start_height = wallet_guard.latest_checkpoint().height() as i32 + 1;
+
+        let chain = blocktalk.chain();
+        let blocks = stream::iter(start_height..=tip_height)
+            .map(|height| async move {
+                let block = chain.get_block(&tip_hash, height).await?;
+                Ok::<_, BlockTalkError>((height, block))
+            })
+            .buffer_unordered(concurrency.max(1));
+        apply_blocks_in_order(&mut wallet_guard, start_height, blocks).await?;
+
+        let wallet_tip = wallet_guard.latest_checkpoint();
+        log::info!(
+            "✅ Wallet sync completed, wallet tip is: {} at height {}",
+            &wallet_tip.hash(),
+            &wallet_tip.height()
+        );
+        Ok(())
+    }
+
     pub fn get_new_address(&self, label: Option<&str>) -> Result<Address, WalletError> {
         let wallet = self.get_current_wallet()?;
         let mut wallet_guard = wallet.lock().unwrap();
@@ -183,108 +996,4097 @@ impl WalletInterface {
 
         // Persist changes to database
         // wallet_guard.persist(wallet_guard.connection())?;
+        drop(wallet_guard);
 
         if let Some(label_text) = label {
-            log::debug!(
-                "Labeling address {} as '{}'",
-                address_info.address,
-                label_text
-            );
-            // TODO: Store label somewhere
+            self.set_address_label(&address_info.address, label_text)?;
         }
 
         Ok(address_info.address)
     }
 
-    pub fn get_balance(&self) -> Result<WalletBalance, WalletError> {
-        let wallet = self.get_current_wallet()?;
-        let wallet_guard = wallet.lock().unwrap();
-        let bdk_balance = wallet_guard.balance();
+    /// Get a new receive address of the given type
+    /// A wallet hands out a single type of address, so other types are refused.
+    pub fn get_new_address_with_type(
+        &self,
+        label: Option<&str>,
+        address_type: AddressType,
+    ) -> Result<Address, WalletError> {
+        if self.address_type()? != Some(address_type) {
+            return Err(WalletError::Generic(format!(
+                "No {} addresses available",
+                address_type.as_str()
+            )));
+        }
+        self.get_new_address(label)
+    }
 
-        Ok(WalletBalance {
-            confirmed: bdk_balance.confirmed,
-            unconfirmed: bdk_balance.untrusted_pending,
-            immature: bdk_balance.immature,
-            total: bdk_balance.confirmed + bdk_balance.untrusted_pending + bdk_balance.immature,
-        })
+    /// Get a new Taproot receive address, from a wallet created with `AddressType::P2TR`
+    pub fn get_taproot_address(&self) -> Result<Address, WalletError> {
+        self.get_new_address_with_type(None, AddressType::P2TR)
     }
 
-    pub fn list_unspent(&self) -> Result<Vec<LocalOutput>, WalletError> {
+    /// Type of the addresses the wallet hands out, `None` if its receive
+    /// descriptor isn't one the wallet generates
+    pub fn address_type(&self) -> Result<Option<AddressType>, WalletError> {
         let wallet = self.get_current_wallet()?;
         let wallet_guard = wallet.lock().unwrap();
-        Ok(wallet_guard.list_unspent().collect())
+        Ok(
+            match wallet_guard.public_descriptor(KeychainKind::External) {
+                Descriptor::Wpkh(_) => Some(AddressType::P2WPKH),
+                Descriptor::Tr(_) => Some(AddressType::P2TR),
+                _ => None,
+            },
+        )
     }
 
-    pub fn list_transactions(&self) -> Result<Vec<Transaction>, WalletError> {
-        let wallet = self.get_current_wallet()?;
-        let wallet_guard = wallet.lock().unwrap();
-        Ok(wallet_guard
-            .transactions()
-            .map(|tx| (*tx.tx_node.tx).clone())
-            .collect())
+    /// Label an address, replacing any label it already has
+    pub fn set_address_label(&self, address: &Address, label: &str) -> Result<(), WalletError> {
+        log::debug!("Labeling address {} as '{}'", address, label);
+        let is_mine = {
+            let wallet = self.get_current_wallet()?;
+            let wallet_guard = wallet.lock().unwrap();
+            wallet_guard.is_mine(address.script_pubkey())
+        };
+        let purpose = if is_mine { "receive" } else { "send" };
+        self.database
+            .store_address_label(&address.to_string(), label, purpose)
     }
 
-    pub async fn rescan_blockchain(
+    pub fn get_address_label(&self, address: &Address) -> Result<Option<String>, WalletError> {
+        self.database.get_address_label(&address.to_string())
+    }
+
+    /// Addresses carrying `label`, each with its purpose
+    pub fn get_addresses_by_label(
         &self,
-        start_height: i32,
-        stop_height: Option<i32>,
-    ) -> Result<(i32, i32), WalletError> {
-        log::info!(
-            "Rescanning blockchain from height {} to {:?}",
-            start_height,
-            stop_height
-        );
+        label: &str,
+    ) -> Result<Vec<(Address, String)>, WalletError> {
+        let mut addresses = Vec::new();
+        for (address, address_label, purpose) in self.database.list_address_labels()? {
+            if address_label != label {
+                continue;
+            }
+            let address = Address::from_str(&address)
+                .ok()
+                .and_then(|address| address.require_network(self.network).ok())
+                .ok_or_else(|| {
+                    WalletError::DatabaseError(format!("Invalid labelled address {}", address))
+                })?;
+            addresses.push((address, purpose));
+        }
+        Ok(addresses)
+    }
+
+    // Label of every labelled address, keyed by the address's script
+    fn address_labels(&self) -> Result<HashMap<ScriptBuf, String>, WalletError> {
+        let mut labels = HashMap::new();
+        for (address, label, _) in self.database.list_address_labels()? {
+            if let Ok(address) = Address::from_str(&address) {
+                labels.insert(address.assume_checked().script_pubkey(), label);
+            }
+        }
+        Ok(labels)
+    }
+
+    /// Look up an address in the wallet's keychains
+    pub fn get_address_info(&self, address: &Address) -> Result<AddressInfo, WalletError> {
+        let script_pubkey = address.script_pubkey();
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+
+        let mut info = AddressInfo {
+            address: address.clone(),
+            script_pubkey: script_pubkey.clone(),
+            derivation: wallet_guard.derivation_of_spk(script_pubkey),
+            pubkey: None,
+            hd_key_path: None,
+            master_fingerprint: None,
+            timestamp: None,
+            labels: self.get_address_label(address)?.into_iter().collect(),
+        };
+        let (keychain, index) = match info.derivation {
+            Some(derivation) => derivation,
+            None => return Ok(info),
+        };
+
+        let descriptor = wallet_guard
+            .public_descriptor(keychain)
+            .at_derivation_index(index)
+            .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+        let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+        // Our descriptors hold a single key, so report the first one
+        descriptor.for_each_key(|key| {
+            info.pubkey = key.derive_public_key(&secp).ok();
+            info.hd_key_path = key.full_derivation_path();
+            info.master_fingerprint = Some(key.master_fingerprint());
+            false
+        });
+        info.timestamp = self
+            .database
+            .get_setting("created_at")?
+            .and_then(|t| t.parse().ok());
+
+        Ok(info)
+    }
+
+    pub fn get_balance(&self) -> Result<WalletBalance, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let bdk_balance = wallet_guard.balance();
+
+        Ok(WalletBalance {
+            confirmed: bdk_balance.confirmed,
+            unconfirmed: bdk_balance.untrusted_pending,
+            immature: bdk_balance.immature,
+            total: bdk_balance.confirmed + bdk_balance.untrusted_pending + bdk_balance.immature,
+        })
+    }
+
+    /// List the wallet's unspent outputs, leaving out locked ones
+    pub fn list_unspent(&self) -> Result<Vec<LocalOutput>, WalletError> {
+        let locked = self.database.list_locked_outpoints()?;
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        Ok(wallet_guard
+            .list_unspent()
+            .filter(|output| !locked.contains(&output.outpoint))
+            .collect())
+    }
+
+    /// Lock an unspent output so coin selection no longer picks it
+    /// Locks are stored in the wallet database and so survive restarts.
+    pub fn lock_outpoint(&self, outpoint: OutPoint) -> Result<(), WalletError> {
+        {
+            let wallet = self.get_current_wallet()?;
+            let wallet_guard = wallet.lock().unwrap();
+            let wallet_tx = wallet_guard
+                .get_tx(outpoint.txid)
+                .ok_or(WalletError::TransactionNotFound(outpoint.txid))?;
+            if outpoint.vout as usize >= wallet_tx.tx_node.tx.output.len() {
+                return Err(WalletError::Generic(
+                    "Invalid parameter, vout index out of bounds".to_string(),
+                ));
+            }
+            if wallet_guard.get_utxo(outpoint).is_none() {
+                return Err(WalletError::Generic(
+                    "Invalid parameter, expected unspent output".to_string(),
+                ));
+            }
+        }
+        if self.database.list_locked_outpoints()?.contains(&outpoint) {
+            return Err(WalletError::Generic(
+                "Invalid parameter, output already locked".to_string(),
+            ));
+        }
+        self.database.lock_outpoint(&outpoint)
+    }
+
+    /// Make a locked output available to coin selection again
+    pub fn unlock_outpoint(&self, outpoint: OutPoint) -> Result<(), WalletError> {
+        if !self.database.unlock_outpoint(&outpoint)? {
+            return Err(WalletError::Generic(
+                "Invalid parameter, expected locked output".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn unlock_all_outpoints(&self) -> Result<(), WalletError> {
+        self.database.unlock_all_outpoints()
+    }
+
+    pub fn list_locked_outpoints(&self) -> Result<Vec<OutPoint>, WalletError> {
+        self.database.list_locked_outpoints()
+    }
 
+    /// List the wallet's unspent outputs with confirmation counts measured against the node's tip
+    pub async fn list_unspent_details(&self) -> Result<Vec<UnspentOutput>, WalletError> {
         let blocktalk = self.get_blocktalk().await?;
-        let (tip_height, tip_hash) = blocktalk.chain().get_tip().await?;
-        log::info!("Current blockchain tip is at height {}", tip_height);
+        let (tip_height, _) = blocktalk.chain().get_tip().await?;
 
-        let stop_height = stop_height.unwrap_or(tip_height);
-        let actual_stop_height = std::cmp::min(stop_height, tip_height);
+        let outputs = self.list_unspent()?;
+        let labels = self.address_labels()?;
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+
+        let mut unspent = Vec::new();
+        for output in outputs {
+            let txid = output.outpoint.txid;
+            let confirmations = match output.chain_position {
+                ChainPosition::Confirmed { anchor, .. } => {
+                    confirmations_at(tip_height as u32, anchor.block_id.height)
+                }
+                ChainPosition::Unconfirmed { .. } => 0,
+            };
+            let descriptor = wallet_guard
+                .public_descriptor(output.keychain)
+                .at_derivation_index(output.derivation_index)
+                .ok()
+                .map(|descriptor| descriptor.to_string());
+
+            unspent.push(UnspentOutput {
+                txid,
+                vout: output.outpoint.vout,
+                address: Address::from_script(&output.txout.script_pubkey, self.network).ok(),
+                label: labels
+                    .get(&output.txout.script_pubkey)
+                    .cloned()
+                    .unwrap_or_default(),
+                script_pubkey: output.txout.script_pubkey.clone(),
+                amount: output.txout.value,
+                confirmations,
+                descriptor,
+                safe: confirmations > 0 || output.keychain == KeychainKind::Internal,
+            });
+        }
+
+        Ok(unspent)
+    }
+
+    /// Number of confirmations of a block at `block_height`, measured against the node's tip
+    pub async fn get_confirmations(&self, block_height: u32) -> Result<u32, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        let (tip_height, _) = blocktalk.chain().get_tip().await?;
+        Ok(confirmations_at(tip_height as u32, block_height))
+    }
+
+    /// Summarise the loaded wallet's state (matches Bitcoin Core `getwalletinfo`)
+    pub fn get_wallet_info(&self) -> Result<WalletInfoResponse, WalletError> {
+        let balance = self.get_balance()?;
+        let txcount = self.list_transactions()?.len() as u32;
+        let keypoolsize = {
+            let wallet = self.get_current_wallet()?;
+            let wallet_guard = wallet.lock().unwrap();
+            // BDK watches `lookahead` scripts beyond the last revealed index,
+            // which is the closest equivalent of Core's keypool
+            wallet_guard.spk_index().lookahead()
+        };
+        let paytxfee: u64 = self.setting_or("paytxfee", 0)?;
+
+        Ok(WalletInfoResponse {
+            walletname: self.wallet_name(),
+            walletversion: WALLET_VERSION,
+            balance: balance.confirmed.to_btc(),
+            unconfirmed_balance: balance.unconfirmed.to_btc(),
+            immature_balance: balance.immature.to_btc(),
+            txcount,
+            keypoololdest: self.setting_or("created_at", 0)?,
+            keypoolsize,
+            keypoolsize_hd_internal: keypoolsize,
+            paytxfee: Amount::from_sat(paytxfee).to_btc(),
+            private_keys_enabled: self.setting_or("private_keys_enabled", true)?,
+            avoid_reuse: self.setting_or("avoid_reuse", false)?,
+            scanning: false,
+            descriptors: true,
+        })
+    }
+
+    /// Name of the wallet, taken from its database file
+    pub fn wallet_name(&self) -> String {
+        self.database
+            .path()
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Fee rate preferred for new transactions, if one has been set
+    /// Rounded up to a whole sat/kwu, so it never pays less than was asked for.
+    pub fn get_pay_tx_fee(&self) -> Result<Option<FeeRate>, WalletError> {
+        let sat_per_kvb: u64 = self.setting_or("paytxfee", 0)?;
+        if sat_per_kvb == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(FeeRate::from_sat_per_kwu(sat_per_kvb.div_ceil(4))))
+        }
+    }
+
+    /// Set the fee per 1000 vbytes preferred for new transactions, `None` to go
+    /// back to estimation
+    pub fn set_pay_tx_fee(&self, fee_per_kvb: Option<Amount>) -> Result<(), WalletError> {
+        let sat_per_kvb = fee_per_kvb.map(Amount::to_sat).unwrap_or(0);
+        self.database
+            .store_setting("paytxfee", &sat_per_kvb.to_string())
+    }
+
+    // Read a stored setting, falling back to `default` if it was never written
+    fn setting_or<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, WalletError> {
+        match self.database.get_setting(name)? {
+            Some(value) => value.parse().map_err(|_| {
+                WalletError::DatabaseError(format!("Invalid value for setting {}", name))
+            }),
+            None => Ok(default),
+        }
+    }
 
+    pub fn list_transactions(&self) -> Result<Vec<Transaction>, WalletError> {
         let wallet = self.get_current_wallet()?;
-        let mut wallet_guard = wallet.lock().unwrap();
+        let wallet_guard = wallet.lock().unwrap();
+        Ok(wallet_guard
+            .transactions()
+            .map(|tx| (*tx.tx_node.tx).clone())
+            .collect())
+    }
 
-        if start_height == 0 {
-            // Full rescan from genesis
-            log::info!("Performing full rescan from genesis");
-            // wallet_guard.reset_to_height(0)?;
-        } else if start_height > 0 {
-            // Partial rescan from a specific height
-            log::info!("Performing partial rescan from height {}", start_height);
-            // wallet_guard.reset_to_height(start_height as u32)?;
+    /// Group the wallet's addresses by common ownership, as Bitcoin Core's
+    /// `listaddressgroupings` does
+    /// Addresses spent from by the same transaction are grouped together with
+    /// the transaction's change, as anyone watching the chain would assume one
+    /// owner holds them all. Every other address is a group of its own.
+    pub fn list_address_groupings(&self) -> Result<Vec<Vec<AddressGroupEntry>>, WalletError> {
+        let transactions = self.list_transactions()?;
+        let labels = self.address_labels()?;
+        let by_txid: HashMap<Txid, &Transaction> = transactions
+            .iter()
+            .map(|tx| (tx.compute_txid(), tx))
+            .collect();
+
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let mut groups = ScriptGroups::default();
+        for tx in &transactions {
+            let mut linked: Vec<usize> = tx
+                .input
+                .iter()
+                .filter_map(|input| {
+                    let prevout = input.previous_output;
+                    by_txid
+                        .get(&prevout.txid)
+                        .and_then(|prev_tx| prev_tx.output.get(prevout.vout as usize))
+                })
+                .filter(|txout| wallet_guard.is_mine(txout.script_pubkey.clone()))
+                .map(|txout| groups.insert(&txout.script_pubkey))
+                .collect();
+            let spends_ours = !linked.is_empty();
+
+            for txout in &tx.output {
+                if !wallet_guard.is_mine(txout.script_pubkey.clone()) {
+                    continue;
+                }
+                let index = groups.insert(&txout.script_pubkey);
+                let is_change = matches!(
+                    wallet_guard.derivation_of_spk(txout.script_pubkey.clone()),
+                    Some((KeychainKind::Internal, _))
+                );
+                if spends_ours && is_change {
+                    linked.push(index);
+                }
+            }
+            for pair in linked.windows(2) {
+                groups.union(pair[0], pair[1]);
+            }
+        }
+
+        let mut balances: HashMap<ScriptBuf, Amount> = HashMap::new();
+        for output in wallet_guard.list_unspent() {
+            *balances.entry(output.txout.script_pubkey).or_default() += output.txout.value;
+        }
+        let mut groupings: Vec<Vec<AddressGroupEntry>> = groups
+            .into_groups()
+            .into_iter()
+            .map(|scripts| {
+                let mut entries: Vec<AddressGroupEntry> = scripts
+                    .into_iter()
+                    .filter_map(|script| {
+                        Some(AddressGroupEntry {
+                            address: Address::from_script(&script, self.network).ok()?,
+                            amount: balances.get(&script).copied().unwrap_or_default(),
+                            label: labels.get(&script).cloned(),
+                        })
+                    })
+                    .collect();
+                entries.sort_by_key(|entry| entry.address.to_string());
+                entries
+            })
+            .filter(|entries| !entries.is_empty())
+            .collect();
+        groupings.sort_by_key(|entries| entries[0].address.to_string());
+        Ok(groupings)
+    }
+
+    /// List wallet transactions with confirmation details, oldest first
+    pub fn list_transaction_details(&self) -> Result<Vec<WalletTransaction>, WalletError> {
+        let labels = self.address_labels()?;
+        // One query for all of it, rather than one per transaction
+        let mut tx_metadata: HashMap<Txid, TransactionMetadata> =
+            self.database.list_tx_metadata()?.into_iter().collect();
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let tip_height = wallet_guard.latest_checkpoint().height();
+
+        let mut details = Vec::new();
+        for wallet_tx in wallet_guard.transactions() {
+            let tx = wallet_tx.tx_node.tx.as_ref();
+            let txid = wallet_tx.tx_node.txid;
+            let metadata = tx_metadata
+                .remove(&txid)
+                .unwrap_or_else(unrecorded_tx_metadata);
+
+            let (category, amount, fee) = net_amount(&wallet_guard, tx);
+
+            // Pick the output that best describes the payment
+            let output = tx.output.iter().enumerate().find(|(_, txout)| {
+                wallet_guard.is_mine(txout.script_pubkey.clone())
+                    == (category == TransactionCategory::Receive)
+            });
+            let (address, vout) = match output {
+                Some((vout, txout)) => (
+                    Address::from_script(&txout.script_pubkey, self.network).ok(),
+                    Some(vout as u32),
+                ),
+                None => (None, None),
+            };
+            // The paid address's label takes precedence over one set on the transaction
+            let label = output
+                .and_then(|(_, txout)| labels.get(&txout.script_pubkey).cloned())
+                .unwrap_or(metadata.label);
+
+            let (block_hash, block_height, block_time, confirmations) =
+                confirmation_status(&wallet_tx.chain_position, tip_height);
+
+            details.push(WalletTransaction {
+                txid,
+                category,
+                address,
+                vout,
+                amount,
+                fee,
+                label,
+                confirmations,
+                block_hash,
+                block_height,
+                block_time,
+                time_received: metadata.timestamp,
+                abandoned: metadata.abandoned,
+            });
         }
 
-        // Process blocks in the specified range
-        for height in start_height..=actual_stop_height {
-            if let Ok(block) = blocktalk.chain().get_block(&tip_hash, height as i32).await {
+        details.sort_by_key(|tx| tx.block_time.unwrap_or(tx.time_received));
+        Ok(details)
+    }
+
+    /// Get the full details of a wallet transaction, or `None` if the wallet doesn't know it
+    pub async fn get_transaction_detail(
+        &self,
+        txid: &Txid,
+    ) -> Result<Option<TransactionDetail>, WalletError> {
+        let mut detail = {
+            let wallet = self.get_current_wallet()?;
+            let wallet_guard = wallet.lock().unwrap();
+            let tip_height = wallet_guard.latest_checkpoint().height();
+
+            let wallet_tx = match wallet_guard.get_tx(*txid) {
+                Some(wallet_tx) => wallet_tx,
+                None => return Ok(None),
+            };
+            let tx = wallet_tx.tx_node.tx.as_ref();
+            let metadata = self.get_or_create_tx_metadata(txid)?;
+
+            let (category, amount, fee) = net_amount(&wallet_guard, tx);
+            let details = output_details(tx, self.network, category, |script| {
                 wallet_guard
-                    .apply_block(&block, height as u32)
-                    .map_err(|e| {
-                        WalletError::Generic(format!("Failed to apply block during rescan: {}", e))
-                    })?;
-            } else {
-                log::warn!("Failed to retrieve block at height {}", height);
+                    .derivation_of_spk(script.to_owned())
+                    .map(|(keychain, _)| keychain)
+            });
+            let (block_hash, block_height, block_time, confirmations) =
+                confirmation_status(&wallet_tx.chain_position, tip_height);
+
+            TransactionDetail {
+                txid: *txid,
+                amount,
+                fee,
+                confirmations,
+                block_hash,
+                block_height,
+                block_index: None,
+                block_time,
+                time_received: metadata.timestamp,
+                comment: metadata.comment,
+                label: metadata.label,
+                abandoned: metadata.abandoned,
+                details,
+                transaction: tx.clone(),
+            }
+        };
+
+        // The wallet only tracks the containing block, so ask the node for the position in it
+        if let Some(block_hash) = detail.block_hash {
+            let blocktalk = self.get_blocktalk().await?;
+            if let Some(block) = blocktalk.chain().get_block_by_hash(&block_hash).await? {
+                detail.block_index = block
+                    .txdata
+                    .iter()
+                    .position(|block_tx| block_tx.compute_txid() == *txid);
             }
         }
 
-        log::info!(
-            "Blockchain rescan completed from {} to {}",
-            start_height,
-            actual_stop_height
-        );
-        Ok((start_height, actual_stop_height))
+        Ok(Some(detail))
     }
-}
 
-fn generate_descriptors(network: Network) -> Result<(String, String), WalletError> {
-    let secp = bitcoin::secp256k1::Secp256k1::new();
-    let mut rng = rand::thread_rng();
-    let xprv = bitcoin::bip32::ExtendedPrivKey::new_master(network, &mut rng.gen::<[u8; 32]>())
-        .map_err(|e| WalletError::Generic(format!("Failed to generate master key: {}", e)))?;
+    /// Abandon an unconfirmed transaction and every wallet transaction spending
+    /// its outputs, so the outputs they spent can be spent again
+    /// BDK treats them as evicted from the mempool, leaving them out of the
+    /// wallet's transactions and balance until they are seen again. A
+    /// transaction still in the node's mempool therefore comes back with its
+    /// next mempool notification, as in Bitcoin Core.
+    pub fn abandon_transaction(&self, txid: &Txid) -> Result<(), WalletError> {
+        let abandoned: Vec<Txid> = {
+            let wallet = self.get_current_wallet()?;
+            let mut wallet_guard = wallet.lock().unwrap();
+            let wallet_tx = wallet_guard
+                .get_tx(*txid)
+                .ok_or(WalletError::TransactionNotFound(*txid))?;
+            if wallet_tx.chain_position.is_confirmed() {
+                return Err(WalletError::Generic(
+                    "Transaction not eligible for abandonment".to_string(),
+                ));
+            }
+
+            let abandoned: Vec<Txid> = std::iter::once(*txid)
+                .chain(
+                    wallet_guard
+                        .tx_graph()
+                        .walk_descendants(*txid, |_, descendant| Some(descendant)),
+                )
+                .collect();
+            let now = chrono::Utc::now().timestamp() as u64;
+            wallet_guard.apply_evicted_txs(abandoned.iter().map(|txid| (*txid, now)));
+            self.database.persist_wallet(&mut wallet_guard)?;
+            abandoned
+        };
+
+        for txid in abandoned {
+            let mut metadata = self.get_or_create_tx_metadata(&txid)?;
+            metadata.abandoned = true;
+            self.database.store_tx_metadata(&txid, &metadata)?;
+            log::info!("Abandoned transaction {}", txid);
+        }
+        Ok(())
+    }
+
+    /// Fee paid by a wallet transaction, `None` if the wallet doesn't know the
+    /// transaction or an output it spends
+    /// The fee is recorded with the transaction's metadata once worked out.
+    pub fn get_transaction_fee(&self, txid: &Txid) -> Result<Option<Amount>, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let wallet_tx = match wallet_guard.get_tx(*txid) {
+            Some(wallet_tx) => wallet_tx,
+            None => return Ok(None),
+        };
+
+        let mut metadata = self.get_or_create_tx_metadata(txid)?;
+        if metadata.fee.is_some() {
+            return Ok(metadata.fee);
+        }
+        let fee = transaction_fee(&wallet_tx.tx_node.tx, |outpoint| {
+            wallet_guard.tx_graph().get_txout(outpoint).cloned()
+        });
+        if fee.is_some() {
+            metadata.fee = fee;
+            self.database.store_tx_metadata(txid, &metadata)?;
+        }
+        Ok(fee)
+    }
+
+    // Look up stored metadata, recording now as the time received if this is
+    // the first time the wallet has seen the transaction
+    fn get_or_create_tx_metadata(&self, txid: &Txid) -> Result<TransactionMetadata, WalletError> {
+        if let Some(metadata) = self.database.get_tx_metadata(txid)? {
+            return Ok(metadata);
+        }
+
+        let metadata = unrecorded_tx_metadata();
+        self.database.store_tx_metadata(txid, &metadata)?;
+        Ok(metadata)
+    }
+
+    /// Ask the node for a fee rate estimate, `None` if it doesn't have enough data
+    pub async fn estimate_smart_fee(
+        &self,
+        target_blocks: u32,
+        conservative: bool,
+    ) -> Result<Option<FeeRate>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk
+            .mempool()
+            .estimate_fee_rate(target_blocks, conservative)
+            .await?)
+    }
+
+    /// Height and hash of the node's active chain tip
+    pub async fn get_tip(&self) -> Result<(i32, BlockHash), WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.chain().get_tip().await?)
+    }
+
+    /// The node's tip and sync status
+    pub async fn get_chain_info(&self) -> Result<ChainInfo, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.chaininfo().await?)
+    }
+
+    /// Re-check the node's last `num_blocks` blocks, all of them if 0
+    /// Returns false if a block fails a check
+    pub async fn verify_chain(
+        &self,
+        check_level: u8,
+        num_blocks: u32,
+    ) -> Result<bool, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk
+            .chain()
+            .verify_chain(check_level, num_blocks)
+            .await?)
+    }
+
+    /// Whether the node is still in initial block download
+    pub async fn is_initial_block_download(&self) -> Result<bool, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(!blocktalk.chain().is_synced().await?)
+    }
+
+    /// Hash of the active chain block at `height`, `None` if it is above the tip
+    pub async fn get_block_hash(&self, height: i32) -> Result<Option<BlockHash>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        let (tip_height, _) = blocktalk.chain().get_tip().await?;
+        if height < 0 || height > tip_height {
+            return Ok(None);
+        }
+        Ok(Some(blocktalk.chain().get_block_hash(height).await?))
+    }
+
+    /// Fetch a block along with its chain context, `None` if the node doesn't have it
+    pub async fn get_block_details(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockDetails>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        let chain = blocktalk.chain();
+        let details = match chain.get_block_verbose(block_hash).await? {
+            Some(details) => details,
+            None => return Ok(None),
+        };
+
+        let median_time = chain.get_median_time_past(block_hash).await?;
+
+        Ok(Some(BlockDetails {
+            height: (details.confirmations >= 0).then_some(details.height),
+            confirmations: details.confirmations,
+            median_time,
+            next_block_hash: details.next_block_hash,
+            block: details.block,
+        }))
+    }
 
-    let external = format!("wpkh({}/0/*)", xprv);
-    let internal = format!("wpkh({}/1/*)", xprv);
+    /// Find a transaction in the mempool, or failing that in `block_hash` if given
+    /// Without a block hash confirmed transactions can't be found, as the node
+    /// offers no transaction index lookup over IPC.
+    pub async fn get_raw_transaction(
+        &self,
+        txid: &Txid,
+        block_hash: Option<&BlockHash>,
+    ) -> Result<Option<RawTransaction>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+
+        if let Some(transaction) = blocktalk.mempool().get_mempool_transaction(txid).await? {
+            return Ok(Some(RawTransaction {
+                transaction,
+                block_hash: None,
+                confirmations: 0,
+                block_time: None,
+            }));
+        }
+        let block_hash = match block_hash {
+            Some(block_hash) => block_hash,
+            None => return Ok(None),
+        };
+
+        let chain = blocktalk.chain();
+        let block = match chain.get_block_by_hash(block_hash).await? {
+            Some(block) => block,
+            None => return Err(WalletError::BlockNotFound(*block_hash)),
+        };
+        let transaction = match block
+            .txdata
+            .into_iter()
+            .find(|tx| tx.compute_txid() == *txid)
+        {
+            Some(transaction) => transaction,
+            None => return Ok(None),
+        };
+
+        let (tip_height, _) = chain.get_tip().await?;
+        let confirmations = match chain.get_block_height(block_hash).await? {
+            Some(height) => tip_height - height + 1,
+            None => -1,
+        };
+
+        Ok(Some(RawTransaction {
+            transaction,
+            block_hash: Some(*block_hash),
+            confirmations,
+            block_time: Some(block.header.time),
+        }))
+    }
+
+    pub async fn get_mempool_info(&self) -> Result<MempoolInfo, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.mempool().get_mempool_info().await?)
+    }
+
+    /// Virtual size of the mempool paying each range of fee rates, lowest first
+    pub async fn get_fee_histogram(&self) -> Result<Vec<FeeHistogramBucket>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.mempool().get_fee_histogram().await?)
+    }
+
+    /// Check whether the node would accept `txs` into its mempool, without
+    /// broadcasting them
+    pub async fn test_mempool_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<Vec<MempoolAcceptResult>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.mempool().test_mempool_accept(txs).await?)
+    }
+
+    /// Check whether the node would accept `txs`, parents before children, as
+    /// a package, without broadcasting them
+    pub async fn test_package_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<Vec<PackageAcceptResult>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.mempool().test_package_accept(txs).await?)
+    }
+
+    /// Submit `txs`, parents before children, to the node as a package,
+    /// rejecting it if any pays more than `max_fee_rate` (zero disables the
+    /// check)
+    pub async fn submit_package(
+        &self,
+        txs: &[Transaction],
+        max_fee_rate: FeeRate,
+    ) -> Result<Vec<PackageAcceptResult>, WalletError> {
+        log::info!("Submitting package of {} transactions", txs.len());
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk
+            .mempool()
+            .submit_package(txs, max_fee_rate)
+            .await?)
+    }
+
+    pub async fn get_network_info(&self) -> Result<NetworkInfo, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.network().get_network_info().await?)
+    }
+
+    pub async fn get_raw_mempool(&self) -> Result<Vec<Txid>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.mempool().get_raw_mempool().await?)
+    }
+
+    pub async fn get_mempool_entries(&self) -> Result<Vec<MempoolEntry>, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        Ok(blocktalk.mempool().get_mempool_entries().await?)
+    }
+
+    pub async fn estimate_fee_rate(&self, target_blocks: u32) -> Result<FeeRate, WalletError> {
+        match self.estimate_smart_fee(target_blocks, false).await? {
+            Some(fee_rate) => Ok(fee_rate),
+            None => {
+                log::warn!(
+                    "Node has insufficient data for fee estimation, using minimum relay fee rate"
+                );
+                Ok(FeeRate::BROADCAST_MIN)
+            }
+        }
+    }
+
+    /// Create an unsigned transaction paying the given recipients
+    /// When no fee rate is supplied the wallet's `paytxfee` preference is used,
+    /// otherwise one is estimated for a 2-block target.
+    /// The fee is split equally between the recipients listed in `subtract_fee_from`.
+    /// Coin selection always spends the outputs in `coin_control.must_use` and
+    /// never those in `coin_control.must_not_use`.
+    pub async fn create_transaction(
+        &self,
+        recipients: &[TxRecipient],
+        fee_rate: Option<FeeRate>,
+        subtract_fee_from: &[usize],
+        coin_control: &CoinControlOptions,
+    ) -> Result<Psbt, WalletError> {
+        let options = FundingOptions {
+            coin_control: coin_control.clone(),
+            ..Default::default()
+        };
+        self.fund_transaction(recipients, fee_rate, subtract_fee_from, &options)
+            .await
+    }
+
+    /// Create and fund an unsigned PSBT, returning it with its fee and the
+    /// position of the change output, or -1 if there is none
+    /// Inputs spending the wallet's coins carry their derivation paths so that
+    /// an external signer can tell which keys to sign with.
+    pub async fn create_funded_psbt(
+        &self,
+        recipients: &[TxRecipient],
+        fee_rate: Option<FeeRate>,
+        subtract_fee_indices: Vec<usize>,
+        options: &FundingOptions,
+    ) -> Result<(Psbt, Amount, i32), WalletError> {
+        let psbt = self
+            .fund_transaction(recipients, fee_rate, &subtract_fee_indices, options)
+            .await?;
+        let fee = psbt
+            .fee()
+            .map_err(|e| WalletError::Generic(format!("Failed to calculate fee: {}", e)))?;
+
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let change_position = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .position(|output| {
+                options.change_script.as_ref() == Some(&output.script_pubkey)
+                    || matches!(
+                        wallet_guard.derivation_of_spk(output.script_pubkey.clone()),
+                        Some((KeychainKind::Internal, _))
+                    )
+            })
+            .map_or(-1, |position| position as i32);
 
-    Ok((external, internal))
+        Ok((psbt, fee, change_position))
+    }
+
+    async fn fund_transaction(
+        &self,
+        recipients: &[TxRecipient],
+        fee_rate: Option<FeeRate>,
+        subtract_fee_from: &[usize],
+        options: &FundingOptions,
+    ) -> Result<Psbt, WalletError> {
+        let fee_rate = match fee_rate.or(self.get_pay_tx_fee()?) {
+            Some(fee_rate) => fee_rate,
+            None => self.estimate_fee_rate(DEFAULT_CONFIRMATION_TARGET).await?,
+        };
+        log::debug!(
+            "Creating transaction with fee rate {} sat/vB",
+            fee_rate.to_sat_per_vb_ceil()
+        );
+
+        // Locked outputs are only spent when asked for explicitly
+        let mut options = options.clone();
+        for outpoint in self.database.list_locked_outpoints()? {
+            if !options.inputs.contains(&outpoint)
+                && !options.coin_control.must_use.contains(&outpoint)
+            {
+                options.coin_control.must_not_use.push(outpoint);
+            }
+        }
+
+        let wallet = self.get_current_wallet()?;
+        let mut wallet_guard = wallet.lock().unwrap();
+        let psbt = build_psbt(&mut wallet_guard, recipients, fee_rate, None, &options)?;
+
+        if subtract_fee_from.is_empty() {
+            return Ok(psbt);
+        }
+
+        // BDK cannot take the fee out of recipient amounts, so rebuild the
+        // transaction with the reduced amounts and the fee fixed at what the
+        // first attempt needed
+        let fee = psbt
+            .fee()
+            .map_err(|e| WalletError::Generic(format!("Failed to calculate fee: {}", e)))?;
+        let adjusted = subtract_fee(recipients, subtract_fee_from, fee)?;
+        build_psbt(&mut wallet_guard, &adjusted, fee_rate, Some(fee), &options)
+    }
+
+    /// Sign the wallet's inputs of a PSBT and finalize what can be, returning
+    /// whether every input is finalized
+    pub fn sign_psbt(&self, psbt: &mut Psbt) -> Result<bool, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let sign_options = SignOptions {
+            // Sighash types set in the PSBT were chosen by whoever created it
+            allow_all_sighashes: psbt.inputs.iter().any(|input| input.sighash_type.is_some()),
+            ..Default::default()
+        };
+        wallet_guard
+            .sign(psbt, sign_options)
+            .map_err(|e| WalletError::Generic(format!("Failed to sign transaction: {}", e)))
+    }
+
+    /// Add the wallet's knowledge of its coins to a PSBT: the spent outputs,
+    /// scripts and key derivation paths of every input the wallet owns
+    pub fn update_psbt(
+        &self,
+        psbt: &mut Psbt,
+        sighash_type: Option<PsbtSighashType>,
+    ) -> Result<(), WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        for (index, input) in psbt.unsigned_tx.input.iter().enumerate() {
+            if let Some(utxo) = wallet_guard.get_utxo(input.previous_output) {
+                let wallet_input = wallet_guard
+                    .get_psbt_input(utxo, sighash_type, false)
+                    .map_err(|e| WalletError::Generic(format!("Failed to prepare input: {}", e)))?;
+                psbt.inputs[index].combine(wallet_input);
+                if sighash_type.is_some() {
+                    psbt.inputs[index].sighash_type = sighash_type;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Finalize the inputs of a PSBT, returning the network serializable
+    /// transaction once every input is finalized, or `None` while signatures
+    /// are still missing
+    pub fn finalize_psbt(&self, psbt: &mut Psbt) -> Result<Option<Transaction>, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let complete = wallet_guard
+            .finalize_psbt(psbt, SignOptions::default())
+            .map_err(|e| WalletError::Generic(format!("Failed to finalize PSBT: {}", e)))?;
+        if !complete {
+            return Ok(None);
+        }
+
+        let tx = psbt
+            .clone()
+            .extract_tx()
+            .map_err(|e| WalletError::Generic(format!("Failed to extract transaction: {}", e)))?;
+        if tx.is_coinbase() {
+            return Err(WalletError::Generic(
+                "Cannot extract a coinbase transaction from a PSBT".to_string(),
+            ));
+        }
+        if tx.weight().to_wu() > MAX_STANDARD_TX_WEIGHT as u64 {
+            return Err(WalletError::Generic(format!(
+                "Transaction weight {} exceeds the standard limit of {}",
+                tx.weight().to_wu(),
+                MAX_STANDARD_TX_WEIGHT
+            )));
+        }
+        Ok(Some(tx))
+    }
+
+    /// Sign the wallet's inputs of a raw transaction in place, returning whether
+    /// every input is now signed
+    /// Inputs the wallet doesn't own are left untouched. Any existing signatures
+    /// on inputs we own are replaced.
+    pub fn sign_transaction(
+        &self,
+        tx: &mut Transaction,
+        sighash_type: Option<PsbtSighashType>,
+    ) -> Result<bool, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+
+        let mut unsigned = tx.clone();
+        for input in unsigned.input.iter_mut() {
+            input.script_sig = ScriptBuf::new();
+            input.witness = Witness::new();
+        }
+        let mut psbt = Psbt::from_unsigned_tx(unsigned)
+            .map_err(|e| WalletError::Generic(format!("Failed to create PSBT: {}", e)))?;
+
+        for (index, input) in tx.input.iter().enumerate() {
+            if let Some(utxo) = wallet_guard.get_utxo(input.previous_output) {
+                psbt.inputs[index] = wallet_guard
+                    .get_psbt_input(utxo, sighash_type, false)
+                    .map_err(|e| WalletError::Generic(format!("Failed to prepare input: {}", e)))?;
+            }
+        }
+
+        let sign_options = SignOptions {
+            // A non-default sighash type was asked for explicitly
+            allow_all_sighashes: sighash_type.is_some(),
+            ..Default::default()
+        };
+        wallet_guard
+            .sign(&mut psbt, sign_options)
+            .map_err(|e| WalletError::Generic(format!("Failed to sign transaction: {}", e)))?;
+
+        for (input, psbt_input) in tx.input.iter_mut().zip(psbt.inputs.iter()) {
+            if let Some(script_sig) = &psbt_input.final_script_sig {
+                input.script_sig = script_sig.clone();
+            }
+            if let Some(witness) = &psbt_input.final_script_witness {
+                input.witness = witness.clone();
+            }
+        }
+
+        Ok(tx
+            .input
+            .iter()
+            .all(|input| !input.script_sig.is_empty() || !input.witness.is_empty()))
+    }
+
+    /// Broadcast a transaction through the node, returning its txid
+    pub async fn broadcast_transaction(&self, tx: &Transaction) -> Result<Txid, WalletError> {
+        self.broadcast_transaction_with_max_fee(tx, DEFAULT_MAX_TX_FEE)
+            .await
+    }
+
+    /// Broadcast a transaction through the node, which rejects it if it pays
+    /// more than `max_tx_fee` (zero disables the check)
+    pub async fn broadcast_transaction_with_max_fee(
+        &self,
+        tx: &Transaction,
+        max_tx_fee: Amount,
+    ) -> Result<Txid, WalletError> {
+        let txid = tx.compute_txid();
+        log::info!("Broadcasting transaction {}", txid);
+
+        let blocktalk = self.get_blocktalk().await?;
+        let (error, accepted) = blocktalk
+            .mempool()
+            .broadcast_transaction(tx, max_tx_fee.to_sat() as i64, true)
+            .await?;
+        if !accepted {
+            log::error!("Transaction {} rejected: {}", txid, error);
+            return Err(WalletError::TransactionRejected(error));
+        }
+
+        log::info!("Transaction {} broadcast successfully", txid);
+        Ok(txid)
+    }
+
+    /// Lowest fee rate a replacement of the wallet transaction `txid` may pay
+    pub fn min_bump_fee_rate(&self, txid: &Txid) -> Result<FeeRate, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let wallet_guard = wallet.lock().unwrap();
+        let wallet_tx = wallet_guard
+            .get_tx(*txid)
+            .ok_or(WalletError::TransactionNotFound(*txid))?;
+        let fee_rate = wallet_guard
+            .calculate_fee_rate(&wallet_tx.tx_node.tx)
+            .map_err(|e| WalletError::Generic(format!("Failed to calculate fee: {}", e)))?;
+        Ok(FeeRate::from_sat_per_kwu(
+            fee_rate.to_sat_per_kwu() + INCREMENTAL_RELAY_FEE.to_sat_per_kwu(),
+        ))
+    }
+
+    /// Replace an unconfirmed wallet transaction with one paying `new_fee_rate`,
+    /// returning the txid of the broadcast replacement
+    /// The extra fee comes out of the change, with more inputs added if the
+    /// change doesn't cover it.
+    pub async fn bump_fee(&self, txid: &Txid, new_fee_rate: FeeRate) -> Result<Txid, WalletError> {
+        let tx = self.create_fee_bump(txid, new_fee_rate)?;
+        let new_txid = self.broadcast_transaction(&tx).await?;
+
+        // Record the replacement straight away so the wallet stops counting
+        // the original and the replacement itself can be bumped
+        let wallet = self.get_current_wallet()?;
+        wallet
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(tx, chrono::Utc::now().timestamp() as u64)]);
+
+        let mut metadata = self.get_or_create_tx_metadata(txid)?;
+        metadata.timestamp = chrono::Utc::now().timestamp() as u64;
+        metadata.block_height = None;
+        self.database.store_tx_metadata(&new_txid, &metadata)?;
+
+        log::info!("Replaced transaction {} with {}", txid, new_txid);
+        Ok(new_txid)
+    }
+
+    // Build and sign a replacement for `txid` paying `new_fee_rate`
+    fn create_fee_bump(
+        &self,
+        txid: &Txid,
+        new_fee_rate: FeeRate,
+    ) -> Result<Transaction, WalletError> {
+        let min_fee_rate = self.min_bump_fee_rate(txid)?;
+        if new_fee_rate < min_fee_rate {
+            return Err(WalletError::Generic(format!(
+                "Insufficient total fee rate, must be at least {} sat/vB",
+                min_fee_rate.to_sat_per_vb_ceil()
+            )));
+        }
+
+        let wallet = self.get_current_wallet()?;
+        let mut wallet_guard = wallet.lock().unwrap();
+        let original = wallet_guard
+            .get_tx(*txid)
+            .ok_or(WalletError::TransactionNotFound(*txid))?;
+        if let ChainPosition::Confirmed { .. } = original.chain_position {
+            return Err(WalletError::Generic(
+                "Transaction has been mined, or is conflicted with a mined transaction".to_string(),
+            ));
+        }
+        if !original
+            .tx_node
+            .tx
+            .input
+            .iter()
+            .all(|input| input.sequence.is_rbf())
+        {
+            return Err(WalletError::Generic(
+                "Transaction is not BIP 125 replaceable".to_string(),
+            ));
+        }
+
+        let mut builder = wallet_guard
+            .build_fee_bump(*txid)
+            .map_err(|e| WalletError::Generic(format!("Failed to bump fee: {}", e)))?;
+        builder.fee_rate(new_fee_rate);
+        let mut psbt = builder.finish().map_err(create_tx_error)?;
+
+        let finalized = wallet_guard
+            .sign(&mut psbt, SignOptions::default())
+            .map_err(|e| WalletError::Generic(format!("Failed to sign transaction: {}", e)))?;
+        if !finalized {
+            return Err(WalletError::Generic(
+                "Transaction could not be fully signed".to_string(),
+            ));
+        }
+        psbt.extract_tx()
+            .map_err(|e| WalletError::Generic(format!("Failed to extract transaction: {}", e)))
+    }
+
+    /// Speed up an unconfirmed transaction by spending its output `vout` in a
+    /// child paying enough for the two together to reach `target_fee_rate`,
+    /// returning the child's txid
+    /// The output must be the wallet's, and the child pays what's left of it
+    /// back to the wallet. The parent's fee is the wallet's own record when it
+    /// funded the parent and otherwise the node's. A parent already in the
+    /// mempool only needs the child broadcast; otherwise the two are submitted
+    /// as a package, which the node only takes if the parent pays the minimum
+    /// relay fee on its own.
+    pub async fn create_cpfp(
+        &self,
+        parent_txid: &Txid,
+        vout: u32,
+        target_fee_rate: FeeRate,
+    ) -> Result<Txid, WalletError> {
+        let parent_fee = match self.get_transaction_fee(parent_txid)? {
+            Some(fee) => fee,
+            None => {
+                let blocktalk = self.get_blocktalk().await?;
+                blocktalk
+                    .mempool()
+                    .get_mempool_entry(parent_txid)
+                    .await?
+                    .and_then(|entry| entry.fee)
+                    .map(|fee| Amount::from_sat(fee as u64))
+                    .ok_or_else(|| {
+                        WalletError::Generic(format!(
+                            "Fee of transaction {} is not known",
+                            parent_txid
+                        ))
+                    })?
+            }
+        };
+
+        let wallet = self.get_current_wallet()?;
+        let parent = wallet
+            .lock()
+            .unwrap()
+            .get_tx(*parent_txid)
+            .map(|parent| (*parent.tx_node.tx).clone())
+            .ok_or(WalletError::TransactionNotFound(*parent_txid))?;
+        let child = self.create_cpfp_child(parent_txid, vout, target_fee_rate, parent_fee)?;
+        let txid = child.compute_txid();
+        let blocktalk = self.get_blocktalk().await?;
+        if blocktalk.mempool().is_in_mempool(parent_txid).await? {
+            self.broadcast_transaction(&child).await?;
+        } else {
+            // The child's fee was worked out here to pay for its parent, so
+            // there is no user maximum to hold it to
+            let results = self
+                .submit_package(&[parent, child.clone()], FeeRate::ZERO)
+                .await?;
+            if let Some(error) = results.into_iter().find_map(|result| result.error) {
+                log::error!(
+                    "Package of {} and {} rejected: {}",
+                    parent_txid,
+                    txid,
+                    error
+                );
+                return Err(WalletError::TransactionRejected(error));
+            }
+        }
+        wallet
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(child, chrono::Utc::now().timestamp() as u64)]);
+
+        log::info!("Bumped transaction {} with child {}", parent_txid, txid);
+        Ok(txid)
+    }
+
+    // Build and sign a child of `parent_txid` spending its output `vout`, so
+    // the pair pays `target_fee_rate` given the parent pays `parent_fee`
+    fn create_cpfp_child(
+        &self,
+        parent_txid: &Txid,
+        vout: u32,
+        target_fee_rate: FeeRate,
+        parent_fee: Amount,
+    ) -> Result<Transaction, WalletError> {
+        let wallet = self.get_current_wallet()?;
+        let mut wallet_guard = wallet.lock().unwrap();
+        let parent = wallet_guard
+            .get_tx(*parent_txid)
+            .ok_or(WalletError::TransactionNotFound(*parent_txid))?;
+        if let ChainPosition::Confirmed { .. } = parent.chain_position {
+            return Err(WalletError::Generic(
+                "Transaction has been mined, or is conflicted with a mined transaction".to_string(),
+            ));
+        }
+        let parent_vsize = parent.tx_node.tx.vsize() as u64;
+        let outpoint = OutPoint::new(*parent_txid, vout);
+        if wallet_guard.get_utxo(outpoint).is_none() {
+            return Err(WalletError::Generic(format!(
+                "Output {} is not an unspent output of the wallet",
+                outpoint
+            )));
+        }
+        let drain_script = wallet_guard
+            .next_unused_address(KeychainKind::Internal)
+            .script_pubkey();
+
+        // The child's size doesn't depend on its fee, so a draft gives it
+        let draft = build_cpfp_child(
+            &mut wallet_guard,
+            outpoint,
+            &drain_script,
+            target_fee_rate,
+            None,
+        )?;
+        let child_vsize = draft.vsize() as u64;
+        let package_fee = target_fee_rate
+            .fee_vb(parent_vsize + child_vsize)
+            .ok_or_else(|| WalletError::Generic("Fee rate is too high".to_string()))?;
+        let child_fee = package_fee
+            .checked_sub(parent_fee)
+            .filter(|fee| *fee > Amount::ZERO)
+            .ok_or_else(|| {
+                WalletError::Generic(format!(
+                    "Transaction already pays at least {} sat/vB",
+                    target_fee_rate.to_sat_per_vb_floor()
+                ))
+            })?;
+        // The child must be relayed on its own merits too
+        let min_child_fee = FeeRate::BROADCAST_MIN
+            .fee_vb(child_vsize)
+            .unwrap_or_default();
+
+        build_cpfp_child(
+            &mut wallet_guard,
+            outpoint,
+            &drain_script,
+            target_fee_rate,
+            Some(child_fee.max(min_child_fee)),
+        )
+    }
+
+    /// Pay `amount` to `address`, returning the txid of the broadcast transaction
+    pub async fn send_to_address(
+        &self,
+        address: &Address,
+        amount: Amount,
+        subtract_fee: bool,
+        fee_rate: Option<FeeRate>,
+        comment: &str,
+        coin_control: &CoinControlOptions,
+    ) -> Result<Txid, WalletError> {
+        let recipient = TxRecipient {
+            script: address.script_pubkey(),
+            amount,
+        };
+        let subtract_fee_from: &[usize] = if subtract_fee { &[0] } else { &[] };
+
+        let mut psbt = self
+            .create_transaction(&[recipient], fee_rate, subtract_fee_from, coin_control)
+            .await?;
+        if !self.sign_psbt(&mut psbt)? {
+            return Err(WalletError::Generic(
+                "Transaction could not be fully signed".to_string(),
+            ));
+        }
+        let tx = psbt
+            .extract_tx()
+            .map_err(|e| WalletError::Generic(format!("Failed to extract transaction: {}", e)))?;
+
+        let txid = self.broadcast_transaction(&tx).await?;
+
+        let mut metadata = self.get_or_create_tx_metadata(&txid)?;
+        metadata.comment = comment.to_string();
+        self.database.store_tx_metadata(&txid, &metadata)?;
+
+        Ok(txid)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Path of the wallet's database file
+    pub fn path(&self) -> &Path {
+        self.database.path()
+    }
+
+    /// Socket of the node the wallet syncs with
+    pub fn node_socket(&self) -> &str {
+        &self.node_socket
+    }
+
+    /// Names of the wallets in the same directory as this one, in order
+    pub fn list_wallet_dir(&self) -> Result<Vec<String>, WalletError> {
+        let wallet_dir = self.path().parent().unwrap_or(Path::new(""));
+        Ok(super::manager::wallet_files(wallet_dir)?
+            .into_keys()
+            .collect())
+    }
+
+    /// Start a rescan in the background, returning immediately
+    /// Fails if a rescan is already running.
+    pub fn start_rescan(
+        self: &Arc<Self>,
+        start_height: i32,
+        stop_height: Option<i32>,
+    ) -> Result<(), WalletError> {
+        let mut handle = self.rescan_handle.lock().unwrap();
+        if handle.as_ref().is_some_and(|handle| !handle.is_finished()) {
+            return Err(WalletError::Generic(
+                "Wallet is currently rescanning. Abort existing rescan or wait.".to_string(),
+            ));
+        }
+
+        self.rescan_progress.store(0, Ordering::SeqCst);
+        self.rescan_height.store(start_height - 1, Ordering::SeqCst);
+        let progress = self.rescan_progress.clone();
+        let current_height = self.rescan_height.clone();
+        let report: Box<dyn Fn(i32, i32) + Send> = Box::new(move |height, stop_height| {
+            current_height.store(height, Ordering::SeqCst);
+            progress.store(
+                rescan_progress(start_height, stop_height, height),
+                Ordering::SeqCst,
+            );
+        });
+        let wallet_interface = self.clone();
+        // The node client is not Send, so the rescan gets its own thread with a
+        // single-threaded runtime rather than running on the RPC server's threads
+        let thread = std::thread::Builder::new()
+            .name("rescan".to_string())
+            .spawn(move || {
+                let rt = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+
+                let local = LocalSet::new();
+                let result = rt.block_on(local.run_until(async {
+                    tokio::task::spawn_local(async move {
+                        wallet_interface
+                            .rescan_blockchain(start_height, stop_height, Some(report))
+                            .await
+                    })
+                    .await
+                }));
+                match result {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => log::error!("Background rescan failed: {}", e),
+                    Err(e) => log::error!("Background rescan panicked: {}", e),
+                }
+            })
+            .map_err(|e| WalletError::Generic(format!("Failed to start rescan: {}", e)))?;
+
+        *handle = Some(thread);
+        Ok(())
+    }
+
+    /// Fraction of the active rescan completed, or `None` if no rescan is running
+    pub fn rescan_progress(&self) -> Option<f64> {
+        let handle = self.rescan_handle.lock().unwrap();
+        match handle.as_ref() {
+            Some(handle) if !handle.is_finished() => Some(
+                self.rescan_progress.load(Ordering::SeqCst) as f64 / RESCAN_PROGRESS_SCALE as f64,
+            ),
+            _ => None,
+        }
+    }
+
+    /// Height of the last block scanned by the active rescan, or `None` if no
+    /// rescan is running
+    pub fn rescan_height(&self) -> Option<i32> {
+        let handle = self.rescan_handle.lock().unwrap();
+        match handle.as_ref() {
+            Some(handle) if !handle.is_finished() => {
+                Some(self.rescan_height.load(Ordering::SeqCst))
+            }
+            _ => None,
+        }
+    }
+
+    /// Rescan blocks from `start_height` to `stop_height`, or the tip if not given,
+    /// returning the range scanned
+    /// `progress` is called with the height of each block once it is scanned
+    /// and the height the rescan stops at.
+    pub async fn rescan_blockchain(
+        &self,
+        start_height: i32,
+        stop_height: Option<i32>,
+        progress: Option<Box<dyn Fn(i32, i32) + Send>>,
+    ) -> Result<(i32, i32), WalletError> {
+        log::info!(
+            "Rescanning blockchain from height {} to {:?}",
+            start_height,
+            stop_height
+        );
+
+        let blocktalk = self.get_blocktalk().await?;
+        let (tip_height, _) = blocktalk.chain().get_tip().await?;
+        log::info!("Current blockchain tip is at height {}", tip_height);
+
+        let stop_height = stop_height.unwrap_or(tip_height);
+        let actual_stop_height = std::cmp::min(stop_height, tip_height);
+
+        if start_height == 0 {
+            // Full rescan from genesis
+            log::info!("Performing full rescan from genesis");
+            // wallet_guard.reset_to_height(0)?;
+        } else if start_height > 0 {
+            // Partial rescan from a specific height
+            log::info!("Performing partial rescan from height {}", start_height);
+            // wallet_guard.reset_to_height(start_height as u32)?;
+        }
+
+        let chain = Blockchain::new(blocktalk.connection().clone());
+        let blocks = chain.block_stream(start_height, actual_stop_height);
+        self.scan_blocks(blocks, actual_stop_height, progress.as_deref())
+            .await?;
+
+        log::info!(
+            "Blockchain rescan completed from {} to {}",
+            start_height,
+            actual_stop_height
+        );
+        Ok((start_height, actual_stop_height))
+    }
+
+    /// Rescan like `rescan_blockchain`, starting from the first block that could
+    /// contain transactions made at or after `timestamp`
+    pub async fn rescan_blockchain_from_timestamp(
+        &self,
+        timestamp: u64,
+        stop_height: Option<i32>,
+        progress: Option<Box<dyn Fn(i32, i32) + Send>>,
+    ) -> Result<(i32, i32), WalletError> {
+        let start_height = self.rescan_start_height(timestamp).await?;
+        log::info!(
+            "Rescanning from height {} for transactions after timestamp {}",
+            start_height,
+            timestamp
+        );
+        self.rescan_blockchain(start_height, stop_height, progress)
+            .await
+    }
+
+    /// Rescan like `rescan_blockchain`, sending `(current_height, stop_height)`
+    /// to `progress` as each block is scanned
+    /// Updates are dropped rather than holding up the rescan while the channel
+    /// is full, except for the last one.
+    pub async fn rescan_blockchain_with_channel(
+        &self,
+        start_height: i32,
+        stop_height: Option<i32>,
+        progress: mpsc::Sender<(i32, i32)>,
+    ) -> Result<(i32, i32), WalletError> {
+        let dropped = Arc::new(Mutex::new(None));
+        let report: Box<dyn Fn(i32, i32) + Send> = {
+            let progress = progress.clone();
+            let dropped = dropped.clone();
+            Box::new(move |height, stop_height| {
+                let update = (height, stop_height);
+                *dropped.lock().unwrap() = progress.try_send(update).err().map(|_| update);
+            })
+        };
+
+        let result = self
+            .rescan_blockchain(start_height, stop_height, Some(report))
+            .await;
+        let last_update = dropped.lock().unwrap().take();
+        if let Some(update) = last_update {
+            // Fails only if the receiver has gone away
+            let _ = progress.send(update).await;
+        }
+        result
+    }
+
+    /// Check a descriptor and describe it like Bitcoin Core's `getdescriptorinfo`
+    /// The descriptor is returned in canonical form, with any private keys
+    /// replaced by their public keys and its checksum appended. `addr(...)` and
+    /// `raw(...)` descriptors are accepted but are not solvable.
+    pub fn parse_descriptor(&self, desc: &str) -> Result<DescriptorInfo, WalletError> {
+        descriptor_info(desc, self.network)
+    }
+
+    /// Derive the addresses of a descriptor, over the inclusive `range` of
+    /// indices if it is ranged. The descriptor doesn't need to be in the wallet.
+    /// Derived addresses are cached, as recovery tools tend to ask for the same
+    /// ranges repeatedly.
+    pub fn derive_addresses(
+        &self,
+        desc: &str,
+        range: Option<(u32, u32)>,
+    ) -> Result<Vec<Address>, WalletError> {
+        let body = desc.split('#').next().unwrap_or(desc);
+        if let Some(script) = addr_or_raw_script(body, self.network)? {
+            let address = Address::from_script(&script, self.network).map_err(|_| {
+                WalletError::InvalidDescriptor(
+                    "Descriptor does not have a corresponding address".to_string(),
+                )
+            })?;
+            return Ok(vec![address]);
+        }
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, desc)
+            .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+        let (start, end) = match (descriptor.has_wildcard(), range) {
+            (true, Some(range)) => range,
+            (false, None) => (0, 0),
+            (true, None) => {
+                return Err(WalletError::InvalidDescriptor(
+                    "Range must be specified for a ranged descriptor".to_string(),
+                ))
+            }
+            (false, Some(_)) => {
+                return Err(WalletError::InvalidDescriptor(
+                    "Range should not be specified for an un-ranged descriptor".to_string(),
+                ))
+            }
+        };
+
+        let key = descriptor.to_string();
+        let mut cache = self.derived_addresses.lock().unwrap();
+        (start..=end)
+            .map(|index| {
+                if let Some(address) = cache.get(&(key.clone(), index)) {
+                    return Ok(address.clone());
+                }
+                let address = descriptor
+                    .at_derivation_index(index)
+                    .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?
+                    .address(self.network)
+                    .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+                cache.insert((key.clone(), index), address.clone());
+                Ok(address)
+            })
+            .collect()
+    }
+
+    /// Find the unspent outputs paying to `descriptors`, like Bitcoin Core's
+    /// `scantxoutset`
+    ///
+    /// Descriptors may be `addr(...)`, `raw(...)` or output descriptors, and
+    /// ranged descriptors are searched over their first 1000 indices. The IPC
+    /// interface has no access to the node's UTXO set, so the whole chain is
+    /// scanned instead, following matching outputs until they are spent. Only
+    /// one scan runs at a time, and it can be stopped by `abort_scan_txout_set`.
+    pub async fn scan_txout_set(&self, descriptors: &[String]) -> Result<ScanResult, WalletError> {
+        let scripts = scan_scripts(descriptors, self.network)?;
+        if self.scan_running.swap(true, Ordering::SeqCst) {
+            return Err(WalletError::Generic(
+                "Scan already in progress, use action \"abort\" or \"status\"".to_string(),
+            ));
+        }
+        self.scan_abort.store(false, Ordering::SeqCst);
+        self.scan_progress.store(0, Ordering::SeqCst);
+        let result = self.scan_chain(&scripts).await;
+        self.scan_running.store(false, Ordering::SeqCst);
+        result
+    }
+
+    /// Stop a running `scan_txout_set`, returning whether there was one
+    pub fn abort_scan_txout_set(&self) -> bool {
+        let running = self.scan_running.load(Ordering::SeqCst);
+        if running {
+            self.scan_abort.store(true, Ordering::SeqCst);
+        }
+        running
+    }
+
+    /// Fraction of the running `scan_txout_set` completed, or `None` if no
+    /// scan is running
+    pub fn scan_txout_set_progress(&self) -> Option<f64> {
+        self.scan_running.load(Ordering::SeqCst).then(|| {
+            self.scan_progress.load(Ordering::SeqCst) as f64 / RESCAN_PROGRESS_SCALE as f64
+        })
+    }
+
+    async fn scan_chain(
+        &self,
+        scripts: &HashMap<ScriptBuf, String>,
+    ) -> Result<ScanResult, WalletError> {
+        let blocktalk = self.get_blocktalk().await?;
+        let (tip_height, best_block) = blocktalk.chain().get_tip().await?;
+        log::info!(
+            "Scanning {} blocks for {} scripts",
+            tip_height + 1,
+            scripts.len()
+        );
+
+        let chain = Blockchain::new(blocktalk.connection().clone());
+        let mut blocks = chain.block_stream(0, tip_height);
+        let mut unspents = BTreeMap::new();
+        while let Some(result) = blocks.next().await {
+            if self.scan_abort.load(Ordering::SeqCst) {
+                log::info!("Scan aborted");
+                return Err(WalletError::Generic("Scan aborted".to_string()));
+            }
+            let (height, block) = result?;
+            scan_block(&block, height as u32, scripts, &mut unspents);
+            self.scan_progress
+                .store(rescan_progress(0, tip_height, height), Ordering::SeqCst);
+        }
+
+        let mut unspents: Vec<ScanUnspent> = unspents.into_values().collect();
+        unspents.sort_by_key(|unspent| unspent.height);
+        let total_amount = unspents.iter().map(|unspent| unspent.amount).sum();
+        log::info!("Scan found {} unspent outputs", unspents.len());
+        Ok(ScanResult {
+            unspents,
+            total_amount,
+            height: tip_height,
+            best_block,
+        })
+    }
+
+    // Apply scanned blocks in order, only holding the wallet lock while applying
+    // each block so other requests can proceed
+    async fn scan_blocks(
+        &self,
+        mut blocks: impl Stream<Item = Result<(i32, Block), BlockTalkError>> + Unpin,
+        stop_height: i32,
+        progress: Option<&(dyn Fn(i32, i32) + Send)>,
+    ) -> Result<(), WalletError> {
+        let wallet = self.get_current_wallet()?;
+        while let Some(result) = blocks.next().await {
+            let (height, block) = result?;
+            wallet
+                .lock()
+                .unwrap()
+                .apply_block(&block, height as u32)
+                .map_err(|e| {
+                    WalletError::Generic(format!("Failed to apply block during rescan: {}", e))
+                })?;
+            if let Some(progress) = progress {
+                progress(height, stop_height);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Apply blocks that may arrive in any order, starting from `start_height`
+// Every block must be applied after its parent, so early arrivals are buffered
+async fn apply_blocks_in_order(
+    wallet: &mut Wallet,
+    start_height: i32,
+    blocks: impl Stream<Item = Result<(i32, Block), BlockTalkError>>,
+) -> Result<(), WalletError> {
+    let mut blocks = std::pin::pin!(blocks);
+    let mut pending = BTreeMap::new();
+    let mut next_height = start_height as u32;
+    while let Some(result) = blocks.next().await {
+        let (height, block) = result?;
+        pending.insert(height as u32, block);
+        while let Some(block) = pending.remove(&next_height) {
+            wallet
+                .apply_block(&block, next_height)
+                .map_err(|e| WalletError::Generic(format!("Failed to apply block: {}", e)))?;
+            next_height += 1;
+        }
+    }
+
+    if let Some(height) = pending.keys().next() {
+        return Err(WalletError::Generic(format!(
+            "Missing block at height {} before block at height {}",
+            next_height, height
+        )));
+    }
+    Ok(())
+}
+
+// Whether a transaction pays to the wallet or spends one of its outputs
+fn is_relevant(wallet: &Wallet, tx: &Transaction) -> bool {
+    tx.output
+        .iter()
+        .any(|output| wallet.is_mine(output.script_pubkey.clone()))
+        || tx
+            .input
+            .iter()
+            .any(|input| wallet.get_utxo(input.previous_output).is_some())
+}
+
+// Scaled fraction of the blocks from `start_height` to `stop_height` processed
+// once `height` has been scanned
+fn rescan_progress(start_height: i32, stop_height: i32, height: i32) -> u32 {
+    let total = (stop_height - start_height + 1).max(1) as u64;
+    let done = (height - start_height + 1).clamp(0, total as i32) as u64;
+    (done * RESCAN_PROGRESS_SCALE as u64 / total) as u32
+}
+
+// Fee paid by a transaction, looking up the outputs it spends with `prevout`
+// `None` if any of them is unknown; coinbase transactions pay no fee
+fn transaction_fee(
+    tx: &Transaction,
+    prevout: impl Fn(OutPoint) -> Option<TxOut>,
+) -> Option<Amount> {
+    if tx.is_coinbase() {
+        return Some(Amount::ZERO);
+    }
+    let mut input_value = Amount::ZERO;
+    for input in &tx.input {
+        input_value += prevout(input.previous_output)?.value;
+    }
+    let output_value: Amount = tx.output.iter().map(|output| output.value).sum();
+    input_value.checked_sub(output_value)
+}
+
+// Classify a transaction from the wallet's point of view, returning its net
+// amount and, for transactions the wallet funded, the fee paid
+fn net_amount(
+    wallet: &Wallet,
+    tx: &Transaction,
+) -> (TransactionCategory, SignedAmount, Option<Amount>) {
+    let (sent, received) = wallet.sent_and_received(tx);
+    let category = if sent > received {
+        TransactionCategory::Send
+    } else {
+        TransactionCategory::Receive
+    };
+    let fee = match category {
+        TransactionCategory::Send => transaction_fee(tx, |outpoint| {
+            wallet.tx_graph().get_txout(outpoint).cloned()
+        }),
+        TransactionCategory::Receive => None,
+    };
+    // Core reports sends without the fee, which is listed separately
+    let amount = SignedAmount::from_sat(
+        received.to_sat() as i64 - sent.to_sat() as i64
+            + fee.map(|fee| fee.to_sat() as i64).unwrap_or(0),
+    );
+    (category, amount, fee)
+}
+
+// Confirmations of a block at `block_height` when the tip is at `tip_height`,
+// zero if the block is above the tip
+fn confirmations_at(tip_height: u32, block_height: u32) -> u32 {
+    if block_height > tip_height {
+        0
+    } else {
+        tip_height - block_height + 1
+    }
+}
+
+// Block hash, height, time and confirmation count for a chain position
+fn confirmation_status(
+    position: &ChainPosition<ConfirmationBlockTime>,
+    tip_height: u32,
+) -> (Option<BlockHash>, Option<u32>, Option<u64>, u32) {
+    match position {
+        ChainPosition::Confirmed { anchor, .. } => (
+            Some(anchor.block_id.hash),
+            Some(anchor.block_id.height),
+            Some(anchor.confirmation_time),
+            confirmations_at(tip_height, anchor.block_id.height),
+        ),
+        ChainPosition::Unconfirmed { .. } => (None, None, None, 0),
+    }
+}
+
+// Break a transaction down into the payments it makes, as reported by
+// `gettransaction`. `keychain_of` returns the wallet keychain owning a script.
+// Outputs to our own external keychain are receives; when the wallet funded
+// the transaction every other output is a send, except change back to the
+// internal keychain.
+fn output_details(
+    tx: &Transaction,
+    network: Network,
+    category: TransactionCategory,
+    keychain_of: impl Fn(&Script) -> Option<KeychainKind>,
+) -> Vec<TransactionOutputDetail> {
+    let mut details = Vec::new();
+    for (vout, txout) in tx.output.iter().enumerate() {
+        let address = Address::from_script(&txout.script_pubkey, network).ok();
+        let amount = SignedAmount::from_sat(txout.value.to_sat() as i64);
+        match (keychain_of(&txout.script_pubkey), category) {
+            (Some(KeychainKind::Internal), TransactionCategory::Send) => continue,
+            (Some(_), _) => details.push(TransactionOutputDetail {
+                category: TransactionCategory::Receive,
+                address,
+                amount,
+                vout: vout as u32,
+            }),
+            (None, TransactionCategory::Send) => details.push(TransactionOutputDetail {
+                category: TransactionCategory::Send,
+                address,
+                amount: -amount,
+                vout: vout as u32,
+            }),
+            (None, TransactionCategory::Receive) => continue,
+        }
+    }
+    details
+}
+
+// Build a transaction paying `recipients` at `fee_rate`, or paying exactly
+// `absolute_fee` if given
+fn build_psbt(
+    wallet: &mut Wallet,
+    recipients: &[TxRecipient],
+    fee_rate: FeeRate,
+    absolute_fee: Option<Amount>,
+    options: &FundingOptions,
+) -> Result<Psbt, WalletError> {
+    check_coin_control(wallet, &options.coin_control)?;
+
+    let mut builder = wallet
+        .build_tx()
+        .coin_selection(CoinControlSelection::new(options.coin_control.clone()));
+    for recipient in recipients {
+        builder.add_recipient(recipient.script.clone(), recipient.amount);
+    }
+    match absolute_fee {
+        Some(fee) => {
+            builder.fee_absolute(fee);
+        }
+        None => {
+            builder.fee_rate(fee_rate);
+        }
+    }
+    if !options.inputs.is_empty() {
+        builder
+            .add_utxos(&options.inputs)
+            .map_err(|e| WalletError::Generic(format!("Invalid input: {}", e)))?;
+        builder.manually_selected_only();
+    }
+    if let Some(lock_time) = options.lock_time {
+        builder.nlocktime(lock_time);
+    }
+    if let Some(change_script) = &options.change_script {
+        // Without drain_wallet, only the change is sent to the drain script
+        builder.drain_to(change_script.clone());
+    }
+    builder.finish().map_err(create_tx_error)
+}
+
+// Metadata for a transaction the wallet has no record of, first seen now
+fn unrecorded_tx_metadata() -> TransactionMetadata {
+    TransactionMetadata {
+        timestamp: chrono::Utc::now().timestamp() as u64,
+        block_height: None,
+        fee: None,
+        comment: String::new(),
+        label: String::new(),
+        abandoned: false,
+    }
+}
+
+// Build and sign a child spending only `outpoint`, all of it to `drain_script`
+// less a fee at `fee_rate`, or exactly `absolute_fee` if given
+fn build_cpfp_child(
+    wallet: &mut Wallet,
+    outpoint: OutPoint,
+    drain_script: &Script,
+    fee_rate: FeeRate,
+    absolute_fee: Option<Amount>,
+) -> Result<Transaction, WalletError> {
+    let mut builder = wallet.build_tx();
+    builder
+        .add_utxo(outpoint)
+        .map_err(|e| WalletError::Generic(format!("Invalid input: {}", e)))?;
+    builder
+        .manually_selected_only()
+        .drain_to(drain_script.to_owned());
+    match absolute_fee {
+        Some(fee) => {
+            builder.fee_absolute(fee);
+        }
+        None => {
+            builder.fee_rate(fee_rate);
+        }
+    }
+    let mut psbt = builder.finish().map_err(create_tx_error)?;
+
+    let finalized = wallet
+        .sign(&mut psbt, SignOptions::default())
+        .map_err(|e| WalletError::Generic(format!("Failed to sign transaction: {}", e)))?;
+    if !finalized {
+        return Err(WalletError::Generic(
+            "Transaction could not be fully signed".to_string(),
+        ));
+    }
+    psbt.extract_tx()
+        .map_err(|e| WalletError::Generic(format!("Failed to extract transaction: {}", e)))
+}
+
+// Reject coin control that contradicts itself or names outputs the wallet can't spend
+fn check_coin_control(
+    wallet: &Wallet,
+    coin_control: &CoinControlOptions,
+) -> Result<(), WalletError> {
+    for outpoint in &coin_control.must_use {
+        if coin_control.must_not_use.contains(outpoint) {
+            return Err(WalletError::Generic(format!(
+                "Output {} is both required and excluded by coin control",
+                outpoint
+            )));
+        }
+        if wallet.get_utxo(*outpoint).is_none() {
+            return Err(WalletError::Generic(format!(
+                "Output {} is not an unspent output of the wallet",
+                outpoint
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn create_tx_error(e: CreateTxError) -> WalletError {
+    match e {
+        CreateTxError::CoinSelection(e) => WalletError::InsufficientFunds(e.to_string()),
+        e => WalletError::Generic(format!("Failed to create transaction: {}", e)),
+    }
+}
+
+// Reduce the selected recipients' amounts to cover `fee`, split equally with
+// any remainder taken from the first selected recipient
+fn subtract_fee(
+    recipients: &[TxRecipient],
+    subtract_fee_from: &[usize],
+    fee: Amount,
+) -> Result<Vec<TxRecipient>, WalletError> {
+    let count = subtract_fee_from.len() as u64;
+    let share = fee.to_sat() / count;
+    let remainder = fee.to_sat() % count;
+
+    let mut adjusted = recipients.to_vec();
+    for (i, &index) in subtract_fee_from.iter().enumerate() {
+        let recipient = adjusted.get_mut(index).ok_or_else(|| {
+            WalletError::Generic(format!(
+                "Invalid recipient index {} for fee subtraction",
+                index
+            ))
+        })?;
+        let deduction = Amount::from_sat(if i == 0 { share + remainder } else { share });
+        recipient.amount = recipient.amount.checked_sub(deduction).ok_or_else(|| {
+            WalletError::InsufficientFunds(
+                "The transaction amount is too small to pay the fee".to_string(),
+            )
+        })?;
+    }
+    Ok(adjusted)
+}
+
+// Parse a descriptor the wallet can derive addresses from, with any private keys in it
+fn parse_active_descriptor(
+    desc: &str,
+) -> Result<(Descriptor<DescriptorPublicKey>, KeyMap), WalletError> {
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let (descriptor, keymap) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, desc)
+        .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+    if !descriptor.has_wildcard() {
+        return Err(WalletError::InvalidDescriptor(
+            "Active descriptors must be ranged".to_string(),
+        ));
+    }
+    Ok((descriptor, keymap))
+}
+
+// Scripts for `scantxoutset` to look for, each with the descriptor it came from
+fn scan_scripts(
+    descriptors: &[String],
+    network: Network,
+) -> Result<HashMap<ScriptBuf, String>, WalletError> {
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let mut scripts = HashMap::new();
+    for desc in descriptors {
+        let body = desc.split('#').next().unwrap_or(desc);
+        if let Some(script) = addr_or_raw_script(body, network)? {
+            scripts.insert(script, body.to_string());
+        } else {
+            let (descriptor, _) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, desc)
+                .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+            let indices = if descriptor.has_wildcard() {
+                0..DEFAULT_SCAN_RANGE
+            } else {
+                0..1
+            };
+            for index in indices {
+                let derived = descriptor
+                    .at_derivation_index(index)
+                    .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+                scripts.insert(derived.script_pubkey(), derived.to_string());
+            }
+        }
+    }
+    Ok(scripts)
+}
+
+// The script of an `addr(...)` or `raw(...)` descriptor, which miniscript does
+// not parse, or `None` for any other descriptor
+fn addr_or_raw_script(body: &str, network: Network) -> Result<Option<ScriptBuf>, WalletError> {
+    if let Some(address) = body.strip_prefix("addr(").and_then(|s| s.strip_suffix(')')) {
+        let script = Address::from_str(address)
+            .map_err(|e| WalletError::InvalidAddress(format!("{}: {}", address, e)))?
+            .require_network(network)
+            .map_err(|e| WalletError::InvalidAddress(format!("{}: {}", address, e)))?
+            .script_pubkey();
+        Ok(Some(script))
+    } else if let Some(hex) = body.strip_prefix("raw(").and_then(|s| s.strip_suffix(')')) {
+        let script = ScriptBuf::from_hex(hex)
+            .map_err(|e| WalletError::InvalidDescriptor(format!("{}: {}", body, e)))?;
+        Ok(Some(script))
+    } else {
+        Ok(None)
+    }
+}
+
+// Describe a descriptor like `getdescriptorinfo`
+fn descriptor_info(desc: &str, network: Network) -> Result<DescriptorInfo, WalletError> {
+    let (body, given_checksum) = match desc.split_once('#') {
+        Some((body, checksum)) => (body, Some(checksum)),
+        None => (desc, None),
+    };
+    let checksum =
+        calc_checksum(body).map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+    if let Some(given_checksum) = given_checksum {
+        if given_checksum != checksum {
+            return Err(WalletError::InvalidDescriptor(format!(
+                "Provided checksum '{}' does not match computed checksum '{}'",
+                given_checksum, checksum
+            )));
+        }
+    }
+
+    if addr_or_raw_script(body, network)?.is_some() {
+        return Ok(DescriptorInfo {
+            descriptor: format!("{}#{}", body, checksum),
+            checksum,
+            is_range: false,
+            is_solvable: false,
+            has_private_keys: false,
+        });
+    }
+    let secp = bitcoin::secp256k1::Secp256k1::new();
+    let (descriptor, keymap) = Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, desc)
+        .map_err(|e| WalletError::InvalidDescriptor(e.to_string()))?;
+    Ok(DescriptorInfo {
+        descriptor: descriptor.to_string(),
+        checksum,
+        is_range: descriptor.has_wildcard(),
+        is_solvable: true,
+        has_private_keys: !keymap.is_empty(),
+    })
+}
+
+// Follow outputs paying to `scripts` through `block`, adding the ones it
+// creates and removing the ones it spends
+fn scan_block(
+    block: &Block,
+    height: u32,
+    scripts: &HashMap<ScriptBuf, String>,
+    unspents: &mut BTreeMap<OutPoint, ScanUnspent>,
+) {
+    for tx in &block.txdata {
+        for input in &tx.input {
+            unspents.remove(&input.previous_output);
+        }
+        let txid = tx.compute_txid();
+        for (vout, output) in tx.output.iter().enumerate() {
+            if let Some(desc) = scripts.get(&output.script_pubkey) {
+                unspents.insert(
+                    OutPoint::new(txid, vout as u32),
+                    ScanUnspent {
+                        txid,
+                        vout: vout as u32,
+                        script_pubkey: output.script_pubkey.clone(),
+                        desc: desc.clone(),
+                        amount: output.value,
+                        height,
+                    },
+                );
+            }
+        }
+    }
+}
+
+// Disjoint sets of scripts, merged as transactions link them
+#[derive(Default)]
+struct ScriptGroups {
+    scripts: Vec<ScriptBuf>,
+    indices: HashMap<ScriptBuf, usize>,
+    parents: Vec<usize>,
+}
+
+impl ScriptGroups {
+    // Index of `script`, adding it in a set of its own if it's new
+    fn insert(&mut self, script: &Script) -> usize {
+        if let Some(&index) = self.indices.get(script) {
+            return index;
+        }
+        let index = self.scripts.len();
+        self.scripts.push(script.to_owned());
+        self.indices.insert(script.to_owned(), index);
+        self.parents.push(index);
+        index
+    }
+
+    // Representative of the set holding `index`, pointing everything on the
+    // way straight at it
+    fn find(&mut self, index: usize) -> usize {
+        let mut root = index;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        let mut index = index;
+        while self.parents[index] != root {
+            let next = self.parents[index];
+            self.parents[index] = root;
+            index = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parents[b] = a;
+        }
+    }
+
+    fn into_groups(mut self) -> Vec<Vec<ScriptBuf>> {
+        let mut groups: BTreeMap<usize, Vec<ScriptBuf>> = BTreeMap::new();
+        for index in 0..self.scripts.len() {
+            let root = self.find(index);
+            groups
+                .entry(root)
+                .or_default()
+                .push(self.scripts[index].clone());
+        }
+        groups.into_values().collect()
+    }
+}
+
+fn generate_descriptors(
+    network: Network,
+    address_type: AddressType,
+) -> Result<(String, String), WalletError> {
+    let mut rng = rand::thread_rng();
+    let xprv = bitcoin::bip32::Xpriv::new_master(network, &rng.gen::<[u8; 32]>())
+        .map_err(|e| WalletError::Generic(format!("Failed to generate master key: {}", e)))?;
+
+    Ok(address_type.descriptors(&xprv, network))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::PubkeyHash;
+
+    fn p2pkh(n: u8) -> ScriptBuf {
+        ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([n; 20]))
+    }
+
+    fn transaction(outputs: &[(u8, u64)]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: outputs
+                .iter()
+                .map(|&(n, value)| TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: p2pkh(n),
+                })
+                .collect(),
+        }
+    }
+
+    // Script 1 is a receive address of ours and script 2 is our change
+    fn keychain_of(script: &Script) -> Option<KeychainKind> {
+        if script == p2pkh(1).as_script() {
+            Some(KeychainKind::External)
+        } else if script == p2pkh(2).as_script() {
+            Some(KeychainKind::Internal)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_output_details_for_send_skip_change() {
+        let tx = transaction(&[(9, 50_000), (2, 40_000)]);
+        let details = output_details(
+            &tx,
+            Network::Regtest,
+            TransactionCategory::Send,
+            keychain_of,
+        );
+
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].category, TransactionCategory::Send);
+        assert_eq!(details[0].amount, SignedAmount::from_sat(-50_000));
+        assert_eq!(details[0].vout, 0);
+        assert!(details[0].address.is_some());
+    }
+
+    #[test]
+    fn test_output_details_for_receive() {
+        let tx = transaction(&[(9, 50_000), (1, 20_000)]);
+        let details = output_details(
+            &tx,
+            Network::Regtest,
+            TransactionCategory::Receive,
+            keychain_of,
+        );
+
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].category, TransactionCategory::Receive);
+        assert_eq!(details[0].amount, SignedAmount::from_sat(20_000));
+        assert_eq!(details[0].vout, 1);
+    }
+
+    #[test]
+    fn test_rescan_progress() {
+        assert_eq!(rescan_progress(0, 9, 0), RESCAN_PROGRESS_SCALE / 10);
+        assert_eq!(rescan_progress(0, 9, 4), RESCAN_PROGRESS_SCALE / 2);
+        assert_eq!(rescan_progress(0, 9, 9), RESCAN_PROGRESS_SCALE);
+        assert_eq!(rescan_progress(5, 5, 5), RESCAN_PROGRESS_SCALE);
+    }
+
+    #[tokio::test]
+    async fn test_background_rescan_finishes_without_node() {
+        let path = std::env::temp_dir().join(format!("rescan-test-{}.sqlite", std::process::id()));
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+
+        wallet.start_rescan(0, None).unwrap();
+        // The rescan fails straight away without a node, after which no rescan is reported
+        while wallet.rescan_progress().is_some() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        wallet.start_rescan(0, None).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sign_transaction_spending_wallet_output() {
+        let path = std::env::temp_dir().join(format!("sign-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(funding.clone(), 0)]);
+
+        let mut spend = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::new(funding.compute_txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: p2pkh(9),
+            }],
+        };
+        assert!(wallet.sign_transaction(&mut spend, None).unwrap());
+        assert!(!spend.input[0].witness.is_empty());
+
+        // Inputs the wallet doesn't own stay unsigned
+        spend.input.push(bitcoin::TxIn::default());
+        assert!(!wallet.sign_transaction(&mut spend, None).unwrap());
+        assert!(spend.input[1].witness.is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_get_address_info() {
+        let path =
+            std::env::temp_dir().join(format!("address-info-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let info = wallet.get_address_info(&address).unwrap();
+        assert!(info.is_mine());
+        assert!(!info.is_change());
+        assert_eq!(info.derivation, Some((KeychainKind::External, 0)));
+        assert!(info.pubkey.is_some());
+        assert_eq!(info.hd_key_path, Some("m/86'/1'/0'/0/0".parse().unwrap()));
+
+        let unrelated = Address::from_script(&p2pkh(9), Network::Regtest).unwrap();
+        let info = wallet.get_address_info(&unrelated).unwrap();
+        assert!(!info.is_mine());
+        assert!(info.pubkey.is_none());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_create_wallet_with_descriptors() {
+        let path =
+            std::env::temp_dir().join(format!("custom-desc-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+
+        let xprv = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[3; 32]).unwrap();
+        let external = format!("wpkh({}/0/*)", xprv);
+        let internal = format!("wpkh({}/1/*)", xprv);
+
+        // Descriptors are checked before anything is created
+        let result = wallet.create_wallet(CreateWalletOptions {
+            external_descriptor: Some("wpkh(not-a-key/0/*)".to_string()),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(WalletError::InvalidDescriptor(_))));
+        let result = wallet.create_wallet(CreateWalletOptions {
+            external_descriptor: Some(format!("wpkh({}/0/0)", xprv)),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(WalletError::InvalidDescriptor(_))));
+        assert!(wallet.get_current_wallet().is_err());
+
+        wallet
+            .create_wallet(CreateWalletOptions {
+                external_descriptor: Some(external.clone()),
+                internal_descriptor: Some(internal),
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(wallet.get_wallet_info().unwrap().private_keys_enabled);
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (descriptor, _) =
+            Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, &external).unwrap();
+        let expected = descriptor
+            .at_derivation_index(0)
+            .unwrap()
+            .address(Network::Regtest)
+            .unwrap();
+        assert_eq!(wallet.get_new_address(None).unwrap(), expected);
+
+        // The descriptors are used again when the wallet is reopened
+        let reloaded = wallet.database.load_wallet(Network::Regtest).unwrap();
+        assert_eq!(
+            reloaded
+                .lock()
+                .unwrap()
+                .public_descriptor(KeychainKind::External)
+                .to_string(),
+            descriptor.to_string()
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_mnemonic_round_trip() {
+        let paths: Vec<_> = ["mnemonic-test", "restore-test"]
+            .iter()
+            .map(|name| {
+                std::env::temp_dir().join(format!("{}-{}.sqlite", name, std::process::id()))
+            })
+            .collect();
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+        let wallet = WalletInterface::new(&paths[0], "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        let restored = WalletInterface::new(&paths[1], "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+
+        let mnemonic = WalletInterface::generate_mnemonic().unwrap();
+        assert_eq!(mnemonic.split_whitespace().count(), 12);
+        wallet
+            .create_wallet_from_mnemonic(&mnemonic, Some("hunter2"))
+            .unwrap();
+
+        assert!(wallet.export_mnemonic(None).is_err());
+        assert!(matches!(
+            wallet.export_mnemonic(Some("hunter3")),
+            Err(WalletError::PassphraseIncorrect)
+        ));
+        let exported = wallet.export_mnemonic(Some("hunter2")).unwrap();
+        assert_eq!(exported, mnemonic);
+
+        restored
+            .create_wallet_from_mnemonic(&exported, None)
+            .unwrap();
+        assert_eq!(restored.export_mnemonic(None).unwrap(), mnemonic);
+        for _ in 0..3 {
+            assert_eq!(
+                wallet.get_new_address(None).unwrap(),
+                restored.get_new_address(None).unwrap()
+            );
+        }
+
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xpub_round_trip() {
+        let paths: Vec<_> = ["xpub-test", "xpub-watch-test", "xpub-mainnet-test"]
+            .iter()
+            .map(|name| {
+                std::env::temp_dir().join(format!("{}-{}.sqlite", name, std::process::id()))
+            })
+            .collect();
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        // The test vector mnemonic of BIP84, which hardware wallet emulators
+        // also start from
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+        let mainnet = WalletInterface::new(&paths[2], "/nonexistent/node.sock", Network::Bitcoin)
+            .await
+            .unwrap();
+        mainnet.create_wallet_from_mnemonic(mnemonic, None).unwrap();
+        assert_eq!(
+            mainnet.get_master_fingerprint().unwrap(),
+            [0x73, 0xc5, 0xda, 0x0a]
+        );
+        let account = DerivationPath::from_str("m/84'/0'/0'").unwrap();
+        let xpub = mainnet.get_xpub(&account).unwrap();
+        let first = xpub
+            .derive_pub(&secp, &DerivationPath::from_str("m/0/0").unwrap())
+            .unwrap();
+        assert_eq!(
+            Address::p2wpkh(&first.to_pub(), Network::Bitcoin).to_string(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+
+        let wallet = WalletInterface::new(&paths[0], "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        let watch_only =
+            WalletInterface::new(&paths[1], "/nonexistent/node.sock", Network::Regtest)
+                .await
+                .unwrap();
+        wallet.create_wallet_from_mnemonic(mnemonic, None).unwrap();
+        let account = DerivationPath::from_str("m/84'/1'/0'").unwrap();
+        let xpub = wallet.get_xpub(&account).unwrap();
+
+        assert!(watch_only
+            .import_xpub(&xpub, &DerivationPath::from_str("m/84'/1'").unwrap())
+            .is_err());
+        assert!(watch_only
+            .import_xpub(&xpub, &DerivationPath::from_str("m/44'/1'/0'").unwrap())
+            .is_err());
+        watch_only.import_xpub(&xpub, &account).unwrap();
+        assert!(!watch_only.get_wallet_info().unwrap().private_keys_enabled);
+        assert!(watch_only.get_xpub(&account).is_err());
+        for _ in 0..3 {
+            assert_eq!(
+                wallet.get_new_address(None).unwrap(),
+                watch_only.get_new_address(None).unwrap()
+            );
+        }
+
+        for path in &paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_watch_only_wallet() {
+        let path =
+            std::env::temp_dir().join(format!("watch-only-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[4; 32]).unwrap();
+        let xpub = bitcoin::bip32::Xpub::from_priv(&secp, &xprv);
+
+        let result = wallet.create_watch_only_wallet(&format!("wpkh({}/0/*)", xprv));
+        assert!(matches!(result, Err(WalletError::InvalidDescriptor(_))));
+
+        wallet
+            .create_watch_only_wallet(&format!("wpkh({}/0/*)", xpub))
+            .unwrap();
+        assert!(!wallet.get_wallet_info().unwrap().private_keys_enabled);
+
+        let address = wallet.get_new_address(None).unwrap();
+        let info = wallet.get_address_info(&address).unwrap();
+        assert!(info.is_mine());
+        assert!(!info.is_change());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_import_watch_only_descriptor() {
+        let path = std::env::temp_dir().join(format!("import-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions {
+                blank: true,
+                disable_private_keys: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[7; 32]).unwrap();
+        let xpub = bitcoin::bip32::Xpub::from_priv(&secp, &xprv);
+
+        // Private keys are refused by a wallet with private keys disabled
+        assert!(wallet
+            .import_descriptor(&format!("wpkh({}/0/*)", xprv), false, None)
+            .is_err());
+
+        let descriptor = format!("wpkh({}/0/*)", xpub);
+        wallet.import_descriptor(&descriptor, false, None).unwrap();
+        // Importing the same descriptor again is a no-op
+        wallet.import_descriptor(&descriptor, false, None).unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let info = wallet.get_address_info(&address).unwrap();
+        assert!(info.is_mine());
+        assert!(!info.is_change());
+
+        // The receive descriptor can't be replaced once the wallet exists
+        assert!(wallet
+            .import_descriptor(&format!("wpkh({}/1/*)", xpub), false, None)
+            .is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_create_funded_psbt() {
+        let path = std::env::temp_dir().join(format!("psbt-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions {
+                address_type: AddressType::P2TR,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(funding.clone(), 0)]);
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let (psbt, fee, change_position) = wallet
+            .create_funded_psbt(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                Vec::new(),
+                &FundingOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(fee > Amount::ZERO);
+        assert_eq!(psbt.fee().unwrap(), fee);
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+        let change = &psbt.unsigned_tx.output[change_position as usize];
+        assert_eq!(change.value, Amount::from_sat(60_000) - fee);
+
+        // The wallet's input carries its key origin for external signers
+        assert_eq!(psbt.inputs.len(), 1);
+        assert!(!psbt.inputs[0].tap_key_origins.is_empty());
+
+        let parsed = Psbt::deserialize(&psbt.serialize()).unwrap();
+        assert_eq!(parsed, psbt);
+        assert_eq!(psbt.to_string().parse::<Psbt>().unwrap(), psbt);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_process_funded_psbt() {
+        let path = std::env::temp_dir().join(format!("process-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions {
+                address_type: AddressType::P2TR,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(funding, 0)]);
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let (psbt, _, _) = wallet
+            .create_funded_psbt(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                Vec::new(),
+                &FundingOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        // A PSBT stripped of the wallet's input data is restored by updating it
+        let mut psbt = Psbt::from_unsigned_tx(psbt.unsigned_tx).unwrap();
+        wallet.update_psbt(&mut psbt, None).unwrap();
+        assert!(psbt.inputs[0].witness_utxo.is_some());
+        assert!(!psbt.inputs[0].tap_key_origins.is_empty());
+
+        assert!(wallet.sign_psbt(&mut psbt).unwrap());
+        // A Taproot key path spend is witnessed by a lone Schnorr signature
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        assert_eq!(witness.len(), 1);
+        assert_eq!(witness.nth(0).unwrap().len(), 64);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_taproot_addresses() {
+        for (network, prefix) in [(Network::Bitcoin, "bc1p"), (Network::Regtest, "bcrt1p")] {
+            let path = std::env::temp_dir().join(format!(
+                "taproot-{}-test-{}.sqlite",
+                network,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", network)
+                .await
+                .unwrap();
+            wallet
+                .create_wallet(CreateWalletOptions {
+                    address_type: AddressType::P2TR,
+                    ..Default::default()
+                })
+                .unwrap();
+
+            assert_eq!(wallet.address_type().unwrap(), Some(AddressType::P2TR));
+            assert!(wallet
+                .get_taproot_address()
+                .unwrap()
+                .to_string()
+                .starts_with(prefix));
+            assert!(wallet
+                .get_new_address_with_type(None, AddressType::P2WPKH)
+                .is_err());
+
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_wallet_uses_segwit_v0() {
+        let path = std::env::temp_dir().join(format!("segwit-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        assert_eq!(wallet.address_type().unwrap(), Some(AddressType::P2WPKH));
+        assert!(wallet
+            .get_new_address_with_type(None, AddressType::P2WPKH)
+            .unwrap()
+            .to_string()
+            .starts_with("bcrt1q"));
+        assert!(wallet.get_taproot_address().is_err());
+
+        // Generated descriptors are kept, so the wallet reopens with the same keys
+        let reloaded = wallet.database.load_wallet(Network::Regtest).unwrap();
+        assert_eq!(
+            reloaded
+                .lock()
+                .unwrap()
+                .public_descriptor(KeychainKind::External)
+                .to_string(),
+            wallet
+                .get_current_wallet()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .public_descriptor(KeychainKind::External)
+                .to_string()
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_finalize_signed_psbt() {
+        let path =
+            std::env::temp_dir().join(format!("finalize-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(funding, 0)]);
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let (mut psbt, _, _) = wallet
+            .create_funded_psbt(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                Vec::new(),
+                &FundingOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        // Nothing can be extracted before the wallet has signed
+        assert!(wallet.finalize_psbt(&mut psbt).unwrap().is_none());
+
+        wallet.sign_psbt(&mut psbt).unwrap();
+        let tx = wallet.finalize_psbt(&mut psbt).unwrap().unwrap();
+        assert_eq!(tx.compute_txid(), psbt.unsigned_tx.compute_txid());
+        assert!(!tx.input[0].witness.is_empty());
+        assert!(!tx.is_coinbase());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // Fund a new wallet with one unconfirmed output per amount
+    async fn funded_wallet(path: &Path, amounts: &[u64]) -> (Arc<WalletInterface>, Vec<OutPoint>) {
+        let _ = std::fs::remove_file(path);
+        let wallet = WalletInterface::new(path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let mut output = Vec::new();
+        for &amount in amounts {
+            output.push(TxOut {
+                value: Amount::from_sat(amount),
+                script_pubkey: wallet.get_new_address(None).unwrap().script_pubkey(),
+            });
+        }
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output,
+        };
+        let txid = funding.compute_txid();
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(funding, 0)]);
+
+        let outpoints = (0..amounts.len() as u32)
+            .map(|vout| OutPoint::new(txid, vout))
+            .collect();
+        (wallet, outpoints)
+    }
+
+    fn spent_outpoints(psbt: &Psbt) -> Vec<OutPoint> {
+        psbt.unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_coin_control_never_selects_frozen_output() {
+        let path = std::env::temp_dir().join(format!("frozen-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000, 80_000]).await;
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let fee_rate = Some(FeeRate::from_sat_per_vb_unchecked(2));
+        for frozen in &outpoints {
+            let coin_control = CoinControlOptions {
+                must_not_use: vec![*frozen],
+                ..Default::default()
+            };
+            let psbt = wallet
+                .create_transaction(&recipients, fee_rate, &[], &coin_control)
+                .await
+                .unwrap();
+            assert!(!spent_outpoints(&psbt).contains(frozen));
+        }
+
+        // Freezing every output leaves nothing to fund the payment with
+        let coin_control = CoinControlOptions {
+            must_not_use: outpoints,
+            ..Default::default()
+        };
+        assert!(matches!(
+            wallet
+                .create_transaction(&recipients, fee_rate, &[], &coin_control)
+                .await,
+            Err(WalletError::InsufficientFunds(_))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_coin_control_always_selects_mandatory_output() {
+        let path =
+            std::env::temp_dir().join(format!("mandatory-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000, 80_000, 5_000]).await;
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(10_000),
+        }];
+        let fee_rate = Some(FeeRate::from_sat_per_vb_unchecked(2));
+        for mandatory in &outpoints {
+            let coin_control = CoinControlOptions {
+                must_use: vec![*mandatory],
+                ..Default::default()
+            };
+            let psbt = wallet
+                .create_transaction(&recipients, fee_rate, &[], &coin_control)
+                .await
+                .unwrap();
+            assert!(spent_outpoints(&psbt).contains(mandatory));
+        }
+
+        // An output can't be both required and excluded
+        let coin_control = CoinControlOptions {
+            must_use: vec![outpoints[0]],
+            must_not_use: vec![outpoints[0]],
+        };
+        assert!(wallet
+            .create_transaction(&recipients, fee_rate, &[], &coin_control)
+            .await
+            .is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn listed(wallet: &WalletInterface) -> Vec<OutPoint> {
+        wallet
+            .list_unspent()
+            .unwrap()
+            .iter()
+            .map(|output| output.outpoint)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_locked_output_is_not_listed_or_spent() {
+        let path = std::env::temp_dir().join(format!("lock-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000, 80_000]).await;
+
+        wallet.lock_outpoint(outpoints[0]).unwrap();
+        assert!(wallet.lock_outpoint(outpoints[0]).is_err());
+        assert_eq!(wallet.list_locked_outpoints().unwrap(), vec![outpoints[0]]);
+        assert_eq!(listed(&wallet), vec![outpoints[1]]);
+
+        // Only the unlocked output can fund the payment
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let fee_rate = Some(FeeRate::from_sat_per_vb_unchecked(2));
+        let psbt = wallet
+            .create_transaction(&recipients, fee_rate, &[], &CoinControlOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(spent_outpoints(&psbt), vec![outpoints[1]]);
+
+        wallet.unlock_outpoint(outpoints[0]).unwrap();
+        assert!(wallet.unlock_outpoint(outpoints[0]).is_err());
+        assert!(wallet.list_locked_outpoints().unwrap().is_empty());
+        assert!(listed(&wallet).contains(&outpoints[0]));
+
+        // Outputs the wallet doesn't have can't be locked
+        assert!(wallet
+            .lock_outpoint(OutPoint::new(outpoints[0].txid, 2))
+            .is_err());
+        assert!(wallet.lock_outpoint(OutPoint::null()).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_address_labels() {
+        let path = std::env::temp_dir().join(format!("label-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let address = wallet.get_new_address(Some("savings")).unwrap();
+        assert_eq!(
+            wallet.get_address_label(&address).unwrap(),
+            Some("savings".to_string())
+        );
+        assert_eq!(
+            wallet.get_address_info(&address).unwrap().labels,
+            vec!["savings".to_string()]
+        );
+
+        let other = Address::from_script(&p2pkh(9), Network::Regtest).unwrap();
+        wallet.set_address_label(&other, "savings").unwrap();
+        let mut labelled = wallet.get_addresses_by_label("savings").unwrap();
+        labelled.sort_by_key(|(address, _)| address.to_string());
+        let mut expected = vec![
+            (address.clone(), "receive".to_string()),
+            (other, "send".to_string()),
+        ];
+        expected.sort_by_key(|(address, _)| address.to_string());
+        assert_eq!(labelled, expected);
+
+        wallet.set_address_label(&address, "rent").unwrap();
+        assert_eq!(wallet.get_addresses_by_label("savings").unwrap().len(), 1);
+        assert!(wallet.get_addresses_by_label("unused").unwrap().is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_list_transaction_details_only_reads() {
+        let path =
+            std::env::temp_dir().join(format!("list-details-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000]).await;
+        let txid = outpoints[0].txid;
+
+        // Listing a transaction without metadata doesn't record any
+        let details = wallet.list_transaction_details().unwrap();
+        assert_eq!(details.len(), 1);
+        assert_eq!(details[0].txid, txid);
+        assert!(wallet.database.get_tx_metadata(&txid).unwrap().is_none());
+
+        let metadata = TransactionMetadata {
+            timestamp: 1_700_000_000,
+            label: "savings".to_string(),
+            ..unrecorded_tx_metadata()
+        };
+        wallet.database.store_tx_metadata(&txid, &metadata).unwrap();
+        let details = wallet.list_transaction_details().unwrap();
+        assert_eq!(details[0].time_received, 1_700_000_000);
+        assert_eq!(details[0].label, "savings");
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_export_and_import_wallet() {
+        let path = std::env::temp_dir().join(format!("backup-test-{}.sqlite", std::process::id()));
+        let restored_path =
+            std::env::temp_dir().join(format!("restore-test-{}.sqlite", std::process::id()));
+        let backup_path =
+            std::env::temp_dir().join(format!("backup-test-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&restored_path);
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000, 80_000]).await;
+
+        let labelled = wallet.get_new_address(Some("savings")).unwrap();
+        let addresses = [
+            wallet.get_new_address(None).unwrap(),
+            labelled.clone(),
+            wallet.get_new_address(None).unwrap(),
+        ];
+        wallet.lock_outpoint(outpoints[1]).unwrap();
+        let metadata = TransactionMetadata {
+            timestamp: 1_700_000_000,
+            block_height: Some(120),
+            fee: Some(Amount::from_sat(1_410)),
+            comment: "rent".to_string(),
+            label: "landlord".to_string(),
+            abandoned: true,
+        };
+        let txid = outpoints[0].txid;
+        wallet.database.store_tx_metadata(&txid, &metadata).unwrap();
+        wallet.export_wallet(&backup_path).unwrap();
+
+        let restored = WalletInterface::import_wallet(
+            &backup_path,
+            &restored_path,
+            "/nonexistent/node.sock",
+            Network::Regtest,
+        )
+        .await
+        .unwrap();
+        for address in &addresses {
+            assert!(restored.get_address_info(address).unwrap().is_mine());
+        }
+        // Addresses already handed out are not handed out again
+        let next = restored.get_new_address(None).unwrap();
+        assert!(!addresses.contains(&next));
+        assert_eq!(next, wallet.get_new_address(None).unwrap());
+
+        assert_eq!(
+            restored.get_address_label(&labelled).unwrap(),
+            Some("savings".to_string())
+        );
+        let restored_metadata = restored.database.get_tx_metadata(&txid).unwrap().unwrap();
+        assert_eq!(restored_metadata.timestamp, metadata.timestamp);
+        assert_eq!(restored_metadata.block_height, metadata.block_height);
+        assert_eq!(restored_metadata.fee, metadata.fee);
+        assert_eq!(restored_metadata.comment, metadata.comment);
+        assert_eq!(restored_metadata.label, metadata.label);
+        assert!(restored_metadata.abandoned);
+        assert_eq!(
+            restored.database.list_locked_outpoints().unwrap(),
+            vec![outpoints[1]]
+        );
+
+        // A backup can't be restored over an existing wallet
+        assert!(restored.restore_wallet(&backup_path).await.is_err());
+        assert!(WalletInterface::import_wallet(
+            &backup_path,
+            &restored_path,
+            "/nonexistent/node.sock",
+            Network::Regtest,
+        )
+        .await
+        .is_err());
+
+        for file in [&path, &restored_path, &backup_path] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fee_bump_replaces_inputs_at_higher_fee_rate() {
+        let path = std::env::temp_dir().join(format!("bump-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000]).await;
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let mut psbt = wallet
+            .create_transaction(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                &[],
+                &CoinControlOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert!(wallet.sign_psbt(&mut psbt).unwrap());
+        let original = psbt.extract_tx().unwrap();
+        let txid = original.compute_txid();
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(original.clone(), 1)]);
+
+        let original_fee_rate = FeeRate::from_sat_per_vb_unchecked(2);
+        assert!(wallet.min_bump_fee_rate(&txid).unwrap() > original_fee_rate);
+        // A replacement must pay more than the original
+        assert!(wallet.create_fee_bump(&txid, original_fee_rate).is_err());
+
+        let replacement = wallet
+            .create_fee_bump(&txid, FeeRate::from_sat_per_vb_unchecked(10))
+            .unwrap();
+        assert_ne!(replacement.compute_txid(), txid);
+        // Spending the same output makes the two conflict in the mempool
+        assert_eq!(replacement.input[0].previous_output, outpoints[0]);
+        assert!(replacement
+            .output
+            .iter()
+            .any(|output| output.script_pubkey == p2pkh(9)
+                && output.value == Amount::from_sat(40_000)));
+
+        let fee = |tx: &Transaction| {
+            Amount::from_sat(100_000) - tx.output.iter().map(|output| output.value).sum()
+        };
+        let original_rate = fee(&original).to_sat() as f64 / original.vsize() as f64;
+        let replacement_rate = fee(&replacement).to_sat() as f64 / replacement.vsize() as f64;
+        assert!(replacement_rate > original_rate);
+        assert!(replacement_rate >= 10.0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_cpfp_child_brings_package_to_target_fee_rate() {
+        let path = std::env::temp_dir().join(format!("cpfp-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000]).await;
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let mut psbt = wallet
+            .create_transaction(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                &[],
+                &CoinControlOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert!(wallet.sign_psbt(&mut psbt).unwrap());
+        let parent = psbt.extract_tx().unwrap();
+        let parent_txid = parent.compute_txid();
+        let change_vout = parent
+            .output
+            .iter()
+            .position(|output| output.script_pubkey != p2pkh(9))
+            .unwrap() as u32;
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(parent.clone(), 1)]);
+        let parent_fee = wallet.get_transaction_fee(&parent_txid).unwrap().unwrap();
+
+        for target in [10, 25] {
+            let target_fee_rate = FeeRate::from_sat_per_vb_unchecked(target);
+            let child = wallet
+                .create_cpfp_child(&parent_txid, change_vout, target_fee_rate, parent_fee)
+                .unwrap();
+            assert_eq!(
+                child.input[0].previous_output,
+                OutPoint::new(parent_txid, change_vout)
+            );
+            assert_eq!(child.input.len(), 1);
+
+            let change = parent.output[change_vout as usize].value;
+            let child_fee = change - child.output.iter().map(|output| output.value).sum();
+            let package_rate =
+                (parent_fee + child_fee).to_sat() as f64 / (parent.vsize() + child.vsize()) as f64;
+            assert!(
+                (package_rate - target as f64).abs() <= 1.0,
+                "package pays {} sat/vB",
+                package_rate
+            );
+        }
+
+        // The parent alone already pays 2 sat/vB, and the funding output is spent
+        assert!(wallet
+            .create_cpfp_child(
+                &parent_txid,
+                change_vout,
+                FeeRate::from_sat_per_vb_unchecked(1),
+                parent_fee
+            )
+            .is_err());
+        assert!(wallet
+            .create_cpfp_child(
+                &outpoints[0].txid,
+                outpoints[0].vout,
+                FeeRate::from_sat_per_vb_unchecked(10),
+                Amount::ZERO
+            )
+            .is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // Blocks extending `parent`, with each block's height
+    fn child_blocks(parent: &Block, parent_height: i32, count: i32) -> Vec<(i32, Block)> {
+        let mut blocks = Vec::new();
+        let mut header = parent.header;
+        for height in parent_height + 1..=parent_height + count {
+            header.prev_blockhash = header.block_hash();
+            header.time += 600;
+            blocks.push((
+                height,
+                Block {
+                    header,
+                    txdata: Vec::new(),
+                },
+            ));
+        }
+        blocks
+    }
+
+    #[tokio::test]
+    async fn test_scan_blocks_reports_progress() {
+        let path = std::env::temp_dir().join(format!("scan-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let blocks = child_blocks(&genesis, 0, 5);
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let report: Box<dyn Fn(i32, i32) + Send> = {
+            let reported = reported.clone();
+            Box::new(move |height, stop_height| {
+                reported.lock().unwrap().push((height, stop_height))
+            })
+        };
+
+        wallet
+            .scan_blocks(
+                tokio_stream::iter(blocks.into_iter().map(Ok)),
+                5,
+                Some(report.as_ref()),
+            )
+            .await
+            .unwrap();
+
+        let reported = reported.lock().unwrap();
+        assert_eq!(
+            *reported,
+            (1..=5).map(|height| (height, 5)).collect::<Vec<_>>()
+        );
+        assert!(reported.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(reported.last().map(|&(height, _)| height), Some(5));
+        let tip_height = wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .latest_checkpoint()
+            .height();
+        assert_eq!(tip_height, 5);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_scan_scripts() {
+        let address = Address::from_script(&p2pkh(1), Network::Regtest).unwrap();
+        let xprv = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[1; 32]).unwrap();
+        let xpub = bitcoin::bip32::Xpub::from_priv(&bitcoin::secp256k1::Secp256k1::new(), &xprv);
+        let descriptors = vec![
+            format!("addr({})", address),
+            format!("raw({})", p2pkh(2).to_hex_string()),
+            format!("wpkh({}/0/*)", xpub),
+        ];
+
+        let scripts = scan_scripts(&descriptors, Network::Regtest).unwrap();
+        assert_eq!(scripts.len(), 2 + DEFAULT_SCAN_RANGE as usize);
+        assert_eq!(scripts[&p2pkh(1)], descriptors[0]);
+        assert_eq!(scripts[&p2pkh(2)], descriptors[1]);
+        let ranged = scripts
+            .values()
+            .filter(|desc| desc.starts_with("wpkh("))
+            .collect::<Vec<_>>();
+        assert!(ranged.iter().all(|desc| desc.contains('#')));
+
+        let mainnet = vec![format!(
+            "addr({})",
+            Address::from_script(&p2pkh(1), Network::Bitcoin).unwrap()
+        )];
+        assert!(matches!(
+            scan_scripts(&mainnet, Network::Regtest),
+            Err(WalletError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            scan_scripts(&["wpkh(nonsense)".to_string()], Network::Regtest),
+            Err(WalletError::InvalidDescriptor(_))
+        ));
+    }
+
+    #[test]
+    fn test_descriptor_info() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[1; 32]).unwrap();
+        let xpub = bitcoin::bip32::Xpub::from_priv(&secp, &xprv);
+
+        let ranged = format!("tr({}/0/*)", xpub);
+        let info = descriptor_info(&ranged, Network::Regtest).unwrap();
+        let checksum = calc_checksum(&ranged).unwrap();
+        assert_eq!(info.descriptor, format!("{}#{}", ranged, checksum));
+        assert_eq!(info.checksum, checksum);
+        assert!(info.is_range && info.is_solvable && !info.has_private_keys);
+
+        // The checksum given is checked, and the one returned is for the input
+        assert_eq!(
+            descriptor_info(&info.descriptor, Network::Regtest).unwrap(),
+            info
+        );
+        assert!(matches!(
+            descriptor_info(&format!("{}#qqqqqqqq", ranged), Network::Regtest),
+            Err(WalletError::InvalidDescriptor(_))
+        ));
+
+        let key1 = bitcoin::PublicKey::new(xpub.derive_pub(&secp, &[0.into()]).unwrap().public_key);
+        let key2 = bitcoin::PublicKey::new(xpub.derive_pub(&secp, &[1.into()]).unwrap().public_key);
+        let multisig = format!("wsh(multi(2,{},{}))", key1, key2);
+        let info = descriptor_info(&multisig, Network::Regtest).unwrap();
+        assert_eq!(info.checksum, calc_checksum(&multisig).unwrap());
+        assert!(!info.is_range && info.is_solvable && !info.has_private_keys);
+
+        let private = format!("wpkh({}/0/*)", xprv);
+        let info = descriptor_info(&private, Network::Regtest).unwrap();
+        assert_eq!(info.checksum, calc_checksum(&private).unwrap());
+        assert!(info.descriptor.starts_with(&format!("wpkh({}/0/*)#", xpub)));
+        assert!(info.has_private_keys);
+
+        let raw = format!("raw({})", p2pkh(1).to_hex_string());
+        let info = descriptor_info(&raw, Network::Regtest).unwrap();
+        assert_eq!(info.descriptor, format!("{}#{}", raw, info.checksum));
+        assert!(!info.is_range && !info.is_solvable && !info.has_private_keys);
+
+        assert!(matches!(
+            descriptor_info("wpkh(nonsense)", Network::Regtest),
+            Err(WalletError::InvalidDescriptor(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_descriptors() {
+        let path = std::env::temp_dir().join(format!(
+            "list-descriptors-test-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions {
+                blank: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert!(wallet.list_descriptors().unwrap().is_empty());
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let xprv = bitcoin::bip32::Xpriv::new_master(Network::Regtest, &[2; 32]).unwrap();
+        let xpub = bitcoin::bip32::Xpub::from_priv(&secp, &xprv);
+        let public = |desc: &str| {
+            Descriptor::<DescriptorPublicKey>::parse_descriptor(&secp, desc)
+                .unwrap()
+                .0
+                .to_string()
+        };
+
+        // A taproot change descriptor with private keys, waiting for a receive descriptor
+        let change = format!("tr({}/1/*)", xprv);
+        wallet
+            .import_descriptor(&change, true, Some(1_700_000_000))
+            .unwrap();
+        let listed = wallet.list_descriptors().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].internal && !listed[0].active);
+
+        let watch_only = format!("wpkh({}/0/*)", xpub);
+        wallet.import_descriptor(&watch_only, false, None).unwrap();
+        let listed = wallet.list_descriptors().unwrap();
+        assert_eq!(listed.len(), 2);
+
+        let receive = &listed[0];
+        assert_eq!(receive.desc, public(&watch_only));
+        assert!(receive.desc.contains('#'));
+        assert!(receive.active && !receive.internal);
+        assert_eq!(
+            receive.timestamp,
+            wallet.setting_or("created_at", 0).unwrap()
+        );
+        assert_eq!(receive.next_index, 0);
+
+        let change_entry = &listed[1];
+        assert_eq!(change_entry.desc, public(&change));
+        assert!(!change_entry.desc.contains(&xprv.to_string()));
+        assert!(change_entry.active && change_entry.internal);
+        assert_eq!(change_entry.timestamp, 1_700_000_000);
+        assert!(change_entry.range.is_some());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_derive_addresses() {
+        let path = std::env::temp_dir().join(format!("derive-test-{}.sqlite", std::process::id()));
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+
+        // The BIP84 test vectors, from the account key of the "abandon ... about" mnemonic
+        let mnemonic = Mnemonic::parse(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let master =
+            bitcoin::bip32::Xpriv::new_master(Network::Bitcoin, &mnemonic.to_seed("")).unwrap();
+        let account = master
+            .derive_priv(
+                &secp,
+                &bitcoin::bip32::DerivationPath::from_str("m/84'/0'/0'").unwrap(),
+            )
+            .unwrap();
+        let xpub = bitcoin::bip32::Xpub::from_priv(&secp, &account);
+        let script = |address: &str| {
+            Address::from_str(address)
+                .unwrap()
+                .assume_checked()
+                .script_pubkey()
+        };
+
+        let receive = format!("wpkh({}/0/*)", xpub);
+        for _ in 0..2 {
+            let addresses = wallet.derive_addresses(&receive, Some((0, 1))).unwrap();
+            let scripts = addresses
+                .iter()
+                .map(Address::script_pubkey)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                scripts,
+                vec![
+                    script("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"),
+                    script("bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"),
+                ]
+            );
+            assert!(addresses[0].to_string().starts_with("bcrt1q"));
+        }
+        assert_eq!(wallet.derived_addresses.lock().unwrap().len(), 2);
+
+        let change = format!("wpkh({}/1/0)", xpub);
+        let addresses = wallet.derive_addresses(&change, None).unwrap();
+        assert_eq!(
+            addresses[0].script_pubkey(),
+            script("bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el")
+        );
+
+        assert!(wallet.derive_addresses(&receive, None).is_err());
+        assert!(wallet.derive_addresses(&change, Some((0, 1))).is_err());
+        let raw = format!("raw({})", p2pkh(1).to_hex_string());
+        assert_eq!(
+            wallet.derive_addresses(&raw, None).unwrap()[0].script_pubkey(),
+            p2pkh(1)
+        );
+        assert!(wallet.derive_addresses("raw(6a)", None).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_scan_block_follows_outputs_until_spent() {
+        let scripts = HashMap::from([(p2pkh(1), "addr(ours)".to_string())]);
+        let funding = transaction(&[(1, 50_000), (9, 10_000), (1, 30_000)]);
+        let mut spend = transaction(&[(1, 20_000), (9, 29_000)]);
+        spend.input.push(bitcoin::TxIn {
+            previous_output: OutPoint::new(funding.compute_txid(), 0),
+            ..Default::default()
+        });
+        let block = |txdata| Block {
+            header: bitcoin::blockdata::constants::genesis_block(Network::Regtest).header,
+            txdata,
+        };
+
+        let mut unspents = BTreeMap::new();
+        scan_block(&block(vec![funding.clone()]), 1, &scripts, &mut unspents);
+        assert_eq!(unspents.len(), 2);
+        scan_block(&block(vec![spend.clone()]), 2, &scripts, &mut unspents);
+
+        let mut found: Vec<_> = unspents.into_values().collect();
+        found.sort_by_key(|unspent| unspent.height);
+        assert_eq!(
+            found,
+            vec![
+                ScanUnspent {
+                    txid: funding.compute_txid(),
+                    vout: 2,
+                    script_pubkey: p2pkh(1),
+                    desc: "addr(ours)".to_string(),
+                    amount: Amount::from_sat(30_000),
+                    height: 1,
+                },
+                ScanUnspent {
+                    txid: spend.compute_txid(),
+                    vout: 0,
+                    script_pubkey: p2pkh(1),
+                    desc: "addr(ours)".to_string(),
+                    amount: Amount::from_sat(20_000),
+                    height: 2,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transaction_fee() {
+        let funding = transaction(&[(1, 60_000), (1, 40_000)]);
+        let mut spend = transaction(&[(9, 70_000), (2, 29_000)]);
+        spend.input = (0..2)
+            .map(|vout| bitcoin::TxIn {
+                previous_output: OutPoint::new(funding.compute_txid(), vout),
+                ..Default::default()
+            })
+            .collect();
+        let prevout = |outpoint: OutPoint| {
+            (outpoint.txid == funding.compute_txid())
+                .then(|| funding.output[outpoint.vout as usize].clone())
+        };
+        assert_eq!(
+            transaction_fee(&spend, prevout),
+            Some(Amount::from_sat(1_000))
+        );
+        assert_eq!(transaction_fee(&spend, |_| None), None);
+
+        let mut coinbase = transaction(&[(1, 5_000_000_000)]);
+        coinbase.input = vec![bitcoin::TxIn::default()];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(transaction_fee(&coinbase, |_| None), Some(Amount::ZERO));
+    }
+
+    #[test]
+    fn test_subtract_fee() {
+        let recipients: Vec<TxRecipient> = [(1, 50_000), (2, 30_000), (3, 20_000)]
+            .iter()
+            .map(|&(n, sats)| TxRecipient {
+                script: p2pkh(n),
+                amount: Amount::from_sat(sats),
+            })
+            .collect();
+        let amounts = |recipients: Vec<TxRecipient>| {
+            recipients
+                .iter()
+                .map(|recipient| recipient.amount.to_sat())
+                .collect::<Vec<_>>()
+        };
+
+        let fee = Amount::from_sat(1_001);
+        assert_eq!(
+            amounts(subtract_fee(&recipients, &[1], fee).unwrap()),
+            vec![50_000, 28_999, 20_000]
+        );
+        // The first recipient listed pays the remainder of an uneven split
+        assert_eq!(
+            amounts(subtract_fee(&recipients, &[2, 0], fee).unwrap()),
+            vec![49_500, 30_000, 19_499]
+        );
+
+        assert!(matches!(
+            subtract_fee(&recipients, &[3], fee),
+            Err(WalletError::Generic(_))
+        ));
+        assert!(matches!(
+            subtract_fee(&recipients, &[2], Amount::from_sat(20_001)),
+            Err(WalletError::InsufficientFunds(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_address_groupings() {
+        let path =
+            std::env::temp_dir().join(format!("groupings-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let addresses: Vec<_> = (0..4)
+            .map(|_| wallet.get_new_address(None).unwrap())
+            .collect();
+        wallet.set_address_label(&addresses[3], "savings").unwrap();
+        // The second address is paid twice, so two transactions can spend from it
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: [0, 1, 1, 2, 3]
+                .iter()
+                .map(|&n| TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: addresses[n].script_pubkey(),
+                })
+                .collect(),
+        };
+        let funding_txid = funding.compute_txid();
+        let wallet_handle = wallet.get_current_wallet().unwrap();
+        let change = wallet_handle
+            .lock()
+            .unwrap()
+            .reveal_next_address(KeychainKind::Internal)
+            .address;
+        let spend = |vouts: [u32; 2], outputs: Vec<TxOut>| Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vouts
+                .iter()
+                .map(|&vout| bitcoin::TxIn {
+                    previous_output: OutPoint::new(funding_txid, vout),
+                    ..Default::default()
+                })
+                .collect(),
+            output: outputs,
+        };
+        let first = spend(
+            [0, 1],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(15_000),
+                    script_pubkey: p2pkh(9),
+                },
+                TxOut {
+                    value: Amount::from_sat(4_000),
+                    script_pubkey: change.script_pubkey(),
+                },
+            ],
+        );
+        let second = spend(
+            [2, 3],
+            vec![TxOut {
+                value: Amount::from_sat(19_000),
+                script_pubkey: p2pkh(9),
+            }],
+        );
+        wallet_handle.lock().unwrap().apply_unconfirmed_txs([
+            (funding, 0),
+            (first, 1),
+            (second, 2),
+        ]);
+
+        let groupings = wallet.list_address_groupings().unwrap();
+        assert_eq!(groupings.len(), 2);
+        let (linked, unrelated) = if groupings[0].len() == 1 {
+            (&groupings[1], &groupings[0])
+        } else {
+            (&groupings[0], &groupings[1])
+        };
+        let mut expected: Vec<_> = addresses[..3]
+            .iter()
+            .chain([&change])
+            .map(Address::to_string)
+            .collect();
+        expected.sort();
+        assert_eq!(
+            linked
+                .iter()
+                .map(|entry| entry.address.to_string())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        let linked_amount: Amount = linked.iter().map(|entry| entry.amount).sum();
+        assert_eq!(linked_amount, Amount::from_sat(4_000));
+        assert_eq!(
+            unrelated,
+            &vec![AddressGroupEntry {
+                address: addresses[3].clone(),
+                amount: Amount::from_sat(10_000),
+                label: Some("savings".to_string()),
+            }]
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_abandon_transaction() {
+        let path = std::env::temp_dir().join(format!("abandon-test-{}.sqlite", std::process::id()));
+        let (wallet, outpoints) = funded_wallet(&path, &[100_000]).await;
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let (psbt, _, change_position) = wallet
+            .create_funded_psbt(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                Vec::new(),
+                &FundingOptions::default(),
+            )
+            .await
+            .unwrap();
+        let spend = psbt.unsigned_tx;
+        let spend_txid = spend.compute_txid();
+        // A child spending the change goes when its parent does
+        let child = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint::new(spend_txid, change_position as u32),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: p2pkh(8),
+            }],
+        };
+        let child_txid = child.compute_txid();
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(spend, 1), (child, 2)]);
+        let txids = |wallet: &WalletInterface| -> Vec<Txid> {
+            wallet
+                .list_transactions()
+                .unwrap()
+                .iter()
+                .map(Transaction::compute_txid)
+                .collect()
+        };
+        assert!(txids(&wallet).contains(&spend_txid));
+        assert!(wallet.list_unspent().unwrap().is_empty());
+
+        wallet.abandon_transaction(&spend_txid).unwrap();
+        assert_eq!(txids(&wallet), vec![outpoints[0].txid]);
+        let unspent: Vec<_> = wallet
+            .list_unspent()
+            .unwrap()
+            .iter()
+            .map(|output| output.outpoint)
+            .collect();
+        assert_eq!(unspent, outpoints);
+        for txid in [spend_txid, child_txid] {
+            assert!(
+                wallet
+                    .database
+                    .get_tx_metadata(&txid)
+                    .unwrap()
+                    .unwrap()
+                    .abandoned
+            );
+        }
+
+        assert!(matches!(
+            wallet.abandon_transaction(&spend_txid),
+            Err(WalletError::TransactionNotFound(_))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_get_transaction_fee() {
+        let path = std::env::temp_dir().join(format!("fee-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let funding = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(funding, 0)]);
+
+        let recipients = [TxRecipient {
+            script: p2pkh(9),
+            amount: Amount::from_sat(40_000),
+        }];
+        let (psbt, fee, _) = wallet
+            .create_funded_psbt(
+                &recipients,
+                Some(FeeRate::from_sat_per_vb_unchecked(2)),
+                Vec::new(),
+                &FundingOptions::default(),
+            )
+            .await
+            .unwrap();
+        let spend = psbt.unsigned_tx;
+        let txid = spend.compute_txid();
+        wallet
+            .get_current_wallet()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .apply_unconfirmed_txs([(spend.clone(), 1)]);
+
+        let output_value: Amount = spend.output.iter().map(|output| output.value).sum();
+        assert_eq!(
+            wallet.get_transaction_fee(&txid).unwrap(),
+            Some(Amount::from_sat(100_000) - output_value)
+        );
+        assert_eq!(wallet.get_transaction_fee(&txid).unwrap(), Some(fee));
+        assert_eq!(
+            wallet.database.get_tx_metadata(&txid).unwrap().unwrap().fee,
+            Some(fee)
+        );
+        assert_eq!(
+            wallet
+                .get_transaction_fee(&Txid::from_byte_array([1; 32]))
+                .unwrap(),
+            None
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_block_unconfirms_transactions() {
+        let path =
+            std::env::temp_dir().join(format!("disconnect-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let address = wallet.get_new_address(None).unwrap();
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let mut blocks = child_blocks(&genesis, 0, 2);
+        let payments: Vec<_> = (0..2u64)
+            .map(|n| Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::from_consensus(n as u32),
+                input: vec![],
+                output: vec![TxOut {
+                    value: Amount::from_sat(10_000 * (n + 1)),
+                    script_pubkey: address.script_pubkey(),
+                }],
+            })
+            .collect();
+        blocks[0].1.txdata.push(payments[0].clone());
+        blocks[1].1.header.prev_blockhash = blocks[0].1.block_hash();
+        blocks[1].1.txdata.push(payments[1].clone());
+        for (_, block) in &blocks {
+            wallet.process_block(block).await.unwrap();
+        }
+        let confirmed_at = |tx: &Transaction| {
+            wallet
+                .database
+                .get_tx_metadata(&tx.compute_txid())
+                .unwrap()
+                .unwrap()
+                .block_height
+        };
+        assert_eq!(confirmed_at(&payments[0]), Some(1));
+        assert_eq!(confirmed_at(&payments[1]), Some(2));
+
+        wallet
+            .process_disconnected_block(&blocks[1].1.block_hash())
+            .unwrap();
+        assert_eq!(confirmed_at(&payments[0]), Some(1));
+        assert_eq!(confirmed_at(&payments[1]), None);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_blocks_applied_in_height_order() {
+        let path =
+            std::env::temp_dir().join(format!("parallel-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        // Deliver each pair of blocks in reverse order, as concurrent fetches might
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let mut blocks = child_blocks(&genesis, 0, 6);
+        for pair in blocks.chunks_mut(2) {
+            pair.reverse();
+        }
+        let tip_hash = blocks[4].1.block_hash();
+
+        let current_wallet = wallet.get_current_wallet().unwrap();
+        let mut wallet_guard = current_wallet.lock().unwrap();
+        apply_blocks_in_order(
+            &mut wallet_guard,
+            1,
+            tokio_stream::iter(blocks.into_iter().map(Ok)),
+        )
+        .await
+        .unwrap();
+        let tip = wallet_guard.latest_checkpoint();
+        assert_eq!(tip.height(), 6);
+        assert_eq!(tip.hash(), tip_hash);
+
+        drop(wallet_guard);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_missing_block_is_reported() {
+        let path = std::env::temp_dir().join(format!("gap-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+
+        let genesis = bitcoin::blockdata::constants::genesis_block(Network::Regtest);
+        let mut blocks = child_blocks(&genesis, 0, 3);
+        blocks.remove(1);
+
+        let current_wallet = wallet.get_current_wallet().unwrap();
+        let mut wallet_guard = current_wallet.lock().unwrap();
+        let result = apply_blocks_in_order(
+            &mut wallet_guard,
+            1,
+            tokio_stream::iter(blocks.into_iter().map(Ok)),
+        )
+        .await;
+        assert!(matches!(result, Err(WalletError::Generic(_))));
+        assert_eq!(wallet_guard.latest_checkpoint().height(), 1);
+
+        drop(wallet_guard);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_confirmations_at() {
+        assert_eq!(confirmations_at(100, 100), 1);
+        assert_eq!(confirmations_at(105, 100), 6);
+        assert_eq!(confirmations_at(100, 101), 0);
+    }
+
+    #[test]
+    fn test_confirmation_status() {
+        let anchor = ConfirmationBlockTime {
+            block_id: bdk_wallet::chain::BlockId {
+                height: 100,
+                hash: BlockHash::all_zeros(),
+            },
+            confirmation_time: 1_700_000_000,
+        };
+        let position = ChainPosition::Confirmed {
+            anchor,
+            transitively: None,
+        };
+        assert_eq!(
+            confirmation_status(&position, 105),
+            (
+                Some(BlockHash::all_zeros()),
+                Some(100),
+                Some(1_700_000_000),
+                6
+            )
+        );
+
+        let position = ChainPosition::Unconfirmed { last_seen: None };
+        assert_eq!(confirmation_status(&position, 105), (None, None, None, 0));
+    }
 }
@@ -0,0 +1,401 @@
+use bitcoin::Network;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::interface::WalletInterface;
+use crate::error::WalletError;
+
+/// First bytes of every SQLite database file
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// The wallets loaded by the server, by name
+///
+/// Each wallet is a database file in the wallet directory, named after the
+/// file without its extension, and gets its own `WalletInterface`.
+pub struct WalletManager {
+    wallet_dir: PathBuf,
+    node_socket: String,
+    network: Network,
+    wallets: HashMap<String, Arc<WalletInterface>>,
+    // Names of the wallets being opened, kept from being loaded twice at once
+    loading: HashSet<String>,
+}
+
+impl WalletManager {
+    pub fn new(wallet_dir: &Path, node_socket: &str, network: Network) -> Self {
+        Self {
+            wallet_dir: wallet_dir.to_path_buf(),
+            node_socket: node_socket.to_string(),
+            network,
+            wallets: HashMap::new(),
+            loading: HashSet::new(),
+        }
+    }
+
+    /// Manage the wallets in the directory of `wallet`, starting with `wallet`
+    /// itself
+    pub fn with_wallet(wallet: Arc<WalletInterface>) -> Self {
+        let wallet_dir = wallet
+            .path()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let mut manager = Self::new(&wallet_dir, wallet.node_socket(), wallet.network());
+        manager.wallets.insert(wallet.wallet_name(), wallet);
+        manager
+    }
+
+    pub fn wallet_dir(&self) -> &Path {
+        &self.wallet_dir
+    }
+
+    /// Open the wallet in the database at `path` and sync it with the node
+    /// The wallet stays loaded if the node can't be reached, as it can still
+    /// be used offline, and catches up on its next sync. `manager` is only
+    /// locked to check the name and add the wallet, so requests to other
+    /// wallets go on while it is opened and synced.
+    pub async fn load_wallet(
+        manager: &RwLock<Self>,
+        name: &str,
+        path: &Path,
+    ) -> Result<(), WalletError> {
+        if !path.is_file() {
+            return Err(WalletError::WalletNotFound(format!(
+                "Wallet file verification failed. Failed to load database path '{}'. Path does not exist.",
+                path.display()
+            )));
+        }
+        Self::add_wallet(manager, name, |node_socket, network| async move {
+            let wallet = WalletInterface::new(path, &node_socket, network).await?;
+            wallet.open_wallet()?;
+            if let Err(e) = wallet.sync_wallet().await {
+                log::warn!("Loaded wallet {} without syncing it: {}", name, e);
+            }
+            Ok(wallet)
+        })
+        .await?;
+        log::info!("Loaded wallet {}", name);
+        Ok(())
+    }
+
+    /// Load the wallet called `name` from the wallet directory
+    pub async fn load_wallet_by_name(
+        manager: &RwLock<Self>,
+        name: &str,
+    ) -> Result<(), WalletError> {
+        let path = manager.read().await.wallet_path(name)?;
+        Self::load_wallet(manager, name, &path).await
+    }
+
+    /// Restore the backup at `backup_file` into a new wallet called `name` in
+    /// the wallet directory, and load it
+    /// Fails if a wallet by that name is loaded or already in the directory.
+    pub async fn restore_wallet(
+        manager: &RwLock<Self>,
+        name: &str,
+        backup_file: &Path,
+    ) -> Result<(), WalletError> {
+        let (path, exists) = {
+            let manager = manager.read().await;
+            let exists = wallet_files(&manager.wallet_dir)?.contains_key(name);
+            (manager.wallet_dir.join(name), exists)
+        };
+        if exists || path.exists() {
+            return Err(WalletError::Generic(format!(
+                "Failed to create database path '{}'. Database already exists.",
+                path.display()
+            )));
+        }
+        Self::add_wallet(manager, name, |node_socket, network| async move {
+            let wallet =
+                WalletInterface::import_wallet(backup_file, &path, &node_socket, network).await;
+            // Don't leave a half restored wallet taking the name
+            if wallet.is_err() {
+                let _ = std::fs::remove_file(&path);
+            }
+            wallet
+        })
+        .await?;
+        log::info!("Restored wallet {} from {}", name, backup_file.display());
+        Ok(())
+    }
+
+    // Add the wallet `open` gives as `name`, unless a wallet by that name is
+    // loaded or being loaded. `open` is given the node socket and network and
+    // runs without `manager` locked.
+    async fn add_wallet<F, Fut>(
+        manager: &RwLock<Self>,
+        name: &str,
+        open: F,
+    ) -> Result<(), WalletError>
+    where
+        F: FnOnce(String, Network) -> Fut,
+        Fut: Future<Output = Result<Arc<WalletInterface>, WalletError>>,
+    {
+        let (node_socket, network) = {
+            let mut manager = manager.write().await;
+            if manager.wallets.contains_key(name) || !manager.loading.insert(name.to_string()) {
+                return Err(WalletError::WalletAlreadyLoaded(name.to_string()));
+            }
+            (manager.node_socket.clone(), manager.network)
+        };
+
+        let wallet = open(node_socket, network).await;
+        let mut manager = manager.write().await;
+        manager.loading.remove(name);
+        manager.wallets.insert(name.to_string(), wallet?);
+        Ok(())
+    }
+
+    /// Stop serving the wallet called `name`
+    /// Its interface is left without a wallet, so anyone still holding it gets
+    /// an error rather than a wallet that is no longer managed.
+    pub fn unload_wallet(&mut self, name: &str) -> Result<(), WalletError> {
+        let wallet = self.wallets.remove(name).ok_or_else(|| {
+            WalletError::WalletNotFound(
+                "Requested wallet does not exist or is not loaded".to_string(),
+            )
+        })?;
+        wallet.close_wallet();
+        log::info!("Unloaded wallet {}", name);
+        Ok(())
+    }
+
+    pub fn get_wallet(&self, name: &str) -> Option<&Arc<WalletInterface>> {
+        self.wallets.get(name)
+    }
+
+    /// The loaded wallet, if exactly one is loaded
+    pub fn only_wallet(&self) -> Option<&Arc<WalletInterface>> {
+        match self.wallets.len() {
+            1 => self.wallets.values().next(),
+            _ => None,
+        }
+    }
+
+    /// Names of the loaded wallets, in order
+    pub fn list_wallets(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.wallets.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Names of the wallets in the wallet directory, loaded or not, in order
+    pub fn list_wallet_dir(&self) -> Result<Vec<String>, WalletError> {
+        Ok(wallet_files(&self.wallet_dir)?.into_keys().collect())
+    }
+
+    // Path of the database of the wallet called `name`
+    fn wallet_path(&self, name: &str) -> Result<PathBuf, WalletError> {
+        let mut files = wallet_files(&self.wallet_dir)?;
+        Ok(files
+            .remove(name)
+            .unwrap_or_else(|| self.wallet_dir.join(name)))
+    }
+}
+
+/// The SQLite databases in `wallet_dir`, by wallet name
+pub(super) fn wallet_files(wallet_dir: &Path) -> Result<BTreeMap<String, PathBuf>, WalletError> {
+    let entries = std::fs::read_dir(wallet_dir).map_err(|e| {
+        WalletError::Generic(format!(
+            "Failed to read wallet directory {}: {}",
+            wallet_dir.display(),
+            e
+        ))
+    })?;
+
+    let mut files = BTreeMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !is_sqlite_file(&path) {
+            continue;
+        }
+        if let Some(name) = path.file_stem() {
+            files.insert(name.to_string_lossy().into_owned(), path);
+        }
+    }
+    Ok(files)
+}
+
+fn is_sqlite_file(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| &header == SQLITE_HEADER)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::CreateWalletOptions;
+
+    // Empty wallet directory unique to `name`
+    fn wallet_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn create_wallet(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(format!("{}.dat", name));
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+        path
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_load_and_unload_wallets() {
+        let dir = wallet_dir("manager-test");
+        let first = create_wallet(&dir, "first").await;
+        create_wallet(&dir, "second").await;
+        std::fs::write(dir.join("notes.txt"), "not a wallet").unwrap();
+        let manager = RwLock::new(WalletManager::new(
+            &dir,
+            "/nonexistent/node.sock",
+            Network::Regtest,
+        ));
+
+        assert_eq!(
+            manager.read().await.list_wallet_dir().unwrap(),
+            vec!["first", "second"]
+        );
+        assert!(manager.read().await.list_wallets().is_empty());
+
+        WalletManager::load_wallet(&manager, "first", &first)
+            .await
+            .unwrap();
+        WalletManager::load_wallet_by_name(&manager, "second")
+            .await
+            .unwrap();
+        assert_eq!(manager.read().await.list_wallets(), vec!["first", "second"]);
+        assert!(manager.read().await.only_wallet().is_none());
+        let wallet = manager.read().await.get_wallet("first").unwrap().clone();
+        assert!(wallet.get_balance().is_ok());
+        assert_eq!(wallet.list_wallet_dir().unwrap(), vec!["first", "second"]);
+
+        assert!(matches!(
+            WalletManager::load_wallet(&manager, "first", &first).await,
+            Err(WalletError::WalletAlreadyLoaded(_))
+        ));
+        assert!(matches!(
+            WalletManager::load_wallet_by_name(&manager, "third").await,
+            Err(WalletError::WalletNotFound(_))
+        ));
+
+        let mut manager = manager.into_inner();
+        manager.unload_wallet("first").unwrap();
+        assert!(manager.get_wallet("first").is_none());
+        assert!(wallet.get_balance().is_err());
+        assert_eq!(manager.list_wallets(), vec!["second"]);
+        assert!(manager.only_wallet().is_some());
+        assert!(matches!(
+            manager.unload_wallet("first"),
+            Err(WalletError::WalletNotFound(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_restore_wallet() {
+        let dir = wallet_dir("manager-restore-test");
+        let original = create_wallet(&dir, "original").await;
+        let backup = dir.join("backup.json");
+        let manager = RwLock::new(WalletManager::new(
+            &dir,
+            "/nonexistent/node.sock",
+            Network::Regtest,
+        ));
+        WalletManager::load_wallet(&manager, "original", &original)
+            .await
+            .unwrap();
+        let wallet = manager.read().await.get_wallet("original").unwrap().clone();
+        let address = wallet.get_new_address(None).unwrap();
+        wallet.export_wallet(&backup).unwrap();
+
+        WalletManager::restore_wallet(&manager, "restored", &backup)
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.read().await.list_wallets(),
+            vec!["original", "restored"]
+        );
+        let restored = manager.read().await.get_wallet("restored").unwrap().clone();
+        assert_eq!(restored.path(), dir.join("restored"));
+        assert!(restored.get_address_info(&address).unwrap().is_mine());
+
+        // Names already taken, loaded or not, are turned down
+        for name in ["original", "restored"] {
+            assert!(WalletManager::restore_wallet(&manager, name, &backup)
+                .await
+                .is_err());
+        }
+        manager.write().await.unload_wallet("restored").unwrap();
+        assert!(WalletManager::restore_wallet(&manager, "restored", &backup)
+            .await
+            .is_err());
+
+        // A failed restore leaves nothing behind
+        assert!(
+            WalletManager::restore_wallet(&manager, "missing", &dir.join("missing.json"))
+                .await
+                .is_err()
+        );
+        assert!(!dir.join("missing").exists());
+        assert_eq!(manager.read().await.list_wallets(), vec!["original"]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_loads() {
+        let dir = wallet_dir("manager-concurrent-test");
+        let names = ["alpha", "beta", "gamma", "delta"];
+        for name in names {
+            create_wallet(&dir, name).await;
+        }
+        let manager = Arc::new(RwLock::new(WalletManager::new(
+            &dir,
+            "/nonexistent/node.sock",
+            Network::Regtest,
+        )));
+
+        // Every wallet is asked for twice at once, so each is loaded once and
+        // the second request finds it already loaded or loading
+        let loads = names.iter().chain(names.iter()).map(|name| {
+            let manager = manager.clone();
+            tokio::spawn(async move { WalletManager::load_wallet_by_name(&manager, name).await })
+        });
+        let results = futures::future::join_all(loads).await;
+        let loaded = results
+            .iter()
+            .filter(|result| matches!(result, Ok(Ok(()))))
+            .count();
+        let already_loaded = results
+            .iter()
+            .filter(|result| matches!(result, Ok(Err(WalletError::WalletAlreadyLoaded(_)))))
+            .count();
+        assert_eq!((loaded, already_loaded), (names.len(), names.len()));
+
+        let manager = manager.read().await;
+        assert_eq!(
+            manager.list_wallets(),
+            vec!["alpha", "beta", "delta", "gamma"]
+        );
+        for name in names {
+            assert_eq!(manager.get_wallet(name).unwrap().wallet_name(), name);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,180 @@
+//! BIP39 mnemonics for wallet seeds, and encryption of the stored mnemonic
+
+use bip39::Mnemonic;
+use bitcoin::bip32::Xpriv;
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::Network;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::Rng;
+
+use super::types::AddressType;
+use crate::error::WalletError;
+
+/// Number of words in a newly generated mnemonic
+const MNEMONIC_WORDS: usize = 12;
+
+/// PBKDF2 rounds used to derive an encryption key from a passphrase
+const KEY_DERIVATION_ROUNDS: u32 = 100_000;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Generate a new random mnemonic
+pub(crate) fn generate_mnemonic() -> Result<Mnemonic, WalletError> {
+    Mnemonic::generate(MNEMONIC_WORDS)
+        .map_err(|e| WalletError::Generic(format!("Failed to generate mnemonic: {}", e)))
+}
+
+/// Parse a mnemonic, checking its words and checksum
+pub(crate) fn parse_mnemonic(mnemonic: &str) -> Result<Mnemonic, WalletError> {
+    Mnemonic::parse_normalized(mnemonic)
+        .map_err(|e| WalletError::InvalidDescriptor(format!("Invalid mnemonic: {}", e)))
+}
+
+/// Receive and change descriptors for the first account of a mnemonic's seed
+pub(crate) fn mnemonic_descriptors(
+    mnemonic: &Mnemonic,
+    network: Network,
+    address_type: AddressType,
+) -> Result<(String, String), WalletError> {
+    let seed = mnemonic.to_seed("");
+    let xprv = Xpriv::new_master(network, &seed)
+        .map_err(|e| WalletError::Generic(format!("Failed to derive master key: {}", e)))?;
+    Ok(address_type.descriptors(&xprv, network))
+}
+
+/// Encrypt a secret with a key derived from `passphrase`
+/// The result holds the salt and nonce needed to decrypt it.
+pub(crate) fn encrypt_secret(secret: &[u8], passphrase: &str) -> Result<Vec<u8>, WalletError> {
+    let mut rng = rand::thread_rng();
+    let salt: [u8; SALT_LEN] = rng.gen();
+    let nonce: [u8; NONCE_LEN] = rng.gen();
+
+    let key = derive_key(passphrase, &salt, KEY_DERIVATION_ROUNDS);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), secret)
+        .map_err(|_| WalletError::Generic("Failed to encrypt secret".to_string()))?;
+
+    Ok([&salt[..], &nonce[..], &ciphertext[..]].concat())
+}
+
+/// Decrypt a secret produced by `encrypt_secret`
+pub(crate) fn decrypt_secret(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>, WalletError> {
+    if encrypted.len() < SALT_LEN + NONCE_LEN {
+        return Err(WalletError::DatabaseError(
+            "Encrypted secret is truncated".to_string(),
+        ));
+    }
+    let (salt, rest) = encrypted.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let key = derive_key(passphrase, salt, KEY_DERIVATION_ROUNDS);
+    // A wrong passphrase fails authentication like any other tampering would
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| WalletError::PassphraseIncorrect)
+}
+
+// PBKDF2-HMAC-SHA256, computing only the single block a 32 byte key needs
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> [u8; 32] {
+    let prf = |data: &[&[u8]]| {
+        let mut engine = HmacEngine::<sha256::Hash>::new(passphrase.as_bytes());
+        for chunk in data {
+            engine.input(chunk);
+        }
+        Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+    };
+
+    let mut block = prf(&[salt, &1u32.to_be_bytes()]);
+    let mut key = block;
+    for _ in 1..rounds {
+        block = prf(&[&block]);
+        for (key_byte, block_byte) in key.iter_mut().zip(block) {
+            *key_byte ^= block_byte;
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_key_matches_pbkdf2_vectors() {
+        // RFC 7914 section 11 and the widely used PBKDF2-HMAC-SHA256 vectors
+        assert_eq!(
+            hex::encode(derive_key("password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex::encode(derive_key("password", b"salt", 2)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+    }
+
+    #[test]
+    fn test_secret_round_trip() {
+        let encrypted = encrypt_secret(b"abandon ability", "hunter2").unwrap();
+        assert!(!encrypted
+            .windows(b"abandon".len())
+            .any(|window| window == b"abandon"));
+        assert_eq!(
+            decrypt_secret(&encrypted, "hunter2").unwrap(),
+            b"abandon ability"
+        );
+        assert!(matches!(
+            decrypt_secret(&encrypted, "hunter3"),
+            Err(WalletError::PassphraseIncorrect)
+        ));
+    }
+
+    #[test]
+    fn test_mnemonic_descriptors() {
+        // The test vector mnemonic of BIP84 and BIP86, and its first receive address
+        let mnemonic = parse_mnemonic(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let (external, internal) =
+            mnemonic_descriptors(&mnemonic, Network::Bitcoin, AddressType::P2WPKH).unwrap();
+        assert!(external.ends_with("/84'/0'/0'/0/*)"));
+        assert!(internal.ends_with("/84'/0'/0'/1/*)"));
+
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let (descriptor, _) = bdk_wallet::miniscript::Descriptor::<
+            bdk_wallet::miniscript::DescriptorPublicKey,
+        >::parse_descriptor(&secp, &external)
+        .unwrap();
+        let address = descriptor
+            .at_derivation_index(0)
+            .unwrap()
+            .address(Network::Bitcoin)
+            .unwrap();
+        assert_eq!(
+            address.to_string(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+
+        // BIP86's first receive address for the same mnemonic
+        let (external, _) =
+            mnemonic_descriptors(&mnemonic, Network::Bitcoin, AddressType::P2TR).unwrap();
+        let (descriptor, _) = bdk_wallet::miniscript::Descriptor::<
+            bdk_wallet::miniscript::DescriptorPublicKey,
+        >::parse_descriptor(&secp, &external)
+        .unwrap();
+        let address = descriptor
+            .at_derivation_index(0)
+            .unwrap()
+            .address(Network::Bitcoin)
+            .unwrap();
+        assert_eq!(
+            address.to_string(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+
+        assert!(parse_mnemonic("abandon abandon abandon").is_err());
+    }
+}
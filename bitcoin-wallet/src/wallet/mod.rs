@@ -1,13 +1,21 @@
+mod backup;
+mod coin_selection;
 mod config;
 mod database;
 mod interface;
+mod manager;
+mod mnemonic;
 mod notification;
 mod transaction;
 mod types;
 
 // pub use database::WalletDatabase;
 pub use interface::WalletInterface;
+pub use manager::WalletManager;
 // pub use notification::NotificationProcessor;
 // pub use transaction::{TransactionBuilder, TransactionBroadcaster};
 pub use config::{DatabaseConfig, WalletConfig};
-pub use types::{CreateWalletOptions, TxRecipient, WalletBalance};
+pub use types::{
+    AddressGroupEntry, AddressInfo, AddressType, BlockDetails, CoinControlOptions, CreateWalletOptions, DescriptorEntry, DescriptorInfo, FundingOptions, RawTransaction, ScanResult, ScanUnspent, TransactionCategory, TransactionDetail, TransactionOutputDetail,
+    TxRecipient, UnspentOutput, WalletBalance, WalletTransaction,
+};
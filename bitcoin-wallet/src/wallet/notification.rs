@@ -2,101 +2,313 @@
 
 use async_trait::async_trait;
 use blocktalk::{ChainNotification, NotificationHandler};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
 use super::interface::WalletInterface;
 use super::types::WalletEvent;
 use crate::error::WalletError;
 
 /// Handler for blockchain notifications
+/// Notifications the wallet cares about are passed on as events for a
+/// `NotificationProcessor`, so the node's notification thread is never held
+/// up applying them.
 #[derive(Clone)]
 pub(crate) struct WalletNotificationHandler {
-    wallet: Arc<WalletInterface>,
     tx: mpsc::Sender<WalletEvent>,
 }
 
 impl WalletNotificationHandler {
-    pub fn new(wallet: Arc<WalletInterface>, tx: mpsc::Sender<WalletEvent>) -> Self {
-        Self { wallet, tx }
+    pub fn new(tx: mpsc::Sender<WalletEvent>) -> Self {
+        Self { tx }
     }
 }
 
-// #[async_trait]
-// impl NotificationHandler for WalletNotificationHandler {
-//     async fn handle_notification(
-//         &self,
-//         notification: ChainNotification,
-//     ) -> Result<(), blocktalk::BlockTalkError> {
-//         match notification {
-//             ChainNotification::BlockConnected(block) => {
-//                 if let Err(e) = self.tx.send(WalletEvent::BlockConnected(block.clone())).await {
-//                     log::error!("Failed to send block connected event: {}", e);
-//                 }
-//             }
-//             ChainNotification::BlockDisconnected(hash) => {
-//                 if let Err(e) = self.tx.send(WalletEvent::BlockDisconnected(hash)).await {
-//                     log::error!("Failed to send block disconnected event: {}", e);
-//                 }
-//             }
-//             ChainNotification::TransactionAddedToMempool(tx) => {
-//                 if let Err(e) = self.tx.send(WalletEvent::TransactionDetected(tx)).await {
-//                     log::error!("Failed to send transaction detected event: {}", e);
-//                 }
-//             }
-//             _ => {}
-//         }
-//         Ok(())
-//     }
-// }
+#[async_trait]
+impl NotificationHandler for WalletNotificationHandler {
+    async fn handle_notification(
+        &self,
+        notification: ChainNotification,
+    ) -> Result<(), blocktalk::BlockTalkError> {
+        let event = match notification {
+            ChainNotification::BlockConnected(block) => WalletEvent::BlockConnected(block),
+            ChainNotification::BlockDisconnected(hash) => WalletEvent::BlockDisconnected(hash),
+            ChainNotification::TransactionAddedToMempool(tx) => {
+                WalletEvent::TransactionDetected(tx)
+            }
+            _ => return Ok(()),
+        };
+        if let Err(e) = self.tx.send(event).await {
+            log::error!("Failed to send wallet event: {}", e);
+        }
+        Ok(())
+    }
+}
 
+/// Applies wallet events from a `WalletNotificationHandler` to the wallet
 pub struct NotificationProcessor {
     wallet: Arc<WalletInterface>,
-    rx: Arc<Mutex<mpsc::Receiver<WalletEvent>>>,
+    rx: mpsc::Receiver<WalletEvent>,
 }
 
-// impl NotificationProcessor {
-//     /// Create a new notification processor
-//     pub fn new(wallet: Arc<WalletInterface>, rx: mpsc::Receiver<WalletEvent>) -> Self {
-//         Self {
-//             wallet,
-//             rx: Arc::new(Mutex::new(rx)),
-//         }
-//     }
-
-//     /// Start processing notifications in a background task
-//     pub fn start(&self) {
-//         let wallet = self.wallet.clone();
-//         let rx = self.rx.clone();
-
-//         tokio::task::spawn(async move {
-//             let mut rx_guard = rx.lock().unwrap().clone();
-
-//             while let Some(event) = rx_guard.recv().await {
-//                 if let Err(e) = Self::process_event(&wallet, event).await {
-//                     log::error!("Error processing wallet event: {}", e);
-//                 }
-//             }
-//         });
-//     }
-
-//     /// Process a wallet event
-//     async fn process_event(wallet: &Arc<WalletInterface>, event: WalletEvent) -> Result<(), WalletError> {
-//         match event {
-//             WalletEvent::BlockConnected(block) => {
-//                 wallet.process_block(&block).await?;
-//             }
-//             WalletEvent::BlockDisconnected(_hash) => {
-//                 // On block disconnection, we need to resync the wallet
-//                 wallet.sync().await?;
-//             }
-//             WalletEvent::TransactionDetected(tx) => {
-//                 wallet.process_transaction(&tx, None).await?;
-//             }
-//             WalletEvent::SyncRequested => {
-//                 wallet.sync().await?;
-//             }
-//         }
-//         Ok(())
-//     }
-// }
+impl NotificationProcessor {
+    /// Create a new notification processor
+    pub(crate) fn new(wallet: Arc<WalletInterface>, rx: mpsc::Receiver<WalletEvent>) -> Self {
+        Self { wallet, rx }
+    }
+
+    /// Start processing notifications in a background task, which runs until
+    /// every sender of events has been dropped
+    /// Syncing uses the node client, which is not Send, so this must be called
+    /// from within a `LocalSet`.
+    pub fn start(self) -> JoinHandle<()> {
+        let Self { wallet, mut rx } = self;
+        tokio::task::spawn_local(async move {
+            while let Some(event) = rx.recv().await {
+                if let Err(e) = Self::process_event(&wallet, event).await {
+                    log::error!("Error processing wallet event: {}", e);
+                }
+            }
+            log::debug!("Wallet event channel closed, stopping notification processor");
+        })
+    }
+
+    /// Process a wallet event
+    async fn process_event(
+        wallet: &Arc<WalletInterface>,
+        event: WalletEvent,
+    ) -> Result<(), WalletError> {
+        match event {
+            WalletEvent::BlockConnected(block) => wallet.process_block(&block).await,
+            WalletEvent::BlockDisconnected(hash) => wallet.handle_reorg(&[hash], &[]).await,
+            WalletEvent::TransactionDetected(tx) => wallet.process_mempool_transaction(&tx).await,
+            WalletEvent::SyncRequested => wallet.sync_wallet().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::CreateWalletOptions;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{
+        Amount, Block, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+    };
+    use tokio::task::LocalSet;
+
+    async fn test_wallet(name: &str) -> (Arc<WalletInterface>, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("{}-{}.sqlite", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let wallet = WalletInterface::new(&path, "/nonexistent/node.sock", Network::Regtest)
+            .await
+            .unwrap();
+        wallet
+            .create_wallet(CreateWalletOptions::default())
+            .unwrap();
+        (wallet, path)
+    }
+
+    fn payment(script_pubkey: ScriptBuf, sats: u64) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(bitcoin::Txid::from_byte_array([7; 32]), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(sats),
+                script_pubkey,
+            }],
+        }
+    }
+
+    // Block at height 1 mining 50 BTC to `script_pubkey` and including `txdata`
+    fn mined_block(script_pubkey: ScriptBuf, txdata: Vec<Transaction>) -> Block {
+        mined_block_at(
+            1,
+            genesis_block(Network::Regtest).block_hash(),
+            script_pubkey,
+            txdata,
+        )
+    }
+
+    // Block at `height` on top of `parent` mining 50 BTC to `script_pubkey` and
+    // including `txdata`
+    fn mined_block_at(
+        height: i64,
+        parent: bitcoin::BlockHash,
+        script_pubkey: ScriptBuf,
+        txdata: Vec<Transaction>,
+    ) -> Block {
+        let genesis = genesis_block(Network::Regtest);
+        let coinbase = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::builder().push_int(height).into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(5_000_000_000),
+                script_pubkey,
+            }],
+        };
+        let mut header = genesis.header;
+        header.prev_blockhash = parent;
+        header.time += 600 * height as u32;
+        Block {
+            header,
+            txdata: std::iter::once(coinbase).chain(txdata).collect(),
+        }
+    }
+
+    // Deliver `notifications` through a handler and wait for the processor to
+    // apply them all
+    async fn deliver(wallet: &Arc<WalletInterface>, notifications: Vec<ChainNotification>) {
+        let (tx, rx) = mpsc::channel(8);
+        let handler = WalletNotificationHandler::new(tx);
+        let processor = NotificationProcessor::new(wallet.clone(), rx).start();
+        for notification in notifications {
+            handler.handle_notification(notification).await.unwrap();
+        }
+        drop(handler);
+        processor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mined_block_updates_balance() {
+        let (wallet, path) = test_wallet("notify-block-test").await;
+        let address = wallet.get_new_address(None).unwrap();
+        let block = mined_block(
+            address.script_pubkey(),
+            vec![payment(address.script_pubkey(), 30_000)],
+        );
+
+        LocalSet::new()
+            .run_until(deliver(
+                &wallet,
+                vec![ChainNotification::BlockConnected(block)],
+            ))
+            .await;
+
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!(balance.confirmed, Amount::from_sat(30_000));
+        assert_eq!(balance.immature, Amount::from_sat(5_000_000_000));
+        assert_eq!(wallet.list_transactions().unwrap().len(), 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_mempool_transaction_is_unconfirmed() {
+        let (wallet, path) = test_wallet("notify-mempool-test").await;
+        let address = wallet.get_new_address(None).unwrap();
+        let unrelated = payment(ScriptBuf::new_op_return([1; 4]), 10_000);
+
+        LocalSet::new()
+            .run_until(deliver(
+                &wallet,
+                vec![
+                    ChainNotification::TransactionAddedToMempool(payment(
+                        address.script_pubkey(),
+                        20_000,
+                    )),
+                    ChainNotification::TransactionAddedToMempool(unrelated),
+                ],
+            ))
+            .await;
+
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!(balance.confirmed, Amount::ZERO);
+        assert_eq!(balance.unconfirmed, Amount::from_sat(20_000));
+        assert_eq!(wallet.list_transactions().unwrap().len(), 1);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // Two blocks, the second paying 30,000 sats to `script_pubkey`, and a block
+    // replacing the second one that pays 40,000 sats instead
+    fn reorg_blocks(script_pubkey: ScriptBuf) -> (Block, Block, Block) {
+        let first = mined_block(ScriptBuf::new_op_return([1; 4]), vec![]);
+        let stale = mined_block_at(
+            2,
+            first.block_hash(),
+            ScriptBuf::new_op_return([2; 4]),
+            vec![payment(script_pubkey.clone(), 30_000)],
+        );
+        let replacement = mined_block_at(
+            2,
+            first.block_hash(),
+            ScriptBuf::new_op_return([3; 4]),
+            vec![payment(script_pubkey, 40_000)],
+        );
+        (first, stale, replacement)
+    }
+
+    #[tokio::test]
+    async fn test_reorg_replaces_stale_block() {
+        let (wallet, path) = test_wallet("notify-reorg-test").await;
+        let address = wallet.get_new_address(None).unwrap();
+        let (first, stale, replacement) = reorg_blocks(address.script_pubkey());
+        let stale_hash = stale.block_hash();
+
+        LocalSet::new()
+            .run_until(deliver(
+                &wallet,
+                vec![
+                    ChainNotification::BlockConnected(first),
+                    ChainNotification::BlockConnected(stale),
+                    ChainNotification::BlockDisconnected(stale_hash),
+                    ChainNotification::BlockConnected(replacement.clone()),
+                ],
+            ))
+            .await;
+
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!(balance.confirmed, Amount::from_sat(40_000));
+        assert_eq!(balance.unconfirmed, Amount::ZERO);
+        let unspent = wallet.list_unspent().unwrap();
+        assert_eq!(unspent.len(), 1);
+        assert_eq!(
+            unspent[0].outpoint.txid,
+            replacement.txdata[1].compute_txid()
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_block_on_fork_is_handled_as_reorg() {
+        let (wallet, path) = test_wallet("notify-fork-test").await;
+        let address = wallet.get_new_address(None).unwrap();
+        let (first, stale, replacement) = reorg_blocks(address.script_pubkey());
+
+        // The stale block's disconnection is never announced
+        LocalSet::new()
+            .run_until(deliver(
+                &wallet,
+                vec![
+                    ChainNotification::BlockConnected(first),
+                    ChainNotification::BlockConnected(stale),
+                    ChainNotification::BlockConnected(replacement),
+                ],
+            ))
+            .await;
+
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!(balance.confirmed, Amount::from_sat(40_000));
+        assert_eq!(balance.unconfirmed, Amount::ZERO);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,6 +1,12 @@
 //! Common types used in the wallet module
 
-use bitcoin::{Amount, BlockHash, ScriptBuf, Txid};
+use bdk_wallet::KeychainKind;
+use bitcoin::absolute::LockTime;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpriv, Xpub};
+use bitcoin::{
+    Address, Amount, BlockHash, Network, OutPoint, PublicKey, ScriptBuf, SignedAmount, Transaction,
+    Txid,
+};
 
 /// Transaction recipient for creating transactions
 #[derive(Clone)]
@@ -12,6 +18,30 @@ pub struct TxRecipient {
     pub amount: Amount,
 }
 
+/// Constraints on how a new transaction is funded
+#[derive(Debug, Clone, Default)]
+pub struct FundingOptions {
+    /// Inputs to spend. When set, no other inputs are selected.
+    pub inputs: Vec<OutPoint>,
+
+    pub lock_time: Option<LockTime>,
+
+    /// Script to send change to instead of a new change address
+    pub change_script: Option<ScriptBuf>,
+
+    pub coin_control: CoinControlOptions,
+}
+
+/// Wallet outputs coin selection must or must not spend
+#[derive(Debug, Clone, Default)]
+pub struct CoinControlOptions {
+    /// Outputs always spent by the transaction
+    pub must_use: Vec<OutPoint>,
+
+    /// Outputs never selected, such as frozen coins
+    pub must_not_use: Vec<OutPoint>,
+}
+
 /// Balance information for the wallet (matches Bitcoin Core format)
 #[derive(Debug, Clone, Copy)]
 pub struct WalletBalance {
@@ -28,6 +58,275 @@ pub struct WalletBalance {
     pub total: Amount,
 }
 
+/// Whether a wallet transaction pays out of or into the wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionCategory {
+    Send,
+    Receive,
+}
+
+impl TransactionCategory {
+    /// Category name as used by Bitcoin Core
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionCategory::Send => "send",
+            TransactionCategory::Receive => "receive",
+        }
+    }
+}
+
+/// An address in a `listaddressgroupings` group
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddressGroupEntry {
+    pub address: Address,
+
+    /// Unspent balance of the address
+    pub amount: Amount,
+
+    pub label: Option<String>,
+}
+
+/// An unspent output owned by the wallet (matches Bitcoin Core `listunspent` entries)
+#[derive(Debug, Clone)]
+pub struct UnspentOutput {
+    pub txid: Txid,
+
+    pub vout: u32,
+
+    pub address: Option<Address>,
+
+    pub label: String,
+
+    pub script_pubkey: ScriptBuf,
+
+    pub amount: Amount,
+
+    pub confirmations: u32,
+
+    /// Output descriptor for the derived script, including checksum
+    pub descriptor: Option<String>,
+
+    /// Whether the output is confirmed or change from one of our own transactions
+    pub safe: bool,
+}
+
+/// A descriptor of the wallet, as listed by `listdescriptors`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorEntry {
+    /// Public form of the descriptor, with its checksum
+    pub desc: String,
+
+    /// When the descriptor was imported, or the wallet created
+    pub timestamp: u64,
+
+    pub active: bool,
+
+    /// Whether this is the change descriptor
+    pub internal: bool,
+
+    /// Indices the wallet watches, for ranged descriptors
+    pub range: Option<[u32; 2]>,
+
+    pub next_index: u32,
+}
+
+/// What `getdescriptorinfo` reports about a descriptor
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorInfo {
+    /// Canonical form of the descriptor, without private keys, with its checksum
+    pub descriptor: String,
+
+    /// Checksum of the descriptor as given
+    pub checksum: String,
+
+    pub is_range: bool,
+
+    pub is_solvable: bool,
+
+    pub has_private_keys: bool,
+}
+
+/// An unspent output found by `scantxoutset`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanUnspent {
+    pub txid: Txid,
+
+    pub vout: u32,
+
+    pub script_pubkey: ScriptBuf,
+
+    /// The scanned descriptor the output matched, derived at the matching index
+    /// for ranged descriptors
+    pub desc: String,
+
+    pub amount: Amount,
+
+    /// Height of the block that created the output
+    pub height: u32,
+}
+
+/// Outputs found by `scantxoutset`, as of the chain tip at `height`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanResult {
+    pub unspents: Vec<ScanUnspent>,
+
+    pub total_amount: Amount,
+
+    pub height: i32,
+
+    pub best_block: BlockHash,
+}
+
+/// A wallet transaction with confirmation details (matches Bitcoin Core `listtransactions` entries)
+#[derive(Debug, Clone)]
+pub struct WalletTransaction {
+    pub txid: Txid,
+
+    pub category: TransactionCategory,
+
+    /// Address paid by the transaction, the counterparty for sends and ours for receives
+    pub address: Option<Address>,
+
+    /// Output index of `address`
+    pub vout: Option<u32>,
+
+    /// Net amount, negative for sends (excluding the fee)
+    pub amount: SignedAmount,
+
+    /// Fee paid, only known for transactions funded by the wallet
+    pub fee: Option<Amount>,
+
+    pub label: String,
+
+    pub confirmations: u32,
+
+    pub block_hash: Option<BlockHash>,
+
+    pub block_height: Option<u32>,
+
+    pub block_time: Option<u64>,
+
+    /// When the wallet first saw the transaction
+    pub time_received: u64,
+
+    /// Whether the transaction was abandoned with `abandontransaction`
+    pub abandoned: bool,
+}
+
+/// A single payment within a wallet transaction (an entry of `gettransaction`'s `details`)
+#[derive(Debug, Clone)]
+pub struct TransactionOutputDetail {
+    pub category: TransactionCategory,
+
+    pub address: Option<Address>,
+
+    /// Output value, negative for sends
+    pub amount: SignedAmount,
+
+    pub vout: u32,
+}
+
+/// Full details of a single wallet transaction (matches Bitcoin Core `gettransaction`)
+#[derive(Debug, Clone)]
+pub struct TransactionDetail {
+    pub txid: Txid,
+
+    /// Net amount, negative for sends (excluding the fee)
+    pub amount: SignedAmount,
+
+    /// Fee paid, only known for transactions funded by the wallet
+    pub fee: Option<Amount>,
+
+    pub confirmations: u32,
+
+    pub block_hash: Option<BlockHash>,
+
+    pub block_height: Option<u32>,
+
+    /// Position of the transaction within its block
+    pub block_index: Option<usize>,
+
+    pub block_time: Option<u64>,
+
+    /// When the wallet first saw the transaction
+    pub time_received: u64,
+
+    pub comment: String,
+
+    pub label: String,
+
+    /// Whether the transaction was abandoned with `abandontransaction`
+    pub abandoned: bool,
+
+    pub details: Vec<TransactionOutputDetail>,
+
+    pub transaction: Transaction,
+}
+
+/// A block with its position in the active chain (matches Bitcoin Core `getblock`)
+#[derive(Debug, Clone)]
+pub struct BlockDetails {
+    pub block: bitcoin::Block,
+
+    /// Height, `None` if the block is not in the active chain
+    pub height: Option<i32>,
+
+    /// Confirmations, -1 if the block is not in the active chain
+    pub confirmations: i32,
+
+    /// Median time of the block and the 10 before it
+    pub median_time: u32,
+
+    pub next_block_hash: Option<BlockHash>,
+}
+
+/// A transaction found by `getrawtransaction`, with the block containing it if confirmed
+#[derive(Debug, Clone)]
+pub struct RawTransaction {
+    pub transaction: Transaction,
+
+    pub block_hash: Option<BlockHash>,
+
+    /// Confirmations, 0 in the mempool and -1 if the block is not in the active chain
+    pub confirmations: i32,
+
+    pub block_time: Option<u32>,
+}
+
+/// What the wallet knows about an address (matches Bitcoin Core `getaddressinfo`)
+#[derive(Debug, Clone)]
+pub struct AddressInfo {
+    pub address: Address,
+
+    pub script_pubkey: ScriptBuf,
+
+    /// Keychain and derivation index, if the address belongs to the wallet
+    pub derivation: Option<(KeychainKind, u32)>,
+
+    /// Public key the address pays to, for single key addresses of ours
+    pub pubkey: Option<PublicKey>,
+
+    /// Full derivation path of the key from the master key
+    pub hd_key_path: Option<DerivationPath>,
+
+    pub master_fingerprint: Option<Fingerprint>,
+
+    /// Wallet creation time, as addresses are derived from the wallet's descriptors
+    pub timestamp: Option<u64>,
+
+    pub labels: Vec<String>,
+}
+
+impl AddressInfo {
+    pub fn is_mine(&self) -> bool {
+        self.derivation.is_some()
+    }
+
+    pub fn is_change(&self) -> bool {
+        matches!(self.derivation, Some((KeychainKind::Internal, _)))
+    }
+}
+
 /// Transaction metadata for wallet operations
 #[derive(Clone, Debug)]
 pub(crate) struct TransactionMetadata {
@@ -36,6 +335,8 @@ pub(crate) struct TransactionMetadata {
     pub fee: Option<Amount>,
     pub comment: String,
     pub label: String,
+    /// Set by `abandontransaction`
+    pub abandoned: bool,
 }
 
 /// Events that trigger wallet actions
@@ -47,14 +348,107 @@ pub(crate) enum WalletEvent {
     SyncRequested,
 }
 
+/// Kind of address a wallet's generated descriptors hand out
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AddressType {
+    /// Native segwit v0 addresses from BIP84 descriptors
+    #[default]
+    P2WPKH,
+
+    /// Taproot key path addresses from BIP86 descriptors
+    P2TR,
+}
+
+impl AddressType {
+    /// Address type name as used by Bitcoin Core
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressType::P2WPKH => "bech32",
+            AddressType::P2TR => "bech32m",
+        }
+    }
+
+    /// Parse one of Bitcoin Core's address type names, `None` for types the
+    /// wallet can't create
+    pub fn from_core_name(name: &str) -> Option<Self> {
+        match name {
+            "bech32" => Some(AddressType::P2WPKH),
+            "bech32m" => Some(AddressType::P2TR),
+            _ => None,
+        }
+    }
+
+    /// Receive and change descriptors for the first account under `xprv`
+    pub(crate) fn descriptors(&self, xprv: &Xpriv, network: Network) -> (String, String) {
+        // Test networks share BIP44 coin type 1
+        let coin_type = match network {
+            Network::Bitcoin => 0,
+            _ => 1,
+        };
+        let (script, purpose) = self.script_and_purpose();
+
+        let account = format!("{}/{}'/{}'/0'", xprv, purpose, coin_type);
+        (
+            format!("{}({}/0/*)", script, account),
+            format!("{}({}/1/*)", script, account),
+        )
+    }
+
+    /// Receive and change descriptors for an account's public key
+    pub(crate) fn xpub_descriptors(&self, xpub: &Xpub) -> (String, String) {
+        let (script, _) = self.script_and_purpose();
+        (
+            format!("{}({}/0/*)", script, xpub),
+            format!("{}({}/1/*)", script, xpub),
+        )
+    }
+
+    /// Address type of the BIP44 style account at `path`, by its purpose
+    pub(crate) fn from_account_path(path: &DerivationPath) -> Option<Self> {
+        let purpose = match *path.as_ref().first()? {
+            ChildNumber::Hardened { index } => index,
+            ChildNumber::Normal { .. } => return None,
+        };
+        [AddressType::P2WPKH, AddressType::P2TR]
+            .into_iter()
+            .find(|address_type| address_type.script_and_purpose().1 == purpose)
+    }
+
+    // Descriptor script function and BIP44 purpose of the address type
+    fn script_and_purpose(&self) -> (&'static str, u32) {
+        match self {
+            AddressType::P2WPKH => ("wpkh", 84),
+            AddressType::P2TR => ("tr", 86),
+        }
+    }
+}
+
 pub struct CreateWalletOptions {
     pub wallet_name: String,
     pub disable_private_keys: bool,
     pub blank: bool,
-    pub passphrase: Option<String>,
+
+    /// Encrypts the stored mnemonic, and nothing else. The wallet's private
+    /// descriptors are kept unencrypted in the same database, so anyone who can
+    /// read the file can spend from the wallet with or without it.
+    pub mnemonic_passphrase: Option<String>,
+
     pub avoid_reuse: bool,
     pub descriptors: bool,
     pub load_on_startup: bool,
+
+    /// Receive descriptor to create the wallet with instead of generating one
+    pub external_descriptor: Option<String>,
+
+    /// Change descriptor to go with `external_descriptor`
+    pub internal_descriptor: Option<String>,
+
+    /// BIP39 mnemonic to derive the wallet's descriptors from
+    pub mnemonic: Option<String>,
+
+    /// Kind of address to generate descriptors for, when none are supplied
+    pub address_type: AddressType,
 }
 
 impl Default for CreateWalletOptions {
@@ -63,10 +457,14 @@ impl Default for CreateWalletOptions {
             wallet_name: String::new(),
             disable_private_keys: false,
             blank: false,
-            passphrase: None,
+            mnemonic_passphrase: None,
             avoid_reuse: false,
             descriptors: true,
             load_on_startup: false,
+            external_descriptor: None,
+            internal_descriptor: None,
+            mnemonic: None,
+            address_type: AddressType::default(),
         }
     }
 }
@@ -0,0 +1,188 @@
+//! Latency of fetching the chain tip and sync status from a node over a Unix
+//! socket, one request after another and with independent requests in flight
+//! together, and the throughput of 100 tip requests made one at a time and
+//! through `ConcurrentChainInterface`
+//!
+//! Run with `cargo bench --bench tip_latency`.
+
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::Network;
+use blocktalk::chain_capnp::chain;
+use blocktalk::proxy_capnp::thread;
+use blocktalk::{Blockchain, ChainInterface, ConcurrentChainInterface};
+use capnp::capability::Promise;
+use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use tokio::task::LocalSet;
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+const ITERATIONS: u32 = 2_000;
+const BATCH_ITERATIONS: u32 = 50;
+const BATCH_SIZE: usize = 100;
+
+// Synced node whose tip is the regtest genesis block
+struct BenchNode;
+
+impl chain::Server for BenchNode {
+    fn get_height(
+        &mut self,
+        _: chain::GetHeightParams,
+        mut results: chain::GetHeightResults,
+    ) -> Promise<(), capnp::Error> {
+        results.get().set_result(0);
+        results.get().set_has_result(true);
+        Promise::ok(())
+    }
+
+    fn get_block_hash(
+        &mut self,
+        _: chain::GetBlockHashParams,
+        mut results: chain::GetBlockHashResults,
+    ) -> Promise<(), capnp::Error> {
+        let genesis = genesis_block(Network::Regtest);
+        results.get().set_result(genesis.block_hash().as_ref());
+        Promise::ok(())
+    }
+
+    fn is_initial_block_download(
+        &mut self,
+        _: chain::IsInitialBlockDownloadParams,
+        mut results: chain::IsInitialBlockDownloadResults,
+    ) -> Promise<(), capnp::Error> {
+        results.get().set_result(false);
+        Promise::ok(())
+    }
+
+    fn find_block(
+        &mut self,
+        _: chain::FindBlockParams,
+        mut results: chain::FindBlockResults,
+    ) -> Promise<(), capnp::Error> {
+        let genesis = genesis_block(Network::Regtest);
+        let mut block = results.get().init_block();
+        block.set_height(0);
+        block.set_time(genesis.header.time as i64);
+        block.set_found(true);
+        results.get().set_result(true);
+        Promise::ok(())
+    }
+}
+
+struct BenchThread;
+
+impl thread::Server for BenchThread {}
+
+// Serve `BenchNode` on one end of a Unix socket pair and connect to it from the other
+fn connect() -> chain::Client {
+    let (client_stream, server_stream) = UnixStream::pair().expect("Failed to create socket pair");
+
+    let (reader, writer) = server_stream.into_split();
+    let network = twoparty::VatNetwork::new(
+        reader.compat(),
+        writer.compat_write(),
+        rpc_twoparty_capnp::Side::Server,
+        Default::default(),
+    );
+    let node: chain::Client = capnp_rpc::new_client(BenchNode);
+    tokio::task::spawn_local(RpcSystem::new(Box::new(network), Some(node.client)));
+
+    let (reader, writer) = client_stream.into_split();
+    let network = twoparty::VatNetwork::new(
+        reader.compat(),
+        writer.compat_write(),
+        rpc_twoparty_capnp::Side::Client,
+        Default::default(),
+    );
+    let mut rpc = RpcSystem::new(Box::new(network), None);
+    let client = rpc.bootstrap(rpc_twoparty_capnp::Side::Server);
+    tokio::task::spawn_local(rpc);
+    client
+}
+
+async fn bench<F, Fut>(name: &str, mut call: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        call().await;
+    }
+    let per_call = start.elapsed() / ITERATIONS;
+    println!("{:<32} {:>10.1?}", name, per_call);
+}
+
+// Time `batch`, which makes `BATCH_SIZE` requests, and report requests per second
+async fn bench_batch<F, Fut>(name: &str, mut batch: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let start = Instant::now();
+    for _ in 0..BATCH_ITERATIONS {
+        batch().await;
+    }
+    let requests = (BATCH_ITERATIONS as usize * BATCH_SIZE) as f64;
+    let per_second = requests / start.elapsed().as_secs_f64();
+    println!("{:<32} {:>10.0} requests/s", name, per_second);
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build runtime");
+    let local = LocalSet::new();
+    local.block_on(&runtime, async {
+        // Without the tip cache every call goes to the node
+        let chain = Blockchain::from_client(connect(), capnp_rpc::new_client(BenchThread))
+            .with_tip_cache_ttl(Duration::ZERO);
+        let chain = &chain;
+        chain.get_chaininfo().await.expect("Node did not respond");
+
+        bench("get_tip", move || async move {
+            chain.get_tip().await.unwrap();
+        })
+        .await;
+        bench("get_tip, then is_synced", move || async move {
+            chain.get_tip().await.unwrap();
+            chain.is_synced().await.unwrap();
+        })
+        .await;
+        bench("get_tip and is_synced together", move || async move {
+            tokio::try_join!(chain.get_tip(), chain.is_synced()).unwrap();
+        })
+        .await;
+        bench("get_chaininfo", move || async move {
+            chain.get_chaininfo().await.unwrap();
+        })
+        .await;
+
+        let chain: Arc<dyn ChainInterface> = Arc::new(
+            Blockchain::from_client(connect(), capnp_rpc::new_client(BenchThread))
+                .with_tip_cache_ttl(Duration::ZERO),
+        );
+        let concurrent =
+            ConcurrentChainInterface::new(chain.clone()).with_max_in_flight(BATCH_SIZE);
+        let (chain, concurrent) = (&chain, &concurrent);
+        bench_batch("100 get_tip, sequential", move || async move {
+            for _ in 0..BATCH_SIZE {
+                chain.get_tip().await.unwrap();
+            }
+        })
+        .await;
+        bench_batch("100 get_tip, concurrent", move || async move {
+            let tips = concurrent
+                .run(
+                    0..BATCH_SIZE,
+                    |chain, _| async move { chain.get_tip().await },
+                )
+                .await;
+            assert!(tips.iter().all(Result::is_ok));
+        })
+        .await;
+    });
+}
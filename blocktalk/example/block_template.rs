@@ -0,0 +1,125 @@
+use bitcoin::Block;
+use blocktalk::{BlockTalk, BlockTalkError, MiningInterface};
+use std::path::Path;
+use std::time::Duration;
+use tokio::task::LocalSet;
+
+/// How often the template is rebuilt to pick up new mempool transactions
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of refreshes before the example exits
+const REFRESH_COUNT: usize = 6;
+
+#[tokio::main]
+async fn main() -> Result<(), BlockTalkError> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        println!("Usage: {} <socket_path>", args[0]);
+        println!(
+            "Example: {} ../bitcoin/datadir_blocktalk/regtest/node.sock",
+            args[0]
+        );
+        return Ok(());
+    }
+
+    let socket_path = &args[1];
+
+    if !check_socket_path(socket_path) {
+        return Ok(());
+    }
+
+    let local = LocalSet::new();
+    local
+        .run_until(async {
+            let blocktalk = match connect_to_node(socket_path).await {
+                Some(bt) => bt,
+                None => return Ok(()),
+            };
+
+            let mining = blocktalk.mining();
+            let template = mining.get_block_template().await?;
+            let mut previous = match print_template(&template, "Initial template") {
+                Some(block) => block,
+                None => return Ok(()),
+            };
+
+            for _ in 0..REFRESH_COUNT {
+                tokio::time::sleep(REFRESH_INTERVAL).await;
+                let template = refresh_template(mining.as_ref()).await?;
+                let block = match print_template(&template, "Refreshed template") {
+                    Some(block) => block,
+                    None => return Ok(()),
+                };
+
+                if block.header.prev_blockhash != previous.header.prev_blockhash {
+                    println!("🔔 New best block, template now builds on a new tip");
+                } else if block.txdata.len() != previous.txdata.len() {
+                    println!(
+                        "📥 Template changed by {} transaction(s)",
+                        block.txdata.len() as i64 - previous.txdata.len() as i64
+                    );
+                }
+                previous = block;
+            }
+
+            Ok(())
+        })
+        .await
+}
+
+/// Rebuilds the block template from the node's current mempool
+async fn refresh_template(mining: &dyn MiningInterface) -> Result<Vec<u8>, BlockTalkError> {
+    Ok(mining.update_block_template(Vec::new()).await?)
+}
+
+/// Decodes and summarises a block template
+fn print_template(template: &[u8], title: &str) -> Option<Block> {
+    match bitcoin::consensus::deserialize::<Block>(template) {
+        Ok(block) => {
+            println!(
+                "⛏️ {}: {} transaction(s) on top of {}",
+                title,
+                block.txdata.len(),
+                block.header.prev_blockhash
+            );
+            Some(block)
+        }
+        Err(e) => {
+            println!("⛔️ Failed to decode block template: {}", e);
+            None
+        }
+    }
+}
+
+/// Checks if the socket path exists and prints helpful error if not
+fn check_socket_path(socket_path: &str) -> bool {
+    if Path::new(socket_path).exists() {
+        return true;
+    }
+
+    println!("Error: Socket file {} does not exist!", socket_path);
+    println!("Please check that:");
+    println!("1. Bitcoin node is running");
+    println!("2. Bitcoin node is configured to use this Unix socket path");
+    println!("3. You have the correct permissions to access the socket");
+    false
+}
+
+/// Attempts to connect to the Bitcoin node with timeout
+async fn connect_to_node(socket_path: &str) -> Option<BlockTalk> {
+    println!("⏳ Connecting to Bitcoin node...");
+    match tokio::time::timeout(Duration::from_secs(5), BlockTalk::init(socket_path)).await {
+        Ok(Ok(bt)) => {
+            println!("✅ Connected successfully!");
+            Some(bt)
+        }
+        Ok(Err(e)) => {
+            println!("⛔️ Error connecting to Bitcoin node: {}", e);
+            None
+        }
+        Err(_) => {
+            println!("⏲️ Connection timed out after 5 seconds");
+            None
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use bitcoin::Block;
+use blocktalk::{BlockTalk, BlockTalkError};
+use std::path::Path;
+use std::time::Duration;
+use tokio::task::LocalSet;
+
+#[tokio::main]
+async fn main() -> Result<(), BlockTalkError> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        println!("Usage: {} <socket_path>", args[0]);
+        println!(
+            "Example: {} ../bitcoin/datadir_blocktalk/regtest/node.sock",
+            args[0]
+        );
+        return Ok(());
+    }
+
+    let socket_path = &args[1];
+
+    if !check_socket_path(socket_path) {
+        return Ok(());
+    }
+
+    let local = LocalSet::new();
+    local
+        .run_until(async {
+            let blocktalk = match connect_to_node(socket_path).await {
+                Some(bt) => bt,
+                None => return Ok(()),
+            };
+
+            let template = blocktalk.mining().get_block_template().await?;
+            let mut block: Block = match bitcoin::consensus::deserialize(&template) {
+                Ok(block) => block,
+                Err(e) => {
+                    println!("⛔️ Failed to decode block template: {}", e);
+                    return Ok(());
+                }
+            };
+            println!(
+                "⛏️ Mining on top of {} with {} transaction(s)",
+                block.header.prev_blockhash,
+                block.txdata.len()
+            );
+
+            // Regtest difficulty is low enough to find a solution in a few tries
+            let target = block.header.target();
+            while block.header.validate_pow(target).is_err() {
+                block.header.nonce = match block.header.nonce.checked_add(1) {
+                    Some(nonce) => nonce,
+                    None => {
+                        println!("⛔️ Ran out of nonces, is this a regtest node?");
+                        return Ok(());
+                    }
+                };
+            }
+            println!(
+                "✅ Found block {} with nonce {}",
+                block.block_hash(),
+                block.header.nonce
+            );
+
+            match blocktalk.submit_block(&block).await? {
+                None => println!("✅ Block accepted by the node"),
+                Some(reason) => println!("⛔️ Block rejected: {}", reason),
+            }
+
+            Ok(())
+        })
+        .await
+}
+
+/// Checks if the socket path exists and prints helpful error if not
+fn check_socket_path(socket_path: &str) -> bool {
+    if Path::new(socket_path).exists() {
+        return true;
+    }
+
+    println!("Error: Socket file {} does not exist!", socket_path);
+    println!("Please check that:");
+    println!("1. Bitcoin node is running");
+    println!("2. Bitcoin node is configured to use this Unix socket path");
+    println!("3. You have the correct permissions to access the socket");
+    false
+}
+
+/// Attempts to connect to the Bitcoin node with timeout
+async fn connect_to_node(socket_path: &str) -> Option<BlockTalk> {
+    println!("⏳ Connecting to Bitcoin node...");
+    match tokio::time::timeout(Duration::from_secs(5), BlockTalk::init(socket_path)).await {
+        Ok(Ok(bt)) => {
+            println!("✅ Connected successfully!");
+            Some(bt)
+        }
+        Ok(Err(e)) => {
+            println!("⛔️ Error connecting to Bitcoin node: {}", e);
+            None
+        }
+        Err(_) => {
+            println!("⏲️ Connection timed out after 5 seconds");
+            None
+        }
+    }
+}
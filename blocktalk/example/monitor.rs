@@ -18,10 +18,12 @@ impl NotificationHandler for BlockMonitor {
         notification: ChainNotification,
     ) -> Result<(), BlockTalkError> {
         match notification {
-            ChainNotification::UpdatedBlockTip(_) => {
-                println!("\n╔═══════════════════════╗");
-                println!("║   Block Tip Updated   ║");
-                println!("╚═══════════════════════╝");
+            ChainNotification::UpdatedBlockTip(hash) => {
+                println!("\n╔════════════════════════════════════════════════════════════════════════╗");
+                println!("║                           Block Tip Updated                            ║");
+                println!("╠════════════════════════════════════════════════════════════════════════╣");
+                println!("║ Hash         │ {:<60} ║", hash);
+                println!("╚══════════════╧══════════════════════════════════════════════════════════╝");
             }
 
             ChainNotification::BlockConnected(block) => {
@@ -142,7 +144,7 @@ async fn main() -> Result<(), BlockTalkError> {
             let handler = Arc::new(BlockMonitor {
                 latest_height: Arc::new(Mutex::new(0)),
             });
-            chain.add_notification_handler(handler.clone()).await?;
+            let handler_id = chain.add_notification_handler(handler.clone()).await?;
 
             // Start receiving chain updates
             chain.begin_chain_updates().await?;
@@ -154,6 +156,7 @@ async fn main() -> Result<(), BlockTalkError> {
             println!("\nStopping chain updates...");
             
             chain.stop_chain_updates().await?;
+            chain.remove_notification_handler(handler_id).await?;
             Ok(())
         })
         .await
@@ -0,0 +1,229 @@
+use bitcoin::opcodes::OP_TRUE;
+use bitcoin::script::Builder;
+use bitcoin::{Address, Block, BlockHash, Network};
+use std::sync::Arc;
+
+use crate::{BlockTalk, BlockTalkError, MiningInterface, DEFAULT_BLOCK_RESERVED_WEIGHT};
+
+/// Control over a regtest node for integration tests
+/// Only built with the `admin` feature, so it can't be used by accident
+/// against a real node.
+#[async_trait::async_trait(?Send)]
+pub trait AdminInterface {
+    /// Mine `n_blocks` blocks paying their rewards to `address`, returning
+    /// their hashes in the order they were mined
+    /// Each block is built from a new block template, so it includes the
+    /// node's mempool. Solving one only takes a few hashes on regtest.
+    async fn generate_to_address(
+        &self,
+        n_blocks: u32,
+        address: &Address,
+    ) -> Result<Vec<BlockHash>, BlockTalkError>;
+}
+
+/// Administers a node through its mining interface
+pub struct Admin {
+    mining: Arc<dyn MiningInterface>,
+}
+
+impl Admin {
+    pub fn new(mining: Arc<dyn MiningInterface>) -> Self {
+        Self { mining }
+    }
+
+    // Mine one block on a new template, paying the reward to `address`
+    async fn mine_block(&self, address: &Address) -> Result<BlockHash, BlockTalkError> {
+        let template = self
+            .mining
+            .get_block_template_with_options(true, DEFAULT_BLOCK_RESERVED_WEIGHT)
+            .await?;
+        let mut block: Block = bitcoin::consensus::deserialize(&template).map_err(|e| {
+            BlockTalkError::node_error(format!("Failed to decode block template: {}", e), -1)
+        })?;
+
+        // The node's coinbase pays the reward in its first output and commits
+        // to the other transactions' witnesses in a later one, which doesn't
+        // cover the coinbase, so only the first output needs changing
+        let coinbase = block
+            .txdata
+            .first_mut()
+            .filter(|tx| tx.is_coinbase() && !tx.output.is_empty())
+            .ok_or_else(|| {
+                BlockTalkError::node_error("Block template has no coinbase".to_string(), -1)
+            })?;
+        coinbase.output[0].script_pubkey = address.script_pubkey();
+        if let Some(merkle_root) = block.compute_merkle_root() {
+            block.header.merkle_root = merkle_root;
+        }
+
+        solve(&mut block)?;
+        let block_hash = block.block_hash();
+        match self.mining.submit_block(&block).await? {
+            None => Ok(block_hash),
+            Some(reason) => Err(BlockTalkError::node_error(
+                format!("Mined block {} was rejected: {}", block_hash, reason),
+                -1,
+            )),
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl AdminInterface for Admin {
+    async fn generate_to_address(
+        &self,
+        n_blocks: u32,
+        address: &Address,
+    ) -> Result<Vec<BlockHash>, BlockTalkError> {
+        log::info!("Mining {} blocks to {}", n_blocks, address);
+        let mut block_hashes = Vec::with_capacity(n_blocks as usize);
+        for _ in 0..n_blocks {
+            block_hashes.push(self.mine_block(address).await?);
+        }
+        Ok(block_hashes)
+    }
+}
+
+// Find a nonce giving `block` enough work for its target
+// Regtest's target is met by about every other hash, and no other network's
+// target can be met by trying every nonce.
+fn solve(block: &mut Block) -> Result<(), BlockTalkError> {
+    let target = block.header.target();
+    for nonce in 0..=u32::MAX {
+        block.header.nonce = nonce;
+        if block.header.validate_pow(target).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(BlockTalkError::node_error(
+        format!("No nonce solves block {}", block.block_hash()),
+        -1,
+    ))
+}
+
+/// A connected node with the means to mine on it, for integration tests
+pub struct TestHarness {
+    pub blocktalk: BlockTalk,
+    pub admin: Admin,
+}
+
+impl TestHarness {
+    pub fn new(blocktalk: BlockTalk) -> Self {
+        let admin = Admin::new(blocktalk.mining().clone());
+        Self { blocktalk, admin }
+    }
+
+    /// Mine `n_blocks` blocks paying to an anyone-can-spend address
+    pub async fn mine(&self, n_blocks: u32) -> Result<Vec<BlockHash>, BlockTalkError> {
+        self.mine_to(n_blocks, &anyone_can_spend_address()).await
+    }
+
+    /// Mine `n_blocks` blocks paying to `address`
+    pub async fn mine_to(
+        &self,
+        n_blocks: u32,
+        address: &Address,
+    ) -> Result<Vec<BlockHash>, BlockTalkError> {
+        self.admin.generate_to_address(n_blocks, address).await
+    }
+}
+
+// Regtest P2WSH address of `OP_TRUE`, which anyone can spend
+fn anyone_can_spend_address() -> Address {
+    let script = Builder::new().push_opcode(OP_TRUE).into_script();
+    Address::p2wsh(&script, Network::Regtest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Transaction;
+    use std::sync::Mutex;
+
+    // Node that builds templates on its tip and accepts any block solving one
+    struct TestMining {
+        chain: Mutex<Vec<Block>>,
+    }
+
+    impl TestMining {
+        fn new() -> Self {
+            Self {
+                chain: Mutex::new(vec![genesis_block(Network::Regtest)]),
+            }
+        }
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl MiningInterface for TestMining {
+        async fn get_block_template(&self) -> Result<Vec<u8>, capnp::Error> {
+            self.get_block_template_with_options(true, DEFAULT_BLOCK_RESERVED_WEIGHT)
+                .await
+        }
+
+        async fn get_block_template_with_options(
+            &self,
+            _: bool,
+            _: u32,
+        ) -> Result<Vec<u8>, capnp::Error> {
+            let chain = self.chain.lock().unwrap();
+            let tip = chain.last().unwrap();
+            let mut block = tip.clone();
+            block.header.prev_blockhash = tip.block_hash();
+            block.header.time = tip.header.time + 1;
+            // The coinbase's input must differ from block to block, as
+            // BIP34's height does
+            block.txdata[0].input[0].script_sig =
+                Builder::new().push_int(chain.len() as i64).into_script();
+            block.header.merkle_root = block.compute_merkle_root().unwrap();
+            Ok(bitcoin::consensus::serialize(&block))
+        }
+
+        async fn update_block_template(
+            &self,
+            _: Vec<Transaction>,
+        ) -> Result<Vec<u8>, capnp::Error> {
+            self.get_block_template().await
+        }
+
+        async fn submit_block(&self, block: &Block) -> Result<Option<String>, capnp::Error> {
+            let mut chain = self.chain.lock().unwrap();
+            let extends_tip = block.header.prev_blockhash == chain.last().unwrap().block_hash();
+            let valid = block.check_merkle_root()
+                && block.header.validate_pow(block.header.target()).is_ok();
+            if !(extends_tip && valid) {
+                return Ok(Some("rejected".to_string()));
+            }
+            chain.push(block.clone());
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_to_address() {
+        let mining = Arc::new(TestMining::new());
+        let admin = Admin::new(mining.clone());
+        let address = anyone_can_spend_address();
+
+        let block_hashes = admin.generate_to_address(3, &address).await.unwrap();
+        let chain = mining.chain.lock().unwrap();
+        assert_eq!(chain.len(), 4);
+        assert_eq!(
+            block_hashes,
+            chain[1..].iter().map(Block::block_hash).collect::<Vec<_>>()
+        );
+        for block in &chain[1..] {
+            assert_eq!(
+                block.txdata[0].output[0].script_pubkey,
+                address.script_pubkey()
+            );
+        }
+
+        drop(chain);
+        assert!(admin
+            .generate_to_address(0, &address)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+use bitcoin::bip158::{self, BlockFilterWriter};
+use bitcoin::{Block, BlockHash, ScriptBuf};
+
+use crate::error::ChainErrorKind;
+use crate::BlockTalkError;
+
+/// A BIP158 compact filter of a block's scripts, with the hash of the block
+/// it was built for, which is needed to query it
+///
+/// The IPC interface can test a filter from the node's filter index but not
+/// return one, so filters are built here from the block. The block alone does
+/// not have the scripts of the outputs it spends, so unlike the node's basic
+/// filter only output scripts are included. A filter will match payments to a
+/// script but not spends from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockFilter {
+    pub block_hash: BlockHash,
+    pub filter: bip158::BlockFilter,
+}
+
+impl BlockFilter {
+    /// Build a filter of the output scripts in `block`
+    /// `OP_RETURN` scripts are left out, as in BIP158.
+    pub fn from_block(block: &Block) -> Result<Self, BlockTalkError> {
+        let block_hash = block.block_hash();
+        let mut content = Vec::new();
+        let mut writer = BlockFilterWriter::new(&mut content, block);
+        writer.add_output_scripts();
+        writer
+            .finish()
+            .map_err(|e| filter_error(&block_hash, &bip158::Error::Io(e)))?;
+        Ok(Self {
+            block_hash,
+            filter: bip158::BlockFilter::new(&content),
+        })
+    }
+
+    /// Check whether any of `scripts` may be in the block
+    /// Filters have false positives, at a rate of about 1 in 784931, but no
+    /// false negatives.
+    pub fn matches_any(&self, scripts: &[ScriptBuf]) -> Result<bool, BlockTalkError> {
+        self.filter
+            .match_any(
+                &self.block_hash,
+                scripts.iter().map(|script| script.as_bytes()),
+            )
+            .map_err(|e| filter_error(&self.block_hash, &e))
+    }
+}
+
+fn filter_error(block_hash: &BlockHash, e: &bip158::Error) -> BlockTalkError {
+    BlockTalkError::chain_error(
+        ChainErrorKind::InvalidBlockData,
+        format!("Invalid filter for block {}: {}", block_hash, e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Blockchain, ChainInterface, Connection, InProcessConnectionProvider};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Network;
+
+    #[test]
+    fn test_genesis_filter() {
+        // The testnet genesis block from the BIP158 test vectors, which has
+        // no inputs to leave out
+        let block = genesis_block(Network::Testnet);
+        let filter = BlockFilter::from_block(&block).unwrap();
+        assert_eq!(filter.block_hash, block.block_hash());
+        assert_eq!(filter.filter.content, vec![0x01, 0x9d, 0xfc, 0xa8]);
+    }
+
+    #[test]
+    fn test_filter_matches_output_scripts() {
+        let block = genesis_block(Network::Regtest);
+        let filter = BlockFilter::from_block(&block).unwrap();
+        let coinbase_script = block.txdata[0].output[0].script_pubkey.clone();
+        let other_script = ScriptBuf::from_bytes(vec![0x51]);
+
+        assert!(filter.matches_any(&[coinbase_script.clone()]).unwrap());
+        assert!(filter
+            .matches_any(&[other_script.clone(), coinbase_script])
+            .unwrap());
+        assert!(!filter.matches_any(&[other_script]).unwrap());
+        assert!(!filter.matches_any(&[]).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_block_filter() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let connection =
+                    Connection::connect("in-process", Box::new(InProcessConnectionProvider::new()))
+                        .await
+                        .unwrap();
+                let chain = Blockchain::new(connection);
+                let genesis = genesis_block(Network::Regtest);
+
+                let filter = chain
+                    .get_block_filter(&genesis.block_hash())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(filter, BlockFilter::from_block(&genesis).unwrap());
+                let coinbase_script = genesis.txdata[0].output[0].script_pubkey.clone();
+                assert!(chain.match_filter(&filter, &[coinbase_script]).unwrap());
+
+                let missing = chain.get_block_filter(&BlockHash::all_zeros()).await;
+                assert_eq!(missing.unwrap(), None);
+            })
+            .await;
+    }
+}
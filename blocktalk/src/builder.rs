@@ -0,0 +1,327 @@
+#[cfg(feature = "health")]
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    BlockTalk, BlockTalkError, BlockTalkLogConfig, ChainInterface, Connection, ConnectionProvider,
+    MempoolInterface, MiningInterface, NetworkInterface, UnixConnectionProvider,
+};
+
+/// How long `BlockTalkBuilder::build` waits for each connection attempt by default
+pub const DEFAULT_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What to do when connecting to the node fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReconnectPolicy {
+    /// Give up after the first failed attempt
+    #[default]
+    Never,
+    /// Try again up to `max_retries` times, waiting `delay` before each retry
+    Fixed { max_retries: u32, delay: Duration },
+}
+
+/// Builder for a `BlockTalk` with non-default options
+///
+/// Interfaces that are not supplied are created from the node connection.
+///
+/// ```no_run
+/// # use blocktalk::{BlockTalk, BlockTalkError, ReconnectPolicy};
+/// # use std::time::Duration;
+/// # async fn connect() -> Result<BlockTalk, BlockTalkError> {
+/// BlockTalk::builder()
+///     .socket_path("/path/to/node.sock")
+///     .connection_timeout(Duration::from_secs(5))
+///     .reconnect_policy(ReconnectPolicy::Fixed {
+///         max_retries: 3,
+///         delay: Duration::from_secs(1),
+///     })
+///     .build()
+///     .await
+/// # }
+/// ```
+pub struct BlockTalkBuilder {
+    socket_path: String,
+    connection_timeout: Duration,
+    reconnect_policy: ReconnectPolicy,
+    provider: Option<Arc<dyn ConnectionProvider>>,
+    chain: Option<Arc<dyn ChainInterface>>,
+    mempool: Option<Arc<dyn MempoolInterface>>,
+    mining: Option<Arc<dyn MiningInterface>>,
+    network: Option<Arc<dyn NetworkInterface>>,
+    log_config: Option<BlockTalkLogConfig>,
+    #[cfg(feature = "health")]
+    health_addr: Option<SocketAddr>,
+}
+
+impl Default for BlockTalkBuilder {
+    fn default() -> Self {
+        Self {
+            socket_path: String::new(),
+            connection_timeout: DEFAULT_CONNECTION_TIMEOUT,
+            reconnect_policy: ReconnectPolicy::default(),
+            provider: None,
+            chain: None,
+            mempool: None,
+            mining: None,
+            network: None,
+            log_config: None,
+            #[cfg(feature = "health")]
+            health_addr: None,
+        }
+    }
+}
+
+impl BlockTalkBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path of the node's IPC socket, or its address for providers that ignore the path
+    pub fn socket_path(mut self, path: &str) -> Self {
+        self.socket_path = path.to_string();
+        self
+    }
+
+    /// How long to wait for each connection attempt
+    pub fn connection_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout;
+        self
+    }
+
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Connect with `provider` instead of a Unix socket
+    pub fn connection_provider(mut self, provider: Box<dyn ConnectionProvider>) -> Self {
+        self.provider = Some(Arc::from(provider));
+        self
+    }
+
+    pub fn chain_interface(mut self, chain: Arc<dyn ChainInterface>) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    pub fn mempool_interface(mut self, mempool: Arc<dyn MempoolInterface>) -> Self {
+        self.mempool = Some(mempool);
+        self
+    }
+
+    pub fn mining_interface(mut self, mining: Arc<dyn MiningInterface>) -> Self {
+        self.mining = Some(mining);
+        self
+    }
+
+    pub fn network_interface(mut self, network: Arc<dyn NetworkInterface>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Log BlockTalk's modules at the levels in `config`, from before
+    /// connecting
+    /// The levels only take effect with `BlockTalkLogger` installed as the logger.
+    pub fn log_config(mut self, config: BlockTalkLogConfig) -> Self {
+        self.log_config = Some(config);
+        self
+    }
+
+    /// Serve liveness and readiness probes on `addr` once connected, see
+    /// `BlockTalk::health_endpoint`
+    /// The endpoint stops when the `BlockTalk` and all its clones are dropped
+    /// or disconnected.
+    #[cfg(feature = "health")]
+    pub fn health_endpoint(mut self, addr: SocketAddr) -> Self {
+        self.health_addr = Some(addr);
+        self
+    }
+
+    /// Connect to the node and build the `BlockTalk`
+    /// Fails without connecting if the socket path is empty or the timeout is zero.
+    pub async fn build(self) -> Result<BlockTalk, BlockTalkError> {
+        self.validate()?;
+        if let Some(config) = &self.log_config {
+            config.apply();
+        }
+        let connection = self.connect().await?;
+        log::info!("BlockTalk initialized successfully");
+
+        let blocktalk = BlockTalk::from_parts(
+            connection,
+            self.chain,
+            self.mempool,
+            self.mining,
+            self.network,
+        );
+        #[cfg(feature = "health")]
+        let blocktalk = start_health_endpoint(blocktalk, self.health_addr);
+        Ok(blocktalk)
+    }
+
+    fn validate(&self) -> Result<(), BlockTalkError> {
+        if self.socket_path.is_empty() {
+            return Err(BlockTalkError::Connection(
+                "Socket path must not be empty".to_string(),
+            ));
+        }
+        if self.connection_timeout.is_zero() {
+            return Err(BlockTalkError::Connection(
+                "Connection timeout must be greater than zero".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Connect to the node, retrying as allowed by the reconnect policy
+    async fn connect(&self) -> Result<Arc<Connection>, BlockTalkError> {
+        let provider = self
+            .provider
+            .clone()
+            .unwrap_or_else(|| Arc::new(UnixConnectionProvider));
+
+        let mut retries = 0;
+        loop {
+            let error = match tokio::time::timeout(
+                self.connection_timeout,
+                Connection::connect(&self.socket_path, Box::new(provider.clone())),
+            )
+            .await
+            {
+                Ok(Ok(connection)) => return Ok(connection),
+                Ok(Err(e)) => e,
+                Err(_) => BlockTalkError::Connection(format!(
+                    "Timed out connecting to {} after {:?}",
+                    self.socket_path, self.connection_timeout
+                )),
+            };
+
+            match self.reconnect_policy {
+                ReconnectPolicy::Fixed { max_retries, delay } if retries < max_retries => {
+                    retries += 1;
+                    log::warn!(
+                        "Failed to connect to node: {}, retrying in {:?} ({}/{})",
+                        error,
+                        delay,
+                        retries,
+                        max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "health")]
+fn start_health_endpoint(mut blocktalk: BlockTalk, addr: Option<SocketAddr>) -> BlockTalk {
+    if let Some(addr) = addr {
+        let handle = blocktalk.health_endpoint(addr);
+        blocktalk.health = Some(Arc::new(crate::health::HealthTask::new(handle)));
+    }
+    blocktalk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capnp_rpc::twoparty;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Provider whose connections fail, counting the attempts
+    struct FailingProvider {
+        attempts: Arc<AtomicUsize>,
+        hang: bool,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl ConnectionProvider for FailingProvider {
+        async fn create_network(
+            &self,
+            _path: &str,
+        ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            if self.hang {
+                std::future::pending::<()>().await;
+            }
+            Err(BlockTalkError::node_error(
+                "Connection refused".to_string(),
+                -1,
+            ))
+        }
+    }
+
+    fn failing_builder(hang: bool) -> (BlockTalkBuilder, Arc<AtomicUsize>) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let builder = BlockTalk::builder()
+            .socket_path("test_path")
+            .connection_provider(Box::new(FailingProvider {
+                attempts: attempts.clone(),
+                hang,
+            }));
+        (builder, attempts)
+    }
+
+    #[test]
+    fn test_defaults_and_overrides() {
+        let builder = BlockTalk::builder();
+        assert_eq!(builder.connection_timeout, DEFAULT_CONNECTION_TIMEOUT);
+        assert_eq!(builder.reconnect_policy, ReconnectPolicy::Never);
+        assert!(builder.provider.is_none());
+
+        let policy = ReconnectPolicy::Fixed {
+            max_retries: 2,
+            delay: Duration::from_millis(10),
+        };
+        let builder = builder.socket_path("node.sock").reconnect_policy(policy);
+        assert_eq!(builder.socket_path, "node.sock");
+        assert_eq!(builder.reconnect_policy, policy);
+        // Options that were not set keep their defaults
+        assert_eq!(builder.connection_timeout, DEFAULT_CONNECTION_TIMEOUT);
+        assert!(builder.chain.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_options_fail_before_connecting() {
+        let (builder, attempts) = failing_builder(false);
+        let result = builder.socket_path("").build().await;
+        assert!(matches!(result, Err(BlockTalkError::Connection(_))));
+
+        let (builder, _) = failing_builder(false);
+        let result = builder.connection_timeout(Duration::ZERO).build().await;
+        assert!(matches!(result, Err(BlockTalkError::Connection(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_retries_follow_reconnect_policy() {
+        let (builder, attempts) = failing_builder(false);
+        let result = builder.build().await;
+        assert!(matches!(result, Err(BlockTalkError::Node { code: -1, .. })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let (builder, attempts) = failing_builder(false);
+        let result = builder
+            .reconnect_policy(ReconnectPolicy::Fixed {
+                max_retries: 2,
+                delay: Duration::ZERO,
+            })
+            .build()
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_connection_attempt_times_out() {
+        let (builder, attempts) = failing_builder(true);
+        let result = builder
+            .connection_timeout(Duration::from_millis(10))
+            .build()
+            .await;
+        assert!(matches!(result, Err(BlockTalkError::Connection(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
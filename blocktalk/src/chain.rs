@@ -1,20 +1,113 @@
+use bitcoin::block::Header;
 use bitcoin::consensus::Decodable;
-use bitcoin::hashes::Hash;
-use bitcoin::{Block, BlockHash};
+use bitcoin::hashes::{Hash, HashEngine};
+use bitcoin::pow::Work;
+use bitcoin::{Block, BlockHash, ScriptBuf, TxMerkleNode, Txid};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
 
-use crate::error::ChainErrorKind;
+use crate::block_filter::BlockFilter;
+use crate::chain_stats::{halving_interval, BlockStats};
+use crate::concurrent::DEFAULT_MAX_IN_FLIGHT;
+use crate::error::{BlockTalkResultExt, ChainErrorKind};
+use crate::logging;
+use crate::request_metrics::{self, Interface, RequestTimer};
+use crate::types::{BlockDetails, ScriptMatch};
 use crate::{
     chain_capnp::chain::Client as ChainClient,
-    notification::{ChainNotificationHandler, NotificationHandler},
+    handler_capnp::handler::Client as HandlerClient,
+    notification::{
+        AddressSubscription, ChainNotificationHandler, HandlerId, NotificationFilter,
+        NotificationHandler,
+    },
     proxy_capnp::thread::Client as ThreadClient,
-    BlockTalkError, Connection,
+    BlockTalkError, ChainNotification, Connection,
 };
 
+/// Number of blocks `Blockchain::block_stream` fetches ahead of the consumer
+pub const DEFAULT_BLOCK_STREAM_BUFFER: usize = 8;
+
+/// How long `ChainInterface::get_tip` reuses a tip fetched from the node
+pub const DEFAULT_TIP_CACHE_TTL: Duration = Duration::from_secs(1);
+
+// The node's filter type for BIP158 basic filters, the only type it indexes
+const BASIC_FILTER_TYPE: u8 = 0;
+
+// Number of blocks whose timestamps make up a block's median time past
+const MEDIAN_TIME_SPAN: i32 = 11;
+
+// How long `get_block_ancestors` waits for all the ancestors it asked for
+const ANCESTOR_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// `get_chain_work` remembers the work of every block at a multiple of this
+// height, one per difficulty period
+const CHAIN_WORK_CHECKPOINT_INTERVAL: i32 = 2016;
+
+// Most chain work checkpoints kept, enough for eight million blocks
+const MAX_CHAIN_WORK_CHECKPOINTS: usize = 4096;
+
+/// Summary of the node's active chain, from `ChainInterface::get_chaininfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainInfo {
+    /// Height of the tip
+    pub height: i32,
+    /// Hash of the tip
+    pub best_block_hash: BlockHash,
+    /// Timestamp of the tip
+    pub tip_time: u32,
+    /// Whether the node has finished initial block download
+    pub is_synced: bool,
+    /// Whether the node is still in initial block download
+    pub ibd: bool,
+}
+
+/// Blocks to undo and apply to move from an earlier tip to the current one,
+/// from `Blockchain::get_blocks_since`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainUpdate {
+    /// Heights and hashes of blocks now in the active chain, lowest first
+    pub connected: Vec<(i32, BlockHash)>,
+    /// Hashes of blocks no longer in the active chain, highest first
+    pub disconnected: Vec<BlockHash>,
+}
+
+// Chain tip as last fetched from the node
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CachedTip {
+    height: i32,
+    hash: BlockHash,
+    fetched_at: Instant,
+}
+
+impl CachedTip {
+    pub(crate) fn new(height: i32, hash: BlockHash) -> Self {
+        Self {
+            height,
+            hash,
+            fetched_at: Instant::now(),
+        }
+    }
+
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        self.fetched_at.elapsed() < ttl
+    }
+}
+
 #[async_trait::async_trait(?Send)]
 pub trait ChainInterface {
     /// Get the current tip block's height and hash
+    ///
+    /// The hash is looked up by the height, so this takes two round trips to
+    /// the node. Cap'n Proto can only pipeline calls on capabilities a call
+    /// returns, not on plain values like the height, so the second request has
+    /// to wait for the first.
     async fn get_tip(&self) -> Result<(i32, BlockHash), BlockTalkError>;
 
     /// Get the timestamp of the current chain tip
@@ -34,6 +127,11 @@ pub trait ChainInterface {
     /// Returns true if the node is fully synced, false if it's still in initial block download
     async fn is_synced(&self) -> Result<bool, BlockTalkError>;
 
+    /// Get the tip, its timestamp and the sync status together
+    /// The sync status is requested while the tip is being fetched, so this
+    /// takes one round trip more than `get_tip` rather than two.
+    async fn get_chaininfo(&self) -> Result<ChainInfo, BlockTalkError>;
+
     /// Check if a block is in the best chain
     async fn is_in_best_chain(&self, block_hash: &BlockHash) -> Result<bool, BlockTalkError>;
 
@@ -50,17 +148,185 @@ pub trait ChainInterface {
         block_hash: &BlockHash,
     ) -> Result<Option<Block>, BlockTalkError>;
 
+    /// Get a full block by its hash along with its height, confirmations,
+    /// chain work and the block after it, like Bitcoin Core's `getblock`
+    /// Returns None if the node doesn't have the block. The node doesn't report
+    /// chain work, and working it out can take a request per block back to
+    /// genesis, so it is only filled in if it is already known: for the block
+    /// `get_chain_work` was last called for, and for every 2016th block it
+    /// has walked through.
+    async fn get_block_verbose(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockDetails>, BlockTalkError>;
+
+    /// Get the hash of the active chain block at a height
+    async fn get_block_hash(&self, height: i32) -> Result<BlockHash, BlockTalkError>;
+
+    /// Get a block header by its hash
+    async fn get_block_header(&self, block_hash: &BlockHash) -> Result<Header, BlockTalkError>;
+
+    /// Get the header of the block at a specific height in the active chain
+    async fn get_header_by_height(&self, height: i32) -> Result<Header, BlockTalkError>;
+
+    /// Find the height of the last block with a timestamp at or before `unix_timestamp`
+    /// The search stops early at any such block less than `tolerance_secs` before
+    /// the target, and returns 0 if every block is later than the target.
+    /// Block timestamps are only roughly ordered, so the result is approximate.
+    async fn find_block_at_timestamp(
+        &self,
+        unix_timestamp: u64,
+        tolerance_secs: u64,
+    ) -> Result<i32, BlockTalkError>;
+
+    /// Get the height of a block by its hash
+    /// Returns None if the block is not part of the active chain
+    async fn get_block_height(&self, block_hash: &BlockHash)
+        -> Result<Option<i32>, BlockTalkError>;
+
+    /// Get the hash of the block following a block in the active chain
+    /// Returns None if the block is the tip or not part of the active chain
+    async fn get_next_block_hash(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockHash>, BlockTalkError>;
+
+    /// Get a block's median time past: the median timestamp of the block and
+    /// the 10 blocks before it
+    ///
+    /// BIP113 locktimes and BIP68 relative time locks are checked against the
+    /// median time past of the previous block rather than block timestamps,
+    /// which miners can set out of order. A transaction spending an output
+    /// with a relative time lock of N seconds is final once the median time
+    /// past of the tip exceeds that of the output's block by N.
+    async fn get_median_time_past(&self, block_hash: &BlockHash) -> Result<u32, BlockTalkError>;
+
+    /// Get how many seconds a block's timestamp is ahead of its median time past
+    async fn get_block_time_offset(&self, block_hash: &BlockHash) -> Result<i64, BlockTalkError>;
+
+    /// Get the total proof-of-work of the chain ending at a block, as big-endian bytes
+    ///
+    /// The IPC interface does not expose the node's cached chain work, so it is
+    /// accumulated from the headers of the block and all of its ancestors.
+    /// Headers can only be fetched as part of their full block, so the first
+    /// call downloads every block back to genesis, one request each: on
+    /// mainnet that is the whole chain, and takes hours. Only the running total
+    /// is kept while walking back, not the blocks. `Blockchain` then remembers
+    /// the work of every 2016th block it walked through, and of the last block
+    /// asked about, so a later call fetches at most the 2016 or so blocks back
+    /// to the closest of those.
+    ///
+    /// ```no_run
+    /// # use blocktalk::{chain_work_from_bytes, BlockHash, BlockTalkError, ChainInterface};
+    /// # async fn heavier_tip(
+    /// #     chain: &dyn ChainInterface,
+    /// #     tip_a: BlockHash,
+    /// #     tip_b: BlockHash,
+    /// # ) -> Result<BlockHash, BlockTalkError> {
+    /// let work_a = chain_work_from_bytes(&chain.get_chain_work(&tip_a).await?);
+    /// let work_b = chain_work_from_bytes(&chain.get_chain_work(&tip_b).await?);
+    /// Ok(if work_a >= work_b { tip_a } else { tip_b })
+    /// # }
+    /// ```
+    async fn get_chain_work(&self, block_hash: &BlockHash) -> Result<[u8; 32], BlockTalkError>;
+
+    /// Get aggregate statistics for a block in the active chain
+    ///
+    /// ```no_run
+    /// # use blocktalk::{BlockHash, BlockTalkError, ChainInterface};
+    /// # async fn print_fees(
+    /// #     chain: &dyn ChainInterface,
+    /// #     block_hash: BlockHash,
+    /// # ) -> Result<(), BlockTalkError> {
+    /// let stats = chain.get_block_stats(&block_hash).await?;
+    /// println!("{} transactions paid {} sat in fees", stats.txs, stats.total_fee);
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn get_block_stats(&self, block_hash: &BlockHash) -> Result<BlockStats, BlockTalkError>;
+
+    /// Re-check the last `num_blocks` blocks of the active chain, or all of it
+    /// if `num_blocks` is 0, like Bitcoin Core's `verifychain`
+    ///
+    /// The IPC interface has no verification call, so blocks are fetched and
+    /// checked here. Every level checks each block builds on the one before it,
+    /// and level 1 and above also check proof of work, merkle roots and witness
+    /// commitments. Higher levels need the node's undo data and coins, so they
+    /// check no more than level 1. Returns false, logging the offending block,
+    /// if a check fails.
+    async fn verify_chain(&self, check_level: u8, num_blocks: u32) -> Result<bool, BlockTalkError>;
+
+    /// Build a proof that a transaction is included in a block
+    /// Returns None if the block does not contain the transaction
+    async fn get_tx_merkle_proof(
+        &self,
+        block_hash: &BlockHash,
+        txid: &Txid,
+    ) -> Result<Option<TxMerkleProof>, BlockTalkError>;
+
+    /// Check that a proof from `get_tx_merkle_proof` links a transaction to a
+    /// block header's merkle root
+    fn verify_tx_merkle_proof(
+        &self,
+        proof: &TxMerkleProof,
+        txid: &Txid,
+        merkle_root: TxMerkleNode,
+    ) -> bool {
+        verify_tx_merkle_proof(proof, txid, merkle_root)
+    }
+
+    /// Build a compact filter of the output scripts in a block
+    /// Returns None if the node does not have the block. See `BlockFilter`
+    /// for how these filters differ from the node's BIP158 basic filters.
+    async fn get_block_filter(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockFilter>, BlockTalkError>;
+
+    /// Check whether a block's filter matches any of `scripts`
+    fn match_filter(
+        &self,
+        filter: &BlockFilter,
+        scripts: &[ScriptBuf],
+    ) -> Result<bool, BlockTalkError> {
+        filter.matches_any(scripts)
+    }
+
+    /// Get a block locator for the active chain, the hashes of the tip and the
+    /// ten blocks below it, then of blocks twice as far apart each time, ending
+    /// with the genesis block
+    /// Peers exchange locators to find the last block they have in common.
+    async fn get_block_locator(&self) -> Result<Vec<BlockHash>, BlockTalkError>;
+
+    /// Get the heights and hashes of a block and its `depth` closest
+    /// ancestors, lowest first
+    /// Fewer are returned for blocks less than `depth` above the genesis
+    /// block. The block need not be in the active chain.
+    async fn get_block_ancestors(
+        &self,
+        block_hash: &BlockHash,
+        depth: u32,
+    ) -> Result<Vec<(i32, BlockHash)>, BlockTalkError>;
+
     /// Add a notification handler to receive chain updates
+    /// Returns an id that can be passed to `remove_notification_handler`
     async fn add_notification_handler(
         &self,
         handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError>;
+    ) -> Result<HandlerId, BlockTalkError>;
 
     /// Remove a previously added notification handler
-    async fn remove_notification_handler(
+    async fn remove_notification_handler(&self, id: HandlerId) -> Result<(), BlockTalkError>;
+
+    /// Watch for transactions paying to `script`, as they enter the mempool
+    /// and as they are mined
+    /// The node can't filter notifications by script, so they are filtered as
+    /// they arrive. Chain updates must have begun for events to arrive, and
+    /// the script is no longer watched once the subscription is dropped.
+    async fn subscribe_to_address(
         &self,
-        handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError>;
+        script: &ScriptBuf,
+    ) -> Result<AddressSubscription, BlockTalkError>;
 
     /// Start receiving chain updates
     /// This must be called after adding handlers for them to receive updates
@@ -69,18 +335,40 @@ pub trait ChainInterface {
     /// Stop receiving chain updates
     /// Handlers will stop receiving updates but remain registered
     async fn stop_chain_updates(&self) -> Result<(), BlockTalkError>;
+
+    /// Check whether chain updates are currently being received
+    fn is_receiving_updates(&self) -> bool;
 }
 
 pub struct Blockchain {
     chain_client: ChainClient,
     thread: ThreadClient,
     notification_handler: Arc<Mutex<ChainNotificationHandler>>,
+    subscription: Arc<Mutex<Option<HandlerClient>>>,
+    tip_cache: Arc<RwLock<Option<CachedTip>>>,
+    tip_generation: Arc<AtomicU64>,
+    tip_cache_ttl: Duration,
+    time_window_cache: Arc<RwLock<Option<(BlockHash, Vec<u32>)>>>,
+    locator_cache: Arc<RwLock<Option<(BlockHash, Vec<BlockHash>)>>>,
+    chain_work_cache: Arc<RwLock<HashMap<BlockHash, Work>>>,
+    last_chain_work: Arc<RwLock<Option<(BlockHash, Work)>>>,
 }
 
 #[async_trait::async_trait(?Send)]
 impl ChainInterface for Blockchain {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn get_tip(&self) -> Result<(i32, BlockHash), BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_tip");
+        if let Some(tip) = self.cached_tip() {
+            logging::debug!(height = tip.height; "Using cached chain tip at height {}", tip.height);
+            request_metrics::record_tip_height(tip.height);
+            return Ok((tip.height, tip.hash));
+        }
+
         log::debug!("Fetching current chain tip");
+        // A notification clearing the cache while the tip is being fetched
+        // means the tip fetched may already be out of date
+        let generation = self.tip_generation.load(Ordering::SeqCst);
         let height = {
             let mut height_req = self.chain_client.get_height_request();
             height_req
@@ -96,42 +384,31 @@ impl ChainInterface for Blockchain {
                 log::error!("Failed to get chain height: {}", e);
                 BlockTalkError::chain_error(ChainErrorKind::InvalidHeight, e.to_string())
             })?;
-            response.get()?.get_result()
-        };
-
-        let hash_bytes = {
-            let mut hash_req = self.chain_client.get_block_hash_request();
-            hash_req
+            response
                 .get()
-                .get_context()
-                .map_err(|e| {
-                    log::error!("Failed to get block hash context: {}", e);
-                    BlockTalkError::Connection(e.to_string())
-                })?
-                .set_thread(self.thread.clone());
-
-            hash_req.get().set_height(height);
-            let response = hash_req.send().promise.await.map_err(|e| {
-                log::error!("Failed to get block hash at height {}: {}", height, e);
-                BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
-            })?;
-            response.get()?.get_result()?.to_vec()
+                .context("Failed to read chain height")?
+                .get_result()
         };
 
-        let hash = self.bytes_to_block_hash(&hash_bytes).map_err(|e| {
-            log::error!("Failed to convert hash bytes to BlockHash: {}", e);
-            e
-        })?;
+        let hash = self.get_block_hash(height).await?;
 
         log::debug!(
             "Retrieved chain tip at height {} with hash {}",
             height,
             hash
         );
+        if let Ok(mut cached) = self.tip_cache.write() {
+            if self.tip_generation.load(Ordering::SeqCst) == generation {
+                *cached = Some(CachedTip::new(height, hash));
+            }
+        }
+        request_metrics::record_tip_height(height);
         Ok((height, hash))
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn tip_time(&self) -> Result<u32, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "tip_time");
         log::debug!("Fetching chain tip timestamp");
         let (_, tip_hash) = self.get_tip().await?;
         
@@ -139,16 +416,18 @@ impl ChainInterface for Blockchain {
             .ok_or_else(|| BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, "Tip block not found".to_string()))?;
         
         let timestamp = block.header.time;
-        log::debug!("Chain tip timestamp: {}", timestamp);
+        logging::debug!(timestamp = timestamp; "Chain tip timestamp: {}", timestamp);
         Ok(timestamp)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn get_block(
         &self,
         node_tip_hash: &bitcoin::BlockHash,
         height: i32,
     ) -> Result<Block, BlockTalkError> {
-        log::debug!("Getting block at height {}", height);
+        let _timer = RequestTimer::start(Interface::Chain, "get_block");
+        logging::debug!(height = height; "Getting block at height {}", height);
         let mut find_req = self.chain_client.find_ancestor_by_height_request();
 
         find_req
@@ -180,7 +459,11 @@ impl ChainInterface for Blockchain {
             BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
         })?;
 
-        let mut data = response.get()?.get_ancestor()?.get_data()?;
+        let mut data = response
+            .get()
+            .and_then(|response| response.get_ancestor())
+            .and_then(|ancestor| ancestor.get_data())
+            .with_context(|| format!("Failed to read block at height {}", height))?;
 
         Block::consensus_decode(&mut data).map_err(|e| {
             log::error!("Failed to decode block at height {}: {}", height, e);
@@ -188,13 +471,17 @@ impl ChainInterface for Blockchain {
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn get_genesis_block(&self) -> Result<Block, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_genesis_block");
         log::debug!("Fetching genesis block");
         let (_, tip_hash) = self.get_tip().await?;
         self.get_block(&tip_hash, 0).await
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn is_synced(&self) -> Result<bool, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "is_synced");
         log::debug!("Checking sync status");
         
         let mut ibd_req = self.chain_client.is_initial_block_download_request();
@@ -212,19 +499,45 @@ impl ChainInterface for Blockchain {
             BlockTalkError::Connection(e.to_string())
         })?;
 
-        let is_ibd = ibd_response.get()?.get_result();
-        log::debug!("IBD result value: {}", is_ibd);
+        let is_ibd = ibd_response
+            .get()
+            .context("Failed to read IBD status")?
+            .get_result();
+        logging::debug!(is_ibd = is_ibd; "IBD result value: {}", is_ibd);
         Ok(!is_ibd)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_chaininfo(&self) -> Result<ChainInfo, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_chaininfo");
+        log::debug!("Fetching chain info");
+        let ((height, best_block_hash), is_synced) =
+            tokio::try_join!(self.get_tip(), self.is_synced())?;
+        let (_, tip_time) = self.block_height_and_time(&best_block_hash).await?;
+
+        Ok(ChainInfo {
+            height,
+            best_block_hash,
+            tip_time,
+            is_synced,
+            ibd: !is_synced,
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn is_in_best_chain(&self, block_hash: &BlockHash) -> Result<bool, BlockTalkError> {
-        log::debug!("Checking if block {} is in best chain", block_hash);
+        let _timer = RequestTimer::start(Interface::Chain, "is_in_best_chain");
+        logging::debug!(
+            block_hash = block_hash;
+            "Checking if block {} is in best chain", block_hash
+        );
         let hash_bytes = block_hash.to_raw_hash().to_byte_array();
 
         let mut find_req = self.chain_client.find_block_request();
         find_req
             .get()
-            .get_context()?
+            .get_context()
+            .with_context(|| format!("Failed to get best chain context for {}", block_hash))?
             .set_thread(self.thread.clone());
         find_req.get().set_hash(&hash_bytes);
 
@@ -233,10 +546,14 @@ impl ChainInterface for Blockchain {
             BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
         })?;
 
-        let block_info = response.get()?.get_block().map_err(|e| {
-            log::error!("Failed to get block info for {}: {}", block_hash, e);
-            BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
-        })?;
+        let block_info = response
+            .get()
+            .with_context(|| format!("Failed to read block {}", block_hash))?
+            .get_block()
+            .map_err(|e| {
+                log::error!("Failed to get block info for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
 
         let is_active = block_info.get_in_active_chain() != 0;
 
@@ -252,11 +569,13 @@ impl ChainInterface for Blockchain {
         Ok(is_active)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn find_common_ancestor(
         &self,
         block1_hash: &BlockHash,
         block2_hash: &BlockHash,
     ) -> Result<Option<BlockHash>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "find_common_ancestor");
         log::debug!(
             "Finding common ancestor between blocks {} and {}",
             block1_hash,
@@ -279,6 +598,12 @@ impl ChainInterface for Blockchain {
             let mut params = find_req.get();
             params.set_block_hash1(&hash1_bytes);
             params.set_block_hash2(&hash2_bytes);
+            params
+                .get_ancestor()
+                .map_err(|e| {
+                    BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
+                })?
+                .set_want_hash(true);
         }
 
         let response = find_req.send().promise.await.map_err(|e| {
@@ -286,22 +611,27 @@ impl ChainInterface for Blockchain {
             BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
         })?;
 
-        let ancestor_bytes = response.get()?.get_ancestor()?.get_data()?;
-        if ancestor_bytes.is_empty() {
+        let response = response.get().context("Failed to read common ancestor")?;
+        if !response.get_result() {
             log::debug!("No common ancestor found");
-            Ok(None)
-        } else {
-            let ancestor_hash = self.bytes_to_block_hash(ancestor_bytes)?;
-            log::debug!("Common ancestor found: {}", ancestor_hash);
-            Ok(Some(ancestor_hash))
+            return Ok(None);
         }
+        let ancestor_hash = response
+            .get_ancestor()
+            .and_then(|ancestor| ancestor.get_hash())
+            .context("Failed to read common ancestor hash")?;
+        let ancestor_hash = self.bytes_to_block_hash(ancestor_hash)?;
+        logging::debug!(ancestor_hash = ancestor_hash; "Common ancestor found: {}", ancestor_hash);
+        Ok(Some(ancestor_hash))
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn get_block_by_hash(
         &self,
         block_hash: &BlockHash,
     ) -> Result<Option<Block>, BlockTalkError> {
-        log::debug!("Getting block with hash {}", block_hash);
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_by_hash");
+        logging::debug!(block_hash = block_hash; "Getting block with hash {}", block_hash);
         let hash_bytes = block_hash.to_raw_hash().to_byte_array();
 
         let mut find_req = self.chain_client.find_block_request();
@@ -320,15 +650,24 @@ impl ChainInterface for Blockchain {
             BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
         })?;
 
-        let block_info = response.get()?.get_block()?;
-        if !block_info.has_data() || block_info.get_data()?.is_empty() {
-            log::debug!("No block data found for hash {}", block_hash);
+        let block_info = response
+            .get()
+            .and_then(|response| response.get_block())
+            .with_context(|| format!("Failed to read block {}", block_hash))?;
+        let data = block_info
+            .get_data()
+            .with_context(|| format!("Failed to read data of block {}", block_hash))?;
+        if !block_info.has_data() || data.is_empty() {
+            logging::debug!(block_hash = block_hash; "No block data found for hash {}", block_hash);
             return Ok(None);
         }
 
-        match bitcoin::consensus::deserialize::<Block>(block_info.get_data()?) {
+        match bitcoin::consensus::deserialize::<Block>(data) {
             Ok(block) => {
-                log::debug!("Successfully retrieved block {}", block_hash);
+                logging::debug!(
+                    block_hash = block_hash;
+                    "Successfully retrieved block {}", block_hash
+                );
                 Ok(Some(block))
             }
             Err(e) => {
@@ -341,99 +680,2565 @@ impl ChainInterface for Blockchain {
         }
     }
 
-    async fn add_notification_handler(
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_verbose(
         &self,
-        handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError> {
-        let mut notification_handler = self.notification_handler.lock().map_err(|e| {
-            BlockTalkError::Connection(format!(
-                "Failed to acquire lock for notification handler: {}",
-                e
-            ))
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockDetails>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_verbose");
+        logging::debug!(block_hash = block_hash; "Getting block {} with its details", block_hash);
+        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
+
+        let mut find_req = self.chain_client.find_block_request();
+        find_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get block context for hash {}: {}", block_hash, e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        find_req.get().set_hash(&hash_bytes);
+        {
+            let mut block_param = find_req.get().get_block().map_err(|e| {
+                log::error!("Failed to set block parameters for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+            block_param.set_want_height(true);
+            block_param.set_want_in_active_chain(true);
+            block_param.set_want_data(true);
+            block_param.init_next_block().set_want_hash(true);
+        }
+
+        // The tip, for the confirmation count, is fetched while the node finds the block
+        let find_block = async {
+            find_req.send().promise.await.map_err(|e| {
+                log::error!("Failed to fetch block with hash {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+            })
+        };
+        let (response, (tip_height, _)) = tokio::try_join!(find_block, self.get_tip())?;
+
+        let block_info = response
+            .get()
+            .with_context(|| format!("Failed to read block {}", block_hash))?
+            .get_block()
+            .map_err(|e| {
+                log::error!("Failed to get block info for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+        let data = block_info
+            .get_data()
+            .with_context(|| format!("Failed to read data of block {}", block_hash))?;
+        if !block_info.has_data() || data.is_empty() {
+            logging::debug!(block_hash = block_hash; "No block data found for hash {}", block_hash);
+            return Ok(None);
+        }
+        let block = bitcoin::consensus::deserialize::<Block>(data).map_err(|e| {
+            log::error!("Failed to deserialize block {}: {}", block_hash, e);
+            BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
         })?;
-        notification_handler.register_handler(handler).await
+
+        let height = block_info.get_height();
+        let in_active_chain = block_info.get_in_active_chain() != 0;
+        let confirmations = if in_active_chain {
+            tip_height - height + 1
+        } else {
+            -1
+        };
+        let next_block = block_info
+            .get_next_block()
+            .with_context(|| format!("Failed to read block after {}", block_hash))?;
+        let next_block_hash = if in_active_chain && next_block.get_found() {
+            let next_hash = next_block
+                .get_hash()
+                .with_context(|| format!("Failed to read hash of block after {}", block_hash))?;
+            Some(self.bytes_to_block_hash(next_hash)?)
+        } else {
+            None
+        };
+        let chain_work = self
+            .cached_chain_work(block_hash)
+            .map(|work| work.to_be_bytes());
+
+        logging::debug!(
+            block_hash = block_hash, height = height;
+            "Block {} is at height {} with {} confirmations", block_hash, height, confirmations
+        );
+        Ok(Some(BlockDetails {
+            block,
+            height,
+            confirmations,
+            chain_work,
+            next_block_hash,
+        }))
     }
 
-    async fn remove_notification_handler(
-        &self,
-        handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError> {
-        let mut notification_handler = self.notification_handler.lock().map_err(|e| {
-            BlockTalkError::Connection(format!(
-                "Failed to acquire lock for notification handler: {}",
-                e
-            ))
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_hash(&self, height: i32) -> Result<BlockHash, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_hash");
+        logging::debug!(height = height; "Getting block hash at height {}", height);
+        let mut hash_req = self.chain_client.get_block_hash_request();
+        hash_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get block hash context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        hash_req.get().set_height(height);
+        let response = hash_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to get block hash at height {}: {}", height, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
         })?;
-        // TODO: Implement handler removal in ChainNotificationHandler if possible
-        Ok(())
+        let hash_bytes = response
+            .get()
+            .and_then(|response| response.get_result())
+            .with_context(|| format!("Failed to read block hash at height {}", height))?
+            .to_vec();
+
+        self.bytes_to_block_hash(&hash_bytes).map_err(|e| {
+            log::error!("Failed to convert hash bytes to BlockHash: {}", e);
+            e
+        })
     }
 
-    async fn begin_chain_updates(&self) -> Result<(), BlockTalkError> {
-        log::debug!("Starting chain update notifications");
-        let handler = self.notification_handler.lock().unwrap().clone();
-        let notification_client = capnp_rpc::new_client(handler);
-        let mut handle_req = self.chain_client.handle_notifications_request();
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_header(&self, block_hash: &BlockHash) -> Result<Header, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_header");
+        logging::debug!(block_hash = block_hash; "Getting header for block {}", block_hash);
+        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
 
-        handle_req
+        let mut find_req = self.chain_client.find_block_request();
+        find_req
             .get()
             .get_context()
             .map_err(|e| {
-                log::error!("Failed to get notification context: {}", e);
+                log::error!(
+                    "Failed to get header context for hash {}: {}",
+                    block_hash,
+                    e
+                );
                 BlockTalkError::Connection(e.to_string())
             })?
             .set_thread(self.thread.clone());
 
-        handle_req.get().set_notifications(notification_client);
-        handle_req.send().promise.await.map_err(|e| {
-            log::error!("Failed to start chain updates: {}", e);
-            BlockTalkError::Connection(e.to_string())
+        find_req.get().set_hash(&hash_bytes);
+        // FoundBlockParam has no header-only flag, so the serialized block is
+        // requested and only its 80-byte prefix is decoded
+        find_req
+            .get()
+            .get_block()
+            .map_err(|e| {
+                log::error!("Failed to set block parameters for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?
+            .set_want_data(true);
+
+        let response = find_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to fetch header for block {}: {}", block_hash, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
         })?;
 
-        log::info!("Successfully started chain updates");
-        Ok(())
+        let block_info = response
+            .get()
+            .and_then(|response| response.get_block())
+            .with_context(|| format!("Failed to read block {}", block_hash))?;
+        let data = block_info
+            .get_data()
+            .with_context(|| format!("Failed to read data of block {}", block_hash))?;
+        if !block_info.has_data() || data.is_empty() {
+            logging::debug!(block_hash = block_hash; "No block data found for hash {}", block_hash);
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::BlockNotFound,
+                format!("Block {} not found", block_hash),
+            ));
+        }
+
+        let header = decode_header(data)?;
+        logging::debug!(
+            block_hash = block_hash;
+            "Successfully retrieved header for block {}", block_hash
+        );
+        Ok(header)
     }
 
-    async fn stop_chain_updates(&self) -> Result<(), BlockTalkError> {
-        // TODO: Implement stopping notifications in the Cap'n Proto RPC layer
-        Ok(())
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_header_by_height(&self, height: i32) -> Result<Header, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_header_by_height");
+        logging::debug!(height = height; "Getting header at height {}", height);
+        let block_hash = self.get_block_hash(height).await?;
+        self.get_block_header(&block_hash).await
     }
-}
 
-impl Blockchain {
-    pub fn new(connection: Arc<Connection>) -> Self {
-        Self {
-            chain_client: connection.chain_client().clone(),
-            thread: connection.thread().clone(),
-            notification_handler: Arc::new(Mutex::new(ChainNotificationHandler::new())),
-        }
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn find_block_at_timestamp(
+        &self,
+        unix_timestamp: u64,
+        tolerance_secs: u64,
+    ) -> Result<i32, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "find_block_at_timestamp");
+        logging::debug!(
+            unix_timestamp = unix_timestamp;
+            "Finding block at timestamp {}", unix_timestamp
+        );
+        let (tip_height, _) = self.get_tip().await?;
+        let height = search_block_time(
+            tip_height,
+            unix_timestamp,
+            tolerance_secs,
+            |height| async move { Ok(self.get_header_by_height(height).await?.time) },
+        )
+        .await?;
+
+        log::debug!(
+            "Block at height {} is at timestamp {}",
+            height,
+            unix_timestamp
+        );
+        Ok(height)
     }
 
-    pub fn from_client(chain_client: ChainClient, thread: ThreadClient) -> Self {
-        Self {
-            chain_client,
-            thread,
-            notification_handler: Arc::new(Mutex::new(ChainNotificationHandler::new())),
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_height(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<i32>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_height");
+        logging::debug!(block_hash = block_hash; "Getting height of block {}", block_hash);
+        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
+
+        let mut find_req = self.chain_client.find_block_request();
+        find_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!(
+                    "Failed to get height context for hash {}: {}",
+                    block_hash,
+                    e
+                );
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        find_req.get().set_hash(&hash_bytes);
+        {
+            let mut block_param = find_req.get().get_block().map_err(|e| {
+                log::error!("Failed to set block parameters for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+            block_param.set_want_height(true);
+            block_param.set_want_in_active_chain(true);
         }
-    }
 
-    pub fn notification_handler(&self) -> Arc<Mutex<ChainNotificationHandler>> {
-        self.notification_handler.clone()
-    }
+        let response = find_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to fetch height for block {}: {}", block_hash, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+        })?;
 
-    // Helper method to convert bytes to BlockHash
-    fn bytes_to_block_hash(&self, bytes: &[u8]) -> Result<BlockHash, BlockTalkError> {
-        if bytes.len() != 32 {
-            log::error!("Invalid hash length: expected 32, got {}", bytes.len());
-            return Err(BlockTalkError::chain_error(
-                ChainErrorKind::InvalidBlockData,
-                format!("Invalid hash length: expected 32, got {}", bytes.len()),
-            ));
+        let block_info = response
+            .get()
+            .with_context(|| format!("Failed to read block {}", block_hash))?
+            .get_block()
+            .map_err(|e| {
+                log::error!("Failed to get block info for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+
+        if block_info.get_in_active_chain() == 0 {
+            logging::debug!(
+                block_hash = block_hash;
+                "Block {} is not in the active chain", block_hash
+            );
+            return Ok(None);
         }
 
-        let mut hash_bytes = [0u8; 32];
-        hash_bytes.copy_from_slice(bytes);
-        Ok(BlockHash::from_raw_hash(
-            bitcoin::hashes::Hash::from_byte_array(hash_bytes),
-        ))
+        let height = block_info.get_height();
+        logging::debug!(
+            block_hash = block_hash, height = height;
+            "Block {} is at height {}", block_hash, height
+        );
+        Ok(Some(height))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_next_block_hash(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockHash>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_next_block_hash");
+        logging::debug!(block_hash = block_hash; "Getting block after {}", block_hash);
+        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
+
+        let mut find_req = self.chain_client.find_block_request();
+        find_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!(
+                    "Failed to get next block context for hash {}: {}",
+                    block_hash,
+                    e
+                );
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        find_req.get().set_hash(&hash_bytes);
+        {
+            let block_param = find_req.get().get_block().map_err(|e| {
+                log::error!("Failed to set block parameters for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+            block_param.init_next_block().set_want_hash(true);
+        }
+
+        let response = find_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to fetch block after {}: {}", block_hash, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+        })?;
+
+        let next_block = response
+            .get()
+            .with_context(|| format!("Failed to read block {}", block_hash))?
+            .get_block()
+            .and_then(|block_info| block_info.get_next_block())
+            .map_err(|e| {
+                log::error!("Failed to get next block info for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+
+        // The node only fills in the next block when the block is in the
+        // active chain and is not the tip
+        if !next_block.get_found() {
+            logging::debug!(
+                block_hash = block_hash;
+                "No block follows {} in the active chain", block_hash
+            );
+            return Ok(None);
+        }
+
+        let next_hash = next_block
+            .get_hash()
+            .with_context(|| format!("Failed to read hash of block after {}", block_hash))?;
+        self.bytes_to_block_hash(next_hash).map(Some)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_median_time_past(&self, block_hash: &BlockHash) -> Result<u32, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_median_time_past");
+        let window = self.block_time_window(block_hash).await?;
+        Ok(median_time_past(&window))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_time_offset(&self, block_hash: &BlockHash) -> Result<i64, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_time_offset");
+        let window = self.block_time_window(block_hash).await?;
+        // The window always ends with the block itself
+        let block_time = window[window.len() - 1];
+        Ok(i64::from(block_time) - i64::from(median_time_past(&window)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_chain_work(&self, block_hash: &BlockHash) -> Result<[u8; 32], BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_chain_work");
+        logging::debug!(block_hash = block_hash; "Computing chain work for block {}", block_hash);
+
+        // Walk back to the closest block whose chain work is already known,
+        // or to genesis, adding up the work of the blocks on the way
+        let work = match self.cached_chain_work(block_hash) {
+            Some(work) => work,
+            None => {
+                let (mut height, _) = self.block_height_and_time(block_hash).await?;
+                let mut hash = *block_hash;
+                let mut walked = Work::from_be_bytes([0; 32]);
+                let mut fetched = 0;
+                // Checkpoints passed, with the work walked through before each
+                let mut checkpoints = Vec::new();
+                let known = loop {
+                    if let Some(work) = self.cached_chain_work(&hash) {
+                        break work;
+                    }
+                    let header = self.get_block_header(&hash).await?;
+                    fetched += 1;
+                    if height % CHAIN_WORK_CHECKPOINT_INTERVAL == 0 {
+                        checkpoints.push((hash, walked));
+                    }
+                    walked = walked + header.work();
+                    if header.prev_blockhash == BlockHash::all_zeros() {
+                        break Work::from_be_bytes([0; 32]);
+                    }
+                    hash = header.prev_blockhash;
+                    height -= 1;
+                };
+                logging::debug!(
+                    block_hash = block_hash;
+                    "Fetched {} headers for the chain work of block {}", fetched, block_hash
+                );
+
+                let work = known + walked;
+                if let Ok(mut cached) = self.chain_work_cache.write() {
+                    for (hash, walked) in checkpoints {
+                        if cached.len() >= MAX_CHAIN_WORK_CHECKPOINTS {
+                            break;
+                        }
+                        cached.insert(hash, work - walked);
+                    }
+                }
+                work
+            }
+        };
+        if let Ok(mut last) = self.last_chain_work.write() {
+            *last = Some((*block_hash, work));
+        }
+
+        logging::debug!(
+            block_hash = block_hash;
+            "Chain work for block {} is {:x}", block_hash, work
+        );
+        Ok(work.to_be_bytes())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_stats(&self, block_hash: &BlockHash) -> Result<BlockStats, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_stats");
+        logging::debug!(block_hash = block_hash; "Computing stats for block {}", block_hash);
+        let block = self.get_block_by_hash(block_hash).await?.ok_or_else(|| {
+            BlockTalkError::chain_error(
+                ChainErrorKind::BlockNotFound,
+                format!("Block {} not found", block_hash),
+            )
+        })?;
+        let height = self.get_block_height(block_hash).await?.ok_or_else(|| {
+            BlockTalkError::chain_error(
+                ChainErrorKind::BlockNotFound,
+                format!("Block {} is not in the active chain", block_hash),
+            )
+        })?;
+        let genesis_hash = self.get_block_hash(0).await?;
+
+        Ok(BlockStats::from_block(
+            &block,
+            height as u32,
+            halving_interval(&genesis_hash),
+        ))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn verify_chain(&self, check_level: u8, num_blocks: u32) -> Result<bool, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "verify_chain");
+        let (tip_height, _) = self.get_tip().await?;
+        let chain_length = (tip_height + 1).max(0) as u32;
+        let count = if num_blocks == 0 {
+            chain_length
+        } else {
+            num_blocks.min(chain_length)
+        };
+        let from_height = tip_height - count as i32 + 1;
+        log::info!(
+            "Verifying blocks {} to {} at level {}",
+            from_height,
+            tip_height,
+            check_level
+        );
+
+        let mut blocks = self.block_stream(from_height, tip_height);
+        let mut previous = None;
+        while let Some(result) = blocks.next().await {
+            let (height, block) = result?;
+            if let Err(e) = verify_block(&block, previous.as_ref(), check_level) {
+                log::warn!("Chain verification failed at height {}: {}", height, e);
+                return Ok(false);
+            }
+            previous = Some(block.block_hash());
+        }
+        log::info!("Verified {} blocks", count);
+        Ok(true)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_tx_merkle_proof(
+        &self,
+        block_hash: &BlockHash,
+        txid: &Txid,
+    ) -> Result<Option<TxMerkleProof>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_tx_merkle_proof");
+        logging::debug!(
+            txid = txid, block_hash = block_hash;
+            "Building merkle proof for {} in block {}", txid, block_hash
+        );
+        let block = self.get_block_by_hash(block_hash).await?.ok_or_else(|| {
+            BlockTalkError::chain_error(
+                ChainErrorKind::BlockNotFound,
+                format!("Block {} not found", block_hash),
+            )
+        })?;
+
+        let proof = tx_merkle_proof(&block, txid);
+        if proof.is_none() {
+            logging::debug!(
+                txid = txid, block_hash = block_hash;
+                "Transaction {} is not in block {}", txid, block_hash
+            );
+        }
+        Ok(proof)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_filter(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<Option<BlockFilter>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_filter");
+        logging::debug!(block_hash = block_hash; "Building filter for block {}", block_hash);
+        match self.get_block_by_hash(block_hash).await? {
+            Some(block) => BlockFilter::from_block(&block).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // The locator of the last tip asked about is reused until the tip changes
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_locator(&self) -> Result<Vec<BlockHash>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_locator");
+        let (tip_height, tip_hash) = self.get_tip().await?;
+        if let Ok(cached) = self.locator_cache.read() {
+            if let Some((hash, locator)) = cached.as_ref() {
+                if *hash == tip_hash {
+                    return Ok(locator.clone());
+                }
+            }
+        }
+
+        logging::debug!(height = tip_height; "Building block locator from height {}", tip_height);
+        // Only the hashes are needed, and they are all asked for at once
+        let locator = futures::future::try_join_all(
+            locator_heights(tip_height)
+                .into_iter()
+                .map(|height| self.ancestor_hash(&tip_hash, height)),
+        )
+        .await
+        .with_context(|| format!("Failed to build block locator from {}", tip_hash))?;
+
+        if let Ok(mut cached) = self.locator_cache.write() {
+            *cached = Some((tip_hash, locator.clone()));
+        }
+        Ok(locator)
+    }
+
+    // Up to `DEFAULT_MAX_IN_FLIGHT` ancestors are asked for at once, and the
+    // fetch gives up after `ANCESTOR_FETCH_TIMEOUT` so a slow node can't hang it
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_block_ancestors(
+        &self,
+        block_hash: &BlockHash,
+        depth: u32,
+    ) -> Result<Vec<(i32, BlockHash)>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "get_block_ancestors");
+        let (height, _) = self.block_height_and_time(block_hash).await?;
+        let depth = i32::try_from(depth).unwrap_or(i32::MAX);
+        let from_height = height.saturating_sub(depth).max(0);
+        logging::debug!(
+            block_hash = block_hash, height = height;
+            "Fetching ancestors of block {} from height {}", block_hash, from_height
+        );
+
+        let requests = (from_height..height).map(|ancestor_height| async move {
+            let hash = self.ancestor_hash(block_hash, ancestor_height).await?;
+            Ok::<_, BlockTalkError>((ancestor_height, hash))
+        });
+        // `buffered` yields the ancestors in height order, however the node
+        // answers
+        let fetch = futures::TryStreamExt::try_collect::<Vec<_>>(futures::StreamExt::buffered(
+            futures::stream::iter(requests),
+            DEFAULT_MAX_IN_FLIGHT,
+        ));
+        let mut ancestors = tokio::time::timeout(ANCESTOR_FETCH_TIMEOUT, fetch)
+            .await
+            .map_err(|_| {
+                BlockTalkError::Connection(format!(
+                    "Timed out fetching ancestors of {} after {:?}",
+                    block_hash, ANCESTOR_FETCH_TIMEOUT
+                ))
+            })?
+            .with_context(|| format!("Failed to fetch ancestors of {}", block_hash))?;
+        ancestors.push((height, *block_hash));
+        Ok(ancestors)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, handler))
+    )]
+    async fn add_notification_handler(
+        &self,
+        handler: Arc<dyn NotificationHandler>,
+    ) -> Result<HandlerId, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "add_notification_handler");
+        let mut notification_handler = self.notification_handler.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for notification handler: {}",
+                e
+            ))
+        })?;
+        notification_handler.register_handler(handler).await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn remove_notification_handler(&self, id: HandlerId) -> Result<(), BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "remove_notification_handler");
+        let mut notification_handler = self.notification_handler.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for notification handler: {}",
+                e
+            ))
+        })?;
+        notification_handler.unregister_handler(id).await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn subscribe_to_address(
+        &self,
+        script: &ScriptBuf,
+    ) -> Result<AddressSubscription, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "subscribe_to_address");
+        logging::debug!(script = script; "Subscribing to transactions paying to {}", script);
+        let notification_handler = self.notification_handler.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for notification handler: {}",
+                e
+            ))
+        })?;
+        notification_handler.subscribe_to_script(script.clone())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn begin_chain_updates(&self) -> Result<(), BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "begin_chain_updates");
+        if self.is_receiving_updates() {
+            log::warn!("Chain updates already started, ignoring request");
+            return Ok(());
+        }
+
+        log::debug!("Starting chain update notifications");
+        let handler = self.notification_handler.lock().unwrap().clone();
+        handler.set_active(true);
+        let notification_client = capnp_rpc::new_client(handler);
+        let mut handle_req = self.chain_client.handle_notifications_request();
+
+        handle_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get notification context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        handle_req.get().set_notifications(notification_client);
+        let response = handle_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to start chain updates: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        let subscription = response
+            .get()
+            .context("Failed to read chain updates response")?
+            .get_result()
+            .map_err(|e| {
+                log::error!("Failed to get notification handler: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?;
+        *self.lock_subscription()? = Some(subscription);
+
+        log::info!("Successfully started chain updates");
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn stop_chain_updates(&self) -> Result<(), BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Chain, "stop_chain_updates");
+        let subscription = match self.lock_subscription()?.take() {
+            Some(subscription) => subscription,
+            None => {
+                log::debug!("Chain updates not started, nothing to stop");
+                return Ok(());
+            }
+        };
+
+        log::debug!("Stopping chain update notifications");
+        // Drop anything the node delivers while the disconnect is in flight
+        self.notification_handler.lock().unwrap().set_active(false);
+
+        let mut disconnect_req = subscription.disconnect_request();
+        disconnect_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get disconnect context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        disconnect_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to stop chain updates: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        log::info!("Successfully stopped chain updates");
+        Ok(())
+    }
+
+    fn is_receiving_updates(&self) -> bool {
+        self.subscription
+            .lock()
+            .map(|subscription| subscription.is_some())
+            .unwrap_or(false)
+    }
+}
+
+impl Blockchain {
+    pub fn new(connection: Arc<Connection>) -> Self {
+        Self::from_client(
+            connection.chain_client().clone(),
+            connection.thread().clone(),
+        )
+    }
+
+    pub fn from_client(chain_client: ChainClient, thread: ThreadClient) -> Self {
+        let notification_handler = ChainNotificationHandler::new();
+        Self {
+            chain_client,
+            thread,
+            tip_cache: notification_handler.tip_cache(),
+            tip_generation: notification_handler.tip_generation(),
+            tip_cache_ttl: DEFAULT_TIP_CACHE_TTL,
+            time_window_cache: Arc::new(RwLock::new(None)),
+            locator_cache: Arc::new(RwLock::new(None)),
+            chain_work_cache: Arc::new(RwLock::new(HashMap::new())),
+            last_chain_work: Arc::new(RwLock::new(None)),
+            notification_handler: Arc::new(Mutex::new(notification_handler)),
+            subscription: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Reuse the tip returned by `get_tip` for up to `ttl` instead of asking the
+    /// node again, [`DEFAULT_TIP_CACHE_TTL`] by default
+    ///
+    /// The cached tip is dropped as soon as a block connected, block disconnected
+    /// or updated tip notification arrives. A zero `ttl` disables the cache.
+    pub fn with_tip_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.tip_cache_ttl = ttl;
+        self
+    }
+
+    // The cached tip, if it is younger than the configured TTL
+    fn cached_tip(&self) -> Option<CachedTip> {
+        let cached = self.tip_cache.read().ok()?;
+        cached.filter(|tip| tip.is_fresh(self.tip_cache_ttl))
+    }
+
+    // Chain work of a block worked out by an earlier `get_chain_work`, if it
+    // is a checkpoint or the last block asked about
+    // A block's ancestors never change, so the work is never stale, even
+    // after the block leaves the active chain.
+    fn cached_chain_work(&self, block_hash: &BlockHash) -> Option<Work> {
+        let last = *self.last_chain_work.read().ok()?;
+        match last {
+            Some((hash, work)) if hash == *block_hash => Some(work),
+            _ => self.chain_work_cache.read().ok()?.get(block_hash).copied(),
+        }
+    }
+
+    // Timestamps of a block and up to 10 of its ancestors, oldest first
+    // A block's ancestors never change, so the last window fetched is reused
+    // for as long as the same block is asked about.
+    async fn block_time_window(&self, block_hash: &BlockHash) -> Result<Vec<u32>, BlockTalkError> {
+        if let Ok(cached) = self.time_window_cache.read() {
+            if let Some((hash, window)) = cached.as_ref() {
+                if hash == block_hash {
+                    return Ok(window.clone());
+                }
+            }
+        }
+
+        logging::debug!(
+            block_hash = block_hash;
+            "Fetching median time window for block {}", block_hash
+        );
+        let (height, time) = self.block_height_and_time(block_hash).await?;
+        let mut window = Vec::with_capacity(MEDIAN_TIME_SPAN as usize);
+        for ancestor_height in (height - MEDIAN_TIME_SPAN + 1).max(0)..height {
+            window.push(self.ancestor_time(block_hash, ancestor_height).await?);
+        }
+        window.push(time);
+
+        if let Ok(mut cached) = self.time_window_cache.write() {
+            *cached = Some((*block_hash, window.clone()));
+        }
+        Ok(window)
+    }
+
+    async fn block_height_and_time(
+        &self,
+        block_hash: &BlockHash,
+    ) -> Result<(i32, u32), BlockTalkError> {
+        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
+
+        let mut find_req = self.chain_client.find_block_request();
+        find_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get time context for hash {}: {}", block_hash, e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        find_req.get().set_hash(&hash_bytes);
+        {
+            let mut block_param = find_req.get().get_block().map_err(|e| {
+                log::error!("Failed to set block parameters for {}: {}", block_hash, e);
+                BlockTalkError::chain_error(ChainErrorKind::InvalidBlockData, e.to_string())
+            })?;
+            block_param.set_want_height(true);
+            block_param.set_want_time(true);
+        }
+
+        let response = find_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to fetch time for block {}: {}", block_hash, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+        })?;
+
+        let response = response
+            .get()
+            .with_context(|| format!("Failed to read time of block {}", block_hash))?;
+        if !response.get_result() {
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::BlockNotFound,
+                format!("Block {} not found", block_hash),
+            ));
+        }
+        let block_info = response
+            .get_block()
+            .with_context(|| format!("Failed to read time of block {}", block_hash))?;
+        Ok((block_info.get_height(), block_info.get_time() as u32))
+    }
+
+    async fn ancestor_time(
+        &self,
+        block_hash: &BlockHash,
+        height: i32,
+    ) -> Result<u32, BlockTalkError> {
+        let mut find_req = self.chain_client.find_ancestor_by_height_request();
+        find_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get ancestor context at height {}: {}", height, e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        let mut params = find_req.get();
+        params.set_block_hash(block_hash.as_ref());
+        params.set_ancestor_height(height);
+        params
+            .get_ancestor()
+            .map_err(|e| {
+                BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
+            })?
+            .set_want_time(true);
+
+        let response = find_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to fetch ancestor at height {}: {}", height, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+        })?;
+
+        let response = response
+            .get()
+            .with_context(|| format!("Failed to read ancestor at height {}", height))?;
+        if !response.get_result() {
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::InvalidAncestor,
+                format!("Block {} has no ancestor at height {}", block_hash, height),
+            ));
+        }
+        let ancestor = response
+            .get_ancestor()
+            .with_context(|| format!("Failed to read ancestor at height {}", height))?;
+        Ok(ancestor.get_time() as u32)
+    }
+
+    async fn ancestor_hash(
+        &self,
+        block_hash: &BlockHash,
+        height: i32,
+    ) -> Result<BlockHash, BlockTalkError> {
+        let mut find_req = self.chain_client.find_ancestor_by_height_request();
+        find_req
+            .get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get ancestor context at height {}: {}", height, e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        let mut params = find_req.get();
+        params.set_block_hash(block_hash.as_ref());
+        params.set_ancestor_height(height);
+        params
+            .get_ancestor()
+            .map_err(|e| {
+                BlockTalkError::chain_error(ChainErrorKind::InvalidAncestor, e.to_string())
+            })?
+            .set_want_hash(true);
+
+        let response = find_req.send().promise.await.map_err(|e| {
+            log::error!("Failed to fetch ancestor at height {}: {}", height, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+        })?;
+
+        let response = response
+            .get()
+            .with_context(|| format!("Failed to read ancestor at height {}", height))?;
+        if !response.get_result() {
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::InvalidAncestor,
+                format!("Block {} has no ancestor at height {}", block_hash, height),
+            ));
+        }
+        let ancestor_hash = response
+            .get_ancestor()
+            .and_then(|ancestor| ancestor.get_hash())
+            .with_context(|| format!("Failed to read ancestor hash at height {}", height))?;
+        self.bytes_to_block_hash(ancestor_hash)
+    }
+
+    /// Stream the active chain blocks from `from_height` to `to_height` inclusive,
+    /// in height order
+    ///
+    /// Blocks are fetched by a background task that runs up to
+    /// [`DEFAULT_BLOCK_STREAM_BUFFER`] blocks ahead of the consumer. The stream
+    /// ends after the first error. Like all node requests, it must be polled
+    /// from within a `tokio::task::LocalSet`.
+    ///
+    /// ```no_run
+    /// # use blocktalk::{BlockTalkError, Blockchain};
+    /// # use tokio_stream::StreamExt;
+    /// # async fn scan(chain: &Blockchain) -> Result<(), BlockTalkError> {
+    /// let mut blocks = chain.block_stream(0, 100);
+    /// while let Some(result) = blocks.next().await {
+    ///     let (height, block) = result?;
+    ///     println!("Block {} has {} transactions", height, block.txdata.len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_stream(
+        &self,
+        from_height: i32,
+        to_height: i32,
+    ) -> impl Stream<Item = Result<(i32, Block), BlockTalkError>> + '_ {
+        self.block_stream_with_buffer(from_height, to_height, DEFAULT_BLOCK_STREAM_BUFFER)
+    }
+
+    /// Like `block_stream`, prefetching up to `buffer_size` blocks
+    pub fn block_stream_with_buffer(
+        &self,
+        from_height: i32,
+        to_height: i32,
+        buffer_size: usize,
+    ) -> impl Stream<Item = Result<(i32, Block), BlockTalkError>> + '_ {
+        let (sender, receiver) = mpsc::channel(buffer_size.max(1));
+        // The task gets its own handle on the node, sharing the connection
+        let chain = Blockchain::from_client(self.chain_client.clone(), self.thread.clone());
+
+        tokio::task::spawn_local(async move {
+            if from_height > to_height {
+                return;
+            }
+            let tip_hash = match chain.get_tip().await {
+                Ok((_, tip_hash)) => tip_hash,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+
+            for height in from_height..=to_height {
+                let result = chain
+                    .get_block(&tip_hash, height)
+                    .await
+                    .map(|block| (height, block));
+                let failed = result.is_err();
+                // Stop once the consumer has gone away or after an error
+                if sender.send(result).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+
+        ReceiverStream::new(receiver)
+    }
+
+    /// Find the blocks disconnected and connected since `known_tip` was the tip
+    ///
+    /// Disconnected blocks are listed from `known_tip` down to the fork point,
+    /// the order to undo them in, and connected blocks from the fork point up to
+    /// the current tip, the order to apply them in. Both are empty if
+    /// `known_tip` is still the tip.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn get_blocks_since(
+        &self,
+        known_tip: &BlockHash,
+    ) -> Result<ChainUpdate, BlockTalkError> {
+        let (tip_height, tip_hash) = self.get_tip().await?;
+        if *known_tip == tip_hash {
+            return Ok(ChainUpdate::default());
+        }
+
+        let fork = self
+            .find_common_ancestor(known_tip, &tip_hash)
+            .await?
+            .ok_or_else(|| {
+                BlockTalkError::chain_error(
+                    ChainErrorKind::InvalidAncestor,
+                    format!("Block {} has no common ancestor with the tip", known_tip),
+                )
+            })?;
+        let (fork_height, _) = self.block_height_and_time(&fork).await?;
+        let (known_height, _) = self.block_height_and_time(known_tip).await?;
+        log::debug!(
+            "Chain forked from {} at height {}, tip is now at height {}",
+            known_tip,
+            fork_height,
+            tip_height
+        );
+
+        let mut update = ChainUpdate::default();
+        for height in ((fork_height + 1)..=known_height).rev() {
+            update
+                .disconnected
+                .push(self.ancestor_hash(known_tip, height).await?);
+        }
+        // Walk back from the tip fetched above so a block connected meanwhile
+        // can't mix two tips' chains
+        for height in (fork_height + 1)..=tip_height {
+            let hash = self.ancestor_hash(&tip_hash, height).await?;
+            update.connected.push((height, hash));
+        }
+        Ok(update)
+    }
+
+    /// Check whether the node keeps an index of BIP158 basic block filters,
+    /// which it does when started with `-blockfilterindex`
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn has_block_filter_index(&self) -> Result<bool, BlockTalkError> {
+        let mut req = self.chain_client.has_block_filter_index_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get filter index context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        req.get().set_filter_type(BASIC_FILTER_TYPE);
+
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to check for a block filter index: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+        let response = response
+            .get()
+            .context("Failed to read block filter index status")?;
+        Ok(response.get_result())
+    }
+
+    /// Ask the node whether its basic filter for a block matches any of `scripts`
+    /// Returns None if the node has no filter for the block. Unlike a
+    /// `BlockFilter`, the node's filters also hold the scripts a block spends
+    /// from, so a match may be a spend rather than a payment.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, scripts))
+    )]
+    pub async fn block_filter_matches_any(
+        &self,
+        block_hash: &BlockHash,
+        scripts: &[ScriptBuf],
+    ) -> Result<Option<bool>, BlockTalkError> {
+        let hash_bytes = block_hash.to_raw_hash().to_byte_array();
+
+        let mut req = self.chain_client.block_filter_matches_any_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get filter context for {}: {}", block_hash, e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        req.get().set_filter_type(BASIC_FILTER_TYPE);
+        req.get().set_block_hash(&hash_bytes);
+        {
+            let mut filter_set = req.get().init_filter_set(scripts.len() as u32);
+            for (i, script) in scripts.iter().enumerate() {
+                filter_set.set(i as u32, script.as_bytes());
+            }
+        }
+
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to match filter of block {}: {}", block_hash, e);
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, e.to_string())
+        })?;
+        let response = response
+            .get()
+            .with_context(|| format!("Failed to read filter match for block {}", block_hash))?;
+        Ok(response.get_has_result().then(|| response.get_result()))
+    }
+
+    /// Stream the blocks from `from_height` to `to_height` that may pay to or
+    /// spend from `scripts`
+    ///
+    /// When the node has a block filter index, each block's filter is checked
+    /// first and blocks it rules out are never downloaded. Without one, or for
+    /// a block the index hasn't reached, the block is streamed regardless.
+    /// Filters have no false negatives, so no block with a match is skipped.
+    pub fn matching_block_stream(
+        &self,
+        from_height: i32,
+        to_height: i32,
+        scripts: Vec<ScriptBuf>,
+    ) -> impl Stream<Item = Result<(i32, Block), BlockTalkError>> + '_ {
+        let (sender, receiver) = mpsc::channel(DEFAULT_BLOCK_STREAM_BUFFER);
+        // The task gets its own handle on the node, sharing the connection
+        let chain = Blockchain::from_client(self.chain_client.clone(), self.thread.clone());
+
+        tokio::task::spawn_local(async move {
+            if from_height > to_height {
+                return;
+            }
+            let result = chain
+                .send_matching_blocks(from_height, to_height, &scripts, &sender)
+                .await;
+            if let Err(e) = result {
+                let _ = sender.send(Err(e)).await;
+            }
+        });
+
+        ReceiverStream::new(receiver)
+    }
+
+    // Send the blocks `matching_block_stream` streams, stopping early once the
+    // consumer has gone away
+    async fn send_matching_blocks(
+        &self,
+        from_height: i32,
+        to_height: i32,
+        scripts: &[ScriptBuf],
+        sender: &mpsc::Sender<Result<(i32, Block), BlockTalkError>>,
+    ) -> Result<(), BlockTalkError> {
+        let (_, tip_hash) = self.get_tip().await?;
+        let use_filters = self.has_block_filter_index().await?;
+        if !use_filters {
+            log::debug!("No block filter index, fetching every block");
+        }
+
+        for height in from_height..=to_height {
+            if use_filters {
+                let block_hash = self.ancestor_hash(&tip_hash, height).await?;
+                if self.block_filter_matches_any(&block_hash, scripts).await? == Some(false) {
+                    continue;
+                }
+            }
+            let block = self.get_block(&tip_hash, height).await?;
+            if sender.send(Ok((height, block))).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the outputs paying to `scripts` in the blocks from `from_height` to
+    /// `to_height`, in block order
+    /// Only the blocks `matching_block_stream` streams are downloaded.
+    pub async fn scan_blocks_for_scripts(
+        &self,
+        from_height: i32,
+        to_height: i32,
+        scripts: &[ScriptBuf],
+    ) -> Result<Vec<ScriptMatch>, BlockTalkError> {
+        let wanted: HashSet<&ScriptBuf> = scripts.iter().collect();
+        let mut matches = Vec::new();
+        let mut blocks = self.matching_block_stream(from_height, to_height, scripts.to_vec());
+        while let Some(result) = blocks.next().await {
+            let (height, block) = result?;
+            for tx in &block.txdata {
+                let txid = tx.compute_txid();
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if wanted.contains(&output.script_pubkey) {
+                        matches.push(ScriptMatch {
+                            height,
+                            txid,
+                            vout: vout as u32,
+                            amount: output.value,
+                        });
+                    }
+                }
+            }
+        }
+        logging::debug!(
+            count = matches.len();
+            "Found {} outputs between heights {} and {}", matches.len(), from_height, to_height
+        );
+        Ok(matches)
+    }
+
+    /// Receive every chain notification from now on
+    /// Chain updates must have begun for notifications to arrive.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<ChainNotification> {
+        self.notification_handler.lock().unwrap().subscribe()
+    }
+
+    /// Wait for the next block to be connected to the chain
+    /// Chain updates must have begun for the block to be seen.
+    pub fn wait_for_block(&self) -> impl Future<Output = Result<Block, BlockTalkError>> {
+        let next = self
+            .notification_handler
+            .lock()
+            .unwrap()
+            .next_notification(NotificationFilter::BLOCK_CONNECTED);
+        async move {
+            match next.await? {
+                ChainNotification::BlockConnected(block) => Ok(block),
+                other => Err(BlockTalkError::Connection(format!(
+                    "Expected a block connected notification, got {:?}",
+                    other
+                ))),
+            }
+        }
+    }
+
+    pub fn notification_handler(&self) -> Arc<Mutex<ChainNotificationHandler>> {
+        self.notification_handler.clone()
+    }
+
+    fn lock_subscription(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, Option<HandlerClient>>, BlockTalkError> {
+        self.subscription.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for notification subscription: {}",
+                e
+            ))
+        })
+    }
+
+    // Helper method to convert bytes to BlockHash
+    fn bytes_to_block_hash(&self, bytes: &[u8]) -> Result<BlockHash, BlockTalkError> {
+        if bytes.len() != 32 {
+            log::error!("Invalid hash length: expected 32, got {}", bytes.len());
+            return Err(BlockTalkError::chain_error(
+                ChainErrorKind::InvalidBlockData,
+                format!("Invalid hash length: expected 32, got {}", bytes.len()),
+            ));
+        }
+
+        let mut hash_bytes = [0u8; 32];
+        hash_bytes.copy_from_slice(bytes);
+        Ok(BlockHash::from_raw_hash(
+            bitcoin::hashes::Hash::from_byte_array(hash_bytes),
+        ))
+    }
+}
+
+// Median of a window of block timestamps, taking the upper middle value of
+// an even sized window as the node does
+fn median_time_past(times: &[u32]) -> u32 {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
+
+// Heights of the blocks in a locator from a tip at `tip_height`, stepping
+// back one block at a time for the first ten steps and then doubling the step,
+// as the node does
+fn locator_heights(tip_height: i32) -> Vec<i32> {
+    let mut heights = Vec::new();
+    let mut height = tip_height.max(0);
+    let mut step = 1;
+    loop {
+        heights.push(height);
+        if height == 0 {
+            return heights;
+        }
+        height = (height - step).max(0);
+        if heights.len() > 10 {
+            step *= 2;
+        }
+    }
+}
+
+/// Convert big-endian chain work bytes, as returned by `ChainInterface::get_chain_work`,
+/// into a `Work` value that supports comparison and addition
+pub fn chain_work_from_bytes(work: &[u8; 32]) -> Work {
+    Work::from_be_bytes(*work)
+}
+
+// Binary search heights up to `tip_height` for the last block with a time at or
+// before `target`, fetching block times with `block_time`
+async fn search_block_time<F, Fut>(
+    tip_height: i32,
+    target: u64,
+    tolerance_secs: u64,
+    mut block_time: F,
+) -> Result<i32, BlockTalkError>
+where
+    F: FnMut(i32) -> Fut,
+    Fut: Future<Output = Result<u32, BlockTalkError>>,
+{
+    // The answer is always in low..=high
+    let (mut low, mut high) = (0, tip_height);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        let time = block_time(mid).await? as u64;
+        if time <= target {
+            if target - time < tolerance_secs {
+                return Ok(mid);
+            }
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Proof that a transaction is included in a block, as built by
+/// `ChainInterface::get_tx_merkle_proof`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxMerkleProof {
+    pub block_hash: BlockHash,
+    /// Position of the transaction in the block
+    pub index: u32,
+    /// Sibling hashes on the path from the transaction up to the merkle root
+    pub hashes: Vec<[u8; 32]>,
+}
+
+/// Check that `proof` links `txid` to a block header's `merkle_root`
+///
+/// ```
+/// use bitcoin::blockdata::constants::genesis_block;
+/// use bitcoin::Network;
+/// use blocktalk::{verify_tx_merkle_proof, TxMerkleProof};
+///
+/// // The only transaction in a block is the merkle root itself
+/// let block = genesis_block(Network::Regtest);
+/// let proof = TxMerkleProof {
+///     block_hash: block.block_hash(),
+///     index: 0,
+///     hashes: Vec::new(),
+/// };
+/// let txid = block.txdata[0].compute_txid();
+/// assert!(verify_tx_merkle_proof(&proof, &txid, block.header.merkle_root));
+/// ```
+pub fn verify_tx_merkle_proof(
+    proof: &TxMerkleProof,
+    txid: &Txid,
+    merkle_root: TxMerkleNode,
+) -> bool {
+    let mut index = proof.index;
+    let mut hash = txid.to_byte_array();
+    for sibling in &proof.hashes {
+        hash = if index % 2 == 0 {
+            merkle_parent(&hash, sibling)
+        } else {
+            merkle_parent(sibling, &hash)
+        };
+        index /= 2;
+    }
+    // Leftover index bits mean the path is too short for the claimed position
+    index == 0 && hash == merkle_root.to_byte_array()
+}
+
+// Proof that `block` includes `txid`, or None if it does not
+fn tx_merkle_proof(block: &Block, txid: &Txid) -> Option<TxMerkleProof> {
+    let mut level: Vec<[u8; 32]> = block
+        .txdata
+        .iter()
+        .map(|tx| tx.compute_txid().to_byte_array())
+        .collect();
+    let index = level
+        .iter()
+        .position(|hash| *hash == txid.to_byte_array())?;
+
+    let mut hashes = Vec::new();
+    let mut position = index;
+    while level.len() > 1 {
+        // An odd node out is paired with itself
+        if level.len() % 2 == 1 {
+            level.push(level[level.len() - 1]);
+        }
+        hashes.push(level[position ^ 1]);
+        level = level
+            .chunks(2)
+            .map(|pair| merkle_parent(&pair[0], &pair[1]))
+            .collect();
+        position /= 2;
+    }
+
+    Some(TxMerkleProof {
+        block_hash: block.block_hash(),
+        index: index as u32,
+        hashes,
+    })
+}
+
+// Check `block` builds on `previous`, and from level 1 that it meets its proof
+// of work target and commits to its transactions
+fn verify_block(
+    block: &Block,
+    previous: Option<&BlockHash>,
+    check_level: u8,
+) -> Result<(), BlockTalkError> {
+    let invalid = |reason: &str| {
+        BlockTalkError::chain_error(
+            ChainErrorKind::InvalidBlockData,
+            format!("Block {} {}", block.block_hash(), reason),
+        )
+    };
+
+    if previous.is_some_and(|previous| block.header.prev_blockhash != *previous) {
+        return Err(invalid("does not build on the block before it"));
+    }
+    if check_level == 0 {
+        return Ok(());
+    }
+    if block.header.validate_pow(block.header.target()).is_err() {
+        return Err(invalid("does not meet its proof of work target"));
+    }
+    if !block.check_merkle_root() {
+        return Err(invalid(
+            "has a merkle root that does not match its transactions",
+        ));
+    }
+    if !block.check_witness_commitment() {
+        return Err(invalid("has an invalid witness commitment"));
+    }
+    Ok(())
+}
+
+// Hash two merkle tree nodes into their parent
+fn merkle_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut engine = TxMerkleNode::engine();
+    engine.input(left);
+    engine.input(right);
+    TxMerkleNode::from_engine(engine).to_byte_array()
+}
+
+// Decode a block header from the leading bytes of a serialized block
+fn decode_header(data: &[u8]) -> Result<Header, BlockTalkError> {
+    let mut prefix = data.get(..Header::SIZE).ok_or_else(|| {
+        log::error!(
+            "Block data too short for header: expected at least {}, got {}",
+            Header::SIZE,
+            data.len()
+        );
+        BlockTalkError::chain_error(
+            ChainErrorKind::InvalidBlockData,
+            format!(
+                "Block data too short for header: expected at least {}, got {}",
+                Header::SIZE,
+                data.len()
+            ),
+        )
+    })?;
+
+    Header::consensus_decode(&mut prefix).map_err(|e| {
+        log::error!("Failed to decode block header: {}", e);
+        BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_capnp::{chain, chain_notifications};
+    use crate::proxy_capnp::thread;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+    use capnp::capability::Promise;
+    use capnp_rpc::pry;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::atomic::AtomicUsize;
+
+    // Node with a fixed tip that counts the requests it serves
+    struct TipNode {
+        tip: BlockHash,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl chain::Server for TipNode {
+        fn get_height(
+            &mut self,
+            _: chain::GetHeightParams,
+            mut results: chain::GetHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            results.get().set_result(0);
+            results.get().set_has_result(true);
+            Promise::ok(())
+        }
+
+        fn get_block_hash(
+            &mut self,
+            _: chain::GetBlockHashParams,
+            mut results: chain::GetBlockHashResults,
+        ) -> Promise<(), capnp::Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            results.get().set_result(self.tip.as_ref());
+            Promise::ok(())
+        }
+    }
+
+    // Node whose tip moves while its height is being asked for, announced
+    // through `notifications` once it is set
+    struct MovingTipNode {
+        tip: BlockHash,
+        requests: Arc<AtomicUsize>,
+        notifications: Rc<RefCell<Option<chain_notifications::Client>>>,
+    }
+
+    impl chain::Server for MovingTipNode {
+        fn get_height(
+            &mut self,
+            _: chain::GetHeightParams,
+            mut results: chain::GetHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let notifications = self.notifications.borrow_mut().take();
+            Promise::from_future(async move {
+                if let Some(notifications) = notifications {
+                    notifications
+                        .updated_block_tip_request()
+                        .send()
+                        .promise
+                        .await?;
+                }
+                results.get().set_result(0);
+                results.get().set_has_result(true);
+                Ok(())
+            })
+        }
+
+        fn get_block_hash(
+            &mut self,
+            _: chain::GetBlockHashParams,
+            mut results: chain::GetBlockHashResults,
+        ) -> Promise<(), capnp::Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            results.get().set_result(self.tip.as_ref());
+            Promise::ok(())
+        }
+    }
+
+    struct NodeThread;
+
+    impl thread::Server for NodeThread {}
+
+    #[test]
+    fn test_decode_header_from_block_data() {
+        let block = genesis_block(Network::Regtest);
+        let data = bitcoin::consensus::serialize(&block);
+
+        let header = decode_header(&data).unwrap();
+        assert_eq!(header, block.header);
+        assert_eq!(header.block_hash(), block.block_hash());
+    }
+
+    #[test]
+    fn test_chain_work_bytes_round_trip() {
+        let header = genesis_block(Network::Regtest).header;
+        let work = header.work() + header.work();
+
+        assert_eq!(chain_work_from_bytes(&work.to_be_bytes()), work);
+        assert!(chain_work_from_bytes(&work.to_be_bytes()) > header.work());
+    }
+
+    #[test]
+    fn test_cached_tip_expires_after_ttl() {
+        let tip = CachedTip::new(0, genesis_block(Network::Regtest).block_hash());
+
+        assert!(tip.is_fresh(DEFAULT_TIP_CACHE_TTL));
+        assert!(!tip.is_fresh(Duration::ZERO));
+
+        let stale = CachedTip {
+            fetched_at: Instant::now() - Duration::from_secs(2),
+            ..tip
+        };
+        assert!(!stale.is_fresh(DEFAULT_TIP_CACHE_TTL));
+    }
+
+    #[tokio::test]
+    async fn test_get_tip_reuses_cached_tip() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let tip = genesis_block(Network::Regtest).block_hash();
+                let requests = Arc::new(AtomicUsize::new(0));
+                let chain_client: ChainClient = capnp_rpc::new_client(TipNode {
+                    tip,
+                    requests: requests.clone(),
+                });
+                let thread: ThreadClient = capnp_rpc::new_client(NodeThread);
+                let chain = Blockchain::from_client(chain_client, thread)
+                    .with_tip_cache_ttl(Duration::from_secs(60));
+
+                for _ in 0..100 {
+                    assert_eq!(chain.get_tip().await.unwrap(), (0, tip));
+                }
+                assert!(requests.load(Ordering::SeqCst) < 5);
+
+                // A new tip sends the next call back to the node
+                let sent = requests.load(Ordering::SeqCst);
+                let handler = chain.notification_handler().lock().unwrap().clone();
+                let notifications: chain_notifications::Client = capnp_rpc::new_client(handler);
+                notifications
+                    .updated_block_tip_request()
+                    .send()
+                    .promise
+                    .await
+                    .unwrap();
+                chain.get_tip().await.unwrap();
+                assert_eq!(requests.load(Ordering::SeqCst), sent + 2);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_tip_not_cached_when_tip_moves_during_fetch() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let tip = genesis_block(Network::Regtest).block_hash();
+                let requests = Arc::new(AtomicUsize::new(0));
+                let notifications = Rc::new(RefCell::new(None));
+                let chain_client: ChainClient = capnp_rpc::new_client(MovingTipNode {
+                    tip,
+                    requests: requests.clone(),
+                    notifications: notifications.clone(),
+                });
+                let thread: ThreadClient = capnp_rpc::new_client(NodeThread);
+                let chain = Blockchain::from_client(chain_client, thread)
+                    .with_tip_cache_ttl(Duration::from_secs(60));
+                let handler = chain.notification_handler().lock().unwrap().clone();
+                *notifications.borrow_mut() = Some(capnp_rpc::new_client(handler));
+
+                // The tip fetched may be out of date, so the next call asks again
+                assert_eq!(chain.get_tip().await.unwrap(), (0, tip));
+                assert_eq!(requests.load(Ordering::SeqCst), 2);
+                chain.get_tip().await.unwrap();
+                assert_eq!(requests.load(Ordering::SeqCst), 4);
+
+                // Nothing moved during the second fetch, so its tip was kept
+                chain.get_tip().await.unwrap();
+                assert_eq!(requests.load(Ordering::SeqCst), 4);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_block_resolves_once() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let genesis = genesis_block(Network::Regtest);
+                let chain_client: ChainClient = capnp_rpc::new_client(TipNode {
+                    tip: genesis.block_hash(),
+                    requests: Arc::new(AtomicUsize::new(0)),
+                });
+                let thread: ThreadClient = capnp_rpc::new_client(NodeThread);
+                let chain = Blockchain::from_client(chain_client, thread);
+                let next_block = chain.wait_for_block();
+
+                let handler = chain.notification_handler().lock().unwrap().clone();
+                let notifications: chain_notifications::Client = capnp_rpc::new_client(handler);
+                let mut second = genesis.clone();
+                second.header.prev_blockhash = genesis.block_hash();
+                for block in [&genesis, &second] {
+                    let mut request = notifications.block_connected_request();
+                    {
+                        let mut block_info = request.get().init_block();
+                        block_info.set_hash(block.block_hash().as_ref());
+                        block_info.set_data(&bitcoin::consensus::serialize(block));
+                    }
+                    request.send().promise.await.unwrap();
+                }
+
+                assert_eq!(next_block.await.unwrap(), genesis);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_to_address() {
+        use crate::notification::AddressEvent;
+        use bitcoin::{Amount, Transaction, TxIn, TxOut};
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let genesis = genesis_block(Network::Regtest);
+                let chain_client: ChainClient = capnp_rpc::new_client(TipNode {
+                    tip: genesis.block_hash(),
+                    requests: Arc::new(AtomicUsize::new(0)),
+                });
+                let thread: ThreadClient = capnp_rpc::new_client(NodeThread);
+                let chain = Blockchain::from_client(chain_client, thread);
+
+                let watched = ScriptBuf::new_op_return([1]);
+                let mut subscription = chain.subscribe_to_address(&watched).await.unwrap();
+                let paying = |values: &[(u64, &ScriptBuf)]| Transaction {
+                    version: bitcoin::transaction::Version::TWO,
+                    lock_time: bitcoin::absolute::LockTime::ZERO,
+                    input: vec![TxIn::default()],
+                    output: values
+                        .iter()
+                        .map(|(sats, script)| TxOut {
+                            value: Amount::from_sat(*sats),
+                            script_pubkey: (*script).clone(),
+                        })
+                        .collect(),
+                };
+                let other = ScriptBuf::new_op_return([2]);
+                let unrelated = paying(&[(10_000, &other)]);
+                let payment = paying(&[(30_000, &watched), (5_000, &other), (20_000, &watched)]);
+
+                let handler = chain.notification_handler().lock().unwrap().clone();
+                let notifications: chain_notifications::Client = capnp_rpc::new_client(handler);
+                for tx in [&unrelated, &payment] {
+                    let mut request = notifications.transaction_added_to_mempool_request();
+                    request.get().set_tx(&bitcoin::consensus::serialize(tx));
+                    request.send().promise.await.unwrap();
+                }
+                let mut block = genesis.clone();
+                block.txdata = vec![unrelated.clone(), payment.clone()];
+                let mut request = notifications.block_connected_request();
+                {
+                    let mut block_info = request.get().init_block();
+                    block_info.set_hash(block.block_hash().as_ref());
+                    block_info.set_data(&bitcoin::consensus::serialize(&block));
+                }
+                request.send().promise.await.unwrap();
+
+                let mut expected = AddressEvent {
+                    txid: payment.compute_txid(),
+                    confirmations: 0,
+                    amount: Amount::from_sat(50_000),
+                };
+                assert_eq!(subscription.next_event().await, Some(expected.clone()));
+                expected.confirmations = 1;
+                assert_eq!(subscription.next_event().await, Some(expected));
+                // Nothing was sent for the unrelated transaction
+                assert!(
+                    tokio::time::timeout(Duration::from_millis(10), subscription.next_event())
+                        .await
+                        .is_err()
+                );
+            })
+            .await;
+    }
+
+    // Synced node whose tip is the regtest genesis block
+    struct GenesisNode;
+
+    impl chain::Server for GenesisNode {
+        fn get_height(
+            &mut self,
+            _: chain::GetHeightParams,
+            mut results: chain::GetHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            results.get().set_result(0);
+            results.get().set_has_result(true);
+            Promise::ok(())
+        }
+
+        fn get_block_hash(
+            &mut self,
+            _: chain::GetBlockHashParams,
+            mut results: chain::GetBlockHashResults,
+        ) -> Promise<(), capnp::Error> {
+            let genesis = genesis_block(Network::Regtest);
+            results.get().set_result(genesis.block_hash().as_ref());
+            Promise::ok(())
+        }
+
+        fn is_initial_block_download(
+            &mut self,
+            _: chain::IsInitialBlockDownloadParams,
+            mut results: chain::IsInitialBlockDownloadResults,
+        ) -> Promise<(), capnp::Error> {
+            results.get().set_result(false);
+            Promise::ok(())
+        }
+
+        fn find_block(
+            &mut self,
+            _: chain::FindBlockParams,
+            mut results: chain::FindBlockResults,
+        ) -> Promise<(), capnp::Error> {
+            let genesis = genesis_block(Network::Regtest);
+            let mut block = results.get().init_block();
+            block.set_height(0);
+            block.set_time(genesis.header.time as i64);
+            block.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_chaininfo() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(GenesisNode),
+                    capnp_rpc::new_client(NodeThread),
+                );
+                let genesis = genesis_block(Network::Regtest);
+
+                assert_eq!(
+                    chain.get_chaininfo().await.unwrap(),
+                    ChainInfo {
+                        height: 0,
+                        best_block_hash: genesis.block_hash(),
+                        tip_time: genesis.header.time,
+                        is_synced: true,
+                        ibd: false,
+                    }
+                );
+            })
+            .await;
+    }
+
+    // Hash of the block at `height` on `branch`, 0 for blocks shared by both branches
+    fn branch_hash(height: i32, branch: u8) -> BlockHash {
+        let mut bytes = [0u8; 32];
+        bytes[0] = height as u8;
+        bytes[1] = branch;
+        BlockHash::from_byte_array(bytes)
+    }
+
+    // Node that has reorganized from branch 1, blocks 3 and 4, to branch 2,
+    // blocks 3 to 5, on top of shared blocks 0 to 2
+    struct ReorgedNode;
+
+    impl ReorgedNode {
+        const FORK_HEIGHT: i32 = 2;
+        const TIP_HEIGHT: i32 = 5;
+
+        fn block_hash(height: i32, branch: u8) -> BlockHash {
+            if height <= Self::FORK_HEIGHT {
+                branch_hash(height, 0)
+            } else {
+                branch_hash(height, branch)
+            }
+        }
+    }
+
+    impl chain::Server for ReorgedNode {
+        fn get_height(
+            &mut self,
+            _: chain::GetHeightParams,
+            mut results: chain::GetHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            results.get().set_result(Self::TIP_HEIGHT);
+            results.get().set_has_result(true);
+            Promise::ok(())
+        }
+
+        fn get_block_hash(
+            &mut self,
+            params: chain::GetBlockHashParams,
+            mut results: chain::GetBlockHashResults,
+        ) -> Promise<(), capnp::Error> {
+            let height = pry!(params.get()).get_height();
+            results
+                .get()
+                .set_result(Self::block_hash(height, 2).as_ref());
+            Promise::ok(())
+        }
+
+        fn find_common_ancestor(
+            &mut self,
+            _: chain::FindCommonAncestorParams,
+            mut results: chain::FindCommonAncestorResults,
+        ) -> Promise<(), capnp::Error> {
+            let mut ancestor = results.get().init_ancestor();
+            ancestor.set_hash(branch_hash(Self::FORK_HEIGHT, 0).as_ref());
+            ancestor.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+
+        fn find_block(
+            &mut self,
+            params: chain::FindBlockParams,
+            mut results: chain::FindBlockResults,
+        ) -> Promise<(), capnp::Error> {
+            let height = pry!(pry!(params.get()).get_hash())[0] as i32;
+            let mut block = results.get().init_block();
+            block.set_height(height);
+            block.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+
+        fn find_ancestor_by_height(
+            &mut self,
+            params: chain::FindAncestorByHeightParams,
+            mut results: chain::FindAncestorByHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            let params = pry!(params.get());
+            let branch = pry!(params.get_block_hash())[1];
+            let height = params.get_ancestor_height();
+            let mut ancestor = results.get().init_ancestor();
+            ancestor.set_hash(Self::block_hash(height, branch).as_ref());
+            ancestor.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_blocks_since_reorg() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(ReorgedNode),
+                    capnp_rpc::new_client(NodeThread),
+                );
+
+                let update = chain.get_blocks_since(&branch_hash(4, 1)).await.unwrap();
+                assert_eq!(
+                    update.disconnected,
+                    vec![branch_hash(4, 1), branch_hash(3, 1)]
+                );
+                assert_eq!(
+                    update.connected,
+                    vec![
+                        (3, branch_hash(3, 2)),
+                        (4, branch_hash(4, 2)),
+                        (5, branch_hash(5, 2)),
+                    ]
+                );
+
+                // Nothing has changed since the current tip
+                let update = chain.get_blocks_since(&branch_hash(5, 2)).await.unwrap();
+                assert_eq!(update, ChainUpdate::default());
+            })
+            .await;
+    }
+
+    // Block times ten minutes apart starting from `start`
+    fn block_times(start: u32, count: u32) -> Vec<u32> {
+        (0..count).map(|height| start + height * 600).collect()
+    }
+
+    // Chain of blocks with the given timestamps, whose hashes are their height
+    // repeated, that counts the requests it serves
+    struct TimedChain {
+        times: Vec<u32>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl chain::Server for TimedChain {
+        fn find_block(
+            &mut self,
+            params: chain::FindBlockParams,
+            mut results: chain::FindBlockResults,
+        ) -> Promise<(), capnp::Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let height = pry!(pry!(params.get()).get_hash())[0] as usize;
+            let mut block = results.get().init_block();
+            block.set_height(height as i32);
+            block.set_time(self.times[height] as i64);
+            block.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+
+        fn find_ancestor_by_height(
+            &mut self,
+            params: chain::FindAncestorByHeightParams,
+            mut results: chain::FindAncestorByHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let height = pry!(params.get()).get_ancestor_height() as usize;
+            let mut ancestor = results.get().init_ancestor();
+            ancestor.set_time(self.times[height] as i64);
+            ancestor.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+    }
+
+    #[test]
+    fn test_median_time_past_of_unordered_times() {
+        assert_eq!(median_time_past(&[5, 1, 4, 2, 3]), 3);
+        assert_eq!(median_time_past(&[7]), 7);
+        // Even sized windows near genesis take the upper middle value
+        assert_eq!(median_time_past(&[10, 30]), 30);
+    }
+
+    #[test]
+    fn test_locator_heights() {
+        assert_eq!(locator_heights(0), vec![0]);
+        assert_eq!(locator_heights(5), vec![5, 4, 3, 2, 1, 0]);
+        assert_eq!(locator_heights(10), (0..=10).rev().collect::<Vec<_>>());
+
+        // Eleven blocks one apart, then steps of 2, 4, 8, ... down to genesis
+        let mut expected = (90..=100).rev().collect::<Vec<_>>();
+        expected.extend([89, 87, 83, 75, 59, 27, 0]);
+        assert_eq!(locator_heights(100), expected);
+
+        let heights = locator_heights(800_000);
+        assert_eq!(heights.len(), 31);
+        assert_eq!(heights.last(), Some(&0));
+        let steps = heights.windows(2).map(|w| w[0] - w[1]).collect::<Vec<_>>();
+        for pair in steps[10..steps.len() - 1].windows(2) {
+            assert_eq!(pair[1], pair[0] * 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_median_time_past() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let mut times = block_times(1_600_000_000, 21);
+                // Block 20 claims to be older than its parent
+                times[20] = times[12];
+                let requests = Arc::new(AtomicUsize::new(0));
+                let chain_client: ChainClient = capnp_rpc::new_client(TimedChain {
+                    times: times.clone(),
+                    requests: requests.clone(),
+                });
+                let thread: ThreadClient = capnp_rpc::new_client(NodeThread);
+                let chain = Blockchain::from_client(chain_client, thread);
+                let hash_at = |height: u8| BlockHash::from_byte_array([height; 32]);
+
+                // Heights 10 to 20, with block 20's time sorting among them
+                assert_eq!(
+                    chain.get_median_time_past(&hash_at(20)).await.unwrap(),
+                    times[14]
+                );
+                assert_eq!(
+                    chain.get_block_time_offset(&hash_at(20)).await.unwrap(),
+                    -1200
+                );
+                assert_eq!(requests.load(Ordering::SeqCst), 11);
+
+                assert_eq!(
+                    chain.get_median_time_past(&hash_at(19)).await.unwrap(),
+                    times[14]
+                );
+                assert_eq!(
+                    chain.get_block_time_offset(&hash_at(19)).await.unwrap(),
+                    3000
+                );
+
+                // Fewer than 11 blocks exist near genesis
+                assert_eq!(
+                    chain.get_median_time_past(&hash_at(0)).await.unwrap(),
+                    times[0]
+                );
+                assert_eq!(
+                    chain.get_median_time_past(&hash_at(3)).await.unwrap(),
+                    times[2]
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_search_block_time_finds_last_block_before_target() {
+        let times = block_times(1_600_000_000, 500);
+        let tip_height = times.len() as i32 - 1;
+        let lookup = |height: i32| {
+            let time = times[height as usize];
+            async move { Ok(time) }
+        };
+
+        // Exact match, between blocks, before genesis and after the tip
+        let exact = times[321] as u64;
+        assert_eq!(
+            search_block_time(tip_height, exact, 0, lookup)
+                .await
+                .unwrap(),
+            321
+        );
+        assert_eq!(
+            search_block_time(tip_height, exact + 599, 0, lookup)
+                .await
+                .unwrap(),
+            321
+        );
+        assert_eq!(
+            search_block_time(tip_height, 0, 0, lookup).await.unwrap(),
+            0
+        );
+        assert_eq!(
+            search_block_time(tip_height, u64::MAX, 0, lookup)
+                .await
+                .unwrap(),
+            tip_height
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_block_time_round_trips() {
+        let times = block_times(1_600_000_000, 500);
+        let mut requests = 0;
+        let height = search_block_time(499, times[123] as u64 + 10, 0, |height| {
+            requests += 1;
+            let time = times[height as usize];
+            async move { Ok(time) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(height, 123);
+        // ceil(log2(500)) lookups at most
+        assert!(requests <= 9);
+    }
+
+    #[tokio::test]
+    async fn test_search_block_time_within_tolerance() {
+        let times = block_times(1_600_000_000, 500);
+        let target = times[400] as u64 + 300;
+        let height = search_block_time(499, target, 7_200, |height| {
+            let time = times[height as usize];
+            async move { Ok(time) }
+        })
+        .await
+        .unwrap();
+
+        let found = times[height as usize] as u64;
+        assert!(found <= target);
+        assert!(target - found < 7_200);
+    }
+
+    // Regtest block with `count` transactions and a matching merkle root
+    fn block_with_transactions(count: u32) -> Block {
+        let mut block = genesis_block(Network::Regtest);
+        let coinbase = block.txdata[0].clone();
+        for lock_time in 1..count {
+            let mut tx = coinbase.clone();
+            tx.lock_time = bitcoin::absolute::LockTime::from_consensus(lock_time);
+            block.txdata.push(tx);
+        }
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        block
+    }
+
+    // Regtest chain of `length` blocks starting at genesis, each with a
+    // coinbase and a valid proof of work
+    fn mined_chain(length: u32) -> Vec<Block> {
+        let mut blocks = vec![genesis_block(Network::Regtest)];
+        for height in 1..length {
+            let mut block = blocks[height as usize - 1].clone();
+            block.header.prev_blockhash = block.block_hash();
+            block.txdata[0].lock_time = bitcoin::absolute::LockTime::from_consensus(height);
+            block.header.merkle_root = block.compute_merkle_root().unwrap();
+            while block.header.validate_pow(block.header.target()).is_err() {
+                block.header.nonce += 1;
+            }
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    // Node whose active chain is `blocks`
+    struct BlocksNode {
+        blocks: Vec<Block>,
+    }
+
+    impl chain::Server for BlocksNode {
+        fn get_height(
+            &mut self,
+            _: chain::GetHeightParams,
+            mut results: chain::GetHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            results.get().set_result(self.blocks.len() as i32 - 1);
+            results.get().set_has_result(true);
+            Promise::ok(())
+        }
+
+        fn get_block_hash(
+            &mut self,
+            params: chain::GetBlockHashParams,
+            mut results: chain::GetBlockHashResults,
+        ) -> Promise<(), capnp::Error> {
+            let height = pry!(params.get()).get_height() as usize;
+            results
+                .get()
+                .set_result(self.blocks[height].block_hash().as_ref());
+            Promise::ok(())
+        }
+
+        fn find_ancestor_by_height(
+            &mut self,
+            params: chain::FindAncestorByHeightParams,
+            mut results: chain::FindAncestorByHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            let height = pry!(params.get()).get_ancestor_height() as usize;
+            let mut ancestor = results.get().init_ancestor();
+            ancestor.set_data(&bitcoin::consensus::serialize(&self.blocks[height]));
+            ancestor.set_found(true);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+    }
+
+    #[test]
+    fn test_verify_block() {
+        let blocks = mined_chain(3);
+        let genesis_hash = blocks[0].block_hash();
+        assert!(verify_block(&blocks[1], Some(&genesis_hash), 4).is_ok());
+        assert!(verify_block(&blocks[2], Some(&genesis_hash), 0).is_err());
+
+        // Changing a transaction leaves the header, and its proof of work, valid
+        let mut corrupted = blocks[1].clone();
+        corrupted.txdata[0].lock_time = bitcoin::absolute::LockTime::ZERO;
+        assert!(verify_block(&corrupted, Some(&genesis_hash), 0).is_ok());
+        let result = verify_block(&corrupted, Some(&genesis_hash), 1);
+        assert!(matches!(
+            result,
+            Err(BlockTalkError::Chain {
+                kind: ChainErrorKind::InvalidBlockData,
+                ref message,
+            }) if message.contains(&corrupted.block_hash().to_string())
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_verify_chain() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = mined_chain(5);
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(BlocksNode {
+                        blocks: blocks.clone(),
+                    }),
+                    capnp_rpc::new_client(NodeThread),
+                );
+                assert!(chain.verify_chain(3, 0).await.unwrap());
+                assert!(chain.verify_chain(3, 2).await.unwrap());
+
+                let mut corrupted = blocks;
+                corrupted[3].txdata[0].lock_time = bitcoin::absolute::LockTime::ZERO;
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(BlocksNode { blocks: corrupted }),
+                    capnp_rpc::new_client(NodeThread),
+                );
+                assert!(!chain.verify_chain(3, 0).await.unwrap());
+                // Only the tip is checked, and it is intact
+                assert!(chain.verify_chain(3, 1).await.unwrap());
+            })
+            .await;
+    }
+
+    // Records the name and field names of every span and event
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct RecordedFields(Arc<Mutex<Vec<(String, Vec<&'static str>)>>>);
+
+    #[cfg(feature = "tracing")]
+    impl RecordedFields {
+        fn record(&self, metadata: &tracing::Metadata<'_>) {
+            let fields = metadata.fields().iter().map(|field| field.name()).collect();
+            self.0
+                .lock()
+                .unwrap()
+                .push((metadata.name().to_string(), fields));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for RecordedFields {
+        fn on_new_span(
+            &self,
+            attrs: &tracing::span::Attributes<'_>,
+            _: &tracing::span::Id,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            self.record(attrs.metadata());
+        }
+
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            self.record(event.metadata());
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_tracing_records_fields() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let recorded = RecordedFields::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorded.clone()));
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(BlocksNode {
+                        blocks: mined_chain(3),
+                    }),
+                    capnp_rpc::new_client(NodeThread),
+                );
+                let (_, tip) = chain.get_tip().await.unwrap();
+                chain.get_block(&tip, 1).await.unwrap();
+            })
+            .await;
+
+        let recorded = recorded.0.lock().unwrap();
+        let span = |name: &str| recorded.iter().find(|(recorded, _)| recorded == name);
+        assert_eq!(span("get_tip").unwrap().1, Vec::<&str>::new());
+        assert_eq!(
+            span("get_block").unwrap().1,
+            vec!["node_tip_hash", "height"]
+        );
+        assert_eq!(span("get_block_hash").unwrap().1, vec!["height"]);
+        // "Getting block at height", with the height as a field
+        assert!(recorded.iter().any(|(name, fields)| {
+            name.starts_with("event") && fields.contains(&"height") && fields.contains(&"message")
+        }));
+    }
+
+    // Node serving `blocks` by hash, at their index as height, counting the
+    // blocks it sends
+    struct CountingBlocksNode {
+        blocks: Vec<Block>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    impl chain::Server for CountingBlocksNode {
+        fn find_block(
+            &mut self,
+            params: chain::FindBlockParams,
+            mut results: chain::FindBlockResults,
+        ) -> Promise<(), capnp::Error> {
+            let params = pry!(params.get());
+            let hash = pry!(params.get_hash()).to_vec();
+            let want_data = pry!(params.get_block()).get_want_data();
+            let found = self
+                .blocks
+                .iter()
+                .position(|block| block.block_hash().as_byte_array()[..] == hash[..]);
+            if let Some(height) = found {
+                let mut block_info = results.get().init_block();
+                if want_data {
+                    self.requests.fetch_add(1, Ordering::SeqCst);
+                    block_info.set_data(&bitcoin::consensus::serialize(&self.blocks[height]));
+                }
+                block_info.set_height(height as i32);
+                block_info.set_found(true);
+            }
+            results.get().set_result(found.is_some());
+            Promise::ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_work_walks_back_to_checkpoints() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = mined_chain(10);
+                let requests = Arc::new(AtomicUsize::new(0));
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(CountingBlocksNode {
+                        blocks: blocks.clone(),
+                        requests: requests.clone(),
+                    }),
+                    capnp_rpc::new_client(NodeThread),
+                );
+                let expected = |height: usize| {
+                    blocks[..=height]
+                        .iter()
+                        .fold(Work::from_be_bytes([0; 32]), |work, block| {
+                            work + block.header.work()
+                        })
+                        .to_be_bytes()
+                };
+
+                let middle = chain.get_chain_work(&blocks[5].block_hash()).await.unwrap();
+                assert_eq!(middle, expected(5));
+                assert_eq!(requests.load(Ordering::SeqCst), 6);
+
+                // Only the blocks after the last one asked about are fetched
+                let tip = chain.get_chain_work(&blocks[9].block_hash()).await.unwrap();
+                assert_eq!(tip, expected(9));
+                assert_eq!(requests.load(Ordering::SeqCst), 10);
+
+                // That block and the checkpoint at genesis are known without
+                // asking
+                for height in [9, 0] {
+                    let work = chain
+                        .get_chain_work(&blocks[height].block_hash())
+                        .await
+                        .unwrap();
+                    assert_eq!(work, expected(height));
+                }
+                assert_eq!(requests.load(Ordering::SeqCst), 10);
+
+                // Other blocks walked through aren't kept, so the walk goes back
+                // to the closest checkpoint
+                let work = chain.get_chain_work(&blocks[3].block_hash()).await.unwrap();
+                assert_eq!(work, expected(3));
+                assert_eq!(requests.load(Ordering::SeqCst), 13);
+            })
+            .await;
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_metrics_count_requests() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::from_client(
+                    capnp_rpc::new_client(BlocksNode {
+                        blocks: mined_chain(3),
+                    }),
+                    capnp_rpc::new_client(NodeThread),
+                );
+                // The second call is answered from the tip cache, but still counts
+                chain.get_tip().await.unwrap();
+                chain.get_tip().await.unwrap();
+            })
+            .await;
+
+        let snapshot = snapshotter.snapshot().into_vec();
+        let value = |name: &str, method: Option<&str>| {
+            snapshot
+                .iter()
+                .find(|(key, _, _, _)| {
+                    key.key().name() == name
+                        && key
+                            .key()
+                            .labels()
+                            .all(|label| Some(label.value()) == method)
+                })
+                .map(|(_, _, _, value)| value.clone())
+        };
+        assert_eq!(
+            value("blocktalk_chain_requests_total", Some("get_tip")),
+            Some(DebugValue::Counter(2))
+        );
+        match value("blocktalk_chain_request_duration_seconds", Some("get_tip")) {
+            Some(DebugValue::Histogram(durations)) => assert_eq!(durations.len(), 2),
+            other => panic!("Expected a histogram, got {:?}", other),
+        }
+        match value("blocktalk_tip_height", None) {
+            Some(DebugValue::Gauge(height)) => assert_eq!(height.into_inner(), 2.0),
+            other => panic!("Expected a gauge, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_merkle_proofs_verify_against_header() {
+        for count in [1, 2, 3, 5, 8] {
+            let block = block_with_transactions(count);
+            for tx in &block.txdata {
+                let txid = tx.compute_txid();
+                let proof = tx_merkle_proof(&block, &txid).unwrap();
+                assert_eq!(proof.block_hash, block.block_hash());
+                assert!(verify_tx_merkle_proof(
+                    &proof,
+                    &txid,
+                    block.header.merkle_root
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merkle_proof_rejects_wrong_transaction_or_index() {
+        let block = block_with_transactions(5);
+        let txid = block.txdata[2].compute_txid();
+        let other = block.txdata[3].compute_txid();
+        let proof = tx_merkle_proof(&block, &txid).unwrap();
+
+        assert!(!verify_tx_merkle_proof(
+            &proof,
+            &other,
+            block.header.merkle_root
+        ));
+
+        let moved = TxMerkleProof {
+            index: proof.index + 1,
+            ..proof.clone()
+        };
+        assert!(!verify_tx_merkle_proof(
+            &moved,
+            &txid,
+            block.header.merkle_root
+        ));
+
+        let too_far = TxMerkleProof {
+            index: proof.index + (1 << proof.hashes.len()),
+            ..proof
+        };
+        assert!(!verify_tx_merkle_proof(
+            &too_far,
+            &txid,
+            block.header.merkle_root
+        ));
+    }
+
+    #[test]
+    fn test_no_merkle_proof_for_missing_transaction() {
+        let block = block_with_transactions(3);
+        let missing = block_with_transactions(4).txdata[3].compute_txid();
+        assert_eq!(tx_merkle_proof(&block, &missing), None);
+    }
+
+    #[test]
+    fn test_decode_header_short_data() {
+        let block = genesis_block(Network::Regtest);
+        let data = bitcoin::consensus::serialize(&block.header);
+
+        let result = decode_header(&data[..Header::SIZE - 1]);
+        assert!(matches!(
+            result,
+            Err(BlockTalkError::Chain {
+                kind: ChainErrorKind::InvalidBlockData,
+                ..
+            })
+        ));
     }
 }
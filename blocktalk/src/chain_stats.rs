@@ -0,0 +1,232 @@
+use bitcoin::blockdata::constants::{genesis_block, SUBSIDY_HALVING_INTERVAL};
+use bitcoin::{Amount, Block, BlockHash, Network};
+
+/// Blocks between halvings on regtest
+const REGTEST_SUBSIDY_HALVING_INTERVAL: u32 = 150;
+
+/// Aggregate statistics for a block, like Bitcoin Core's `getblockstats`
+///
+/// A block does not carry the values of the outputs its transactions spend, so
+/// fees are taken to be whatever the coinbase claims on top of the subsidy.
+/// Averages and the median exclude the coinbase transaction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockStats {
+    /// Average fee rate in sat/vB
+    pub avg_fee_rate: u64,
+    /// Average transaction size in bytes
+    pub avg_tx_size: u32,
+    /// Median transaction size in bytes
+    pub median_tx_size: u32,
+    /// Number of inputs, excluding the coinbase input
+    pub ins: u32,
+    /// Number of outputs, including the coinbase outputs
+    pub outs: u32,
+    /// Block subsidy in satoshis
+    pub subsidy: u64,
+    /// Total fees in satoshis
+    pub total_fee: u64,
+    /// Number of transactions, including the coinbase
+    pub txs: u32,
+}
+
+impl BlockStats {
+    /// Compute the statistics of `block` at `height` on a chain that halves the
+    /// subsidy every `halving_interval` blocks
+    ///
+    /// ```
+    /// use bitcoin::blockdata::constants::{genesis_block, SUBSIDY_HALVING_INTERVAL};
+    /// use bitcoin::Network;
+    /// use blocktalk::BlockStats;
+    ///
+    /// let block = genesis_block(Network::Bitcoin);
+    /// let stats = BlockStats::from_block(&block, 0, SUBSIDY_HALVING_INTERVAL);
+    /// assert_eq!(stats.txs, 1);
+    /// assert_eq!(stats.subsidy, 50 * 100_000_000);
+    /// assert_eq!(stats.total_fee, 0);
+    /// ```
+    pub fn from_block(block: &Block, height: u32, halving_interval: u32) -> Self {
+        let subsidy = block_subsidy(height, halving_interval);
+        let claimed: Amount = block
+            .coinbase()
+            .filter(|tx| tx.is_coinbase())
+            .map(|tx| tx.output.iter().map(|output| output.value).sum())
+            .unwrap_or(Amount::ZERO);
+        let total_fee = claimed.to_sat().saturating_sub(subsidy.to_sat());
+
+        let transactions = block.txdata.iter().filter(|tx| !tx.is_coinbase());
+        let mut sizes = Vec::new();
+        let mut total_weight = 0;
+        let mut ins = 0;
+        for tx in transactions {
+            sizes.push(tx.total_size() as u32);
+            total_weight += tx.weight().to_wu();
+            ins += tx.input.len() as u32;
+        }
+        let outs = block.txdata.iter().map(|tx| tx.output.len() as u32).sum();
+
+        let avg_tx_size = match sizes.len() {
+            0 => 0,
+            count => (sizes.iter().map(|&size| size as u64).sum::<u64>() / count as u64) as u32,
+        };
+        let avg_fee_rate = match total_weight {
+            0 => 0,
+            // Fee per virtual byte, where a virtual byte is 4 weight units
+            weight => total_fee * 4 / weight,
+        };
+
+        Self {
+            avg_fee_rate,
+            avg_tx_size,
+            median_tx_size: median(&mut sizes),
+            ins,
+            outs,
+            subsidy: subsidy.to_sat(),
+            total_fee,
+            txs: block.txdata.len() as u32,
+        }
+    }
+}
+
+/// Block subsidy at `height` on a chain that halves it every `halving_interval` blocks
+///
+/// ```
+/// use bitcoin::blockdata::constants::SUBSIDY_HALVING_INTERVAL;
+/// use bitcoin::Amount;
+/// use blocktalk::block_subsidy;
+///
+/// assert_eq!(block_subsidy(840_000, SUBSIDY_HALVING_INTERVAL), Amount::from_sat(312_500_000));
+/// ```
+pub fn block_subsidy(height: u32, halving_interval: u32) -> Amount {
+    let halvings = height / halving_interval.max(1);
+    // The subsidy is zero once it has been shifted all the way down
+    if halvings >= 64 {
+        return Amount::ZERO;
+    }
+    Amount::from_sat(Amount::from_int_btc(50).to_sat() >> halvings)
+}
+
+// Halving interval of the chain starting at `genesis_hash`
+pub(crate) fn halving_interval(genesis_hash: &BlockHash) -> u32 {
+    if *genesis_hash == genesis_block(Network::Regtest).block_hash() {
+        REGTEST_SUBSIDY_HALVING_INTERVAL
+    } else {
+        SUBSIDY_HALVING_INTERVAL
+    }
+}
+
+// Median of `values`, averaging the middle two when there is an even number
+fn median(values: &mut [u32]) -> u32 {
+    if values.is_empty() {
+        return 0;
+    }
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        ((values[mid - 1] as u64 + values[mid] as u64) / 2) as u32
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::hashes::Hash;
+    use bitcoin::transaction::Version;
+    use bitcoin::{OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+
+    fn spend(inputs: u32, outputs: &[u64]) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..inputs)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::from_byte_array([1; 32]), vout),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|&value| TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: ScriptBuf::new(),
+                })
+                .collect(),
+        }
+    }
+
+    // Block at `height` on regtest whose coinbase claims the subsidy plus `fees`
+    fn block_with_fees(height: u32, fees: u64, transactions: Vec<Transaction>) -> Block {
+        let mut block = genesis_block(Network::Regtest);
+        let subsidy = block_subsidy(height, REGTEST_SUBSIDY_HALVING_INTERVAL);
+        block.txdata[0].output[0].value = subsidy + Amount::from_sat(fees);
+        block.txdata.extend(transactions);
+        block
+    }
+
+    #[test]
+    fn test_stats_of_block_with_transactions() {
+        let small = spend(1, &[1_000]);
+        let large = spend(2, &[1_000, 2_000, 3_000]);
+        let block = block_with_fees(10, 5_000, vec![small.clone(), large.clone()]);
+
+        let stats = BlockStats::from_block(&block, 10, REGTEST_SUBSIDY_HALVING_INTERVAL);
+        let sizes = [small.total_size() as u32, large.total_size() as u32];
+        let weight = small.weight().to_wu() + large.weight().to_wu();
+        assert_eq!(
+            stats,
+            BlockStats {
+                avg_fee_rate: 5_000 * 4 / weight,
+                avg_tx_size: (sizes[0] + sizes[1]) / 2,
+                median_tx_size: (sizes[0] + sizes[1]) / 2,
+                ins: 3,
+                outs: 5,
+                subsidy: 50 * 100_000_000,
+                total_fee: 5_000,
+                txs: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stats_of_coinbase_only_block() {
+        let block = block_with_fees(0, 0, vec![]);
+
+        let stats = BlockStats::from_block(&block, 0, REGTEST_SUBSIDY_HALVING_INTERVAL);
+        assert_eq!(stats.txs, 1);
+        assert_eq!(stats.ins, 0);
+        assert_eq!(stats.outs, 1);
+        assert_eq!(stats.total_fee, 0);
+        assert_eq!(stats.avg_fee_rate, 0);
+        assert_eq!(stats.avg_tx_size, 0);
+    }
+
+    #[test]
+    fn test_subsidy_halves() {
+        let interval = REGTEST_SUBSIDY_HALVING_INTERVAL;
+        assert_eq!(
+            block_subsidy(interval - 1, interval),
+            Amount::from_int_btc(50)
+        );
+        assert_eq!(block_subsidy(interval, interval), Amount::from_int_btc(25));
+        assert_eq!(block_subsidy(interval * 64, interval), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_halving_interval_by_network() {
+        let regtest = genesis_block(Network::Regtest).block_hash();
+        let mainnet = genesis_block(Network::Bitcoin).block_hash();
+        assert_eq!(halving_interval(&regtest), REGTEST_SUBSIDY_HALVING_INTERVAL);
+        assert_eq!(halving_interval(&mainnet), SUBSIDY_HALVING_INTERVAL);
+    }
+
+    #[test]
+    fn test_median_of_odd_and_even_counts() {
+        assert_eq!(median(&mut [300, 100, 200]), 200);
+        assert_eq!(median(&mut [400, 100, 300, 200]), 250);
+        assert_eq!(median(&mut []), 0);
+    }
+}
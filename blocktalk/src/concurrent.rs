@@ -0,0 +1,192 @@
+use bitcoin::{Block, BlockHash};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::{BlockTalkError, ChainInterface};
+
+/// Default number of requests `ConcurrentChainInterface` keeps in flight
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 16;
+
+/// Issues independent chain requests together rather than one after another
+///
+/// Cap'n Proto lets any number of requests be outstanding on a connection, and
+/// `Blockchain` awaits only the response to each call it makes, so requests
+/// made together are answered together. This keeps up to `max_in_flight` of
+/// them outstanding, which bounds the work queued on the node when asking for
+/// many blocks or hashes at once. Everything runs on the caller's task, so the
+/// requests need not be `Send`.
+#[derive(Clone)]
+pub struct ConcurrentChainInterface {
+    chain: Arc<dyn ChainInterface>,
+    max_in_flight: usize,
+}
+
+impl ConcurrentChainInterface {
+    pub fn new(chain: Arc<dyn ChainInterface>) -> Self {
+        Self {
+            chain,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+        }
+    }
+
+    /// Set how many requests may be outstanding at once, at least one
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// The interface requests are made through
+    pub fn chain(&self) -> &Arc<dyn ChainInterface> {
+        &self.chain
+    }
+
+    /// Make `request` for each of `items`, with up to `max_in_flight` in flight
+    /// Results are returned in the order of `items`, whatever order the node
+    /// answers in, and a failed request does not stop the others.
+    pub async fn run<I, T, F, Fut>(&self, items: I, request: F) -> Vec<Result<T, BlockTalkError>>
+    where
+        I: IntoIterator,
+        F: Fn(Arc<dyn ChainInterface>, I::Item) -> Fut,
+        Fut: Future<Output = Result<T, BlockTalkError>>,
+    {
+        let mut items = items.into_iter().enumerate();
+        let mut in_flight = FuturesUnordered::new();
+        let mut results = Vec::new();
+        loop {
+            while in_flight.len() < self.max_in_flight {
+                let Some((index, item)) = items.next() else {
+                    break;
+                };
+                let response = request(self.chain.clone(), item);
+                in_flight.push(async move { (index, response.await) });
+            }
+            match in_flight.next().await {
+                Some(result) => results.push(result),
+                None => break,
+            }
+        }
+
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Get the hashes of the active chain blocks at `heights`
+    pub async fn get_block_hashes(
+        &self,
+        heights: impl IntoIterator<Item = i32>,
+    ) -> Result<Vec<BlockHash>, BlockTalkError> {
+        self.run(heights, |chain, height| async move {
+            chain.get_block_hash(height).await
+        })
+        .await
+        .into_iter()
+        .collect()
+    }
+
+    /// Get the blocks with `hashes`, `None` for any the node doesn't have
+    pub async fn get_blocks_by_hash(
+        &self,
+        hashes: impl IntoIterator<Item = BlockHash>,
+    ) -> Result<Vec<Option<Block>>, BlockTalkError> {
+        self.run(hashes, |chain, hash| async move {
+            chain.get_block_by_hash(&hash).await
+        })
+        .await
+        .into_iter()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Blockchain, Connection, InProcessConnectionProvider};
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::Network;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    fn chain_of(length: usize) -> Vec<Block> {
+        let mut blocks = vec![genesis_block(Network::Regtest)];
+        while blocks.len() < length {
+            let parent = blocks.last().unwrap();
+            let mut block = parent.clone();
+            block.header.prev_blockhash = parent.block_hash();
+            block.header.time = parent.header.time + 600;
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    async fn concurrent(blocks: Vec<Block>) -> ConcurrentChainInterface {
+        let provider = InProcessConnectionProvider::new().with_blocks(blocks);
+        let connection = Connection::connect("in-process", Box::new(provider))
+            .await
+            .unwrap();
+        ConcurrentChainInterface::new(Arc::new(Blockchain::new(connection)))
+    }
+
+    #[tokio::test]
+    async fn test_results_in_request_order() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(20);
+                let chain = concurrent(blocks.clone()).await.with_max_in_flight(4);
+
+                let hashes = chain.get_block_hashes((0..20).rev()).await.unwrap();
+                let expected = blocks
+                    .iter()
+                    .rev()
+                    .map(Block::block_hash)
+                    .collect::<Vec<_>>();
+                assert_eq!(hashes, expected);
+
+                let found = chain
+                    .get_blocks_by_hash([blocks[5].block_hash(), BlockHash::all_zeros()])
+                    .await
+                    .unwrap();
+                assert_eq!(found, vec![Some(blocks[5].clone()), None]);
+
+                assert!(chain.get_block_hashes([1, 25, 2]).await.is_err());
+                let results = chain
+                    .run([1, 25, 2], |chain, height| async move {
+                        chain.get_block_hash(height).await
+                    })
+                    .await;
+                assert!(results[0].is_ok() && results[1].is_err() && results[2].is_ok());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = concurrent(chain_of(1)).await.with_max_in_flight(3);
+                let in_flight = Rc::new(Cell::new(0));
+                let most_in_flight = Rc::new(Cell::new(0));
+
+                let results = chain
+                    .run(0..10, |chain, _| {
+                        let in_flight = in_flight.clone();
+                        let most_in_flight = most_in_flight.clone();
+                        async move {
+                            in_flight.set(in_flight.get() + 1);
+                            most_in_flight.set(most_in_flight.get().max(in_flight.get()));
+                            let tip = chain.get_tip().await;
+                            in_flight.set(in_flight.get() - 1);
+                            tip
+                        }
+                    })
+                    .await;
+                assert_eq!(results.len(), 10);
+                assert!(results.iter().all(Result::is_ok));
+                assert_eq!(most_in_flight.get(), 3);
+            })
+            .await;
+    }
+}
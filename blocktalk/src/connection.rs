@@ -1,5 +1,7 @@
 use capnp_rpc::{rpc_twoparty_capnp, twoparty, RpcSystem};
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::task::JoinHandle;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
@@ -7,7 +9,7 @@ use crate::chain_capnp::chain::Client as ChainClient;
 use crate::init_capnp::init::Client as InitClient;
 use crate::proxy_capnp::thread::Client as ThreadClient;
 use crate::BlockTalkError;
-use crate::mining_capnp::block_template::Client as MiningClient;
+use crate::mining_capnp::mining::Client as MiningClient;
 
 #[async_trait::async_trait(?Send)]
 pub trait ConnectionProvider: Send + Sync {
@@ -23,7 +25,12 @@ pub trait ConnectionProvider: Send + Sync {
         RpcSystem<capnp_rpc::rpc_twoparty_capnp::Side>,
         InitClient,
         capnp_rpc::Disconnector<twoparty::VatId>,
-    );
+    ) {
+        let mut rpc = RpcSystem::new(network, None);
+        let init_interface = rpc.bootstrap(rpc_twoparty_capnp::Side::Server);
+        let disconnector = rpc.get_disconnector();
+        (rpc, init_interface, disconnector)
+    }
 
     fn spawn_rpc(
         &self,
@@ -32,49 +39,6 @@ pub trait ConnectionProvider: Send + Sync {
         tokio::task::spawn_local(rpc)
     }
 
-    async fn create_clients(
-        &self,
-        init: &InitClient,
-    ) -> Result<(ThreadClient, ChainClient), BlockTalkError>;
-}
-
-pub struct UnixConnectionProvider;
-
-#[async_trait::async_trait(?Send)]
-impl ConnectionProvider for UnixConnectionProvider {
-    async fn create_network(
-        &self,
-        path: &str,
-    ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
-        let stream = tokio::net::UnixStream::connect(path).await.map_err(|e| {
-            log::error!("Failed to connect to Unix socket at {}: {}", path, e);
-            BlockTalkError::node_error(format!("Failed to connect to Unix socket: {}", e), -1)
-        })?;
-        log::debug!("Unix stream connected successfully");
-
-        let (reader, writer) = stream.into_split();
-        Ok(Box::new(twoparty::VatNetwork::new(
-            reader.compat(),
-            writer.compat_write(),
-            rpc_twoparty_capnp::Side::Client,
-            Default::default(),
-        )))
-    }
-
-    fn create_rpc(
-        &self,
-        network: Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>,
-    ) -> (
-        RpcSystem<capnp_rpc::rpc_twoparty_capnp::Side>,
-        InitClient,
-        capnp_rpc::Disconnector<twoparty::VatId>,
-    ) {
-        let mut rpc = RpcSystem::new(network, None);
-        let init_interface = rpc.bootstrap(rpc_twoparty_capnp::Side::Server);
-        let disconnector = rpc.get_disconnector();
-        (rpc, init_interface, disconnector)
-    }
-
     async fn create_clients(
         &self,
         init: &InitClient,
@@ -128,12 +92,204 @@ impl ConnectionProvider for UnixConnectionProvider {
     }
 }
 
+// Lets a shared provider be used for several connection attempts
+#[async_trait::async_trait(?Send)]
+impl<P: ConnectionProvider + ?Sized> ConnectionProvider for Arc<P> {
+    async fn create_network(
+        &self,
+        path: &str,
+    ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
+        (**self).create_network(path).await
+    }
+
+    fn create_rpc(
+        &self,
+        network: Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>,
+    ) -> (
+        RpcSystem<capnp_rpc::rpc_twoparty_capnp::Side>,
+        InitClient,
+        capnp_rpc::Disconnector<twoparty::VatId>,
+    ) {
+        (**self).create_rpc(network)
+    }
+
+    fn spawn_rpc(
+        &self,
+        rpc: RpcSystem<capnp_rpc::rpc_twoparty_capnp::Side>,
+    ) -> JoinHandle<Result<(), capnp::Error>> {
+        (**self).spawn_rpc(rpc)
+    }
+
+    async fn create_clients(
+        &self,
+        init: &InitClient,
+    ) -> Result<(ThreadClient, ChainClient), BlockTalkError> {
+        (**self).create_clients(init).await
+    }
+}
+
+pub struct UnixConnectionProvider;
+
+#[async_trait::async_trait(?Send)]
+impl ConnectionProvider for UnixConnectionProvider {
+    async fn create_network(
+        &self,
+        path: &str,
+    ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
+        let stream = tokio::net::UnixStream::connect(path).await.map_err(|e| {
+            log::error!("Failed to connect to Unix socket at {}: {}", path, e);
+            BlockTalkError::node_error(format!("Failed to connect to Unix socket: {}", e), -1)
+        })?;
+        log::debug!("Unix stream connected successfully");
+
+        let (reader, writer) = stream.into_split();
+        Ok(Box::new(twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        )))
+    }
+}
+
+pub struct TcpConnectionProvider {
+    addr: SocketAddr,
+}
+
+impl TcpConnectionProvider {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ConnectionProvider for TcpConnectionProvider {
+    async fn create_network(
+        &self,
+        _path: &str,
+    ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
+        let stream = tokio::net::TcpStream::connect(self.addr)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to connect to TCP socket at {}: {}", self.addr, e);
+                BlockTalkError::node_error(format!("Failed to connect to TCP socket: {}", e), -1)
+            })?;
+        stream.set_nodelay(true)?;
+        log::debug!("TCP stream connected successfully");
+
+        let (reader, writer) = stream.into_split();
+        Ok(Box::new(twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        )))
+    }
+}
+
+/// Connects to a node's TCP IPC interface through a SOCKS5 proxy, such as
+/// Tor's, so the node can be an onion service
+///
+/// `target_addr` is `host:port` and is resolved by the proxy, never locally.
+#[cfg(feature = "socks5")]
+pub struct Socks5ConnectionProvider {
+    proxy_addr: SocketAddr,
+    target_addr: String,
+    credentials: Option<(String, String)>,
+}
+
+#[cfg(feature = "socks5")]
+impl Socks5ConnectionProvider {
+    pub fn new(proxy_addr: SocketAddr, target_addr: &str) -> Self {
+        Self {
+            proxy_addr,
+            target_addr: target_addr.to_string(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticate to the proxy with a username and password
+    /// Tor doesn't check them, but uses them to keep connections with
+    /// different credentials on separate circuits.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    pub fn proxy_addr(&self) -> SocketAddr {
+        self.proxy_addr
+    }
+
+    pub fn target_addr(&self) -> &str {
+        &self.target_addr
+    }
+}
+
+#[cfg(feature = "socks5")]
+#[async_trait::async_trait(?Send)]
+impl ConnectionProvider for Socks5ConnectionProvider {
+    async fn create_network(
+        &self,
+        _path: &str,
+    ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
+        let target = self.target_addr.as_str();
+        let stream = match &self.credentials {
+            Some((username, password)) => {
+                tokio_socks::tcp::Socks5Stream::connect_with_password(
+                    self.proxy_addr,
+                    target,
+                    username,
+                    password,
+                )
+                .await
+            }
+            None => tokio_socks::tcp::Socks5Stream::connect(self.proxy_addr, target).await,
+        }
+        .map_err(|e| {
+            log::error!(
+                "Failed to connect to {} through SOCKS5 proxy at {}: {}",
+                target,
+                self.proxy_addr,
+                e
+            );
+            match e {
+                tokio_socks::Error::NoAcceptableAuthMethods
+                | tokio_socks::Error::UnknownAuthMethod
+                | tokio_socks::Error::PasswordAuthFailure(_)
+                | tokio_socks::Error::InvalidAuthValues(_) => {
+                    BlockTalkError::ProxyAuth(e.to_string())
+                }
+                _ => BlockTalkError::node_error(
+                    format!("Failed to connect through SOCKS5 proxy: {}", e),
+                    -1,
+                ),
+            }
+        })?;
+        let stream = stream.into_inner();
+        stream.set_nodelay(true)?;
+        log::debug!("SOCKS5 stream connected successfully");
+
+        let (reader, writer) = stream.into_split();
+        Ok(Box::new(twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        )))
+    }
+}
+
 pub struct Connection {
     rpc_handle: JoinHandle<Result<(), capnp::Error>>,
     disconnector: capnp_rpc::Disconnector<twoparty::VatId>,
     thread: ThreadClient,
     chain_client: ChainClient,
-    mining_client: MiningClient
+    mining_client: MiningClient,
+    health_monitor: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Connection {
@@ -157,19 +313,7 @@ impl Connection {
         }
         let response = mk_mining_req.send().promise.await?;
 
-        let mining_client = response.get()?.get_result()?;
-        log::debug!("Mining client established");
-
-        // Now create a new block to get the block template client
-        let mut create_block_req = mining_client.create_new_block_request();
-        {
-            // Set up the options for creating a new block
-            let mut options = create_block_req.get().init_options();
-            options.set_use_mempool(true);
-            options.set_block_reserved_weight(4000);
-        }
-        let response = create_block_req.send().promise.await?;
-
+        // Block templates are created on demand by `Mining`
         let mining_client = response.get()?.get_result()?;
         log::debug!("Mining client established");
 
@@ -179,7 +323,8 @@ impl Connection {
             disconnector,
             thread,
             chain_client,
-            mining_client
+            mining_client,
+            health_monitor: Mutex::new(None),
         }))
     }
 
@@ -187,8 +332,19 @@ impl Connection {
         Self::connect(socket_path, Box::new(UnixConnectionProvider)).await
     }
 
+    pub async fn connect_tcp(addr: SocketAddr) -> Result<Arc<Self>, BlockTalkError> {
+        Self::connect(
+            &addr.to_string(),
+            Box::new(TcpConnectionProvider::new(addr)),
+        )
+        .await
+    }
+
     pub async fn disconnect(self) -> Result<(), BlockTalkError> {
         log::info!("Disconnecting from node");
+        if let Some(monitor) = self.health_monitor.lock().ok().and_then(|mut m| m.take()) {
+            monitor.abort();
+        }
         self.disconnector.await.map_err(|e| {
             log::error!("Failed to disconnect RPC: {}", e);
             BlockTalkError::Connection(e.to_string())
@@ -215,19 +371,85 @@ impl Connection {
         &self.chain_client
     }
 
-    pub fn mining_client(&self) -> MiningClient {
-        self.mining_client.clone()
+    pub fn mining_client(&self) -> &MiningClient {
+        &self.mining_client
     }
 
     /// Get a reference to the thread client
     pub fn thread(&self) -> &ThreadClient {
         &self.thread
     }
+
+    /// Check the node is responding, returning the round trip time of a
+    /// lightweight request
+    pub async fn ping(&self) -> Result<Duration, BlockTalkError> {
+        ping(&self.chain_client, &self.thread).await
+    }
+
+    /// Ping the node every `interval`, calling `on_failure` if a ping fails
+    /// Monitoring stops after the first failure, and is cancelled by
+    /// `disconnect` or by starting another monitor. The monitor runs on the
+    /// current `LocalSet`, as the node clients are not Send.
+    pub fn start_health_monitor(
+        &self,
+        interval: Duration,
+        on_failure: Arc<dyn Fn() + Send + Sync>,
+    ) {
+        let handle = tokio::task::spawn_local(health_monitor(
+            self.chain_client.clone(),
+            self.thread.clone(),
+            interval,
+            on_failure,
+        ));
+        if let Ok(mut monitor) = self.health_monitor.lock() {
+            if let Some(previous) = monitor.replace(handle) {
+                previous.abort();
+            }
+        }
+    }
+}
+
+async fn ping(
+    chain_client: &ChainClient,
+    thread: &ThreadClient,
+) -> Result<Duration, BlockTalkError> {
+    let started = Instant::now();
+    let mut height_req = chain_client.get_height_request();
+    height_req.get().get_context()?.set_thread(thread.clone());
+    height_req.send().promise.await.map_err(|e| {
+        log::warn!("Node did not respond to ping: {}", e);
+        BlockTalkError::Connection(e.to_string())
+    })?;
+    Ok(started.elapsed())
+}
+
+async fn health_monitor(
+    chain_client: ChainClient,
+    thread: ThreadClient,
+    interval: Duration,
+    on_failure: Arc<dyn Fn() + Send + Sync>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match ping(&chain_client, &thread).await {
+            Ok(rtt) => log::trace!("Node responded to ping in {:?}", rtt),
+            Err(e) => {
+                log::error!("Connection health check failed: {}", e);
+                on_failure();
+                return;
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chain_capnp::chain;
+    use crate::proxy_capnp::thread;
+    use capnp::capability::Promise;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     struct MockConnectionProvider {
         network_error: Option<BlockTalkError>,
@@ -330,4 +552,168 @@ mod tests {
         let result = Connection::connect("test_path", Box::new(provider)).await;
         assert!(matches!(result, Err(e) if e == error));
     }
+
+    #[tokio::test]
+    async fn test_tcp_provider_creates_network() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accept = tokio::spawn(async move { listener.accept().await.map(|_| ()) });
+
+        let provider = TcpConnectionProvider::new(addr);
+        let result = provider.create_network("unused").await;
+        assert!(result.is_ok());
+        assert!(accept.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_provider_connection_refused() {
+        // Bind and immediately drop a listener to get an address nobody is listening on
+        let addr = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        let provider = TcpConnectionProvider::new(addr);
+        let result = provider.create_network("unused").await;
+        assert!(matches!(result, Err(BlockTalkError::Node { code: -1, .. })));
+    }
+
+    // SOCKS5 proxy for a single client, returning the CONNECT request it got
+    // and then serving an in-process node in place of the target. A proxy
+    // requiring authentication accepts none of the client's methods.
+    #[cfg(feature = "socks5")]
+    async fn socks5_proxy(require_auth: bool) -> (SocketAddr, JoinHandle<Vec<u8>>) {
+        use crate::InProcessConnectionProvider;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = tokio::task::spawn_local(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // VER NMETHODS, then the methods
+            let mut greeting = [0u8; 2];
+            stream.read_exact(&mut greeting).await.unwrap();
+            let mut methods = vec![0u8; greeting[1] as usize];
+            stream.read_exact(&mut methods).await.unwrap();
+            if require_auth {
+                stream.write_all(&[0x05, 0xff]).await.unwrap();
+                return Vec::new();
+            }
+            stream.write_all(&[0x05, 0x00]).await.unwrap();
+
+            // VER CMD RSV ATYP, then a length-prefixed domain name and the port
+            let mut request = vec![0u8; 5];
+            stream.read_exact(&mut request).await.unwrap();
+            let mut rest = vec![0u8; request[4] as usize + 2];
+            stream.read_exact(&mut rest).await.unwrap();
+            request.extend(rest);
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+
+            let (reader, writer) = stream.into_split();
+            InProcessConnectionProvider::new().serve(reader, writer);
+            request
+        });
+        (addr, proxy)
+    }
+
+    #[cfg(feature = "socks5")]
+    #[tokio::test]
+    async fn test_socks5_provider_connects_through_proxy() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (proxy_addr, proxy) = socks5_proxy(false).await;
+                let provider = Socks5ConnectionProvider::new(proxy_addr, "node.onion:8332");
+                let connection = Connection::connect("node.onion:8332", Box::new(provider))
+                    .await
+                    .unwrap();
+
+                // CONNECT to the domain name, left for the proxy to resolve
+                let mut expected = vec![0x05, 0x01, 0x00, 0x03, 10];
+                expected.extend_from_slice(b"node.onion");
+                expected.extend_from_slice(&8332u16.to_be_bytes());
+                assert_eq!(proxy.await.unwrap(), expected);
+                assert!(connection.ping().await.is_ok());
+            })
+            .await;
+    }
+
+    #[cfg(feature = "socks5")]
+    #[tokio::test]
+    async fn test_socks5_provider_auth_failure() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (proxy_addr, _proxy) = socks5_proxy(true).await;
+                let provider = Socks5ConnectionProvider::new(proxy_addr, "node.onion:8332");
+                let result = provider.create_network("unused").await;
+                assert!(matches!(result, Err(BlockTalkError::ProxyAuth(_))));
+            })
+            .await;
+    }
+
+    // Node that answers `healthy_calls` height requests and then fails
+    struct FailingNode {
+        healthy_calls: usize,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl chain::Server for FailingNode {
+        fn get_height(
+            &mut self,
+            _: chain::GetHeightParams,
+            mut results: chain::GetHeightResults,
+        ) -> Promise<(), capnp::Error> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) >= self.healthy_calls {
+                return Promise::err(capnp::Error::disconnected("node went away".to_string()));
+            }
+            results.get().set_result(0);
+            results.get().set_has_result(true);
+            Promise::ok(())
+        }
+    }
+
+    struct NodeThread;
+
+    impl thread::Server for NodeThread {}
+
+    #[tokio::test]
+    async fn test_health_monitor_reports_failure() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let calls = Arc::new(AtomicUsize::new(0));
+                let chain_client: ChainClient = capnp_rpc::new_client(FailingNode {
+                    healthy_calls: 3,
+                    calls: calls.clone(),
+                });
+                let thread: ThreadClient = capnp_rpc::new_client(NodeThread);
+
+                assert!(ping(&chain_client, &thread).await.is_ok());
+
+                let failures = Arc::new(AtomicUsize::new(0));
+                let on_failure = {
+                    let failures = failures.clone();
+                    Arc::new(move || {
+                        failures.fetch_add(1, Ordering::SeqCst);
+                    })
+                };
+                tokio::task::spawn_local(health_monitor(
+                    chain_client,
+                    thread,
+                    Duration::from_millis(1),
+                    on_failure,
+                ))
+                .await
+                .unwrap();
+
+                // The monitor stops at the first failed ping
+                assert_eq!(failures.load(Ordering::SeqCst), 1);
+                assert_eq!(calls.load(Ordering::SeqCst), 4);
+            })
+            .await;
+    }
 }
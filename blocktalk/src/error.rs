@@ -1,7 +1,7 @@
 use std::error::Error;
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ChainErrorKind {
     BlockNotFound,
     InvalidHeight,
@@ -11,7 +11,7 @@ pub enum ChainErrorKind {
     Other(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BlockValidationErrorKind {
     InvalidFormat,
     InvalidHash,
@@ -20,7 +20,7 @@ pub enum BlockValidationErrorKind {
     Other(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BlockTalkError {
     Connection(String),
     Io(String),
@@ -36,6 +36,16 @@ pub enum BlockTalkError {
         kind: ChainErrorKind,
         message: String,
     },
+    /// A SOCKS5 proxy refused the credentials offered, or required some
+    /// when none were
+    ProxyAuth(String),
+    /// Returned by a notification handler to have itself unregistered
+    HandlerFinished,
+    /// `source` with a description of what was being done when it happened
+    Context {
+        message: String,
+        source: Box<BlockTalkError>,
+    },
 }
 
 impl BlockTalkError {
@@ -50,6 +60,37 @@ impl BlockTalkError {
     pub fn chain_error(kind: ChainErrorKind, message: String) -> Self {
         BlockTalkError::Chain { kind, message }
     }
+
+    /// The error underneath any context added to it, for matching on its kind
+    pub fn root_cause(&self) -> &BlockTalkError {
+        match self {
+            BlockTalkError::Context { source, .. } => source.root_cause(),
+            other => other,
+        }
+    }
+}
+
+/// Adds context to the error of a result, as `anyhow::Context` does
+pub trait BlockTalkResultExt<T> {
+    /// Describe what was being done when the error happened
+    /// The description is shown ahead of the error's own message.
+    fn context<C: fmt::Display>(self, context: C) -> Result<T, BlockTalkError>;
+
+    /// Like `context`, only building the description if there is an error
+    fn with_context<C: fmt::Display, F: FnOnce() -> C>(self, f: F) -> Result<T, BlockTalkError>;
+}
+
+impl<T, E: Into<BlockTalkError>> BlockTalkResultExt<T> for Result<T, E> {
+    fn context<C: fmt::Display>(self, context: C) -> Result<T, BlockTalkError> {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C: fmt::Display, F: FnOnce() -> C>(self, f: F) -> Result<T, BlockTalkError> {
+        self.map_err(|e| BlockTalkError::Context {
+            message: f().to_string(),
+            source: Box::new(e.into()),
+        })
+    }
 }
 
 impl fmt::Display for BlockTalkError {
@@ -66,11 +107,21 @@ impl fmt::Display for BlockTalkError {
             BlockTalkError::Chain { kind, message } => {
                 write!(f, "Chain error ({:?}): {}", kind, message)
             }
+            BlockTalkError::ProxyAuth(e) => write!(f, "Proxy authentication error: {}", e),
+            BlockTalkError::HandlerFinished => write!(f, "Notification handler finished"),
+            BlockTalkError::Context { message, source } => write!(f, "{}: {}", message, source),
         }
     }
 }
 
-impl Error for BlockTalkError {}
+impl Error for BlockTalkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlockTalkError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl From<capnp::Error> for BlockTalkError {
     fn from(error: capnp::Error) -> Self {
@@ -83,3 +134,91 @@ impl From<std::io::Error> for BlockTalkError {
         BlockTalkError::Io(error.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_capnp_errors_compare_by_message() {
+        let first = BlockTalkError::from(capnp::Error::failed("disconnected".to_string()));
+        let second = BlockTalkError::from(capnp::Error::failed("disconnected".to_string()));
+        let other = BlockTalkError::from(capnp::Error::failed("timed out".to_string()));
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(first.clone(), first);
+    }
+
+    #[test]
+    fn test_errors_deduplicate_in_map() {
+        let not_found =
+            || BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, "gone".to_string());
+        let mut counts = HashMap::new();
+        for error in [
+            not_found(),
+            BlockTalkError::node_error("busy".to_string(), -1),
+            not_found(),
+        ] {
+            *counts.entry(error).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts[&not_found()], 2);
+    }
+
+    #[test]
+    fn test_variants_with_same_message_differ() {
+        let message = "failed".to_string();
+        assert_ne!(
+            BlockTalkError::Connection(message.clone()),
+            BlockTalkError::Io(message.clone())
+        );
+        assert_ne!(
+            BlockTalkError::node_error(message.clone(), -1),
+            BlockTalkError::node_error(message, -2)
+        );
+    }
+
+    #[test]
+    fn test_context_in_display() {
+        let result: Result<(), capnp::Error> =
+            Err(capnp::Error::failed("disconnected".to_string()));
+        let error = result.context("Failed to get tip").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to get tip: Connection error: disconnected"
+        );
+        assert_eq!(
+            error.source().map(ToString::to_string).as_deref(),
+            Some("Connection error: disconnected")
+        );
+
+        let not_found =
+            BlockTalkError::chain_error(ChainErrorKind::BlockNotFound, "gone".to_string());
+        let error = Err::<(), _>(not_found.clone())
+            .context("Failed to get block 5")
+            .with_context(|| format!("Failed to sync from height {}", 3))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to sync from height 3: Failed to get block 5: Chain error (BlockNotFound): gone"
+        );
+        assert_eq!(error.root_cause(), &not_found);
+    }
+
+    #[test]
+    fn test_with_context_is_lazy() {
+        let mut built = false;
+        let result: Result<u32, BlockTalkError> = Ok(1);
+        let value = result
+            .with_context(|| {
+                built = true;
+                "unused"
+            })
+            .unwrap();
+        assert_eq!(value, 1);
+        assert!(!built);
+    }
+}
@@ -0,0 +1,293 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::task::JoinHandle;
+
+use crate::{BlockTalkError, ChainInterface, Connection};
+
+/// How often the node is pinged and its sync status checked
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How recently the node must have answered a ping to be ready
+const READY_PING_AGE: Duration = Duration::from_secs(10);
+
+// What the endpoint last learned about the node
+#[derive(Default)]
+struct HealthState {
+    synced: bool,
+    tip_height: Option<i32>,
+    // When the last successful ping was sent and its round trip time
+    last_ping: Option<(Instant, Duration)>,
+}
+
+impl HealthState {
+    fn is_ready(&self, now: Instant) -> bool {
+        let ping_recent = self
+            .last_ping
+            .is_some_and(|(at, _)| now.saturating_duration_since(at) <= READY_PING_AGE);
+        self.synced && ping_recent
+    }
+
+    fn to_json(&self, ready: bool) -> String {
+        format!(
+            r#"{{"status":"{}","synced":{},"tip_height":{},"last_ping_ms":{}}}"#,
+            if ready { "ok" } else { "degraded" },
+            self.synced,
+            self.tip_height
+                .map_or("null".to_string(), |height| height.to_string()),
+            self.last_ping
+                .map_or("null".to_string(), |(_, rtt)| rtt.as_millis().to_string()),
+        )
+    }
+}
+
+// Health endpoint started by the builder, stopped when the last `BlockTalk`
+// sharing it is dropped or disconnected
+pub(crate) struct HealthTask {
+    handle: JoinHandle<Result<(), BlockTalkError>>,
+}
+
+impl HealthTask {
+    pub(crate) fn new(handle: JoinHandle<Result<(), BlockTalkError>>) -> Self {
+        Self { handle }
+    }
+
+    // Stop the endpoint and wait for it to let go of the connection
+    pub(crate) async fn stop(mut self) {
+        self.handle.abort();
+        let _ = (&mut self.handle).await;
+    }
+}
+
+impl Drop for HealthTask {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+// Serve `/health/live` and `/health/ready` on `addr`, polling the node in the
+// background for the readiness check
+pub(crate) fn spawn(
+    addr: SocketAddr,
+    connection: Arc<Connection>,
+    chain: Arc<dyn ChainInterface>,
+) -> JoinHandle<Result<(), BlockTalkError>> {
+    tokio::task::spawn_local(async move {
+        let listener = std::net::TcpListener::bind(addr).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        })?;
+        // Check the node once before serving, so the first probe gets an answer
+        let state = Arc::new(Mutex::new(HealthState::default()));
+        check_node(&state, &connection, chain.as_ref()).await;
+
+        let service_state = state.clone();
+        let server = Server::from_tcp(listener)
+            .map_err(|e| BlockTalkError::Connection(e.to_string()))?
+            .serve(make_service_fn(move |_| {
+                let state = service_state.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let response = respond(&request, &state);
+                        async move { Ok::<_, Infallible>(response) }
+                    }))
+                }
+            }));
+        log::info!("Serving health checks on {}", addr);
+
+        tokio::select! {
+            result = server => result.map_err(|e| {
+                log::error!("Health endpoint failed: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            }),
+            _ = poll_node(&state, &connection, chain.as_ref()) => Ok(()),
+        }
+    })
+}
+
+// Keep `state` up to date with the node's responsiveness and sync status
+async fn poll_node(
+    state: &Mutex<HealthState>,
+    connection: &Connection,
+    chain: &dyn ChainInterface,
+) {
+    let mut ticker =
+        tokio::time::interval_at(tokio::time::Instant::now() + POLL_INTERVAL, POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        check_node(state, connection, chain).await;
+    }
+}
+
+async fn check_node(
+    state: &Mutex<HealthState>,
+    connection: &Connection,
+    chain: &dyn ChainInterface,
+) {
+    let sent = Instant::now();
+    let ping = connection.ping().await;
+    let chaininfo = chain.get_chaininfo().await;
+
+    let mut state = state.lock().unwrap();
+    match ping {
+        Ok(rtt) => state.last_ping = Some((sent, rtt)),
+        Err(e) => log::warn!("Health check ping failed: {}", e),
+    }
+    match chaininfo {
+        Ok(info) => {
+            state.synced = info.is_synced;
+            state.tip_height = Some(info.height);
+        }
+        Err(e) => {
+            log::warn!("Health check could not get chain info: {}", e);
+            state.synced = false;
+        }
+    }
+}
+
+fn respond(request: &Request<Body>, state: &Mutex<HealthState>) -> Response<Body> {
+    let state = state.lock().unwrap();
+    let ready = state.is_ready(Instant::now());
+    let status = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/health/live") => StatusCode::OK,
+        (&Method::GET, "/health/ready") if ready => StatusCode::OK,
+        (&Method::GET, "/health/ready") => StatusCode::SERVICE_UNAVAILABLE,
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(state.to_json(ready)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockTalk, InProcessConnectionProvider};
+
+    // Address on localhost that nothing is listening on
+    fn free_addr() -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    async fn connect(provider: InProcessConnectionProvider, addr: SocketAddr) -> BlockTalk {
+        BlockTalk::builder()
+            .socket_path("in-process")
+            .connection_provider(Box::new(provider))
+            .health_endpoint(addr)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    // Status code and body of `GET path`, once the endpoint is listening
+    async fn get(addr: SocketAddr, path: &str) -> (u16, serde_json::Value) {
+        let url = format!("http://{}{}", addr, path);
+        for _ in 0..50 {
+            if let Ok(response) = reqwest::get(&url).await {
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap();
+                return (status, serde_json::from_str(&body).unwrap());
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("health endpoint did not start on {}", addr);
+    }
+
+    #[test]
+    fn test_readiness_needs_recent_ping() {
+        let now = Instant::now();
+        let mut state = HealthState {
+            synced: true,
+            tip_height: Some(100),
+            last_ping: Some((now, Duration::from_millis(3))),
+        };
+        assert!(state.is_ready(now));
+        assert_eq!(
+            state.to_json(true),
+            r#"{"status":"ok","synced":true,"tip_height":100,"last_ping_ms":3}"#
+        );
+        assert!(!state.is_ready(now + READY_PING_AGE + Duration::from_secs(1)));
+
+        state.synced = false;
+        assert!(!state.is_ready(now));
+        assert_eq!(
+            HealthState::default().to_json(false),
+            r#"{"status":"degraded","synced":false,"tip_height":null,"last_ping_ms":null}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_healthy_node() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let addr = free_addr();
+                let blocktalk = connect(InProcessConnectionProvider::new(), addr).await;
+
+                let (status, body) = get(addr, "/health/live").await;
+                assert_eq!(status, 200);
+                assert_eq!(body["status"], "ok");
+                assert_eq!(body["synced"], true);
+                assert_eq!(body["tip_height"], 0);
+                assert!(body["last_ping_ms"].is_u64());
+
+                let (status, body) = get(addr, "/health/ready").await;
+                assert_eq!(status, 200);
+                assert_eq!(body["status"], "ok");
+
+                let response = reqwest::get(format!("http://{}/health", addr))
+                    .await
+                    .unwrap();
+                assert_eq!(response.status().as_u16(), 404);
+                blocktalk.disconnect().await.unwrap();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_degraded_node() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                // A node still syncing answers pings but is not ready
+                let addr = free_addr();
+                let provider = InProcessConnectionProvider::new().with_initial_block_download(true);
+                let _syncing = connect(provider, addr).await;
+
+                let (status, body) = get(addr, "/health/ready").await;
+                assert_eq!(status, 503);
+                assert_eq!(body["status"], "degraded");
+                assert_eq!(body["synced"], false);
+                assert!(body["last_ping_ms"].is_u64());
+                let (status, _) = get(addr, "/health/live").await;
+                assert_eq!(status, 200);
+
+                // A node that doesn't answer is not ready either
+                let addr = free_addr();
+                let provider =
+                    InProcessConnectionProvider::new().with_failure("database corrupted");
+                let _failing = connect(provider, addr).await;
+
+                let (status, body) = get(addr, "/health/ready").await;
+                assert_eq!(status, 503);
+                assert_eq!(body["status"], "degraded");
+                assert!(body["last_ping_ms"].is_null());
+                assert!(body["tip_height"].is_null());
+                let (status, _) = get(addr, "/health/live").await;
+                assert_eq!(status, 200);
+            })
+            .await;
+    }
+}
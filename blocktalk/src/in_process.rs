@@ -0,0 +1,957 @@
+use bitcoin::bip158::{self, BlockFilterWriter};
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, Network, OutPoint, ScriptBuf, Transaction};
+use capnp::capability::Promise;
+use capnp_rpc::{pry, rpc_twoparty_capnp, twoparty, RpcSystem};
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use crate::chain_capnp::chain;
+use crate::init_capnp::init;
+use crate::mining_capnp::mining;
+use crate::proxy_capnp::{thread, thread_map};
+use crate::{BlockTalkError, ConnectionProvider};
+
+// Bytes buffered in each direction of the in-process pipe
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Connects to a node served in the same process over an in-memory pipe, for
+/// testing code that talks to a node without running one
+///
+/// The node answers the chain, sync and mempool requests `Blockchain` and
+/// `Mempool` make most often from the blocks and transactions it is built
+/// with. Any other request fails as unimplemented, and mining requests are
+/// not served at all. Each connection gets its own copy of the node, so
+/// transactions broadcast over one connection are not seen by another.
+#[derive(Clone)]
+pub struct InProcessConnectionProvider {
+    node: NodeState,
+}
+
+#[derive(Clone)]
+struct NodeState {
+    blocks: Vec<Block>,
+    stale_blocks: Vec<Block>,
+    mempool: Vec<Transaction>,
+    initial_block_download: bool,
+    block_filter_index: bool,
+    broadcast_rejection: Option<String>,
+    failure: Option<String>,
+}
+
+impl InProcessConnectionProvider {
+    /// A synced node whose chain is just the regtest genesis block
+    pub fn new() -> Self {
+        Self {
+            node: NodeState {
+                blocks: vec![genesis_block(Network::Regtest)],
+                stale_blocks: Vec::new(),
+                mempool: Vec::new(),
+                initial_block_download: false,
+                block_filter_index: false,
+                broadcast_rejection: None,
+                failure: None,
+            },
+        }
+    }
+
+    /// Serve `blocks` as the active chain, starting from the genesis block
+    /// The blocks are not validated, so they only need to link up as far as
+    /// the code under test checks.
+    pub fn with_blocks(mut self, blocks: Vec<Block>) -> Self {
+        self.node.blocks = blocks;
+        self
+    }
+
+    /// Also know `blocks`, which are not in the active chain, as a node keeps
+    /// the blocks of forks it has seen
+    /// Each must build on a block of the active chain or on another of `blocks`.
+    pub fn with_stale_blocks(mut self, blocks: Vec<Block>) -> Self {
+        self.node.stale_blocks = blocks;
+        self
+    }
+
+    /// Start with `transactions` in the mempool
+    pub fn with_mempool(mut self, transactions: Vec<Transaction>) -> Self {
+        self.node.mempool = transactions;
+        self
+    }
+
+    /// Report the node as still in initial block download
+    pub fn with_initial_block_download(mut self, initial_block_download: bool) -> Self {
+        self.node.initial_block_download = initial_block_download;
+        self
+    }
+
+    /// Report whether the node keeps a block filter index, and serve basic
+    /// filter matches from it if so
+    /// The filters cover the output scripts of each block and the scripts of
+    /// the outputs it spends, where those are in earlier blocks.
+    pub fn with_block_filter_index(mut self, block_filter_index: bool) -> Self {
+        self.node.block_filter_index = block_filter_index;
+        self
+    }
+
+    /// Reject every broadcast transaction with `reason`
+    pub fn with_broadcast_rejection(mut self, reason: &str) -> Self {
+        self.node.broadcast_rejection = Some(reason.to_string());
+        self
+    }
+
+    /// Fail every chain request with `message`, as a node in trouble would
+    pub fn with_failure(mut self, message: &str) -> Self {
+        self.node.failure = Some(message.to_string());
+        self
+    }
+
+    // Serve the node to the client at the other end of `reader` and `writer`
+    pub(crate) fn serve<R, W>(&self, reader: R, writer: W)
+    where
+        R: tokio::io::AsyncRead + Unpin + 'static,
+        W: tokio::io::AsyncWrite + Unpin + 'static,
+    {
+        let network = twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Server,
+            Default::default(),
+        );
+        let init: init::Client = capnp_rpc::new_client(InProcessInit {
+            node: self.node.clone(),
+        });
+        tokio::task::spawn_local(RpcSystem::new(Box::new(network), Some(init.client)));
+    }
+}
+
+impl Default for InProcessConnectionProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl ConnectionProvider for InProcessConnectionProvider {
+    async fn create_network(
+        &self,
+        path: &str,
+    ) -> Result<Box<dyn capnp_rpc::VatNetwork<twoparty::VatId>>, BlockTalkError> {
+        log::debug!("Serving in-process node for {}", path);
+        let (client_stream, server_stream) = tokio::io::duplex(PIPE_CAPACITY);
+
+        let (reader, writer) = tokio::io::split(server_stream);
+        self.serve(reader, writer);
+
+        let (reader, writer) = tokio::io::split(client_stream);
+        Ok(Box::new(twoparty::VatNetwork::new(
+            reader.compat(),
+            writer.compat_write(),
+            rpc_twoparty_capnp::Side::Client,
+            Default::default(),
+        )))
+    }
+}
+
+struct InProcessInit {
+    node: NodeState,
+}
+
+impl init::Server for InProcessInit {
+    fn construct(
+        &mut self,
+        _: init::ConstructParams,
+        mut results: init::ConstructResults,
+    ) -> Promise<(), capnp::Error> {
+        results
+            .get()
+            .set_thread_map(capnp_rpc::new_client(InProcessThreadMap));
+        Promise::ok(())
+    }
+
+    fn make_chain(
+        &mut self,
+        _: init::MakeChainParams,
+        mut results: init::MakeChainResults,
+    ) -> Promise<(), capnp::Error> {
+        results
+            .get()
+            .set_result(capnp_rpc::new_client(InProcessChain {
+                node: self.node.clone(),
+            }));
+        Promise::ok(())
+    }
+
+    fn make_mining(
+        &mut self,
+        _: init::MakeMiningParams,
+        mut results: init::MakeMiningResults,
+    ) -> Promise<(), capnp::Error> {
+        results
+            .get()
+            .set_result(capnp_rpc::new_client(InProcessMining));
+        Promise::ok(())
+    }
+}
+
+struct InProcessThreadMap;
+
+impl thread_map::Server for InProcessThreadMap {
+    fn make_thread(
+        &mut self,
+        _: thread_map::MakeThreadParams,
+        mut results: thread_map::MakeThreadResults,
+    ) -> Promise<(), capnp::Error> {
+        results
+            .get()
+            .set_result(capnp_rpc::new_client(InProcessThread));
+        Promise::ok(())
+    }
+}
+
+struct InProcessThread;
+
+impl thread::Server for InProcessThread {}
+
+struct InProcessMining;
+
+impl mining::Server for InProcessMining {}
+
+struct InProcessChain {
+    node: NodeState,
+}
+
+impl InProcessChain {
+    fn check_failure(&self) -> Result<(), capnp::Error> {
+        match &self.node.failure {
+            Some(message) => Err(capnp::Error::failed(message.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn block_height(&self, hash: &[u8]) -> Option<usize> {
+        self.node
+            .blocks
+            .iter()
+            .position(|block| block.block_hash().as_byte_array() == hash)
+    }
+
+    // A stale block and its height, one more than its parent's
+    fn stale_block(&self, hash: &[u8]) -> Option<(usize, &Block)> {
+        let block = self
+            .node
+            .stale_blocks
+            .iter()
+            .find(|block| block.block_hash().as_byte_array() == hash)?;
+        let parent = block.header.prev_blockhash.to_byte_array();
+        let parent_height = self
+            .block_height(&parent)
+            .or_else(|| self.stale_block(&parent).map(|(height, _)| height))?;
+        Some((parent_height + 1, block))
+    }
+
+    // Script of the output spent by `outpoint`, if it is in a block before `height`
+    fn spent_script(&self, height: usize, outpoint: &OutPoint) -> Option<ScriptBuf> {
+        self.node.blocks[..height]
+            .iter()
+            .flat_map(|block| &block.txdata)
+            .find(|tx| tx.compute_txid() == outpoint.txid)
+            .and_then(|tx| tx.output.get(outpoint.vout as usize))
+            .map(|output| output.script_pubkey.clone())
+    }
+
+    // BIP158 basic filter of the block at `height`. Spends of outputs the node
+    // doesn't have add an empty script, which matches nothing.
+    fn basic_filter(&self, height: usize) -> Result<bip158::BlockFilter, capnp::Error> {
+        let block = &self.node.blocks[height];
+        let mut content = Vec::new();
+        let mut writer = BlockFilterWriter::new(&mut content, block);
+        writer.add_output_scripts();
+        writer
+            .add_input_scripts(|outpoint| {
+                Ok::<_, bip158::Error>(self.spent_script(height, outpoint).unwrap_or_default())
+            })
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        writer
+            .finish()
+            .map_err(|e| capnp::Error::failed(e.to_string()))?;
+        Ok(bip158::BlockFilter::new(&content))
+    }
+
+    fn in_mempool(&self, txid: &[u8]) -> bool {
+        self.node
+            .mempool
+            .iter()
+            .any(|tx| tx.compute_txid().as_byte_array() == txid)
+    }
+}
+
+impl chain::Server for InProcessChain {
+    fn get_height(
+        &mut self,
+        _: chain::GetHeightParams,
+        mut results: chain::GetHeightResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        results.get().set_result(self.node.blocks.len() as i32 - 1);
+        results.get().set_has_result(!self.node.blocks.is_empty());
+        Promise::ok(())
+    }
+
+    fn get_block_hash(
+        &mut self,
+        params: chain::GetBlockHashParams,
+        mut results: chain::GetBlockHashResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let height = pry!(params.get()).get_height();
+        let block = usize::try_from(height)
+            .ok()
+            .and_then(|height| self.node.blocks.get(height));
+        match block {
+            Some(block) => {
+                results.get().set_result(block.block_hash().as_ref());
+                Promise::ok(())
+            }
+            None => Promise::err(capnp::Error::failed(format!(
+                "No block at height {}",
+                height
+            ))),
+        }
+    }
+
+    fn find_block(
+        &mut self,
+        params: chain::FindBlockParams,
+        mut results: chain::FindBlockResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let params = pry!(params.get());
+        let hash = pry!(params.get_hash());
+        let active_height = self.block_height(hash);
+        let want_next = params
+            .get_block()
+            .map(|block| block.has_next_block())
+            .unwrap_or(false);
+        let block = active_height
+            .map(|height| (height, &self.node.blocks[height]))
+            .or_else(|| self.stale_block(hash));
+        let mut found = results.get().init_block();
+        if let Some((height, block)) = block {
+            found.set_hash(block.block_hash().as_ref());
+            found.set_height(height as i32);
+            found.set_time(block.header.time as i64);
+            found.set_in_active_chain(i64::from(active_height.is_some()));
+            found.set_data(&bitcoin::consensus::serialize(block));
+            found.set_found(true);
+            let next = active_height.and_then(|height| self.node.blocks.get(height + 1));
+            if let Some(next) = next.filter(|_| want_next) {
+                let mut next_found = found.reborrow().init_next_block();
+                next_found.set_hash(next.block_hash().as_ref());
+                next_found.set_height(height as i32 + 1);
+                next_found.set_found(true);
+            }
+        }
+        results.get().set_result(block.is_some());
+        Promise::ok(())
+    }
+
+    fn find_ancestor_by_height(
+        &mut self,
+        params: chain::FindAncestorByHeightParams,
+        mut results: chain::FindAncestorByHeightResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let params = pry!(params.get());
+        let tip = self.block_height(pry!(params.get_block_hash()));
+        let ancestor_height = params.get_ancestor_height();
+        let ancestor = tip.and_then(|tip| {
+            usize::try_from(ancestor_height)
+                .ok()
+                .filter(|height| *height <= tip)
+        });
+        let mut found = results.get().init_ancestor();
+        if let Some(height) = ancestor {
+            let block = &self.node.blocks[height];
+            found.set_hash(block.block_hash().as_ref());
+            found.set_height(height as i32);
+            found.set_data(&bitcoin::consensus::serialize(block));
+            found.set_found(true);
+        }
+        results.get().set_result(ancestor.is_some());
+        Promise::ok(())
+    }
+
+    fn is_initial_block_download(
+        &mut self,
+        _: chain::IsInitialBlockDownloadParams,
+        mut results: chain::IsInitialBlockDownloadResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        results.get().set_result(self.node.initial_block_download);
+        Promise::ok(())
+    }
+
+    fn has_block_filter_index(
+        &mut self,
+        params: chain::HasBlockFilterIndexParams,
+        mut results: chain::HasBlockFilterIndexResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let basic = pry!(params.get()).get_filter_type() == 0;
+        results
+            .get()
+            .set_result(basic && self.node.block_filter_index);
+        Promise::ok(())
+    }
+
+    fn block_filter_matches_any(
+        &mut self,
+        params: chain::BlockFilterMatchesAnyParams,
+        mut results: chain::BlockFilterMatchesAnyResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let params = pry!(params.get());
+        let height = self
+            .block_height(pry!(params.get_block_hash()))
+            .filter(|_| self.node.block_filter_index && params.get_filter_type() == 0);
+        let Some(height) = height else {
+            results.get().set_has_result(false);
+            return Promise::ok(());
+        };
+        let filter = pry!(self.basic_filter(height));
+        let block_hash = self.node.blocks[height].block_hash();
+        let filter_set = pry!(params.get_filter_set());
+        let matched = pry!(filter
+            .match_any(&block_hash, filter_set.iter().flatten())
+            .map_err(|e| capnp::Error::failed(e.to_string())));
+        results.get().set_result(matched);
+        results.get().set_has_result(true);
+        Promise::ok(())
+    }
+
+    fn is_in_mempool(
+        &mut self,
+        params: chain::IsInMempoolParams,
+        mut results: chain::IsInMempoolResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let txid = pry!(pry!(params.get()).get_txid());
+        results.get().set_result(self.in_mempool(txid));
+        Promise::ok(())
+    }
+
+    fn has_descendants_in_mempool(
+        &mut self,
+        params: chain::HasDescendantsInMempoolParams,
+        mut results: chain::HasDescendantsInMempoolResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let txid = pry!(pry!(params.get()).get_txid());
+        let has_descendants = self.node.mempool.iter().any(|tx| {
+            tx.input
+                .iter()
+                .any(|input| input.previous_output.txid.as_byte_array() == txid)
+        });
+        results.get().set_result(has_descendants);
+        Promise::ok(())
+    }
+
+    fn broadcast_transaction(
+        &mut self,
+        params: chain::BroadcastTransactionParams,
+        mut results: chain::BroadcastTransactionResults,
+    ) -> Promise<(), capnp::Error> {
+        pry!(self.check_failure());
+        let tx: Transaction = pry!(bitcoin::consensus::deserialize(pry!(
+            pry!(params.get()).get_tx()
+        ))
+        .map_err(|e| capnp::Error::failed(e.to_string())));
+        if let Some(reason) = &self.node.broadcast_rejection {
+            results.get().set_error(reason.as_str());
+            results.get().set_result(false);
+            return Promise::ok(());
+        }
+        if !self.in_mempool(tx.compute_txid().as_byte_array()) {
+            self.node.mempool.push(tx);
+        }
+        results.get().set_result(true);
+        Promise::ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ChainErrorKind;
+    use crate::{Blockchain, ChainInterface, Connection, Mempool, MempoolInterface};
+    use bitcoin::{Amount, BlockHash, Sequence, TxIn, TxOut, Witness};
+    use std::sync::Arc;
+    use tokio_stream::StreamExt;
+
+    // Regtest chain of `length` blocks. Only the first is valid, the rest just
+    // link to the block before them.
+    fn chain_of(length: usize) -> Vec<Block> {
+        let mut blocks = vec![genesis_block(Network::Regtest)];
+        while blocks.len() < length {
+            let parent = blocks.last().unwrap();
+            let mut block = parent.clone();
+            block.header.prev_blockhash = parent.block_hash();
+            block.header.time = parent.header.time + 600;
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    // Transaction spending output 0 of `parent`
+    fn spend(parent: &Transaction) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(parent.compute_txid(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::from_sat(1000),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    async fn connect(provider: InProcessConnectionProvider) -> Arc<Connection> {
+        Connection::connect("in-process", Box::new(provider))
+            .await
+            .unwrap()
+    }
+
+    async fn mempool(provider: InProcessConnectionProvider) -> Mempool {
+        let connection = connect(provider).await;
+        Mempool::new(
+            connection.chain_client().clone(),
+            connection.thread().clone(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_ping() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let connection = connect(InProcessConnectionProvider::new()).await;
+                assert!(connection.ping().await.is_ok());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_tip_of_genesis() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::new(connect(InProcessConnectionProvider::new()).await);
+                let genesis = genesis_block(Network::Regtest);
+                assert_eq!(chain.get_tip().await.unwrap(), (0, genesis.block_hash()));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_tip_and_blocks() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(4);
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks.clone());
+                let chain = Blockchain::new(connect(provider).await);
+
+                let (height, tip) = chain.get_tip().await.unwrap();
+                assert_eq!((height, tip), (3, blocks[3].block_hash()));
+                assert_eq!(chain.get_block(&tip, 2).await.unwrap(), blocks[2]);
+                assert_eq!(chain.get_genesis_block().await.unwrap(), blocks[0]);
+                assert_eq!(
+                    chain.get_block_hash(1).await.unwrap(),
+                    blocks[1].block_hash()
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_block_hash_beyond_tip() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::new(connect(InProcessConnectionProvider::new()).await);
+                let result = chain.get_block_hash(1).await;
+                assert!(matches!(
+                    result,
+                    Err(BlockTalkError::Chain {
+                        kind: ChainErrorKind::BlockNotFound,
+                        ..
+                    })
+                ));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_block_by_hash() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(3);
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks.clone());
+                let chain = Blockchain::new(connect(provider).await);
+
+                let block = chain.get_block_by_hash(&blocks[1].block_hash()).await;
+                assert_eq!(block.unwrap(), Some(blocks[1].clone()));
+                let missing = chain.get_block_by_hash(&BlockHash::all_zeros()).await;
+                assert_eq!(missing.unwrap(), None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_block_verbose() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(8);
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks[..5].to_vec());
+                let chain = Blockchain::new(connect(provider).await);
+
+                // Confirmations count down from the genesis block to the tip
+                for (height, block) in blocks[..5].iter().enumerate() {
+                    let details = chain
+                        .get_block_verbose(&block.block_hash())
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    assert_eq!(details.block, *block);
+                    assert_eq!(details.height, height as i32);
+                    assert_eq!(details.confirmations, 5 - height as i32);
+                    assert_eq!(details.chain_work, None);
+                    let next = blocks[..5].get(height + 1).map(Block::block_hash);
+                    assert_eq!(details.next_block_hash, next);
+                }
+
+                // With the tip three blocks further on, the same block has three more
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks.clone());
+                let longer = Blockchain::new(connect(provider).await);
+                let details = longer
+                    .get_block_verbose(&blocks[2].block_hash())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(details.confirmations, 6);
+                assert_eq!(details.next_block_hash, Some(blocks[3].block_hash()));
+
+                // Chain work is filled in once it has been worked out
+                let work = chain.get_chain_work(&blocks[4].block_hash()).await.unwrap();
+                let details = chain
+                    .get_block_verbose(&blocks[4].block_hash())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(details.chain_work, Some(work));
+
+                let missing = chain.get_block_verbose(&BlockHash::all_zeros()).await;
+                assert_eq!(missing.unwrap(), None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_block_locator() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(30);
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks.clone());
+                let chain = Blockchain::new(connect(provider).await);
+
+                let locator = chain.get_block_locator().await.unwrap();
+                let heights = [29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 16, 12, 4, 0];
+                let expected = heights
+                    .iter()
+                    .map(|height| blocks[*height].block_hash())
+                    .collect::<Vec<_>>();
+                assert_eq!(locator, expected);
+                assert_eq!(chain.get_block_locator().await.unwrap(), expected);
+
+                let genesis = Blockchain::new(connect(InProcessConnectionProvider::new()).await);
+                assert_eq!(
+                    genesis.get_block_locator().await.unwrap(),
+                    vec![blocks[0].block_hash()]
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_block_ancestors() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(101);
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks.clone());
+                let chain = Blockchain::new(connect(provider).await);
+                let expected = |heights: std::ops::RangeInclusive<usize>| {
+                    heights
+                        .map(|height| (height as i32, blocks[height].block_hash()))
+                        .collect::<Vec<_>>()
+                };
+
+                let ancestors = chain
+                    .get_block_ancestors(&blocks[100].block_hash(), 50)
+                    .await
+                    .unwrap();
+                assert_eq!(ancestors, expected(50..=100));
+
+                // Stops at the genesis block
+                let ancestors = chain
+                    .get_block_ancestors(&blocks[10].block_hash(), 50)
+                    .await
+                    .unwrap();
+                assert_eq!(ancestors, expected(0..=10));
+                let ancestors = chain
+                    .get_block_ancestors(&blocks[10].block_hash(), 0)
+                    .await
+                    .unwrap();
+                assert_eq!(ancestors, expected(10..=10));
+
+                assert!(chain
+                    .get_block_ancestors(&BlockHash::all_zeros(), 5)
+                    .await
+                    .is_err());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_scan_blocks_for_scripts() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let script = ScriptBuf::from_bytes(vec![0x00, 0x14, 0x01, 0x02]);
+                let mut payment = spend(&genesis_block(Network::Regtest).txdata[0]);
+                payment.output[0].script_pubkey = script.clone();
+                let mut blocks = chain_of(6);
+                blocks[2].txdata.push(payment.clone());
+                blocks[4].txdata.push(spend(&payment));
+
+                for block_filter_index in [true, false] {
+                    let provider = InProcessConnectionProvider::new()
+                        .with_blocks(blocks.clone())
+                        .with_block_filter_index(block_filter_index);
+                    let chain = Blockchain::new(connect(provider).await);
+                    assert_eq!(
+                        chain.has_block_filter_index().await.unwrap(),
+                        block_filter_index
+                    );
+
+                    let matches = chain
+                        .scan_blocks_for_scripts(0, 5, &[script.clone()])
+                        .await
+                        .unwrap();
+                    assert_eq!(matches.len(), 1);
+                    assert_eq!(matches[0].height, 2);
+                    assert_eq!(matches[0].txid, payment.compute_txid());
+                    assert_eq!(matches[0].vout, 0);
+                    assert_eq!(matches[0].amount, Amount::from_sat(1000));
+
+                    // With an index only the payment and the spend are fetched
+                    let heights = chain
+                        .matching_block_stream(0, 5, vec![script.clone()])
+                        .map(|result| result.unwrap().0)
+                        .collect::<Vec<_>>()
+                        .await;
+                    let expected = if block_filter_index {
+                        vec![2, 4]
+                    } else {
+                        (0..=5).collect()
+                    };
+                    assert_eq!(heights, expected);
+                }
+
+                let provider = InProcessConnectionProvider::new().with_block_filter_index(true);
+                let chain = Blockchain::new(connect(provider).await);
+                let missing = chain
+                    .block_filter_matches_any(&BlockHash::all_zeros(), &[script])
+                    .await;
+                assert_eq!(missing.unwrap(), None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_is_in_best_chain() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(2);
+                let provider = InProcessConnectionProvider::new().with_blocks(blocks.clone());
+                let chain = Blockchain::new(connect(provider).await);
+
+                assert!(chain
+                    .is_in_best_chain(&blocks[1].block_hash())
+                    .await
+                    .unwrap());
+                assert!(!chain
+                    .is_in_best_chain(&BlockHash::all_zeros())
+                    .await
+                    .unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_block_height() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let blocks = chain_of(4);
+                // A competing block at height 2, on a fork the node has seen
+                let mut stale = blocks[2].clone();
+                stale.header.time += 1;
+                let provider = InProcessConnectionProvider::new()
+                    .with_blocks(blocks.clone())
+                    .with_stale_blocks(vec![stale.clone()]);
+                let chain = Blockchain::new(connect(provider).await);
+
+                for (height, block) in blocks.iter().enumerate() {
+                    let found = chain.get_block_height(&block.block_hash()).await.unwrap();
+                    assert_eq!(found, Some(height as i32));
+                }
+                let found = chain.get_block_height(&stale.block_hash()).await.unwrap();
+                assert_eq!(found, None);
+                let found = chain
+                    .get_block_height(&BlockHash::all_zeros())
+                    .await
+                    .unwrap();
+                assert_eq!(found, None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_is_synced() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let chain = Blockchain::new(connect(InProcessConnectionProvider::new()).await);
+                assert!(chain.is_synced().await.unwrap());
+
+                let provider = InProcessConnectionProvider::new().with_initial_block_download(true);
+                let chain = Blockchain::new(connect(provider).await);
+                assert!(!chain.is_synced().await.unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_failing_node() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let provider = InProcessConnectionProvider::new().with_failure("database corrupted");
+                let connection = connect(provider).await;
+                assert!(matches!(
+                    connection.ping().await,
+                    Err(BlockTalkError::Connection(ref message)) if message.contains("database corrupted")
+                ));
+
+                let chain = Blockchain::new(connection.clone());
+                assert!(chain.get_tip().await.is_err());
+                assert!(chain.is_synced().await.is_err());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_is_in_mempool() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let parent = genesis_block(Network::Regtest).txdata[0].clone();
+                let tx = spend(&parent);
+                let mempool =
+                    mempool(InProcessConnectionProvider::new().with_mempool(vec![tx.clone()]))
+                        .await;
+
+                assert!(mempool.is_in_mempool(&tx.compute_txid()).await.unwrap());
+                assert!(!mempool.is_in_mempool(&parent.compute_txid()).await.unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_has_descendants_in_mempool() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let parent = genesis_block(Network::Regtest).txdata[0].clone();
+                let child = spend(&parent);
+                let mempool =
+                    mempool(InProcessConnectionProvider::new().with_mempool(vec![child.clone()]))
+                        .await;
+
+                assert!(mempool
+                    .has_descendants_in_mempool(&parent.compute_txid())
+                    .await
+                    .unwrap());
+                assert!(!mempool
+                    .has_descendants_in_mempool(&child.compute_txid())
+                    .await
+                    .unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_transaction_accepted() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let tx = spend(&genesis_block(Network::Regtest).txdata[0]);
+                let mempool = mempool(InProcessConnectionProvider::new()).await;
+
+                assert!(!mempool.is_in_mempool(&tx.compute_txid()).await.unwrap());
+                let (error, accepted) = mempool.broadcast_transaction(&tx, 0, true).await.unwrap();
+                assert!(accepted);
+                assert_eq!(error, "");
+                assert!(mempool.is_in_mempool(&tx.compute_txid()).await.unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_transaction_rejected() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let tx = spend(&genesis_block(Network::Regtest).txdata[0]);
+                let provider = InProcessConnectionProvider::new()
+                    .with_broadcast_rejection("min relay fee not met");
+                let mempool = mempool(provider).await;
+
+                let (error, accepted) = mempool.broadcast_transaction(&tx, 0, true).await.unwrap();
+                assert!(!accepted);
+                assert_eq!(error, "min relay fee not met");
+                assert!(!mempool.is_in_mempool(&tx.compute_txid()).await.unwrap());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_unserved_request_fails() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let mempool = mempool(InProcessConnectionProvider::new()).await;
+                assert!(mempool.estimate_fee_rate(6, false).await.is_err());
+            })
+            .await;
+    }
+}
@@ -1,51 +1,112 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 
+#[cfg(feature = "admin")]
+mod admin;
+mod block_filter;
+mod builder;
 mod chain;
+mod chain_stats;
+mod concurrent;
 mod connection;
 mod error;
 mod generated;
+#[cfg(feature = "health")]
+mod health;
+mod in_process;
+mod logging;
 mod mempool;
+mod network;
 mod notification;
+#[cfg(feature = "serde")]
+mod notification_serde;
 mod mining;
+mod request_metrics;
+mod sync;
+mod types;
 
+#[cfg(feature = "admin")]
+pub use admin::{Admin, AdminInterface, TestHarness};
 pub use bitcoin::BlockHash;
-pub use chain::{Blockchain, ChainInterface};
-pub use connection::{Connection, ConnectionProvider, UnixConnectionProvider};
-pub use mining::{MiningInterface, Mining};
-pub use error::BlockTalkError;
+pub use block_filter::BlockFilter;
+pub use builder::{BlockTalkBuilder, ReconnectPolicy, DEFAULT_CONNECTION_TIMEOUT};
+pub use chain::{
+    chain_work_from_bytes, verify_tx_merkle_proof, Blockchain, ChainInfo, ChainInterface,
+    ChainUpdate, TxMerkleProof, DEFAULT_BLOCK_STREAM_BUFFER, DEFAULT_TIP_CACHE_TTL,
+};
+pub use chain_stats::{block_subsidy, BlockStats};
+pub use concurrent::{ConcurrentChainInterface, DEFAULT_MAX_IN_FLIGHT};
+pub use connection::{
+    Connection, ConnectionProvider, TcpConnectionProvider, UnixConnectionProvider,
+};
+#[cfg(feature = "socks5")]
+pub use connection::Socks5ConnectionProvider;
+pub use mining::{MiningInterface, Mining, DEFAULT_BLOCK_RESERVED_WEIGHT};
+pub use error::{BlockTalkError, BlockTalkResultExt};
 pub use generated::*;
-pub use mempool::{Mempool, MempoolInterface, TransactionAncestry};
+pub use in_process::InProcessConnectionProvider;
+pub use logging::{BlockTalkLogConfig, BlockTalkLogger};
+pub use mempool::{
+    FeeHistogramBucket, Mempool, MempoolAcceptResult, MempoolEntry, MempoolInfo, MempoolInterface,
+    PackageAcceptResult, TransactionAncestry,
+};
+pub use network::{NetworkInfo, NetworkInterface, NodeNetwork};
 pub use notification::ChainNotification;
-pub use notification::NotificationHandler;
+pub use notification::{
+    AddressEvent, AddressSubscription, FilteredNotificationHandler, HandlerId, NotificationFilter,
+    NotificationHandler, DEFAULT_NOTIFICATION_CHANNEL_CAPACITY,
+};
+pub use sync::SyncProgress;
+pub use types::{BlockDetails, ScriptMatch};
 
 #[derive(Clone)]
 pub struct BlockTalk {
     connection: Arc<Connection>,
     chain: Arc<dyn ChainInterface>,
     mempool: Arc<dyn MempoolInterface>,
-    mining: Arc<dyn MiningInterface>
+    mining: Arc<dyn MiningInterface>,
+    network: Arc<dyn NetworkInterface>,
+    #[cfg(feature = "health")]
+    health: Option<Arc<health::HealthTask>>,
 }
 
 impl BlockTalk {
+    /// Start building a `BlockTalk` with custom options
+    pub fn builder() -> BlockTalkBuilder {
+        BlockTalkBuilder::new()
+    }
+
     pub async fn init(socket_path: &str) -> Result<Self, BlockTalkError> {
         log::info!("Initializing BlockTalk with socket path: {}", socket_path);
-        let connection = Connection::connect_default(socket_path).await?;
-        let chain = Arc::new(Blockchain::new(connection.clone()));
-        let mempool = Arc::new(Mempool::new(
-            connection.chain_client().clone(),
-            connection.thread().clone(),
-        ));
-        let mining_client = connection.mining_client();
-        let thread_client = connection.thread().clone();
-        let mining = Arc::new(Mining::new(mining_client, thread_client));
-        log::info!("BlockTalk initialized successfully");
-
-        Ok(Self {
-            connection,
-            chain,
-            mining,
-            mempool,
-        })
+        Self::builder().socket_path(socket_path).build().await
+    }
+
+    /// Connect to a node exposing its IPC interface over TCP
+    pub async fn init_tcp(addr: SocketAddr) -> Result<Self, BlockTalkError> {
+        log::info!("Initializing BlockTalk with TCP address: {}", addr);
+        Self::builder()
+            .socket_path(&addr.to_string())
+            .connection_provider(Box::new(TcpConnectionProvider::new(addr)))
+            .build()
+            .await
+    }
+
+    /// Connect to a node exposing its IPC interface over TCP, through the
+    /// SOCKS5 proxy at `proxy`, such as a Tor client's
+    /// `target` is `host:port`, and the proxy resolves the host, so it may
+    /// be an onion address.
+    #[cfg(feature = "socks5")]
+    pub async fn init_socks5(proxy: SocketAddr, target: &str) -> Result<Self, BlockTalkError> {
+        log::info!(
+            "Initializing BlockTalk with TCP address {} through SOCKS5 proxy {}",
+            target,
+            proxy
+        );
+        Self::builder()
+            .socket_path(target)
+            .connection_provider(Box::new(Socks5ConnectionProvider::new(proxy, target)))
+            .build()
+            .await
     }
 
     pub async fn init_with(
@@ -59,15 +120,57 @@ impl BlockTalk {
             "Initializing BlockTalk with socket path: {} and custom provider",
             socket_path
         );
-        let connection = Connection::connect(socket_path, chain_provider).await?;
-        log::info!("BlockTalk initialized successfully");
+        Self::builder()
+            .socket_path(socket_path)
+            .connection_provider(chain_provider)
+            .chain_interface(chain_interface)
+            .mempool_interface(mempool_interface)
+            .mining_interface(mining_interface)
+            .build()
+            .await
+    }
 
-        Ok(Self {
+    // Assemble a `BlockTalk`, creating any interface not supplied from the connection
+    pub(crate) fn from_parts(
+        connection: Arc<Connection>,
+        chain: Option<Arc<dyn ChainInterface>>,
+        mempool: Option<Arc<dyn MempoolInterface>>,
+        mining: Option<Arc<dyn MiningInterface>>,
+        network: Option<Arc<dyn NetworkInterface>>,
+    ) -> Self {
+        let chain = chain.unwrap_or_else(|| Arc::new(Blockchain::new(connection.clone())));
+        let mempool = mempool.unwrap_or_else(|| {
+            Arc::new(Mempool::new(
+                connection.chain_client().clone(),
+                connection.thread().clone(),
+            ))
+        });
+        let mining = mining.unwrap_or_else(|| {
+            Arc::new(Mining::new(
+                connection.mining_client().clone(),
+                connection.thread().clone(),
+            ))
+        });
+        let network = network.unwrap_or_else(|| {
+            Arc::new(NodeNetwork::new(
+                connection.chain_client().clone(),
+                connection.thread().clone(),
+            ))
+        });
+
+        Self {
             connection,
-            chain: chain_interface,
-            mempool: mempool_interface,
-            mining: mining_interface
-        })
+            chain,
+            mining,
+            mempool,
+            network,
+            #[cfg(feature = "health")]
+            health: None,
+        }
+    }
+
+    pub fn connection(&self) -> &Arc<Connection> {
+        &self.connection
     }
 
     pub fn chain(&self) -> &Arc<dyn ChainInterface> {
@@ -82,8 +185,74 @@ impl BlockTalk {
         &self.mining
     }
 
+    pub fn network(&self) -> &Arc<dyn NetworkInterface> {
+        &self.network
+    }
+
+    /// Submit a block solved from the node's block template
+    /// Returns `None` if the node accepted the block, or the reason it was rejected.
+    pub async fn submit_block(
+        &self,
+        block: &bitcoin::Block,
+    ) -> Result<Option<String>, BlockTalkError> {
+        Ok(self.mining.submit_block(block).await?)
+    }
+
+    /// Get the node's tip and sync status, see `ChainInterface::get_chaininfo`
+    pub async fn chaininfo(&self) -> Result<ChainInfo, BlockTalkError> {
+        self.chain.get_chaininfo().await
+    }
+
+    /// Wait for the node to finish its initial block download, checking every
+    /// `check_interval`
+    /// Fails with a node error if it is still syncing after `timeout`.
+    pub async fn wait_until_synced(
+        &self,
+        check_interval: std::time::Duration,
+        timeout: std::time::Duration,
+    ) -> Result<(), BlockTalkError> {
+        sync::wait_until_synced(self.chain.as_ref(), check_interval, timeout).await
+    }
+
+    /// Estimate how far the node has got through syncing the chain
+    pub async fn sync_progress(&self) -> Result<SyncProgress, BlockTalkError> {
+        sync::sync_progress(self.chain.as_ref()).await
+    }
+
+    /// Check the node is responding, returning the round trip time
+    pub async fn ping(&self) -> Result<std::time::Duration, BlockTalkError> {
+        self.connection.ping().await
+    }
+
+    /// Log BlockTalk's `module`, such as `chain` or `connection`, at `level`
+    /// from now on
+    /// Levels are shared by every `BlockTalk` in the process, and only take
+    /// effect with `BlockTalkLogger` installed as the logger.
+    pub fn set_log_level(module: &str, level: log::LevelFilter) {
+        logging::set_module_level(module, level);
+    }
+
+    /// Serve HTTP liveness and readiness probes on `addr`
+    /// `GET /health/live` answers 200 for as long as the endpoint runs, and
+    /// `GET /health/ready` answers 200 only while the node is synced and
+    /// answered a ping in the last 10 seconds, 503 otherwise. Both return
+    /// `{"status", "synced", "tip_height", "last_ping_ms"}` as JSON. The node
+    /// is checked every 5 seconds from the current `LocalSet`, and the
+    /// endpoint runs until the handle is aborted.
+    #[cfg(feature = "health")]
+    pub fn health_endpoint(
+        &self,
+        addr: SocketAddr,
+    ) -> tokio::task::JoinHandle<Result<(), BlockTalkError>> {
+        health::spawn(addr, self.connection.clone(), self.chain.clone())
+    }
+
     /// Disconnect from the node
     pub async fn disconnect(self) -> Result<(), BlockTalkError> {
+        #[cfg(feature = "health")]
+        if let Some(task) = self.health.and_then(|task| Arc::try_unwrap(task).ok()) {
+            task.stop().await;
+        }
         match Arc::try_unwrap(self.connection) {
             Ok(conn) => conn.disconnect().await,
             Err(_) => Ok(()),
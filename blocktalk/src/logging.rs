@@ -0,0 +1,258 @@
+//! Debug logging that records structured fields when the `tracing` feature is
+//! enabled, and goes through the `log` crate as before otherwise, and log
+//! levels for each of BlockTalk's modules
+
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Prefix of the targets BlockTalk's modules log to
+const TARGET_PREFIX: &str = "blocktalk::";
+
+/// Log a debug message, recording the `field = value` pairs before the `;` as
+/// structured fields when built with `tracing`
+///
+/// `logging::debug!(height = height; "Getting block at height {}", height)`
+macro_rules! debug {
+    ($($field:ident = $value:expr),+ ; $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($field = %$value),+, $($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        log::debug!($($arg)+);
+    }};
+}
+
+pub(crate) use debug;
+
+/// Log levels for BlockTalk's modules, applied by `BlockTalkLogger`
+///
+/// Set them with `BlockTalkBuilder::log_config`, and change them while
+/// running with `BlockTalk::set_log_level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockTalkLogConfig {
+    /// Level for chain queries and notifications from `Blockchain`
+    pub chain_level: LevelFilter,
+    /// Level for mempool queries and broadcasts from `Mempool`
+    pub mempool_level: LevelFilter,
+    /// Level for connecting to and disconnecting from the node
+    pub connection_level: LevelFilter,
+    /// Level for block templates and submissions from `Mining`
+    pub mining_level: LevelFilter,
+}
+
+impl BlockTalkLogConfig {
+    /// Log every module at `level`
+    pub fn new(level: LevelFilter) -> Self {
+        Self {
+            chain_level: level,
+            mempool_level: level,
+            connection_level: level,
+            mining_level: level,
+        }
+    }
+
+    pub(crate) fn apply(&self) {
+        set_module_level("chain", self.chain_level);
+        set_module_level("mempool", self.mempool_level);
+        set_module_level("connection", self.connection_level);
+        set_module_level("mining", self.mining_level);
+    }
+}
+
+impl Default for BlockTalkLogConfig {
+    fn default() -> Self {
+        Self::new(LevelFilter::Info)
+    }
+}
+
+// Levels set for BlockTalk's modules, by module name
+fn module_levels() -> &'static RwLock<HashMap<String, LevelFilter>> {
+    static LEVELS: OnceLock<RwLock<HashMap<String, LevelFilter>>> = OnceLock::new();
+    LEVELS.get_or_init(Default::default)
+}
+
+// Log `module`, such as `chain` or `blocktalk::chain`, at `level`
+// The global maximum level is raised if needed, so the records reach the
+// logger to be filtered there.
+pub(crate) fn set_module_level(module: &str, level: LevelFilter) {
+    let module = module.strip_prefix(TARGET_PREFIX).unwrap_or(module);
+    module_levels()
+        .write()
+        .unwrap()
+        .insert(module.to_string(), level);
+    if level > log::max_level() {
+        log::set_max_level(level);
+    }
+}
+
+// Level set for the BlockTalk module that logs to `target`, if any
+fn module_level(target: &str) -> Option<LevelFilter> {
+    let module = target.strip_prefix(TARGET_PREFIX)?.split("::").next()?;
+    module_levels().read().unwrap().get(module).copied()
+}
+
+/// Logger that applies the levels set for BlockTalk's modules and passes the
+/// records it lets through on to another logger
+///
+/// `log` allows one logger per process, so install this in place of the
+/// application's logger, wrapping it. Records from BlockTalk's modules are
+/// filtered by their module's level if one is set, and everything else by
+/// `level`. The wrapped logger still applies its own filter afterwards, so
+/// it should let through at least the most verbose level set here.
+///
+/// ```no_run
+/// # use blocktalk::{BlockTalkLogConfig, BlockTalkLogger};
+/// # use log::LevelFilter;
+/// # fn install(app_logger: Box<dyn log::Log>) {
+/// BlockTalkLogger::new(app_logger, LevelFilter::Info)
+///     .init()
+///     .expect("no other logger installed");
+/// # }
+/// ```
+pub struct BlockTalkLogger {
+    inner: Box<dyn Log>,
+    level: LevelFilter,
+}
+
+impl BlockTalkLogger {
+    pub fn new(inner: Box<dyn Log>, level: LevelFilter) -> Self {
+        Self { inner, level }
+    }
+
+    /// Install as the logger for the process
+    /// Fails if a logger is already installed.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        let max_level = module_levels()
+            .read()
+            .unwrap()
+            .values()
+            .fold(self.level, |max, &level| max.max(level));
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    fn allows(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= module_level(metadata.target()).unwrap_or(self.level)
+    }
+}
+
+impl Log for BlockTalkLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.allows(metadata) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.allows(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockTalk, InProcessConnectionProvider};
+    use std::sync::{Arc, Mutex, Once};
+    use std::thread::ThreadId;
+
+    type Captured = Arc<Mutex<Vec<(ThreadId, String, log::Level)>>>;
+
+    // Logger recording the target and level of every record with the thread
+    // it was logged from, as tests running alongside log too
+    struct CaptureLogger {
+        records: Captured,
+    }
+
+    impl Log for CaptureLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.records.lock().unwrap().push((
+                std::thread::current().id(),
+                record.target().to_string(),
+                record.level(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn install() -> Captured {
+        static INSTALL: Once = Once::new();
+        static RECORDS: OnceLock<Captured> = OnceLock::new();
+        let records = RECORDS.get_or_init(Default::default).clone();
+        INSTALL.call_once(|| {
+            let logger = CaptureLogger {
+                records: records.clone(),
+            };
+            BlockTalkLogger::new(Box::new(logger), LevelFilter::Info)
+                .init()
+                .unwrap();
+        });
+        records
+    }
+
+    // Whether a debug record was logged to `module` from this thread
+    fn logged_debug(records: &Captured, module: &str) -> bool {
+        let target = format!("{}{}", TARGET_PREFIX, module);
+        let thread = std::thread::current().id();
+        records.lock().unwrap().iter().any(|(id, logged, level)| {
+            *id == thread && *logged == target && *level == log::Level::Debug
+        })
+    }
+
+    #[test]
+    fn test_module_level() {
+        set_module_level("blocktalk::network", LevelFilter::Warn);
+        assert_eq!(module_level("blocktalk::network"), Some(LevelFilter::Warn));
+        assert_eq!(
+            module_level("blocktalk::network::inner"),
+            Some(LevelFilter::Warn)
+        );
+        // Only whole module names match
+        assert_eq!(module_level("blocktalk::networks"), None);
+        assert_eq!(module_level("other::network"), None);
+    }
+
+    #[tokio::test]
+    async fn test_module_levels_filter_logs() {
+        let records = install();
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let config = BlockTalkLogConfig {
+                    chain_level: LevelFilter::Debug,
+                    connection_level: LevelFilter::Off,
+                    ..BlockTalkLogConfig::default()
+                };
+                let blocktalk = BlockTalk::builder()
+                    .socket_path("in-process")
+                    .connection_provider(Box::new(InProcessConnectionProvider::new()))
+                    .log_config(config)
+                    .build()
+                    .await
+                    .unwrap();
+                blocktalk.chain().get_tip().await.unwrap();
+                assert!(logged_debug(&records, "chain"));
+                assert!(!logged_debug(&records, "connection"));
+
+                BlockTalk::set_log_level("connection", LevelFilter::Debug);
+                BlockTalk::builder()
+                    .socket_path("in-process")
+                    .connection_provider(Box::new(InProcessConnectionProvider::new()))
+                    .build()
+                    .await
+                    .unwrap();
+                assert!(logged_debug(&records, "connection"));
+            })
+            .await;
+    }
+}
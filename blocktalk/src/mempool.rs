@@ -1,10 +1,21 @@
+use bitcoin::consensus::Decodable;
 use bitcoin::hashes::Hash;
-use bitcoin::{Transaction, Txid};
+use bitcoin::{
+    Amount, FeeRate, OutPoint, PubkeyHash, PublicKey, ScriptBuf, ScriptHash, Transaction, TxOut,
+    Txid,
+};
+use capnp::capability::Promise;
+use capnp_rpc::pry;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
+use std::sync::Mutex;
 
+use crate::error::{BlockTalkResultExt, ChainErrorKind};
+use crate::logging;
+use crate::request_metrics::{Interface, RequestTimer};
 use crate::{
-    chain_capnp::chain::Client as ChainClient, proxy_capnp::thread::Client as ThreadClient,
-    BlockTalkError,
+    chain_capnp::chain::Client as ChainClient, chain_capnp::chain_notifications,
+    proxy_capnp::thread::Client as ThreadClient, BlockTalkError,
 };
 
 #[derive(Debug)]
@@ -19,6 +30,88 @@ pub struct TransactionAncestry {
     pub ancestor_fees: i64,
 }
 
+/// A transaction currently in the mempool
+///
+/// The IPC interface does not expose the time a transaction entered the mempool,
+/// so unlike Bitcoin Core's `getmempoolentry` no entry time is reported.
+#[derive(Debug)]
+pub struct MempoolEntry {
+    /// The transaction itself
+    pub transaction: Transaction,
+    /// Virtual size in vbytes
+    pub vsize: u64,
+    /// Fee in satoshis, only known when the transaction has no unconfirmed ancestors
+    pub fee: Option<i64>,
+    /// Ancestor and descendant information
+    pub ancestry: TransactionAncestry,
+    /// Unconfirmed transactions this transaction spends from, sorted by txid
+    pub depends: Vec<Txid>,
+    /// Unconfirmed transactions spending from this transaction, sorted by txid
+    pub spent_by: Vec<Txid>,
+}
+
+/// Summary statistics about the node's mempool
+#[derive(Debug, Clone, PartialEq)]
+pub struct MempoolInfo {
+    /// Number of transactions
+    pub size: u64,
+    /// Sum of the transactions' virtual sizes in vbytes
+    pub bytes: u64,
+    /// Serialized size of all transactions in bytes
+    ///
+    /// The node's actual memory usage is not exposed over IPC, so this is an
+    /// approximation of Bitcoin Core's `usage`.
+    pub usage: u64,
+    /// Minimum fee rate for a transaction to be accepted, in BTC/kvB
+    pub mempoolminfee: f64,
+    /// Minimum relay fee rate, in BTC/kvB
+    pub minrelaytxfee: f64,
+}
+
+/// Total virtual size of the mempool transactions paying a fee rate in
+/// `[min_feerate, max_feerate)`, in sat/vB
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeHistogramBucket {
+    pub min_feerate: f64,
+    /// For the highest bucket, the highest fee rate paid, which is included
+    pub max_feerate: f64,
+    /// Sum of the transactions' virtual sizes in vbytes
+    pub vsize: u64,
+}
+
+// Lower bounds of the fee histogram's buckets in sat/vB, as Bitcoin Core's
+// proposed `getmempoolinfo` fee histogram uses
+const FEE_HISTOGRAM_BOUNDS: &[f64] = &[
+    0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 10.0, 12.0, 14.0, 17.0, 20.0, 25.0, 30.0, 40.0,
+    50.0, 60.0, 70.0, 80.0, 100.0, 120.0, 140.0, 170.0, 200.0, 250.0, 300.0, 400.0, 500.0, 600.0,
+    700.0, 800.0, 1000.0, 1200.0, 1400.0, 1700.0, 2000.0, 2500.0, 3000.0, 4000.0, 5000.0, 6000.0,
+    7000.0, 8000.0, 10000.0,
+];
+
+/// Whether the node would accept a transaction into its mempool
+#[derive(Debug, Clone, PartialEq)]
+pub struct MempoolAcceptResult {
+    pub txid: Txid,
+    pub allowed: bool,
+    /// Why the transaction would be rejected, as Bitcoin Core words it
+    pub reject_reason: Option<String>,
+    /// Fee the transaction pays, known once all its inputs have been found
+    pub fee: Option<Amount>,
+}
+
+/// Whether the node would accept, or accepted, a transaction as part of a
+/// package
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackageAcceptResult {
+    pub txid: Txid,
+    pub allowed: bool,
+    /// Why the transaction, or the package it is part of, was rejected
+    pub error: Option<String>,
+    /// Fee rate of the transactions the package adds to the mempool, in
+    /// BTC/kvB, which is what each of them is judged by
+    pub effective_fee_rate: Option<f64>,
+}
+
 #[async_trait::async_trait(?Send)]
 pub trait MempoolInterface {
     /// Check if a transaction is in the mempool
@@ -35,11 +128,97 @@ pub trait MempoolInterface {
         relay: bool,
     ) -> Result<(String, bool), BlockTalkError>;
 
+    /// Check whether the node would accept `txs` into its mempool, without
+    /// submitting them
+    ///
+    /// The IPC interface has no dry run of mempool acceptance, and
+    /// `broadcast_transaction` adds to the mempool even when not relaying, so
+    /// the checks are made here: inputs must exist and be unspent, by the
+    /// mempool or an earlier transaction in `txs`, outputs must not exceed
+    /// inputs, and the node's ancestor and descendant limits must hold. Scripts
+    /// and the node's fee and standardness policies are not checked. Later
+    /// transactions may spend outputs of earlier accepted ones.
+    async fn test_mempool_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<Vec<MempoolAcceptResult>, BlockTalkError>;
+
+    /// Check whether the node would accept `txs`, parents before children, as
+    /// a package, without submitting them
+    ///
+    /// Each transaction is checked as by `test_mempool_accept`. Package relay
+    /// isn't possible over IPC, where the node only takes transactions one at
+    /// a time, so every transaction not already in the mempool must also pay
+    /// the minimum fee rate by itself: a child can't pay for a parent below it.
+    /// Transactions already in the mempool are allowed and left out of the
+    /// package's effective fee rate. If any transaction would be rejected, none
+    /// are allowed, so this agrees with what `submit_package` will broadcast.
+    async fn test_package_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<Vec<PackageAcceptResult>, BlockTalkError>;
+
+    /// Submit `txs`, parents before children, to the node's mempool as a
+    /// package, like Bitcoin Core's `submitpackage`
+    ///
+    /// Nothing is broadcast unless `test_package_accept` allows the whole
+    /// package and no transaction pays more than `max_fee_rate`, which
+    /// `FeeRate::ZERO` leaves unchecked. The IPC interface has no package
+    /// submission, so the transactions are then broadcast one at a time, in
+    /// order and with the same maximum fee, and the node checks each on its
+    /// own. This is not package relay, and it is not atomic: submission stops
+    /// at the first transaction the node rejects, leaving the earlier ones in
+    /// its mempool.
+    async fn submit_package(
+        &self,
+        txs: &[Transaction],
+        max_fee_rate: FeeRate,
+    ) -> Result<Vec<PackageAcceptResult>, BlockTalkError>;
+
     /// Get transaction ancestry information
     async fn get_transaction_ancestry(
         &self,
         txid: &Txid,
     ) -> Result<TransactionAncestry, BlockTalkError>;
+
+    /// Estimate the fee rate needed for a transaction to confirm within `target_blocks`
+    /// Returns None if the node does not have enough data to make an estimate
+    async fn estimate_fee_rate(
+        &self,
+        target_blocks: u32,
+        conservative: bool,
+    ) -> Result<Option<FeeRate>, BlockTalkError>;
+
+    /// Get the txids of all transactions in the mempool, sorted by txid
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>, BlockTalkError>;
+
+    /// Get details about a mempool transaction
+    /// Returns None if the transaction is not in the mempool
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<Option<MempoolEntry>, BlockTalkError>;
+
+    /// Get details about every mempool transaction, sorted by txid
+    /// Unlike `get_mempool_entry`, fees are also derived for transactions with
+    /// unconfirmed ancestors, using the fees of the ancestors in the mempool.
+    async fn get_mempool_entries(&self) -> Result<Vec<MempoolEntry>, BlockTalkError>;
+
+    /// Get the mempool's size and fee limits
+    async fn get_mempool_info(&self) -> Result<MempoolInfo, BlockTalkError>;
+
+    /// Get how much of the mempool pays each range of fee rates, lowest first
+    /// Only non-empty ranges are returned. The IPC interface has no histogram,
+    /// so it is built from `get_mempool_entries`, leaving out any transaction
+    /// whose fee can't be derived.
+    async fn get_fee_histogram(&self) -> Result<Vec<FeeHistogramBucket>, BlockTalkError>;
+
+    /// Get a transaction from the mempool by its txid
+    /// Returns None if the transaction is not in the mempool. The IPC interface
+    /// has no transaction index lookup, so confirmed transactions can't be found
+    /// by txid alone; fetch the containing block with
+    /// `ChainInterface::get_block_by_hash` instead.
+    async fn get_mempool_transaction(
+        &self,
+        txid: &Txid,
+    ) -> Result<Option<Transaction>, BlockTalkError>;
 }
 
 pub struct Mempool {
@@ -49,8 +228,10 @@ pub struct Mempool {
 
 #[async_trait::async_trait(?Send)]
 impl MempoolInterface for Mempool {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn is_in_mempool(&self, txid: &Txid) -> Result<bool, BlockTalkError> {
-        log::debug!("Checking if transaction {} is in mempool", txid);
+        let _timer = RequestTimer::start(Interface::Mempool, "is_in_mempool");
+        logging::debug!(txid = txid; "Checking if transaction {} is in mempool", txid);
         let mut req = self.chain_client.is_in_mempool_request();
 
         req.get()
@@ -68,10 +249,15 @@ impl MempoolInterface for Mempool {
             BlockTalkError::Connection(e.to_string())
         })?;
 
-        Ok(response.get()?.get_result())
+        let response = response
+            .get()
+            .with_context(|| format!("Failed to read mempool status of {}", txid))?;
+        Ok(response.get_result())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn has_descendants_in_mempool(&self, txid: &Txid) -> Result<bool, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "has_descendants_in_mempool");
         log::debug!(
             "Checking if transaction {} has descendants in mempool",
             txid
@@ -93,16 +279,24 @@ impl MempoolInterface for Mempool {
             BlockTalkError::Connection(e.to_string())
         })?;
 
-        Ok(response.get()?.get_result())
+        let response = response
+            .get()
+            .with_context(|| format!("Failed to read descendants of {}", txid))?;
+        Ok(response.get_result())
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, tx), fields(txid = %tx.compute_txid()))
+    )]
     async fn broadcast_transaction(
         &self,
         tx: &Transaction,
         max_tx_fee: i64,
         relay: bool,
     ) -> Result<(String, bool), BlockTalkError> {
-        log::debug!("Broadcasting transaction {}", tx.compute_txid());
+        let _timer = RequestTimer::start(Interface::Mempool, "broadcast_transaction");
+        logging::debug!(txid = tx.compute_txid(); "Broadcasting transaction {}", tx.compute_txid());
         let mut req = self.chain_client.broadcast_transaction_request();
 
         req.get()
@@ -123,21 +317,168 @@ impl MempoolInterface for Mempool {
             BlockTalkError::Connection(e.to_string())
         })?;
 
-        let result = response.get()?;
+        let result = response.get().context("Failed to read broadcast result")?;
         Ok((
             result
-                .get_error()?
+                .get_error()
+                .context("Failed to read broadcast error")?
                 .to_string()
                 .map_err(|e| BlockTalkError::Connection(e.to_string()))?,
             result.get_result(),
         ))
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, txs), fields(count = txs.len()))
+    )]
+    async fn test_mempool_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<Vec<MempoolAcceptResult>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "test_mempool_accept");
+        logging::debug!(
+            count = txs.len();
+            "Testing mempool acceptance of {} transactions", txs.len()
+        );
+        let mempool = self.get_mempool_transactions().await?;
+        let mempool_txids: HashSet<Txid> = mempool.iter().map(|tx| tx.compute_txid()).collect();
+        // Outpoints spent by the mempool and by accepted transactions in `txs`
+        let mut spent: HashSet<OutPoint> = mempool
+            .iter()
+            .flat_map(|tx| tx.input.iter().map(|input| input.previous_output))
+            .collect();
+        // Outputs of accepted transactions in `txs`, which later ones may spend
+        let mut package_outputs: HashMap<OutPoint, TxOut> = HashMap::new();
+
+        let mut results = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let txid = tx.compute_txid();
+            let outpoints: Vec<OutPoint> =
+                tx.input.iter().map(|input| input.previous_output).collect();
+
+            let mut fee = None;
+            let reject_reason = if tx.is_coinbase() {
+                Some("coinbase".to_string())
+            } else if mempool_txids.contains(&txid) {
+                Some("txn-already-in-mempool".to_string())
+            } else if outpoints.iter().any(|outpoint| spent.contains(outpoint)) {
+                Some("txn-mempool-conflict".to_string())
+            } else {
+                let coins = self.find_coins(&outpoints).await?;
+                let prevouts: Option<Vec<TxOut>> = outpoints
+                    .iter()
+                    .zip(coins)
+                    .map(|(outpoint, coin)| coin.or_else(|| package_outputs.get(outpoint).cloned()))
+                    .collect();
+                match prevouts {
+                    None => Some("missing-inputs".to_string()),
+                    Some(prevouts) => {
+                        let input_value: Amount =
+                            prevouts.iter().map(|prevout| prevout.value).sum();
+                        let output_value: Amount =
+                            tx.output.iter().map(|output| output.value).sum();
+                        match input_value.checked_sub(output_value) {
+                            None => Some("bad-txns-in-belowout".to_string()),
+                            Some(paid) => {
+                                fee = Some(paid);
+                                self.check_chain_limits(tx).await?
+                            }
+                        }
+                    }
+                }
+            };
+
+            if reject_reason.is_none() {
+                spent.extend(outpoints);
+                package_outputs.extend(
+                    tx.output
+                        .iter()
+                        .enumerate()
+                        .map(|(vout, output)| (OutPoint::new(txid, vout as u32), output.clone())),
+                );
+            } else {
+                log::debug!(
+                    "Transaction {} would be rejected: {:?}",
+                    txid,
+                    reject_reason
+                );
+            }
+            results.push(MempoolAcceptResult {
+                txid,
+                allowed: reject_reason.is_none(),
+                reject_reason,
+                fee,
+            });
+        }
+        Ok(results)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, txs), fields(count = txs.len()))
+    )]
+    async fn test_package_accept(
+        &self,
+        txs: &[Transaction],
+    ) -> Result<Vec<PackageAcceptResult>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "test_package_accept");
+        logging::debug!(
+            count = txs.len();
+            "Testing package acceptance of {} transactions", txs.len()
+        );
+        self.check_package(txs, FeeRate::ZERO).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self, txs), fields(count = txs.len()))
+    )]
+    async fn submit_package(
+        &self,
+        txs: &[Transaction],
+        max_fee_rate: FeeRate,
+    ) -> Result<Vec<PackageAcceptResult>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "submit_package");
+        logging::debug!(count = txs.len(); "Submitting package of {} transactions", txs.len());
+        let mut results = self.check_package(txs, max_fee_rate).await?;
+        if results.iter().any(|result| !result.allowed) {
+            return Ok(results);
+        }
+
+        let mut failed = false;
+        for (tx, result) in txs.iter().zip(results.iter_mut()) {
+            if failed {
+                result.allowed = false;
+                result.error = Some("package-not-validated".to_string());
+                result.effective_fee_rate = None;
+                continue;
+            }
+            // The node treats transactions already in its mempool as accepted
+            let max_tx_fee = max_fee_rate
+                .fee_vb(tx.vsize() as u64)
+                .map_or(i64::MAX, |fee| {
+                    i64::try_from(fee.to_sat()).unwrap_or(i64::MAX)
+                });
+            let (error, accepted) = self.broadcast_transaction(tx, max_tx_fee, true).await?;
+            if !accepted {
+                log::error!("Transaction {} rejected: {}", result.txid, error);
+                failed = true;
+                result.allowed = false;
+                result.error = Some(error);
+                result.effective_fee_rate = None;
+            }
+        }
+        Ok(results)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
     async fn get_transaction_ancestry(
         &self,
         txid: &Txid,
     ) -> Result<TransactionAncestry, BlockTalkError> {
-        log::debug!("Getting ancestry for transaction {}", txid);
+        let _timer = RequestTimer::start(Interface::Mempool, "get_transaction_ancestry");
+        logging::debug!(txid = txid; "Getting ancestry for transaction {}", txid);
         let mut req = self.chain_client.get_transaction_ancestry_request();
 
         req.get()
@@ -155,7 +496,9 @@ impl MempoolInterface for Mempool {
             BlockTalkError::Connection(e.to_string())
         })?;
 
-        let result = response.get()?;
+        let result = response
+            .get()
+            .with_context(|| format!("Failed to read ancestry of {}", txid))?;
         Ok(TransactionAncestry {
             ancestors: result.get_ancestors(),
             descendants: result.get_descendants(),
@@ -163,6 +506,157 @@ impl MempoolInterface for Mempool {
             ancestor_fees: result.get_ancestorfees(),
         })
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn estimate_fee_rate(
+        &self,
+        target_blocks: u32,
+        conservative: bool,
+    ) -> Result<Option<FeeRate>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "estimate_fee_rate");
+        log::debug!(
+            "Estimating fee rate for {} block target (conservative: {})",
+            target_blocks,
+            conservative
+        );
+        let mut req = self.chain_client.estimate_smart_fee_request();
+
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get fee estimation context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+
+        let mut params = req.get();
+        params.set_num_blocks(target_blocks.try_into().unwrap_or(i32::MAX));
+        params.set_conservative(conservative);
+        params.set_want_calc(false);
+
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to estimate fee rate: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        let fee_rate = response
+            .get()
+            .and_then(|response| response.get_result())
+            .with_context(|| format!("Failed to read fee estimate for {} blocks", target_blocks))?;
+        let fee_rate = decode_fee_rate(fee_rate)?;
+        match fee_rate {
+            Some(rate) => log::debug!("Estimated fee rate: {} sat/vB", rate.to_sat_per_vb_ceil()),
+            None => log::debug!("Insufficient data to estimate fee rate"),
+        }
+        Ok(fee_rate)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_raw_mempool(&self) -> Result<Vec<Txid>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "get_raw_mempool");
+        log::debug!("Listing mempool transactions");
+        let mut txids: Vec<Txid> = self
+            .get_mempool_transactions()
+            .await?
+            .iter()
+            .map(|tx| tx.compute_txid())
+            .collect();
+        txids.sort();
+
+        logging::debug!(count = txids.len(); "Found {} mempool transactions", txids.len());
+        Ok(txids)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_mempool_entry(&self, txid: &Txid) -> Result<Option<MempoolEntry>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "get_mempool_entry");
+        logging::debug!(txid = txid; "Getting mempool entry for transaction {}", txid);
+        if !self.is_in_mempool(txid).await? {
+            logging::debug!(txid = txid; "Transaction {} is not in mempool", txid);
+            return Ok(None);
+        }
+
+        let transactions = self.get_mempool_transactions().await?;
+        let transaction = match transactions.iter().find(|tx| tx.compute_txid() == *txid) {
+            Some(tx) => tx.clone(),
+            None => {
+                // Removed from the mempool between the two requests
+                logging::debug!(txid = txid; "Transaction {} left the mempool", txid);
+                return Ok(None);
+            }
+        };
+
+        let ancestry = self.get_transaction_ancestry(txid).await?;
+        Ok(Some(mempool_entry(transaction, ancestry, &transactions)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_mempool_entries(&self) -> Result<Vec<MempoolEntry>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "get_mempool_entries");
+        log::debug!("Getting all mempool entries");
+        let transactions = self.get_mempool_transactions().await?;
+
+        let mut entries = Vec::with_capacity(transactions.len());
+        for transaction in &transactions {
+            let ancestry = self
+                .get_transaction_ancestry(&transaction.compute_txid())
+                .await?;
+            entries.push(mempool_entry(transaction.clone(), ancestry, &transactions));
+        }
+        derive_ancestor_fees(&mut entries);
+        entries.sort_by_key(|entry| entry.transaction.compute_txid());
+
+        logging::debug!(count = entries.len(); "Found {} mempool entries", entries.len());
+        Ok(entries)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_mempool_transaction(
+        &self,
+        txid: &Txid,
+    ) -> Result<Option<Transaction>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "get_mempool_transaction");
+        logging::debug!(txid = txid; "Getting mempool transaction {}", txid);
+        if !self.is_in_mempool(txid).await? {
+            logging::debug!(txid = txid; "Transaction {} is not in mempool", txid);
+            return Ok(None);
+        }
+
+        let transaction = self
+            .get_mempool_transactions()
+            .await?
+            .into_iter()
+            .find(|tx| tx.compute_txid() == *txid);
+        if transaction.is_none() {
+            // Removed from the mempool between the two requests
+            logging::debug!(txid = txid; "Transaction {} left the mempool", txid);
+        }
+        Ok(transaction)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_mempool_info(&self) -> Result<MempoolInfo, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "get_mempool_info");
+        log::debug!("Getting mempool info");
+        let transactions = self.get_mempool_transactions().await?;
+
+        let mempool_min_fee = self.mempool_min_fee().await?;
+        let min_relay_fee = self.relay_min_fee().await?;
+
+        Ok(summarize_mempool(
+            &transactions,
+            mempool_min_fee,
+            min_relay_fee,
+        ))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    async fn get_fee_histogram(&self) -> Result<Vec<FeeHistogramBucket>, BlockTalkError> {
+        let _timer = RequestTimer::start(Interface::Mempool, "get_fee_histogram");
+        log::debug!("Building mempool fee histogram");
+        let entries = self.get_mempool_entries().await?;
+        Ok(fee_histogram(&entries))
+    }
 }
 
 impl Mempool {
@@ -172,4 +666,1305 @@ impl Mempool {
             thread,
         }
     }
+
+    // The node has no call to list the mempool, but requestMempoolTransactions
+    // replays every mempool transaction through a notifications callback
+    async fn get_mempool_transactions(&self) -> Result<Vec<Transaction>, BlockTalkError> {
+        let collector = MempoolCollector::default();
+        let transactions = collector.transactions.clone();
+
+        let mut req = self.chain_client.request_mempool_transactions_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get mempool context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        req.get()
+            .set_notifications(capnp_rpc::new_client(collector));
+
+        req.send().promise.await.map_err(|e| {
+            log::error!("Failed to request mempool transactions: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        let transactions = transactions.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for mempool transactions: {}",
+                e
+            ))
+        })?;
+        Ok(transactions.clone())
+    }
+
+    // Look up the outputs `outpoints` refer to among the node's unspent coins,
+    // including those created by the mempool. None for outputs that are spent
+    // or don't exist.
+    async fn find_coins(
+        &self,
+        outpoints: &[OutPoint],
+    ) -> Result<Vec<Option<TxOut>>, BlockTalkError> {
+        let mut req = self.chain_client.find_coins_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get coins context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        let mut coins = req.get().init_coins(outpoints.len() as u32);
+        for (i, outpoint) in outpoints.iter().enumerate() {
+            coins
+                .reborrow()
+                .get(i as u32)
+                .set_key(bitcoin::consensus::serialize(outpoint).as_slice())
+                .with_context(|| format!("Failed to request coin {}", outpoint))?;
+        }
+
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to find coins: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        // The node returns the coins keyed by outpoint, in its own order
+        let mut found = HashMap::new();
+        let coins = response
+            .get()
+            .and_then(|response| response.get_coins())
+            .context("Failed to read coins")?;
+        for coin in coins.iter() {
+            let key = coin.get_key().context("Failed to read coin outpoint")?;
+            let outpoint: OutPoint = bitcoin::consensus::deserialize(key).map_err(|e| {
+                BlockTalkError::chain_error(ChainErrorKind::DeserializationFailed, e.to_string())
+            })?;
+            let data = coin
+                .get_value()
+                .with_context(|| format!("Failed to read coin {}", outpoint))?;
+            // Coins that weren't found come back empty
+            if !data.is_empty() {
+                found.insert(outpoint, decode_coin(data)?);
+            }
+        }
+        Ok(outpoints
+            .iter()
+            .map(|outpoint| found.remove(outpoint))
+            .collect())
+    }
+
+    // Check `txs` as a package for `test_package_accept` and `submit_package`,
+    // rejecting transactions paying more than `max_fee_rate` unless it is zero
+    async fn check_package(
+        &self,
+        txs: &[Transaction],
+        max_fee_rate: FeeRate,
+    ) -> Result<Vec<PackageAcceptResult>, BlockTalkError> {
+        let results = self.test_mempool_accept(txs).await?;
+        let in_mempool = |result: &MempoolAcceptResult| {
+            result.reject_reason.as_deref() == Some("txn-already-in-mempool")
+        };
+
+        let rejected = results
+            .iter()
+            .any(|result| !result.allowed && !in_mempool(result));
+        // Fees and sizes of the transactions the package adds to the mempool
+        let (package_fee, package_vsize) = txs
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.allowed)
+            .fold((Amount::ZERO, 0u64), |(fee, vsize), (tx, result)| {
+                (
+                    fee + result.fee.unwrap_or(Amount::ZERO),
+                    vsize + tx.vsize() as u64,
+                )
+            });
+        let min_relay_fee = self.relay_min_fee().await?.unwrap_or(FeeRate::ZERO);
+        let min_fee = self
+            .mempool_min_fee()
+            .await?
+            .unwrap_or(FeeRate::ZERO)
+            .max(min_relay_fee);
+        let below = |fee: Amount, vsize: u64| fee < min_fee.fee_vb(vsize).unwrap_or(Amount::MAX);
+        let above_max = |fee: Amount, vsize: u64| {
+            max_fee_rate > FeeRate::ZERO && fee > max_fee_rate.fee_vb(vsize).unwrap_or(Amount::MAX)
+        };
+        // The node checks each transaction on its own as it is broadcast, so
+        // one paying too little is rejected whatever its children pay
+        let fee_errors: Vec<Option<String>> = txs
+            .iter()
+            .zip(&results)
+            .map(|(tx, result)| {
+                let fee = result.fee.unwrap_or(Amount::ZERO);
+                let vsize = tx.vsize() as u64;
+                if !result.allowed {
+                    None
+                } else if above_max(fee, vsize) {
+                    Some("max-fee-exceeded".to_string())
+                } else if below(fee, vsize) {
+                    Some("mempool min fee not met".to_string())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let package_error = (rejected || fee_errors.iter().any(Option::is_some))
+            .then(|| "package-not-validated".to_string());
+        let effective_fee_rate = (package_vsize > 0)
+            .then(|| Amount::from_sat(package_fee.to_sat() * 1000 / package_vsize).to_btc());
+
+        let results = results
+            .into_iter()
+            .zip(fee_errors)
+            .map(|(result, fee_error)| {
+                if in_mempool(&result) {
+                    return PackageAcceptResult {
+                        txid: result.txid,
+                        allowed: true,
+                        error: None,
+                        effective_fee_rate: None,
+                    };
+                }
+                let error = result
+                    .reject_reason
+                    .or(fee_error)
+                    .or_else(|| package_error.clone());
+                if let Some(error) = &error {
+                    log::debug!("Transaction {} would be rejected: {}", result.txid, error);
+                }
+                PackageAcceptResult {
+                    txid: result.txid,
+                    allowed: error.is_none(),
+                    effective_fee_rate: effective_fee_rate.filter(|_| error.is_none()),
+                    error,
+                }
+            })
+            .collect();
+        Ok(results)
+    }
+
+    // The lowest fee rate the mempool currently accepts, None if the node
+    // reports none
+    async fn mempool_min_fee(&self) -> Result<Option<FeeRate>, BlockTalkError> {
+        let mut req = self.chain_client.mempool_min_fee_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get mempool context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to get mempool minimum fee: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+        let mempool_min_fee = response
+            .get()
+            .and_then(|response| response.get_result())
+            .context("Failed to read mempool minimum fee")?;
+        decode_fee_rate(mempool_min_fee)
+    }
+
+    // The node's minimum relay fee rate, None if the node reports none
+    async fn relay_min_fee(&self) -> Result<Option<FeeRate>, BlockTalkError> {
+        let mut req = self.chain_client.relay_min_fee_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get mempool context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to get minimum relay fee: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+        let min_relay_fee = response
+            .get()
+            .and_then(|response| response.get_result())
+            .context("Failed to read minimum relay fee")?;
+        decode_fee_rate(min_relay_fee)
+    }
+
+    // Check `tx` keeps within the node's ancestor and descendant limits,
+    // returning the reason it doesn't
+    async fn check_chain_limits(&self, tx: &Transaction) -> Result<Option<String>, BlockTalkError> {
+        let mut req = self.chain_client.check_chain_limits_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get chain limits context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        req.get()
+            .set_tx(bitcoin::consensus::serialize(tx).as_slice());
+
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to check chain limits: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+
+        let result = response
+            .get()
+            .and_then(|response| response.get_result())
+            .context("Failed to read chain limits result")?;
+        if !result.has_error() {
+            return Ok(None);
+        }
+        let reason = result
+            .get_error()
+            .and_then(|error| error.get_original())
+            .context("Failed to read chain limits error")?
+            .to_string()
+            .map_err(|e| BlockTalkError::Connection(e.to_string()))?;
+        Ok(Some(reason))
+    }
+}
+
+// Collects the transactions replayed by requestMempoolTransactions
+#[derive(Default)]
+struct MempoolCollector {
+    transactions: Arc<Mutex<Vec<Transaction>>>,
+}
+
+impl chain_notifications::Server for MempoolCollector {
+    fn transaction_added_to_mempool(
+        &mut self,
+        params: chain_notifications::TransactionAddedToMempoolParams,
+        _: chain_notifications::TransactionAddedToMempoolResults,
+    ) -> Promise<(), ::capnp::Error> {
+        let tx = match Transaction::consensus_decode(&mut pry!(pry!(params.get()).get_tx())) {
+            Ok(tx) => tx,
+            Err(e) => {
+                return Promise::err(::capnp::Error::failed(format!(
+                    "Failed to decode transaction: {}",
+                    e
+                )))
+            }
+        };
+
+        match self.transactions.lock() {
+            Ok(mut transactions) => {
+                transactions.push(tx);
+                Promise::ok(())
+            }
+            Err(e) => Promise::err(::capnp::Error::failed(format!(
+                "Failed to acquire lock for mempool transactions: {}",
+                e
+            ))),
+        }
+    }
+
+    fn destroy(
+        &mut self,
+        _params: chain_notifications::DestroyParams,
+        _: chain_notifications::DestroyResults,
+    ) -> Promise<(), ::capnp::Error> {
+        Promise::ok(())
+    }
+}
+
+// Build a mempool entry, linking the transaction to its in-mempool parents and children
+fn mempool_entry(
+    transaction: Transaction,
+    ancestry: TransactionAncestry,
+    mempool: &[Transaction],
+) -> MempoolEntry {
+    let txid = transaction.compute_txid();
+    let mempool_txids: HashSet<Txid> = mempool.iter().map(|tx| tx.compute_txid()).collect();
+
+    let depends: BTreeSet<Txid> = transaction
+        .input
+        .iter()
+        .map(|input| input.previous_output.txid)
+        .filter(|parent| mempool_txids.contains(parent))
+        .collect();
+    let spent_by: BTreeSet<Txid> = mempool
+        .iter()
+        .filter(|tx| {
+            tx.input
+                .iter()
+                .any(|input| input.previous_output.txid == txid)
+        })
+        .map(|tx| tx.compute_txid())
+        .collect();
+
+    // The ancestor count includes the transaction itself, so with no other
+    // ancestors the ancestor fees are exactly this transaction's fee
+    let fee = (ancestry.ancestors == 1).then_some(ancestry.ancestor_fees);
+
+    MempoolEntry {
+        vsize: transaction.vsize() as u64,
+        transaction,
+        fee,
+        ancestry,
+        depends: depends.into_iter().collect(),
+        spent_by: spent_by.into_iter().collect(),
+    }
+}
+
+// Fill in the fees of transactions with unconfirmed ancestors. A transaction's
+// fee is its ancestor fees minus the fees of its other ancestors, so fees are
+// resolved parents first until no more can be derived.
+fn derive_ancestor_fees(entries: &mut [MempoolEntry]) {
+    let index: HashMap<Txid, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.transaction.compute_txid(), i))
+        .collect();
+
+    loop {
+        let mut progress = false;
+        for i in 0..entries.len() {
+            if entries[i].fee.is_some() {
+                continue;
+            }
+
+            let mut ancestors = HashSet::new();
+            let mut pending = entries[i].depends.clone();
+            while let Some(txid) = pending.pop() {
+                if let Some(&ancestor) = index.get(&txid) {
+                    if ancestors.insert(ancestor) {
+                        pending.extend(entries[ancestor].depends.iter().copied());
+                    }
+                }
+            }
+
+            let ancestor_fees: Option<i64> = ancestors
+                .iter()
+                .map(|&ancestor| entries[ancestor].fee)
+                .sum();
+            if let Some(ancestor_fees) = ancestor_fees {
+                entries[i].fee = Some(entries[i].ancestry.ancestor_fees - ancestor_fees);
+                progress = true;
+            }
+        }
+
+        if !progress {
+            break;
+        }
+    }
+}
+
+// Build mempool statistics from its transactions and the node's fee limits.
+// Like Bitcoin Core, the reported mempool minimum fee is never below the relay minimum.
+fn summarize_mempool(
+    transactions: &[Transaction],
+    mempool_min_fee: Option<FeeRate>,
+    min_relay_fee: Option<FeeRate>,
+) -> MempoolInfo {
+    let min_relay_fee = min_relay_fee.unwrap_or(FeeRate::ZERO);
+    let mempool_min_fee = mempool_min_fee.unwrap_or(FeeRate::ZERO).max(min_relay_fee);
+
+    MempoolInfo {
+        size: transactions.len() as u64,
+        bytes: transactions.iter().map(|tx| tx.vsize() as u64).sum(),
+        usage: transactions.iter().map(|tx| tx.total_size() as u64).sum(),
+        mempoolminfee: fee_rate_to_btc_per_kvb(mempool_min_fee),
+        minrelaytxfee: fee_rate_to_btc_per_kvb(min_relay_fee),
+    }
+}
+
+// Sum the virtual sizes of `entries` by fee rate, into the non-empty buckets
+// starting at each of `FEE_HISTOGRAM_BOUNDS`
+fn fee_histogram(entries: &[MempoolEntry]) -> Vec<FeeHistogramBucket> {
+    let mut vsizes = vec![0; FEE_HISTOGRAM_BOUNDS.len()];
+    let mut highest_feerate = 0.0f64;
+    for entry in entries {
+        let Some(fee) = entry.fee.filter(|_| entry.vsize > 0) else {
+            continue;
+        };
+        let feerate = fee as f64 / entry.vsize as f64;
+        let bucket = FEE_HISTOGRAM_BOUNDS
+            .partition_point(|bound| *bound <= feerate)
+            .saturating_sub(1);
+        vsizes[bucket] += entry.vsize;
+        highest_feerate = highest_feerate.max(feerate);
+    }
+
+    vsizes
+        .into_iter()
+        .enumerate()
+        .filter(|(_, vsize)| *vsize > 0)
+        .map(|(bucket, vsize)| FeeHistogramBucket {
+            min_feerate: FEE_HISTOGRAM_BOUNDS[bucket],
+            max_feerate: FEE_HISTOGRAM_BOUNDS
+                .get(bucket + 1)
+                .copied()
+                .unwrap_or(highest_feerate),
+            vsize,
+        })
+        .collect()
+}
+
+// Decode a coin as the node serializes it: a varint of its height and coinbase
+// flag, then its output with the amount and script compressed
+fn decode_coin(mut data: &[u8]) -> Result<TxOut, BlockTalkError> {
+    let invalid = || {
+        log::error!("Invalid coin encoding");
+        BlockTalkError::chain_error(
+            ChainErrorKind::DeserializationFailed,
+            "Invalid coin encoding".to_string(),
+        )
+    };
+
+    read_varint(&mut data).ok_or_else(invalid)?;
+    let value = Amount::from_sat(decompress_amount(
+        read_varint(&mut data).ok_or_else(invalid)?,
+    ));
+
+    // Common scripts are stored as the hash or key they pay to, tagged with
+    // their type; any other script is stored with its length plus 6
+    let script_type = read_varint(&mut data).ok_or_else(invalid)?;
+    let payload_len = match script_type {
+        0 | 1 => 20,
+        2..=5 => 32,
+        n => n.checked_sub(6).ok_or_else(invalid)? as usize,
+    };
+    let payload = data.get(..payload_len).ok_or_else(invalid)?;
+    let script_pubkey = match script_type {
+        0 => ScriptBuf::new_p2pkh(&PubkeyHash::from_slice(payload).map_err(|_| invalid())?),
+        1 => ScriptBuf::new_p2sh(&ScriptHash::from_slice(payload).map_err(|_| invalid())?),
+        2..=5 => {
+            // The key's x coordinate, with the parity of y in the type
+            let mut key = vec![(script_type as u8 & 1) | 2];
+            key.extend_from_slice(payload);
+            let key = PublicKey::from_slice(&key).map_err(|_| invalid())?;
+            if script_type >= 4 {
+                ScriptBuf::new_p2pk(&PublicKey::new_uncompressed(key.inner))
+            } else {
+                ScriptBuf::new_p2pk(&key)
+            }
+        }
+        _ => ScriptBuf::from_bytes(payload.to_vec()),
+    };
+    Ok(TxOut {
+        value,
+        script_pubkey,
+    })
+}
+
+// Read one of Bitcoin Core's variable length integers, 7 bits a byte with the
+// high bit set on all but the last byte
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut n: u64 = 0;
+    loop {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        n = n.checked_mul(128)? | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+        n = n.checked_add(1)?;
+    }
+}
+
+// Undo Bitcoin Core's amount compression, which strips trailing zeros into
+// an exponent
+fn decompress_amount(x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+    let mut x = x - 1;
+    let mut exponent = x % 10;
+    x /= 10;
+    let mut n = if exponent < 9 {
+        let last_digit = x % 9 + 1;
+        x /= 9;
+        x * 10 + last_digit
+    } else {
+        x + 1
+    };
+    while exponent > 0 {
+        n = n.saturating_mul(10);
+        exponent -= 1;
+    }
+    n
+}
+
+pub(crate) fn fee_rate_to_btc_per_kvb(fee_rate: FeeRate) -> f64 {
+    bitcoin::Amount::from_sat(fee_rate.to_sat_per_kwu() * 4).to_btc()
+}
+
+// Decode a serialized CFeeRate (little-endian satoshis per 1000 vbytes)
+// A zero rate is how the node reports that no estimate is available. Rates
+// are rounded up to a whole sat/kwu, so a minimum fee rate is never undershot.
+pub(crate) fn decode_fee_rate(data: &[u8]) -> Result<Option<FeeRate>, BlockTalkError> {
+    let bytes: [u8; 8] = data.try_into().map_err(|_| {
+        log::error!("Invalid fee rate length: expected 8, got {}", data.len());
+        BlockTalkError::chain_error(
+            ChainErrorKind::DeserializationFailed,
+            format!("Invalid fee rate length: expected 8, got {}", data.len()),
+        )
+    })?;
+
+    let sat_per_kvb = i64::from_le_bytes(bytes);
+    if sat_per_kvb <= 0 {
+        return Ok(None);
+    }
+    Ok(Some(FeeRate::from_sat_per_kwu(
+        (sat_per_kvb as u64).div_ceil(4),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_fee_rate() {
+        let data = 2000i64.to_le_bytes();
+        let fee_rate = decode_fee_rate(&data).unwrap();
+        assert_eq!(fee_rate, Some(FeeRate::from_sat_per_vb_unchecked(2)));
+
+        let data = 1001i64.to_le_bytes();
+        let fee_rate = decode_fee_rate(&data).unwrap();
+        assert_eq!(fee_rate, Some(FeeRate::from_sat_per_kwu(251)));
+    }
+
+    #[test]
+    fn test_decode_fee_rate_insufficient_data() {
+        let data = 0i64.to_le_bytes();
+        assert_eq!(decode_fee_rate(&data).unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_fee_rate_invalid_length() {
+        assert!(decode_fee_rate(&[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn test_summarize_empty_mempool() {
+        let info = summarize_mempool(&[], None, Some(FeeRate::from_sat_per_vb_unchecked(1)));
+        assert_eq!(info.size, 0);
+        assert_eq!(info.bytes, 0);
+        assert_eq!(info.usage, 0);
+        assert_eq!(info.mempoolminfee, 0.00001);
+        assert_eq!(info.minrelaytxfee, 0.00001);
+    }
+
+    #[test]
+    fn test_summarize_populated_mempool() {
+        let tx = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).txdata[0]
+            .clone();
+        let info = summarize_mempool(
+            &[tx.clone(), tx],
+            Some(FeeRate::from_sat_per_vb_unchecked(3)),
+            Some(FeeRate::from_sat_per_vb_unchecked(1)),
+        );
+        assert_eq!(info.size, 2);
+        assert_eq!(info.bytes, 408);
+        assert_eq!(info.usage, 408);
+        assert_eq!(info.mempoolminfee, 0.00003);
+        assert_eq!(info.minrelaytxfee, 0.00001);
+    }
+
+    // Mempool entry of `vsize` vbytes paying `fee`, with no other ancestors
+    fn entry(vsize: u64, fee: Option<i64>) -> MempoolEntry {
+        MempoolEntry {
+            transaction: bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest)
+                .txdata[0]
+                .clone(),
+            vsize,
+            fee,
+            ancestry: TransactionAncestry {
+                ancestors: 1,
+                descendants: 1,
+                ancestor_size: vsize,
+                ancestor_fees: fee.unwrap_or(0),
+            },
+            depends: Vec::new(),
+            spent_by: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_fee_histogram() {
+        let entries = [
+            entry(200, Some(100)),     // 0.5 sat/vB
+            entry(100, Some(100)),     // 1 sat/vB
+            entry(150, Some(225)),     // 1.5 sat/vB
+            entry(100, Some(1_100)),   // 11 sat/vB
+            entry(300, Some(3_300)),   // 11 sat/vB
+            entry(100, Some(120_000)), // 1200 sat/vB
+            entry(10, Some(150_000)),  // 15000 sat/vB
+            entry(400, None),
+        ];
+
+        let histogram = fee_histogram(&entries);
+        let bucket = |min_feerate, max_feerate, vsize| FeeHistogramBucket {
+            min_feerate,
+            max_feerate,
+            vsize,
+        };
+        assert_eq!(
+            histogram,
+            vec![
+                bucket(0.0, 1.0, 200),
+                bucket(1.0, 2.0, 250),
+                bucket(10.0, 12.0, 400),
+                bucket(1200.0, 1400.0, 100),
+                bucket(10000.0, 15000.0, 10),
+            ]
+        );
+        assert!(fee_histogram(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mempool_collector_records_transactions() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let collector = MempoolCollector::default();
+                let transactions = collector.transactions.clone();
+                let client: chain_notifications::Client = capnp_rpc::new_client(collector);
+
+                let block = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest);
+                let tx = block.txdata[0].clone();
+                let mut req = client.transaction_added_to_mempool_request();
+                req.get().set_tx(&bitcoin::consensus::serialize(&tx));
+                req.send().promise.await.unwrap();
+
+                let transactions = transactions.lock().unwrap();
+                assert_eq!(transactions.len(), 1);
+                assert_eq!(transactions[0].compute_txid(), tx.compute_txid());
+            })
+            .await;
+    }
+
+    // Stands in for the node, answering broadcastTransaction with a fixed result
+    struct MockChain {
+        reject_reason: Option<&'static str>,
+        max_tx_fee: Arc<Mutex<Option<i64>>>,
+    }
+
+    impl crate::chain_capnp::chain::Server for MockChain {
+        fn broadcast_transaction(
+            &mut self,
+            params: crate::chain_capnp::chain::BroadcastTransactionParams,
+            mut results: crate::chain_capnp::chain::BroadcastTransactionResults,
+        ) -> Promise<(), ::capnp::Error> {
+            *self.max_tx_fee.lock().unwrap() = Some(pry!(params.get()).get_max_tx_fee());
+            let mut results = results.get();
+            results.set_error(self.reject_reason.unwrap_or(""));
+            results.set_result(self.reject_reason.is_none());
+            Promise::ok(())
+        }
+    }
+
+    struct MockThread;
+
+    impl crate::proxy_capnp::thread::Server for MockThread {}
+
+    async fn broadcast_with(reject_reason: Option<&'static str>) -> (String, bool, Option<i64>) {
+        let max_tx_fee = Arc::new(Mutex::new(None));
+        let mempool = Mempool::new(
+            capnp_rpc::new_client(MockChain {
+                reject_reason,
+                max_tx_fee: max_tx_fee.clone(),
+            }),
+            capnp_rpc::new_client(MockThread),
+        );
+
+        let tx = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).txdata[0]
+            .clone();
+        let (error, accepted) = mempool
+            .broadcast_transaction(&tx, 100_000, true)
+            .await
+            .unwrap();
+        let max_tx_fee = *max_tx_fee.lock().unwrap();
+        (error, accepted, max_tx_fee)
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_transaction_accepted() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (error, accepted, max_tx_fee) = broadcast_with(None).await;
+                assert!(accepted);
+                assert!(error.is_empty());
+                assert_eq!(max_tx_fee, Some(100_000));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_transaction_rejected() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (error, accepted, _) =
+                    broadcast_with(Some("bad-txns-inputs-missingorspent")).await;
+                assert!(!accepted);
+                assert_eq!(error, "bad-txns-inputs-missingorspent");
+            })
+            .await;
+    }
+
+    // Stands in for a node whose mempool holds a fixed set of transactions
+    struct MockMempoolChain {
+        transactions: Vec<Transaction>,
+        // Ancestor count and ancestor fees reported for each transaction
+        ancestry: Vec<(Txid, u64, i64)>,
+        // Confirmed unspent outputs
+        coins: Vec<(OutPoint, TxOut)>,
+    }
+
+    impl MockMempoolChain {
+        // Total value of the outputs `tx` spends, if they are all known
+        fn input_value(&self, tx: &Transaction) -> Option<Amount> {
+            let mempool_outputs = self.transactions.iter().flat_map(|tx| {
+                let txid = tx.compute_txid();
+                tx.output
+                    .iter()
+                    .enumerate()
+                    .map(move |(vout, output)| (OutPoint::new(txid, vout as u32), output))
+            });
+            let outputs: Vec<(OutPoint, &TxOut)> = self
+                .coins
+                .iter()
+                .map(|(outpoint, output)| (*outpoint, output))
+                .chain(mempool_outputs)
+                .collect();
+            tx.input
+                .iter()
+                .map(|input| {
+                    outputs
+                        .iter()
+                        .find(|(outpoint, _)| *outpoint == input.previous_output)
+                        .map(|(_, output)| output.value)
+                })
+                .sum()
+        }
+    }
+
+    impl crate::chain_capnp::chain::Server for MockMempoolChain {
+        fn is_in_mempool(
+            &mut self,
+            params: crate::chain_capnp::chain::IsInMempoolParams,
+            mut results: crate::chain_capnp::chain::IsInMempoolResults,
+        ) -> Promise<(), ::capnp::Error> {
+            let txid = pry!(pry!(params.get()).get_txid());
+            let found = self
+                .transactions
+                .iter()
+                .any(|tx| tx.compute_txid().as_byte_array() == txid);
+            results.get().set_result(found);
+            Promise::ok(())
+        }
+
+        fn get_transaction_ancestry(
+            &mut self,
+            params: crate::chain_capnp::chain::GetTransactionAncestryParams,
+            mut results: crate::chain_capnp::chain::GetTransactionAncestryResults,
+        ) -> Promise<(), ::capnp::Error> {
+            let txid = pry!(pry!(params.get()).get_txid());
+            if let Some((_, ancestors, ancestor_fees)) = self
+                .ancestry
+                .iter()
+                .find(|(id, _, _)| id.as_byte_array() == txid)
+            {
+                let mut results = results.get();
+                results.set_ancestors(*ancestors);
+                results.set_descendants(1);
+                results.set_ancestorfees(*ancestor_fees);
+            }
+            Promise::ok(())
+        }
+
+        fn broadcast_transaction(
+            &mut self,
+            params: crate::chain_capnp::chain::BroadcastTransactionParams,
+            mut results: crate::chain_capnp::chain::BroadcastTransactionResults,
+        ) -> Promise<(), ::capnp::Error> {
+            let tx: Transaction = pry!(bitcoin::consensus::deserialize(pry!(
+                pry!(params.get()).get_tx()
+            ))
+            .map_err(|e| capnp::Error::failed(e.to_string())));
+            // Like a real node, check the transaction pays the minimum relay
+            // fee on its own
+            if let Some(input_value) = self.input_value(&tx) {
+                let output_value: Amount = tx.output.iter().map(|output| output.value).sum();
+                let fee = input_value
+                    .checked_sub(output_value)
+                    .unwrap_or(Amount::ZERO);
+                if fee < Amount::from_sat(tx.vsize() as u64) {
+                    results.get().set_error("min relay fee not met");
+                    results.get().set_result(false);
+                    return Promise::ok(());
+                }
+            }
+            self.transactions.push(tx);
+            results.get().set_result(true);
+            Promise::ok(())
+        }
+
+        fn find_coins(
+            &mut self,
+            params: crate::chain_capnp::chain::FindCoinsParams,
+            mut results: crate::chain_capnp::chain::FindCoinsResults,
+        ) -> Promise<(), ::capnp::Error> {
+            let requested = pry!(pry!(params.get()).get_coins());
+            let mut coins = results.get().init_coins(requested.len());
+            for (i, coin) in requested.iter().enumerate() {
+                let key = pry!(coin.get_key());
+                let outpoint: OutPoint = pry!(bitcoin::consensus::deserialize(key)
+                    .map_err(|e| capnp::Error::failed(e.to_string())));
+                let mempool_outputs = self.transactions.iter().flat_map(|tx| {
+                    let txid = tx.compute_txid();
+                    tx.output
+                        .iter()
+                        .enumerate()
+                        .map(move |(vout, output)| (OutPoint::new(txid, vout as u32), output))
+                });
+                let output = self
+                    .coins
+                    .iter()
+                    .map(|(outpoint, output)| (*outpoint, output))
+                    .chain(mempool_outputs)
+                    .find(|(unspent, _)| *unspent == outpoint)
+                    .map(|(_, output)| output);
+
+                let mut entry = coins.reborrow().get(i as u32);
+                pry!(entry.set_key(key));
+                let value = output.map(encode_coin).unwrap_or_default();
+                pry!(entry.set_value(value.as_slice()));
+            }
+            Promise::ok(())
+        }
+
+        fn check_chain_limits(
+            &mut self,
+            _: crate::chain_capnp::chain::CheckChainLimitsParams,
+            _: crate::chain_capnp::chain::CheckChainLimitsResults,
+        ) -> Promise<(), ::capnp::Error> {
+            Promise::ok(())
+        }
+
+        // Nothing is evicted, so the mempool takes anything paying the
+        // minimum relay fee
+        fn mempool_min_fee(
+            &mut self,
+            _: crate::chain_capnp::chain::MempoolMinFeeParams,
+            mut results: crate::chain_capnp::chain::MempoolMinFeeResults,
+        ) -> Promise<(), ::capnp::Error> {
+            results.get().set_result(&0i64.to_le_bytes());
+            Promise::ok(())
+        }
+
+        // 1 sat/vB
+        fn relay_min_fee(
+            &mut self,
+            _: crate::chain_capnp::chain::RelayMinFeeParams,
+            mut results: crate::chain_capnp::chain::RelayMinFeeResults,
+        ) -> Promise<(), ::capnp::Error> {
+            results.get().set_result(&1_000i64.to_le_bytes());
+            Promise::ok(())
+        }
+
+        fn request_mempool_transactions(
+            &mut self,
+            params: crate::chain_capnp::chain::RequestMempoolTransactionsParams,
+            _: crate::chain_capnp::chain::RequestMempoolTransactionsResults,
+        ) -> Promise<(), ::capnp::Error> {
+            let notifications = pry!(pry!(params.get()).get_notifications());
+            let transactions = self.transactions.clone();
+            Promise::from_future(async move {
+                for tx in transactions {
+                    let mut req = notifications.transaction_added_to_mempool_request();
+                    req.get().set_tx(&bitcoin::consensus::serialize(&tx));
+                    req.send().promise.await?;
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_mempool_transaction() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let tx = bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest)
+                    .txdata[0]
+                    .clone();
+                let mempool = Mempool::new(
+                    capnp_rpc::new_client(MockMempoolChain {
+                        transactions: vec![tx.clone()],
+                        ancestry: Vec::new(),
+                        coins: Vec::new(),
+                    }),
+                    capnp_rpc::new_client(MockThread),
+                );
+
+                let found = mempool
+                    .get_mempool_transaction(&tx.compute_txid())
+                    .await
+                    .unwrap();
+                assert_eq!(found, Some(tx));
+
+                let missing = mempool
+                    .get_mempool_transaction(&Txid::all_zeros())
+                    .await
+                    .unwrap();
+                assert_eq!(missing, None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_transaction_can_be_fetched() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let confirmed =
+                    bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).txdata
+                        [0]
+                    .clone();
+                let tx = spend(&confirmed);
+                let txid = tx.compute_txid();
+                let mempool = Mempool::new(
+                    capnp_rpc::new_client(MockMempoolChain {
+                        transactions: Vec::new(),
+                        ancestry: Vec::new(),
+                        coins: Vec::new(),
+                    }),
+                    capnp_rpc::new_client(MockThread),
+                );
+                assert!(!mempool.is_in_mempool(&txid).await.unwrap());
+
+                let (_, accepted) = mempool
+                    .broadcast_transaction(&tx, 100_000, true)
+                    .await
+                    .unwrap();
+                assert!(accepted);
+                assert!(mempool.is_in_mempool(&txid).await.unwrap());
+
+                let found = mempool
+                    .get_mempool_transaction(&txid)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(found, tx);
+            })
+            .await;
+    }
+
+    // Encode `output` as a coin at height 1, without compressing its script
+    fn encode_coin(output: &TxOut) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_varint(&mut data, 2);
+        write_varint(&mut data, compress_amount(output.value.to_sat()));
+        write_varint(&mut data, output.script_pubkey.len() as u64 + 6);
+        data.extend_from_slice(output.script_pubkey.as_bytes());
+        data
+    }
+
+    fn compress_amount(mut n: u64) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        let mut exponent = 0;
+        while n % 10 == 0 && exponent < 9 {
+            n /= 10;
+            exponent += 1;
+        }
+        if exponent < 9 {
+            let last_digit = n % 10;
+            n /= 10;
+            1 + (n * 9 + last_digit - 1) * 10 + exponent
+        } else {
+            1 + (n - 1) * 10 + 9
+        }
+    }
+
+    fn write_varint(data: &mut Vec<u8>, mut n: u64) {
+        let mut bytes = vec![(n & 0x7f) as u8];
+        while n > 0x7f {
+            n = (n >> 7) - 1;
+            bytes.push((n & 0x7f) as u8 | 0x80);
+        }
+        data.extend(bytes.iter().rev());
+    }
+
+    #[test]
+    fn test_decompress_amount() {
+        // Bitcoin Core's compression test vectors
+        assert_eq!(decompress_amount(0x0), 0);
+        assert_eq!(decompress_amount(0x1), 1);
+        assert_eq!(decompress_amount(0x7), 1_000_000);
+        assert_eq!(decompress_amount(0x9), 100_000_000);
+        assert_eq!(decompress_amount(0x32), 5_000_000_000);
+        assert_eq!(decompress_amount(0x1406f40), 2_100_000_000_000_000);
+        for amount in [1_999, 5_009, 123_456_000] {
+            assert_eq!(decompress_amount(compress_amount(amount)), amount);
+        }
+    }
+
+    #[test]
+    fn test_decode_coin() {
+        let output = TxOut {
+            value: Amount::from_sat(1_999),
+            script_pubkey: ScriptBuf::from_bytes(vec![0x6a, 0x01, 0x02]),
+        };
+        assert_eq!(decode_coin(&encode_coin(&output)).unwrap(), output);
+
+        // A pay to pubkey hash output, stored as its hash
+        let hash = [7u8; 20];
+        let mut data = vec![0x00, 0x09, 0x00];
+        data.extend_from_slice(&hash);
+        let coin = decode_coin(&data).unwrap();
+        assert_eq!(coin.value, Amount::ONE_BTC);
+        assert_eq!(
+            coin.script_pubkey,
+            ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array(hash))
+        );
+
+        assert!(decode_coin(&data[..10]).is_err());
+    }
+
+    // A transaction spending the first output of `parent`
+    fn spend(parent: &Transaction) -> Transaction {
+        Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::new(parent.compute_txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: bitcoin::Amount::from_sat(1_000),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_test_mempool_accept() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let confirmed =
+                    bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).txdata
+                        [0]
+                    .clone();
+                let coin = TxOut {
+                    value: Amount::from_sat(5_009),
+                    script_pubkey: ScriptBuf::new(),
+                };
+                let in_mempool = spend(&confirmed);
+                let mempool = Mempool::new(
+                    capnp_rpc::new_client(MockMempoolChain {
+                        transactions: vec![in_mempool.clone()],
+                        ancestry: Vec::new(),
+                        coins: vec![(OutPoint::new(confirmed.compute_txid(), 0), coin)],
+                    }),
+                    capnp_rpc::new_client(MockThread),
+                );
+
+                // Spends the mempool transaction's output, paying a 199 sat fee
+                let mut child = spend(&in_mempool);
+                child.output[0].value = Amount::from_sat(801);
+                // Spends the same confirmed output as the mempool transaction
+                let mut double_spend = spend(&confirmed);
+                double_spend.output[0].value = Amount::from_sat(4_000);
+                // Spends the child's output, which isn't in the mempool
+                let mut orphan = spend(&child);
+                orphan.output[0].value = Amount::from_sat(801);
+
+                let results = mempool
+                    .test_mempool_accept(&[child.clone(), double_spend.clone()])
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    results,
+                    vec![
+                        MempoolAcceptResult {
+                            txid: child.compute_txid(),
+                            allowed: true,
+                            reject_reason: None,
+                            fee: Some(Amount::from_sat(199)),
+                        },
+                        MempoolAcceptResult {
+                            txid: double_spend.compute_txid(),
+                            allowed: false,
+                            reject_reason: Some("txn-mempool-conflict".to_string()),
+                            fee: None,
+                        },
+                    ]
+                );
+
+                let results = mempool
+                    .test_mempool_accept(&[orphan.clone()])
+                    .await
+                    .unwrap();
+                assert_eq!(results[0].reject_reason.as_deref(), Some("missing-inputs"));
+
+                // Outputs of earlier transactions in the list can be spent
+                let results = mempool.test_mempool_accept(&[child, orphan]).await.unwrap();
+                assert!(results.iter().all(|result| result.allowed));
+                assert_eq!(results[1].fee, Some(Amount::ZERO));
+
+                let results = mempool.test_mempool_accept(&[in_mempool]).await.unwrap();
+                assert_eq!(
+                    results[0].reject_reason.as_deref(),
+                    Some("txn-already-in-mempool")
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_package_pays_for_cheap_parent() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let confirmed =
+                    bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).txdata
+                        [0]
+                    .clone();
+                let coin = TxOut {
+                    value: Amount::from_sat(1_010),
+                    script_pubkey: ScriptBuf::new(),
+                };
+                let mempool = Mempool::new(
+                    capnp_rpc::new_client(MockMempoolChain {
+                        transactions: Vec::new(),
+                        ancestry: Vec::new(),
+                        coins: vec![(OutPoint::new(confirmed.compute_txid(), 0), coin)],
+                    }),
+                    capnp_rpc::new_client(MockThread),
+                );
+
+                // Both are 60 vB, and the minimum relay fee is 1 sat/vB. The
+                // parent pays 10 sat and the child 150 sat.
+                let parent = spend(&confirmed);
+                let mut child = spend(&parent);
+                child.output[0].value = Amount::from_sat(850);
+                assert_eq!((parent.vsize(), child.vsize()), (60, 60));
+
+                let results = mempool
+                    .test_package_accept(&[parent.clone()])
+                    .await
+                    .unwrap();
+                assert!(!results[0].allowed);
+                assert_eq!(results[0].error.as_deref(), Some("mempool min fee not met"));
+
+                // Without package relay the node would turn the parent away
+                // however much the child pays, so the dry run and the real
+                // submission both refuse the package and nothing is broadcast
+                let (error, accepted) = mempool
+                    .broadcast_transaction(&parent, 0, true)
+                    .await
+                    .unwrap();
+                assert!(!accepted);
+                assert_eq!(error, "min relay fee not met");
+                let package = [parent.clone(), child.clone()];
+                let tested = mempool.test_package_accept(&package).await.unwrap();
+                let results = mempool
+                    .submit_package(&package, FeeRate::ZERO)
+                    .await
+                    .unwrap();
+                assert_eq!(tested, results);
+                assert!(results.iter().all(|result| !result.allowed));
+                assert_eq!(results[0].error.as_deref(), Some("mempool min fee not met"));
+                assert_eq!(results[1].error.as_deref(), Some("package-not-validated"));
+                assert!(mempool.get_raw_mempool().await.unwrap().is_empty());
+
+                // A rejected child takes its parent down with it
+                let mut greedy = spend(&parent);
+                greedy.output[0].value = Amount::from_sat(1_001);
+                let results = mempool
+                    .submit_package(&[parent.clone(), greedy], FeeRate::ZERO)
+                    .await
+                    .unwrap();
+                assert!(results.iter().all(|result| !result.allowed));
+                assert_eq!(results[0].error.as_deref(), Some("package-not-validated"));
+                assert_eq!(results[1].error.as_deref(), Some("bad-txns-in-belowout"));
+                assert!(mempool.get_raw_mempool().await.unwrap().is_empty());
+
+                // A parent paying 1 sat/vB by itself goes in with its child
+                let mut parent = spend(&confirmed);
+                parent.output[0].value = Amount::from_sat(950);
+                let mut child = spend(&parent);
+                child.output[0].value = Amount::from_sat(850);
+                let package = [parent.clone(), child.clone()];
+
+                // Unless the child pays more than the maximum, 100 sat for its
+                // 60 vB, in which case nothing is broadcast
+                let results = mempool
+                    .submit_package(&package, FeeRate::from_sat_per_vb_unchecked(1))
+                    .await
+                    .unwrap();
+                assert_eq!(results[0].error.as_deref(), Some("package-not-validated"));
+                assert_eq!(results[1].error.as_deref(), Some("max-fee-exceeded"));
+                assert!(mempool.get_raw_mempool().await.unwrap().is_empty());
+
+                let results = mempool.test_package_accept(&package).await.unwrap();
+                assert!(results.iter().all(|result| result.allowed));
+                // 160 sat over 120 vB
+                assert_eq!(results[1].effective_fee_rate, Some(0.00001333));
+                let results = mempool
+                    .submit_package(&package, FeeRate::ZERO)
+                    .await
+                    .unwrap();
+                assert!(results.iter().all(|result| result.allowed));
+                let mut expected = vec![parent.compute_txid(), child.compute_txid()];
+                expected.sort();
+                assert_eq!(mempool.get_raw_mempool().await.unwrap(), expected);
+
+                // Resubmitting adds nothing new
+                let results = mempool.test_package_accept(&package).await.unwrap();
+                assert!(results
+                    .iter()
+                    .all(|result| result.allowed && result.effective_fee_rate.is_none()));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_get_mempool_entries_links_chained_transactions() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let confirmed =
+                    bitcoin::blockdata::constants::genesis_block(bitcoin::Network::Regtest).txdata
+                        [0]
+                    .clone();
+                let parent = spend(&confirmed);
+                let child = spend(&parent);
+                let mempool = Mempool::new(
+                    capnp_rpc::new_client(MockMempoolChain {
+                        transactions: vec![parent.clone(), child.clone()],
+                        ancestry: vec![
+                            (parent.compute_txid(), 1, 300),
+                            (child.compute_txid(), 2, 500),
+                        ],
+                        coins: Vec::new(),
+                    }),
+                    capnp_rpc::new_client(MockThread),
+                );
+
+                let entries = mempool.get_mempool_entries().await.unwrap();
+                assert_eq!(entries.len(), 2);
+
+                let entry = |tx: &Transaction| {
+                    entries
+                        .iter()
+                        .find(|entry| entry.transaction == *tx)
+                        .unwrap()
+                };
+                let parent_entry = entry(&parent);
+                assert!(parent_entry.depends.is_empty());
+                assert_eq!(parent_entry.spent_by, vec![child.compute_txid()]);
+                assert_eq!(parent_entry.fee, Some(300));
+
+                let child_entry = entry(&child);
+                assert_eq!(child_entry.depends, vec![parent.compute_txid()]);
+                assert!(child_entry.spent_by.is_empty());
+                assert_eq!(child_entry.fee, Some(200));
+            })
+            .await;
+    }
 }
@@ -1,47 +1,427 @@
-// use crate::mining_capnp::block_template::Client as BlockTemplateClient;
-use crate::mining_capnp::block_template::Client as MiningClient;
+use bitcoin::{Block, Transaction, Txid};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use crate::mining_capnp::block_template::Client as BlockTemplateClient;
+use crate::mining_capnp::mining::Client as MiningClient;
 use crate::proxy_capnp::thread::Client as ThreadClient;
 
+/// Block weight the node leaves free for the coinbase when building a template
+pub const DEFAULT_BLOCK_RESERVED_WEIGHT: u32 = 4000;
+
 #[async_trait::async_trait(?Send)]
 pub trait MiningInterface {
     /// Get a block template
+    /// A template is built from the mempool on first use and reused until it is
+    /// replaced by `get_block_template_with_options` or `update_block_template`.
     async fn get_block_template(&self) -> Result<Vec<u8>, capnp::Error>;
+
+    /// Build a new block template and return its block
+    /// `reserved_weight` is left free for the coinbase transaction.
+    async fn get_block_template_with_options(
+        &self,
+        use_mempool: bool,
+        reserved_weight: u32,
+    ) -> Result<Vec<u8>, capnp::Error>;
+
+    /// Rebuild the block template so it picks up new mempool transactions,
+    /// returning its block
+    /// The node selects the transactions itself, so `transactions` are only
+    /// included once they have been accepted to its mempool.
+    async fn update_block_template(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<Vec<u8>, capnp::Error>;
+
+    /// Submit a block solved from the block template
+    /// Returns `None` if the node accepted the block, or the reason it was rejected.
+    /// Only the header fields and coinbase are sent, so the other transactions
+    /// must be the template's.
+    async fn submit_block(&self, block: &Block) -> Result<Option<String>, capnp::Error>;
+}
+
+// Block template currently being mined on and the weight it reserves
+#[derive(Clone)]
+struct CurrentTemplate {
+    client: BlockTemplateClient,
+    reserved_weight: u32,
 }
 
 #[derive(Clone)]
 pub struct Mining {
     client: MiningClient,
     thread: ThreadClient,
+    template: Arc<Mutex<Option<CurrentTemplate>>>,
 }
 
 impl Mining {
     pub fn new(client: MiningClient, thread: ThreadClient) -> Self {
-        Self { client, thread }
+        Self {
+            client,
+            thread,
+            template: Arc::new(Mutex::new(None)),
+        }
     }
-}
 
-#[async_trait::async_trait(?Send)]
-impl MiningInterface for Mining {
-    async fn get_block_template(&self) -> Result<Vec<u8>, capnp::Error> {
+    // Ask the node to build a new block template, replacing the current one
+    async fn create_template(
+        &self,
+        use_mempool: bool,
+        reserved_weight: u32,
+    ) -> Result<BlockTemplateClient, capnp::Error> {
+        log::debug!(
+            "Creating block template (use_mempool: {}, reserved_weight: {})",
+            use_mempool,
+            reserved_weight
+        );
+        let mut request = self.client.create_new_block_request();
+        {
+            let mut options = request.get().init_options();
+            options.set_use_mempool(use_mempool);
+            options.set_block_reserved_weight(reserved_weight as u64);
+        }
+        let response = request.send().promise.await?;
+        let client = response.get()?.get_result()?;
+
+        *self.template.lock().unwrap() = Some(CurrentTemplate {
+            client: client.clone(),
+            reserved_weight,
+        });
+        Ok(client)
+    }
+
+    // The current block template, building one from the mempool if there is none
+    async fn current_template(&self) -> Result<BlockTemplateClient, capnp::Error> {
+        let current = self.template.lock().unwrap().clone();
+        match current {
+            Some(template) => Ok(template.client),
+            None => {
+                self.create_template(true, DEFAULT_BLOCK_RESERVED_WEIGHT)
+                    .await
+            }
+        }
+    }
+
+    async fn template_block(
+        &self,
+        template: &BlockTemplateClient,
+    ) -> Result<Vec<u8>, capnp::Error> {
         log::info!("Retrieving new block template");
-        let mut request = self.client.get_block_request();
+        let mut request = template.get_block_request();
 
         // Set the thread context
-        request
-            .get()
-            .get_context()?
-            .set_thread(self.thread.clone());
+        request.get().get_context()?.set_thread(self.thread.clone());
 
         let response = request.send().promise.await?;
         let results = response.get()?;
-        
+
         // Extract the block data and convert to Vec<u8>
         let block_data = results.get_result()?;
-        
+
         // Convert to Vec<u8>
         let block_bytes = block_data.to_vec();
-        
+
         log::info!("Retrieved new block template");
         Ok(block_bytes)
     }
 }
+
+#[async_trait::async_trait(?Send)]
+impl MiningInterface for Mining {
+    async fn get_block_template(&self) -> Result<Vec<u8>, capnp::Error> {
+        let template = self.current_template().await?;
+        self.template_block(&template).await
+    }
+
+    async fn get_block_template_with_options(
+        &self,
+        use_mempool: bool,
+        reserved_weight: u32,
+    ) -> Result<Vec<u8>, capnp::Error> {
+        let template = self.create_template(use_mempool, reserved_weight).await?;
+        self.template_block(&template).await
+    }
+
+    async fn update_block_template(
+        &self,
+        transactions: Vec<Transaction>,
+    ) -> Result<Vec<u8>, capnp::Error> {
+        log::info!("Refreshing block template");
+        let reserved_weight = self
+            .template
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(DEFAULT_BLOCK_RESERVED_WEIGHT, |template| {
+                template.reserved_weight
+            });
+        let template = self.create_template(true, reserved_weight).await?;
+        let block_bytes = self.template_block(&template).await?;
+
+        if !transactions.is_empty() {
+            let block: Block = bitcoin::consensus::deserialize(&block_bytes).map_err(|e| {
+                capnp::Error::failed(format!("Failed to decode block template: {}", e))
+            })?;
+            let included: HashSet<Txid> = block.txdata.iter().map(|tx| tx.compute_txid()).collect();
+            for txid in transactions.iter().map(|tx| tx.compute_txid()) {
+                if !included.contains(&txid) {
+                    log::warn!("Transaction {} is not in the block template", txid);
+                }
+            }
+        }
+        Ok(block_bytes)
+    }
+
+    async fn submit_block(&self, block: &Block) -> Result<Option<String>, capnp::Error> {
+        log::info!(
+            "Submitting block {} ({} bytes)",
+            block.block_hash(),
+            bitcoin::consensus::serialize(block).len()
+        );
+        let coinbase = block
+            .txdata
+            .first()
+            .filter(|tx| tx.is_coinbase())
+            .ok_or_else(|| capnp::Error::failed("Block has no coinbase transaction".to_string()))?;
+
+        let template = self.current_template().await?;
+        let mut request = template.submit_solution_request();
+        request.get().get_context()?.set_thread(self.thread.clone());
+        request
+            .get()
+            .set_version(block.header.version.to_consensus() as u32);
+        request.get().set_timestamp(block.header.time);
+        request.get().set_nonce(block.header.nonce);
+        request
+            .get()
+            .set_coinbase(&bitcoin::consensus::serialize(coinbase));
+
+        let response = request.send().promise.await?;
+        if response.get()?.get_result() {
+            log::info!("Block {} accepted", block.block_hash());
+            Ok(None)
+        } else {
+            // The node does not say why a solution was rejected
+            log::warn!("Block {} rejected", block.block_hash());
+            Ok(Some("rejected".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining_capnp::{block_template, mining};
+    use crate::proxy_capnp::thread;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+    use capnp::capability::Promise;
+    use capnp_rpc::pry;
+
+    // Block template that accepts a solution only if it matches `block`, or
+    // with `check_pow` any solution meeting the block's target
+    struct Template {
+        block: Block,
+        check_pow: bool,
+    }
+
+    impl block_template::Server for Template {
+        fn get_block(
+            &mut self,
+            _: block_template::GetBlockParams,
+            mut results: block_template::GetBlockResults,
+        ) -> Promise<(), capnp::Error> {
+            results
+                .get()
+                .set_result(&bitcoin::consensus::serialize(&self.block));
+            Promise::ok(())
+        }
+
+        fn submit_solution(
+            &mut self,
+            params: block_template::SubmitSolutionParams,
+            mut results: block_template::SubmitSolutionResults,
+        ) -> Promise<(), capnp::Error> {
+            let params = pry!(params.get());
+            let coinbase: Transaction =
+                pry!(bitcoin::consensus::deserialize(pry!(params.get_coinbase()))
+                    .map_err(|e| capnp::Error::failed(e.to_string())));
+            let mut header = self.block.header;
+            header.version = bitcoin::block::Version::from_consensus(params.get_version() as i32);
+            header.time = params.get_timestamp();
+            header.nonce = params.get_nonce();
+            let solved = if self.check_pow {
+                header.validate_pow(header.target()).is_ok()
+            } else {
+                header == self.block.header
+            };
+            let accepted = solved && coinbase == self.block.txdata[0];
+            results.get().set_result(accepted);
+            Promise::ok(())
+        }
+    }
+
+    // Node that builds templates for `block`, recording the options of each
+    struct Node {
+        block: Block,
+        check_pow: bool,
+        created: Arc<Mutex<Vec<(bool, u64)>>>,
+    }
+
+    impl mining::Server for Node {
+        fn create_new_block(
+            &mut self,
+            params: mining::CreateNewBlockParams,
+            mut results: mining::CreateNewBlockResults,
+        ) -> Promise<(), capnp::Error> {
+            let options = pry!(pry!(params.get()).get_options());
+            self.created.lock().unwrap().push((
+                options.get_use_mempool(),
+                options.get_block_reserved_weight(),
+            ));
+            results.get().set_result(capnp_rpc::new_client(Template {
+                block: self.block.clone(),
+                check_pow: self.check_pow,
+            }));
+            Promise::ok(())
+        }
+    }
+
+    struct NodeThread;
+
+    impl thread::Server for NodeThread {}
+
+    fn mining_with_log(block: &Block, check_pow: bool) -> (Mining, Arc<Mutex<Vec<(bool, u64)>>>) {
+        let created = Arc::new(Mutex::new(Vec::new()));
+        let mining = Mining::new(
+            capnp_rpc::new_client(Node {
+                block: block.clone(),
+                check_pow,
+                created: created.clone(),
+            }),
+            capnp_rpc::new_client(NodeThread),
+        );
+        (mining, created)
+    }
+
+    fn mining(block: &Block) -> Mining {
+        mining_with_log(block, false).0
+    }
+
+    #[tokio::test]
+    async fn test_template_created_on_first_use() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let block = genesis_block(Network::Regtest);
+                let (mining, created) = mining_with_log(&block, false);
+                assert!(created.lock().unwrap().is_empty());
+
+                let first = mining.get_block_template().await.unwrap();
+                let second = mining.get_block_template().await.unwrap();
+                assert_eq!(first, bitcoin::consensus::serialize(&block));
+                assert_eq!(first, second);
+                assert_eq!(
+                    *created.lock().unwrap(),
+                    vec![(true, DEFAULT_BLOCK_RESERVED_WEIGHT as u64)]
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_update_keeps_template_options() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let block = genesis_block(Network::Regtest);
+                let (mining, created) = mining_with_log(&block, false);
+
+                mining
+                    .get_block_template_with_options(false, 8000)
+                    .await
+                    .unwrap();
+                let refreshed = mining
+                    .update_block_template(block.txdata.clone())
+                    .await
+                    .unwrap();
+                assert_eq!(refreshed, bitcoin::consensus::serialize(&block));
+                assert_eq!(*created.lock().unwrap(), vec![(false, 8000), (true, 8000)]);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_submit_valid_block() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let block = genesis_block(Network::Regtest);
+                assert!(block.header.validate_pow(block.header.target()).is_ok());
+
+                let result = mining(&block).submit_block(&block).await.unwrap();
+                assert_eq!(result, None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_solve_and_submit_template() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let mut unsolved = genesis_block(Network::Regtest);
+                while unsolved
+                    .header
+                    .validate_pow(unsolved.header.target())
+                    .is_ok()
+                {
+                    unsolved.header.nonce += 1;
+                }
+                let (mining, _) = mining_with_log(&unsolved, true);
+
+                let template = mining.get_block_template().await.unwrap();
+                let mut block: Block = bitcoin::consensus::deserialize(&template).unwrap();
+                assert_eq!(
+                    mining.submit_block(&block).await.unwrap().as_deref(),
+                    Some("rejected")
+                );
+
+                // Regtest's target is met by about every other hash
+                while block.header.validate_pow(block.header.target()).is_err() {
+                    block.header.nonce += 1;
+                }
+                assert_eq!(mining.submit_block(&block).await.unwrap(), None);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejected_block() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let block = genesis_block(Network::Regtest);
+                let mut solved = block.clone();
+                solved.header.nonce += 1;
+
+                let result = mining(&block).submit_block(&solved).await.unwrap();
+                assert_eq!(result.as_deref(), Some("rejected"));
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_submit_block_without_coinbase() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let block = genesis_block(Network::Regtest);
+                let mut empty = block.clone();
+                empty.txdata.clear();
+
+                assert!(mining(&block).submit_block(&empty).await.is_err());
+            })
+            .await;
+    }
+}
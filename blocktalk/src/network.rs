@@ -0,0 +1,129 @@
+use bitcoin::FeeRate;
+
+use crate::mempool::{decode_fee_rate, fee_rate_to_btc_per_kvb};
+use crate::{
+    chain_capnp::chain::Client as ChainClient, proxy_capnp::thread::Client as ThreadClient,
+    BlockTalkError,
+};
+
+/// The node's transaction relay policy
+///
+/// The IPC interface does not expose the node's version, connections or peers,
+/// so unlike Bitcoin Core's `getnetworkinfo` only relay fees are reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkInfo {
+    /// Minimum fee rate for a transaction to be relayed, in BTC/kvB
+    pub relay_fee: f64,
+    /// Minimum fee rate increase for a replacement to be relayed, in BTC/kvB
+    pub incremental_fee: f64,
+}
+
+#[async_trait::async_trait(?Send)]
+pub trait NetworkInterface {
+    /// Get the fee rates the node uses to decide which transactions to relay
+    async fn get_network_info(&self) -> Result<NetworkInfo, BlockTalkError>;
+}
+
+pub struct NodeNetwork {
+    chain_client: ChainClient,
+    thread: ThreadClient,
+}
+
+#[async_trait::async_trait(?Send)]
+impl NetworkInterface for NodeNetwork {
+    async fn get_network_info(&self) -> Result<NetworkInfo, BlockTalkError> {
+        log::debug!("Getting network info");
+
+        let mut req = self.chain_client.relay_min_fee_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get network context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to get minimum relay fee: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+        let relay_fee = decode_fee_rate(response.get()?.get_result()?)?.unwrap_or(FeeRate::ZERO);
+
+        let mut req = self.chain_client.relay_incremental_fee_request();
+        req.get()
+            .get_context()
+            .map_err(|e| {
+                log::error!("Failed to get network context: {}", e);
+                BlockTalkError::Connection(e.to_string())
+            })?
+            .set_thread(self.thread.clone());
+        let response = req.send().promise.await.map_err(|e| {
+            log::error!("Failed to get incremental relay fee: {}", e);
+            BlockTalkError::Connection(e.to_string())
+        })?;
+        let incremental_fee =
+            decode_fee_rate(response.get()?.get_result()?)?.unwrap_or(FeeRate::ZERO);
+
+        Ok(NetworkInfo {
+            relay_fee: fee_rate_to_btc_per_kvb(relay_fee),
+            incremental_fee: fee_rate_to_btc_per_kvb(incremental_fee),
+        })
+    }
+}
+
+impl NodeNetwork {
+    pub fn new(chain_client: ChainClient, thread: ThreadClient) -> Self {
+        Self {
+            chain_client,
+            thread,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use capnp::capability::Promise;
+
+    // Stands in for the node, answering with Bitcoin Core's default relay fees
+    struct MockChain;
+
+    impl crate::chain_capnp::chain::Server for MockChain {
+        fn relay_min_fee(
+            &mut self,
+            _: crate::chain_capnp::chain::RelayMinFeeParams,
+            mut results: crate::chain_capnp::chain::RelayMinFeeResults,
+        ) -> Promise<(), ::capnp::Error> {
+            results.get().set_result(&1000i64.to_le_bytes());
+            Promise::ok(())
+        }
+
+        fn relay_incremental_fee(
+            &mut self,
+            _: crate::chain_capnp::chain::RelayIncrementalFeeParams,
+            mut results: crate::chain_capnp::chain::RelayIncrementalFeeResults,
+        ) -> Promise<(), ::capnp::Error> {
+            results.get().set_result(&1000i64.to_le_bytes());
+            Promise::ok(())
+        }
+    }
+
+    struct MockThread;
+
+    impl crate::proxy_capnp::thread::Server for MockThread {}
+
+    #[tokio::test]
+    async fn test_get_network_info() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let network = NodeNetwork::new(
+                    capnp_rpc::new_client(MockChain),
+                    capnp_rpc::new_client(MockThread),
+                );
+                let info = network.get_network_info().await.unwrap();
+                assert_eq!(info.relay_fee, 0.00001);
+                assert_eq!(info.incremental_fee, 0.00001);
+            })
+            .await;
+    }
+}
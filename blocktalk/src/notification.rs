@@ -1,16 +1,23 @@
 use async_trait::async_trait;
 use bitcoin::hashes::Hash;
-use bitcoin::{consensus::Decodable, Block, BlockHash, Transaction, Txid};
+use bitcoin::{consensus::Decodable, Amount, Block, BlockHash, ScriptBuf, Transaction, Txid};
 use capnp::capability::Promise;
 use capnp_rpc::pry;
-use std::sync::Arc;
-use std::sync::Mutex;
-
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::sync::{Mutex, RwLock};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::chain::CachedTip;
 use crate::chain_capnp::chain_notifications;
 use crate::error::BlockTalkError;
 
 // Public interface
-#[derive(Clone, Debug)]
+/// With the `serde` feature, notifications serialize as their type and data,
+/// with blocks and transactions consensus-encoded as hex
+#[derive(Clone, Debug, PartialEq)]
 pub enum ChainNotification {
     BlockConnected(Block),
     BlockDisconnected(BlockHash),
@@ -28,29 +35,362 @@ pub trait NotificationHandler: Send + Sync {
     ) -> Result<(), BlockTalkError>;
 }
 
+/// Set of notification types a handler wants to receive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NotificationFilter(u8);
+
+impl NotificationFilter {
+    pub const NONE: Self = Self(0);
+    pub const BLOCK_CONNECTED: Self = Self(1 << 0);
+    pub const BLOCK_DISCONNECTED: Self = Self(1 << 1);
+    pub const MEMPOOL_ADD: Self = Self(1 << 2);
+    pub const MEMPOOL_REMOVE: Self = Self(1 << 3);
+    pub const TIP_UPDATED: Self = Self(1 << 4);
+    pub const CHAIN_STATE_FLUSHED: Self = Self(1 << 5);
+    pub const ALL: Self = Self(u8::MAX);
+
+    /// Whether every type in `other` is also in this filter
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether `notification` is one of the types in this filter
+    pub fn matches(&self, notification: &ChainNotification) -> bool {
+        let kind = match notification {
+            ChainNotification::BlockConnected(_) => Self::BLOCK_CONNECTED,
+            ChainNotification::BlockDisconnected(_) => Self::BLOCK_DISCONNECTED,
+            ChainNotification::TransactionAddedToMempool(_) => Self::MEMPOOL_ADD,
+            ChainNotification::TransactionRemovedFromMempool(_) => Self::MEMPOOL_REMOVE,
+            ChainNotification::UpdatedBlockTip(_) => Self::TIP_UPDATED,
+            ChainNotification::ChainStateFlushed => Self::CHAIN_STATE_FLUSHED,
+        };
+        self.contains(kind)
+    }
+}
+
+impl std::ops::BitOr for NotificationFilter {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl Default for NotificationFilter {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// Handler that passes on only the notifications matching a filter
+pub struct FilteredNotificationHandler {
+    inner: Arc<dyn NotificationHandler>,
+    filter: NotificationFilter,
+}
+
+impl FilteredNotificationHandler {
+    pub fn new(inner: Arc<dyn NotificationHandler>, filter: NotificationFilter) -> Self {
+        Self { inner, filter }
+    }
+
+    pub fn filter(&self) -> NotificationFilter {
+        self.filter
+    }
+}
+
+#[async_trait]
+impl NotificationHandler for FilteredNotificationHandler {
+    async fn handle_notification(
+        &self,
+        notification: ChainNotification,
+    ) -> Result<(), BlockTalkError> {
+        if !self.filter.matches(&notification) {
+            return Ok(());
+        }
+        self.inner.handle_notification(notification).await
+    }
+}
+
+/// Number of notifications a `ChainNotificationHandler::subscribe` receiver can
+/// fall behind by before it starts missing them
+pub const DEFAULT_NOTIFICATION_CHANNEL_CAPACITY: usize = 64;
+
+// Handler that passes the first notification it receives to a oneshot channel,
+// then asks to be unregistered
+struct OneShotHandler {
+    sender: Mutex<Option<oneshot::Sender<ChainNotification>>>,
+}
+
+#[async_trait]
+impl NotificationHandler for OneShotHandler {
+    async fn handle_notification(
+        &self,
+        notification: ChainNotification,
+    ) -> Result<(), BlockTalkError> {
+        let sender = self.sender.lock().ok().and_then(|mut sender| sender.take());
+        if let Some(sender) = sender {
+            // The waiter may have given up, which is fine
+            let _ = sender.send(notification);
+        }
+        Err(BlockTalkError::HandlerFinished)
+    }
+}
+
+/// A transaction paying to a script watched by an `AddressSubscription`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressEvent {
+    pub txid: Txid,
+    /// 0 when the transaction enters the mempool, 1 when it is mined
+    pub confirmations: u32,
+    /// Total of the transaction's outputs paying to the script
+    pub amount: Amount,
+}
+
+// Handler that passes on the transactions paying to `script`, until the
+// subscription is dropped
+struct ScriptHandler {
+    script: ScriptBuf,
+    sender: mpsc::UnboundedSender<AddressEvent>,
+}
+
+impl ScriptHandler {
+    fn send(&self, tx: &Transaction, confirmations: u32) -> Result<(), BlockTalkError> {
+        let mut outputs = tx
+            .output
+            .iter()
+            .filter(|output| output.script_pubkey == self.script)
+            .peekable();
+        if outputs.peek().is_none() {
+            return Ok(());
+        }
+        let event = AddressEvent {
+            txid: tx.compute_txid(),
+            confirmations,
+            amount: outputs.map(|output| output.value).sum(),
+        };
+        self.sender
+            .send(event)
+            .map_err(|_| BlockTalkError::HandlerFinished)
+    }
+}
+
+#[async_trait]
+impl NotificationHandler for ScriptHandler {
+    async fn handle_notification(
+        &self,
+        notification: ChainNotification,
+    ) -> Result<(), BlockTalkError> {
+        match notification {
+            ChainNotification::TransactionAddedToMempool(tx) => self.send(&tx, 0),
+            ChainNotification::BlockConnected(block) => {
+                block.txdata.iter().try_for_each(|tx| self.send(tx, 1))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Transactions paying to a script, from `ChainInterface::subscribe_to_address`
+///
+/// The node has no way to filter notifications by script, so every mempool
+/// transaction and connected block is checked as its notification arrives.
+/// Events are queued until read, and the script stops being watched when the
+/// subscription is dropped.
+pub struct AddressSubscription {
+    id: HandlerId,
+    receiver: mpsc::UnboundedReceiver<AddressEvent>,
+    handlers: Weak<Mutex<BTreeMap<HandlerId, FilteredNotificationHandler>>>,
+}
+
+impl AddressSubscription {
+    /// Wait for the next transaction paying to the script
+    /// Returns `None` once the notification handler is gone.
+    pub async fn next_event(&mut self) -> Option<AddressEvent> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for AddressSubscription {
+    fn drop(&mut self) {
+        if let Some(handlers) = self.handlers.upgrade() {
+            if let Ok(mut handlers) = handlers.lock() {
+                handlers.remove(&self.id);
+                log::debug!("Removed address subscription {:?}", self.id);
+            }
+        }
+    }
+}
+
+/// Identifies a registered notification handler so it can be removed later
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HandlerId(u64);
+
 #[derive(Clone)]
 pub struct ChainNotificationHandler {
-    handlers: Arc<Mutex<Vec<Arc<dyn NotificationHandler>>>>,
+    handlers: Arc<Mutex<BTreeMap<HandlerId, FilteredNotificationHandler>>>,
+    next_id: Arc<AtomicU64>,
+    active: Arc<AtomicBool>,
+    tip: Arc<Mutex<Option<BlockHash>>>,
+    tip_cache: Arc<RwLock<Option<CachedTip>>>,
+    tip_generation: Arc<AtomicU64>,
+    broadcast: broadcast::Sender<ChainNotification>,
 }
 
 impl ChainNotificationHandler {
     pub fn new() -> Self {
+        Self::with_channel_capacity(DEFAULT_NOTIFICATION_CHANNEL_CAPACITY)
+    }
+
+    /// Create a handler whose `subscribe` receivers can fall `capacity`
+    /// notifications behind before missing any
+    pub fn with_channel_capacity(capacity: usize) -> Self {
+        let (broadcast, _) = broadcast::channel(capacity.max(1));
         Self {
-            handlers: Arc::new(Mutex::new(Vec::new())),
+            handlers: Arc::new(Mutex::new(BTreeMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            active: Arc::new(AtomicBool::new(true)),
+            tip: Arc::new(Mutex::new(None)),
+            tip_cache: Arc::new(RwLock::new(None)),
+            tip_generation: Arc::new(AtomicU64::new(0)),
+            broadcast,
+        }
+    }
+
+    /// Receive every notification dispatched from now on, without registering a handler
+    /// A receiver that falls too far behind gets `RecvError::Lagged` and then
+    /// continues from the oldest notification still buffered.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainNotification> {
+        self.broadcast.subscribe()
+    }
+
+    /// Enable or disable delivery of notifications to registered handlers
+    pub fn set_active(&self, active: bool) {
+        self.active.store(active, Ordering::SeqCst);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// The most recent tip seen through block connected/disconnected notifications
+    pub fn last_tip(&self) -> Option<BlockHash> {
+        self.tip.lock().ok().and_then(|tip| *tip)
+    }
+
+    fn record_tip(&self, hash: BlockHash) {
+        if let Ok(mut tip) = self.tip.lock() {
+            *tip = Some(hash);
+        }
+    }
+
+    // Tip cached by the `Blockchain` that owns this handler, cleared whenever
+    // a notification reports that the tip has moved
+    pub(crate) fn tip_cache(&self) -> Arc<RwLock<Option<CachedTip>>> {
+        self.tip_cache.clone()
+    }
+
+    // Counts the times the tip cache has been cleared, so a tip fetched before
+    // the latest clear can be told apart and left out of the cache
+    pub(crate) fn tip_generation(&self) -> Arc<AtomicU64> {
+        self.tip_generation.clone()
+    }
+
+    fn invalidate_tip_cache(&self) {
+        if let Ok(mut cached) = self.tip_cache.write() {
+            *cached = None;
+            self.tip_generation.fetch_add(1, Ordering::SeqCst);
         }
     }
 
     pub async fn register_handler(
         &mut self,
         handler: Arc<dyn NotificationHandler>,
-    ) -> Result<(), BlockTalkError> {
+    ) -> Result<HandlerId, BlockTalkError> {
+        self.register_filtered_handler(handler, NotificationFilter::ALL)
+            .await
+    }
+
+    /// Register a handler that is only called for notifications matching `filter`
+    pub async fn register_filtered_handler(
+        &mut self,
+        handler: Arc<dyn NotificationHandler>,
+        filter: NotificationFilter,
+    ) -> Result<HandlerId, BlockTalkError> {
+        self.insert_handler(handler, filter)
+    }
+
+    /// Wait for the next notification matching `filter`
+    /// The notification is only delivered once chain updates have begun.
+    /// Notifications dispatched after this call returns are seen even if the
+    /// future has not been polled yet.
+    pub fn next_notification(
+        &self,
+        filter: NotificationFilter,
+    ) -> impl Future<Output = Result<ChainNotification, BlockTalkError>> {
+        let (sender, receiver) = oneshot::channel();
+        let handler = Arc::new(OneShotHandler {
+            sender: Mutex::new(Some(sender)),
+        });
+        let registered = self.insert_handler(handler, filter);
+
+        async move {
+            registered?;
+            receiver.await.map_err(|_| {
+                BlockTalkError::Connection(
+                    "Notification handler dropped before a notification arrived".to_string(),
+                )
+            })
+        }
+    }
+
+    /// Watch for transactions paying to `script`, in the mempool and in
+    /// connected blocks
+    pub fn subscribe_to_script(
+        &self,
+        script: ScriptBuf,
+    ) -> Result<AddressSubscription, BlockTalkError> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let handler = Arc::new(ScriptHandler { script, sender });
+        let id = self.insert_handler(
+            handler,
+            NotificationFilter::MEMPOOL_ADD | NotificationFilter::BLOCK_CONNECTED,
+        )?;
+        Ok(AddressSubscription {
+            id,
+            receiver,
+            handlers: Arc::downgrade(&self.handlers),
+        })
+    }
+
+    fn insert_handler(
+        &self,
+        handler: Arc<dyn NotificationHandler>,
+        filter: NotificationFilter,
+    ) -> Result<HandlerId, BlockTalkError> {
         let mut guard = self.handlers.lock().map_err(|e| {
             BlockTalkError::Connection(format!(
                 "Failed to acquire lock for registering handler: {}",
                 e
             ))
         })?;
-        guard.push(handler);
+        let id = HandlerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        guard.insert(id, FilteredNotificationHandler::new(handler, filter));
+        log::debug!("Registered notification handler {:?} ({:?})", id, filter);
+        Ok(id)
+    }
+
+    pub async fn unregister_handler(&mut self, id: HandlerId) -> Result<(), BlockTalkError> {
+        let mut guard = self.handlers.lock().map_err(|e| {
+            BlockTalkError::Connection(format!(
+                "Failed to acquire lock for unregistering handler: {}",
+                e
+            ))
+        })?;
+        if guard.remove(&id).is_some() {
+            log::debug!("Unregistered notification handler {:?}", id);
+        } else {
+            log::warn!("Notification handler {:?} was not registered", id);
+        }
         Ok(())
     }
 
@@ -58,6 +398,23 @@ impl ChainNotificationHandler {
         &self,
         notification: ChainNotification,
     ) -> Result<(), BlockTalkError> {
+        if matches!(
+            notification,
+            ChainNotification::BlockConnected(_)
+                | ChainNotification::BlockDisconnected(_)
+                | ChainNotification::UpdatedBlockTip(_)
+        ) {
+            self.invalidate_tip_cache();
+        }
+
+        if !self.is_active() {
+            log::debug!("Chain updates stopped, dropping notification");
+            return Ok(());
+        }
+
+        // Fails only when nobody is subscribed
+        let _ = self.broadcast.send(notification.clone());
+
         let handlers = {
             let guard = self.handlers.lock().map_err(|e| {
                 BlockTalkError::Connection(format!(
@@ -65,13 +422,40 @@ impl ChainNotificationHandler {
                     e
                 ))
             })?;
-            guard.clone()
+            // Skip handlers that would ignore the notification anyway
+            guard
+                .iter()
+                .filter(|(_, handler)| handler.filter.matches(&notification))
+                .map(|(id, handler)| (*id, handler.inner.clone()))
+                .collect::<Vec<_>>()
         };
 
-        for handler in handlers {
-            handler.handle_notification(notification.clone()).await?;
+        let mut result = Ok(());
+        let mut finished = Vec::new();
+        for (id, handler) in handlers {
+            match handler.handle_notification(notification.clone()).await {
+                Ok(()) => {}
+                Err(BlockTalkError::HandlerFinished) => finished.push(id),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
         }
-        Ok(())
+
+        if !finished.is_empty() {
+            let mut guard = self.handlers.lock().map_err(|e| {
+                BlockTalkError::Connection(format!(
+                    "Failed to acquire lock for removing finished handlers: {}",
+                    e
+                ))
+            })?;
+            for id in finished {
+                guard.remove(&id);
+                log::debug!("Removed finished notification handler {:?}", id);
+            }
+        }
+        result
     }
 }
 
@@ -97,6 +481,7 @@ impl chain_notifications::Server for ChainNotificationHandler {
             // Decode the block
             let block = bitcoin::Block::consensus_decode(&mut block_data)
                 .map_err(|e| ::capnp::Error::failed(format!("Failed to decode block: {}", e)))?;
+            handler.record_tip(block.block_hash());
 
             // Dispatch notification
             handler
@@ -130,6 +515,13 @@ impl chain_notifications::Server for ChainNotificationHandler {
                 bitcoin::BlockHash::from(hash_obj)
             };
 
+            // The parent of a disconnected block becomes the new tip
+            if let Ok(prev_hash) =
+                bitcoin::hashes::sha256d::Hash::from_slice(block_info.get_prev_hash()?)
+            {
+                handler.record_tip(bitcoin::BlockHash::from(prev_hash));
+            }
+
             // Dispatch notification
             handler
                 .dispatch_notification(ChainNotification::BlockDisconnected(hash))
@@ -197,31 +589,6 @@ impl chain_notifications::Server for ChainNotificationHandler {
         })
     }
 
-    // fn updated_block_tip(
-    //     &mut self,
-    //     params: chain_notifications::UpdatedBlockTipParams,
-    //     _: chain_notifications::UpdatedBlockTipResults,
-    // ) -> ::capnp::capability::Promise<(), ::capnp::Error> {
-    //     let handler = self.clone();
-
-    //     let context_reader = pry!(pry!(params.get()).get_context());
-    //     // According to the schema, the context reader has the data we need but differently structured
-    //     // We'll need to get the height and hash from appropriate fields based on the context schema
-    //     let block_info = pry!(context_reader.get_block());
-    //     let hash_data = pry!(block_info.get_hash());
-
-    //     let hash = match bitcoin::hashes::sha256d::Hash::from_slice(hash_data) {
-    //         Ok(hash_obj) => bitcoin::BlockHash::from(hash_obj),
-    //         Err(e) => return Promise::err(::capnp::Error::failed(format!("Invalid block hash: {}", e)))
-    //     };
-
-    //     // Convert the async dispatch_notification to a Promise
-    //     Promise::from_future(async move {
-    //         handler.dispatch_notification(ChainNotification::UpdatedBlockTip(hash)).await
-    //             .map_err(|e| ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e)))
-    //     })
-    // }
-
     fn updated_block_tip(
         &mut self,
         _params: chain_notifications::UpdatedBlockTipParams,
@@ -230,14 +597,22 @@ impl chain_notifications::Server for ChainNotificationHandler {
         let handler = self.clone();
 
         let future = async move {
-            // Simply log that we received the notification
-            log::info!("Block tip updated - details skipped");
-
-            let dummy_hash = bitcoin::BlockHash::all_zeros();
+            // The tip has moved even if it isn't known which block it is now
+            handler.invalidate_tip_cache();
+
+            // The updatedBlockTip call carries no block data, but the node always
+            // sends blockConnected/blockDisconnected first, so the tip they
+            // recorded is the one that was just updated
+            let hash = match handler.last_tip() {
+                Some(hash) => hash,
+                None => {
+                    log::warn!("Block tip updated before any block was connected, skipping");
+                    return Ok(());
+                }
+            };
 
-            // Dispatch notification with dummy data
             handler
-                .dispatch_notification(ChainNotification::UpdatedBlockTip(dummy_hash))
+                .dispatch_notification(ChainNotification::UpdatedBlockTip(hash))
                 .await
                 .map_err(|e| {
                     ::capnp::Error::failed(format!("Failed to dispatch notification: {}", e))
@@ -275,3 +650,366 @@ impl chain_notifications::Server for ChainNotificationHandler {
         ::capnp::capability::Promise::ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+    use std::sync::atomic::AtomicUsize;
+
+    struct CountingHandler {
+        count: AtomicUsize,
+    }
+
+    impl CountingHandler {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                count: AtomicUsize::new(0),
+            })
+        }
+
+        fn count(&self) -> usize {
+            self.count.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl NotificationHandler for CountingHandler {
+        async fn handle_notification(
+            &self,
+            _notification: ChainNotification,
+        ) -> Result<(), BlockTalkError> {
+            self.count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct TipRecorder {
+        tips: Mutex<Vec<BlockHash>>,
+    }
+
+    #[async_trait]
+    impl NotificationHandler for TipRecorder {
+        async fn handle_notification(
+            &self,
+            notification: ChainNotification,
+        ) -> Result<(), BlockTalkError> {
+            if let ChainNotification::UpdatedBlockTip(hash) = notification {
+                self.tips.lock().unwrap().push(hash);
+            }
+            Ok(())
+        }
+    }
+
+    fn block_connected() -> ChainNotification {
+        ChainNotification::BlockConnected(genesis_block(Network::Regtest))
+    }
+
+    #[tokio::test]
+    async fn test_unregistered_handler_stops_receiving() {
+        let mut notifier = ChainNotificationHandler::new();
+        let kept = CountingHandler::new();
+        let removed = CountingHandler::new();
+
+        notifier.register_handler(kept.clone()).await.unwrap();
+        let removed_id = notifier.register_handler(removed.clone()).await.unwrap();
+
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+        notifier.unregister_handler(removed_id).await.unwrap();
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+
+        assert_eq!(kept.count(), 2);
+        assert_eq!(removed.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handler_ids_are_unique() {
+        let mut notifier = ChainNotificationHandler::new();
+        let handler = CountingHandler::new();
+
+        let first = notifier.register_handler(handler.clone()).await.unwrap();
+        let second = notifier.register_handler(handler.clone()).await.unwrap();
+        assert_ne!(first, second);
+
+        // Removing one registration leaves the other in place
+        notifier.unregister_handler(first).await.unwrap();
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+        assert_eq!(handler.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_address_subscription_unregisters() {
+        let notifier = ChainNotificationHandler::new();
+        let subscription = notifier
+            .subscribe_to_script(ScriptBuf::new_op_return([1]))
+            .unwrap();
+        assert_eq!(notifier.handlers.lock().unwrap().len(), 1);
+
+        drop(subscription);
+        assert!(notifier.handlers.lock().unwrap().is_empty());
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_inactive_handler_drops_notifications() {
+        let mut notifier = ChainNotificationHandler::new();
+        let handler = CountingHandler::new();
+        notifier.register_handler(handler.clone()).await.unwrap();
+
+        // Clones share state, as with the copy handed to the Cap'n Proto server
+        let server_copy = notifier.clone();
+        notifier.set_active(false);
+        server_copy
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+        assert_eq!(handler.count(), 0);
+
+        notifier.set_active(true);
+        server_copy
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+        assert_eq!(handler.count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_each_receive_notifications() {
+        let notifier = ChainNotificationHandler::new();
+        let mut first = notifier.subscribe();
+        let mut second = notifier.subscribe();
+
+        let block = genesis_block(Network::Regtest);
+        notifier
+            .dispatch_notification(ChainNotification::BlockConnected(block.clone()))
+            .await
+            .unwrap();
+        notifier
+            .dispatch_notification(ChainNotification::UpdatedBlockTip(block.block_hash()))
+            .await
+            .unwrap();
+
+        for receiver in [&mut first, &mut second] {
+            assert!(matches!(
+                receiver.recv().await,
+                Ok(ChainNotification::BlockConnected(_))
+            ));
+            assert!(matches!(
+                receiver.recv().await,
+                Ok(ChainNotification::UpdatedBlockTip(hash)) if hash == block.block_hash()
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_skips_missed_notifications() {
+        let notifier = ChainNotificationHandler::with_channel_capacity(2);
+        let mut receiver = notifier.subscribe();
+
+        let block = genesis_block(Network::Regtest);
+        for _ in 0..3 {
+            notifier
+                .dispatch_notification(ChainNotification::ChainStateFlushed)
+                .await
+                .unwrap();
+        }
+        notifier
+            .dispatch_notification(ChainNotification::UpdatedBlockTip(block.block_hash()))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            receiver.recv().await,
+            Err(broadcast::error::RecvError::Lagged(2))
+        ));
+        assert!(matches!(
+            receiver.recv().await,
+            Ok(ChainNotification::ChainStateFlushed)
+        ));
+        assert!(matches!(
+            receiver.recv().await,
+            Ok(ChainNotification::UpdatedBlockTip(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_without_subscribers() {
+        let notifier = ChainNotificationHandler::new();
+        assert!(notifier
+            .dispatch_notification(block_connected())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_next_notification_fires_once() {
+        let notifier = ChainNotificationHandler::new();
+        let next = notifier.next_notification(NotificationFilter::BLOCK_CONNECTED);
+        assert_eq!(notifier.handlers.lock().unwrap().len(), 1);
+
+        // Other notification types are not delivered
+        notifier
+            .dispatch_notification(ChainNotification::ChainStateFlushed)
+            .await
+            .unwrap();
+        assert_eq!(notifier.handlers.lock().unwrap().len(), 1);
+
+        let block = genesis_block(Network::Regtest);
+        notifier
+            .dispatch_notification(ChainNotification::BlockConnected(block.clone()))
+            .await
+            .unwrap();
+        assert!(notifier.handlers.lock().unwrap().is_empty());
+        assert!(matches!(
+            next.await.unwrap(),
+            ChainNotification::BlockConnected(received) if received == block
+        ));
+
+        // Later blocks have nothing left to notify
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+        assert!(notifier.handlers.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_finished_handler_does_not_block_others() {
+        let mut notifier = ChainNotificationHandler::new();
+        let next = notifier.next_notification(NotificationFilter::ALL);
+        let handler = CountingHandler::new();
+        notifier.register_handler(handler.clone()).await.unwrap();
+
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+        notifier
+            .dispatch_notification(block_connected())
+            .await
+            .unwrap();
+
+        assert!(next.await.is_ok());
+        assert_eq!(handler.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_filtered_handler_receives_matching_notifications() {
+        let mut notifier = ChainNotificationHandler::new();
+        let blocks = CountingHandler::new();
+        let mempool = CountingHandler::new();
+        let everything = CountingHandler::new();
+        notifier
+            .register_filtered_handler(
+                blocks.clone(),
+                NotificationFilter::BLOCK_CONNECTED | NotificationFilter::BLOCK_DISCONNECTED,
+            )
+            .await
+            .unwrap();
+        notifier
+            .register_filtered_handler(mempool.clone(), NotificationFilter::MEMPOOL_REMOVE)
+            .await
+            .unwrap();
+        notifier.register_handler(everything.clone()).await.unwrap();
+
+        let block = genesis_block(Network::Regtest);
+        for notification in [
+            ChainNotification::BlockConnected(block.clone()),
+            ChainNotification::BlockDisconnected(block.block_hash()),
+            ChainNotification::TransactionRemovedFromMempool(block.txdata[0].compute_txid()),
+            ChainNotification::UpdatedBlockTip(block.block_hash()),
+            ChainNotification::ChainStateFlushed,
+        ] {
+            notifier.dispatch_notification(notification).await.unwrap();
+        }
+
+        assert_eq!(blocks.count(), 2);
+        assert_eq!(mempool.count(), 1);
+        assert_eq!(everything.count(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_filtered_handler_wrapper() {
+        let handler = CountingHandler::new();
+        let filtered =
+            FilteredNotificationHandler::new(handler.clone(), NotificationFilter::TIP_UPDATED);
+
+        filtered
+            .handle_notification(block_connected())
+            .await
+            .unwrap();
+        filtered
+            .handle_notification(ChainNotification::UpdatedBlockTip(BlockHash::all_zeros()))
+            .await
+            .unwrap();
+        assert_eq!(handler.count(), 1);
+    }
+
+    #[test]
+    fn test_notification_filter_matches() {
+        let filter = NotificationFilter::MEMPOOL_ADD | NotificationFilter::TIP_UPDATED;
+        assert!(filter.matches(&ChainNotification::UpdatedBlockTip(BlockHash::all_zeros())));
+        assert!(!filter.matches(&block_connected()));
+        assert!(!filter.matches(&ChainNotification::ChainStateFlushed));
+        assert!(NotificationFilter::ALL.matches(&ChainNotification::ChainStateFlushed));
+        assert!(!NotificationFilter::NONE.matches(&block_connected()));
+        assert!(NotificationFilter::ALL.contains(filter));
+        assert!(!filter.contains(NotificationFilter::ALL));
+    }
+
+    #[tokio::test]
+    async fn test_updated_block_tip_reports_connected_block() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let mut notifier = ChainNotificationHandler::new();
+                let recorder = Arc::new(TipRecorder {
+                    tips: Mutex::new(Vec::new()),
+                });
+                notifier.register_handler(recorder.clone()).await.unwrap();
+                let client: chain_notifications::Client = capnp_rpc::new_client(notifier);
+
+                // No block seen yet, so there is no tip to report
+                client
+                    .updated_block_tip_request()
+                    .send()
+                    .promise
+                    .await
+                    .unwrap();
+                assert!(recorder.tips.lock().unwrap().is_empty());
+
+                let block = genesis_block(Network::Regtest);
+                let mut connected_req = client.block_connected_request();
+                {
+                    let mut block_info = connected_req.get().init_block();
+                    block_info.set_hash(block.block_hash().as_ref());
+                    block_info.set_data(&bitcoin::consensus::serialize(&block));
+                }
+                connected_req.send().promise.await.unwrap();
+                client
+                    .updated_block_tip_request()
+                    .send()
+                    .promise
+                    .await
+                    .unwrap();
+
+                let tips = recorder.tips.lock().unwrap();
+                assert_eq!(*tips, vec![block.block_hash()]);
+                assert_ne!(tips[0], BlockHash::all_zeros());
+            })
+            .await;
+    }
+}
@@ -0,0 +1,171 @@
+//! Serde support for `ChainNotification`, behind the `serde` feature
+//!
+//! A notification is represented as its type and data, for example
+//! `{"type": "blockdisconnected", "data": {"hash": "..."}}`. Block and tip
+//! notifications carry a block hash, mempool removals a txid, and connected
+//! blocks and mempool additions the consensus-encoded block or transaction as
+//! `hex`. Chain state flushes have no data.
+
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::consensus::Decodable;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::ChainNotification;
+
+// Names of the notification types, as they appear in `type`
+const NOTIFICATION_TYPES: &[&str] = &[
+    "blockconnected",
+    "blockdisconnected",
+    "transactionaddedtomempool",
+    "transactionremovedfrommempool",
+    "updatedblocktip",
+    "chainstateflushed",
+];
+
+#[derive(Serialize, Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    data: EventData,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct EventData {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    txid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hex: Option<String>,
+}
+
+impl Serialize for ChainNotification {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut data = EventData::default();
+        let kind = match self {
+            ChainNotification::BlockConnected(block) => {
+                data.hex = Some(serialize_hex(block));
+                "blockconnected"
+            }
+            ChainNotification::BlockDisconnected(hash) => {
+                data.hash = Some(hash.to_string());
+                "blockdisconnected"
+            }
+            ChainNotification::TransactionAddedToMempool(tx) => {
+                data.hex = Some(serialize_hex(tx));
+                "transactionaddedtomempool"
+            }
+            ChainNotification::TransactionRemovedFromMempool(txid) => {
+                data.txid = Some(txid.to_string());
+                "transactionremovedfrommempool"
+            }
+            ChainNotification::UpdatedBlockTip(hash) => {
+                data.hash = Some(hash.to_string());
+                "updatedblocktip"
+            }
+            ChainNotification::ChainStateFlushed => "chainstateflushed",
+        };
+        Event {
+            kind: kind.to_string(),
+            data,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChainNotification {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Event { kind, data } = Event::deserialize(deserializer)?;
+        let notification = match kind.as_str() {
+            "blockconnected" => ChainNotification::BlockConnected(decode_hex(data.hex)?),
+            "blockdisconnected" => ChainNotification::BlockDisconnected(parse(data.hash, "hash")?),
+            "transactionaddedtomempool" => {
+                ChainNotification::TransactionAddedToMempool(decode_hex(data.hex)?)
+            }
+            "transactionremovedfrommempool" => {
+                ChainNotification::TransactionRemovedFromMempool(parse(data.txid, "txid")?)
+            }
+            "updatedblocktip" => ChainNotification::UpdatedBlockTip(parse(data.hash, "hash")?),
+            "chainstateflushed" => ChainNotification::ChainStateFlushed,
+            other => return Err(D::Error::unknown_variant(other, NOTIFICATION_TYPES)),
+        };
+        Ok(notification)
+    }
+}
+
+// Parse the hash or txid in `field` of a notification's data
+fn parse<T, E>(value: Option<String>, field: &'static str) -> Result<T, E>
+where
+    T: FromStr,
+    T::Err: Display,
+    E: Error,
+{
+    value
+        .ok_or_else(|| E::missing_field(field))?
+        .parse()
+        .map_err(E::custom)
+}
+
+// Decode the consensus-encoded block or transaction in a notification's data
+fn decode_hex<T: Decodable, E: Error>(hex: Option<String>) -> Result<T, E> {
+    deserialize_hex(&hex.ok_or_else(|| E::missing_field("hex"))?).map_err(E::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::Network;
+
+    #[test]
+    fn test_notifications_round_trip() {
+        let block = genesis_block(Network::Regtest);
+        let tx = block.txdata[0].clone();
+        let notifications = [
+            ChainNotification::BlockConnected(block.clone()),
+            ChainNotification::BlockDisconnected(block.block_hash()),
+            ChainNotification::TransactionAddedToMempool(tx.clone()),
+            ChainNotification::TransactionRemovedFromMempool(tx.compute_txid()),
+            ChainNotification::UpdatedBlockTip(block.block_hash()),
+            ChainNotification::ChainStateFlushed,
+        ];
+
+        for notification in notifications {
+            let json = serde_json::to_string(&notification).unwrap();
+            let decoded: ChainNotification = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, notification, "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_notification_json() {
+        let block = genesis_block(Network::Regtest);
+        let json =
+            serde_json::to_value(ChainNotification::BlockDisconnected(block.block_hash())).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "blockdisconnected",
+                "data": { "hash": block.block_hash().to_string() },
+            })
+        );
+
+        let json = serde_json::to_value(ChainNotification::ChainStateFlushed).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "type": "chainstateflushed", "data": {} })
+        );
+        let decoded: ChainNotification =
+            serde_json::from_str(r#"{"type": "chainstateflushed"}"#).unwrap();
+        assert_eq!(decoded, ChainNotification::ChainStateFlushed);
+
+        assert!(serde_json::from_str::<ChainNotification>(r#"{"type": "blocks"}"#).is_err());
+        assert!(
+            serde_json::from_str::<ChainNotification>(r#"{"type": "updatedblocktip"}"#).is_err()
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Request counts and durations for monitoring, recorded through the
+//! `metrics` crate when the `metrics` feature is enabled
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// Node interface a request goes through, which names its metrics
+#[derive(Clone, Copy)]
+pub(crate) enum Interface {
+    Chain,
+    Mempool,
+}
+
+/// Counts a request when started, and records how long it took when dropped
+pub(crate) struct RequestTimer {
+    #[cfg(feature = "metrics")]
+    duration: metrics::Histogram,
+    #[cfg(feature = "metrics")]
+    start: Instant,
+}
+
+impl RequestTimer {
+    pub(crate) fn start(interface: Interface, method: &'static str) -> Self {
+        #[cfg(feature = "metrics")]
+        {
+            let (requests, duration) = match interface {
+                Interface::Chain => (
+                    "blocktalk_chain_requests_total",
+                    "blocktalk_chain_request_duration_seconds",
+                ),
+                Interface::Mempool => (
+                    "blocktalk_mempool_requests_total",
+                    "blocktalk_mempool_request_duration_seconds",
+                ),
+            };
+            metrics::counter!(requests, "method" => method).increment(1);
+            Self {
+                duration: metrics::histogram!(duration, "method" => method),
+                start: Instant::now(),
+            }
+        }
+        #[cfg(not(feature = "metrics"))]
+        {
+            let _ = (interface, method);
+            Self {}
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        self.duration.record(self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// Record the height of the chain tip as last seen
+pub(crate) fn record_tip_height(height: i32) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("blocktalk_tip_height").set(height as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = height;
+}
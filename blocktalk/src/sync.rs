@@ -0,0 +1,167 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{BlockTalkError, ChainInterface};
+
+/// Average time between blocks the chain height is estimated with
+const TARGET_BLOCK_SPACING: u64 = 600;
+
+/// How far the node has got through syncing the chain
+///
+/// The IPC interface does not report how many headers the node has, so
+/// `headers` is an estimate: the tip height plus one block for every ten
+/// minutes since the tip was mined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncProgress {
+    /// Estimated height of the best chain
+    pub headers: i32,
+    /// Height of the node's tip
+    pub blocks: i32,
+    /// `blocks` as a fraction of `headers`, between 0 and 1
+    pub progress: f64,
+}
+
+impl SyncProgress {
+    // Estimate progress from the tip and the current time, both in seconds
+    // since the epoch
+    fn estimate(blocks: i32, tip_time: u32, now: u64) -> Self {
+        let behind = now.saturating_sub(tip_time as u64) / TARGET_BLOCK_SPACING;
+        let headers = blocks.saturating_add(behind.min(i32::MAX as u64) as i32);
+        let progress = if headers > 0 {
+            blocks.max(0) as f64 / headers as f64
+        } else {
+            1.0
+        };
+        Self {
+            headers,
+            blocks,
+            progress,
+        }
+    }
+}
+
+// Poll `chain` every `check_interval` until it has left initial block download
+pub(crate) async fn wait_until_synced(
+    chain: &dyn ChainInterface,
+    check_interval: Duration,
+    timeout: Duration,
+) -> Result<(), BlockTalkError> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if chain.is_synced().await? {
+            return Ok(());
+        }
+        if tokio::time::Instant::now() + check_interval > deadline {
+            log::warn!("Node did not finish syncing within {:?}", timeout);
+            return Err(BlockTalkError::node_error("sync timeout".to_string(), -1));
+        }
+        log::debug!(
+            "Node is still syncing, checking again in {:?}",
+            check_interval
+        );
+        tokio::time::sleep(check_interval).await;
+    }
+}
+
+pub(crate) async fn sync_progress(
+    chain: &dyn ChainInterface,
+) -> Result<SyncProgress, BlockTalkError> {
+    let (blocks, _) = chain.get_tip().await?;
+    let tip_time = chain.tip_time().await?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    Ok(SyncProgress::estimate(blocks, tip_time, now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain_capnp::chain;
+    use crate::proxy_capnp::thread;
+    use crate::Blockchain;
+    use capnp::capability::Promise;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Node that is in initial block download for its first `syncing_checks` checks
+    struct SyncingNode {
+        syncing_checks: usize,
+        checks: Arc<AtomicUsize>,
+    }
+
+    impl chain::Server for SyncingNode {
+        fn is_initial_block_download(
+            &mut self,
+            _: chain::IsInitialBlockDownloadParams,
+            mut results: chain::IsInitialBlockDownloadResults,
+        ) -> Promise<(), capnp::Error> {
+            let check = self.checks.fetch_add(1, Ordering::SeqCst);
+            results.get().set_result(check < self.syncing_checks);
+            Promise::ok(())
+        }
+    }
+
+    struct NodeThread;
+
+    impl thread::Server for NodeThread {}
+
+    fn syncing_chain(syncing_checks: usize, checks: Arc<AtomicUsize>) -> Blockchain {
+        Blockchain::from_client(
+            capnp_rpc::new_client(SyncingNode {
+                syncing_checks,
+                checks,
+            }),
+            capnp_rpc::new_client(NodeThread),
+        )
+    }
+
+    #[test]
+    fn test_estimate_sync_progress() {
+        let progress = SyncProgress::estimate(100, 1_000_000, 1_000_000 + 100 * 600);
+        assert_eq!(progress.headers, 200);
+        assert_eq!(progress.blocks, 100);
+        assert_eq!(progress.progress, 0.5);
+
+        // A tip mined in the last ten minutes counts as caught up
+        let progress = SyncProgress::estimate(100, 1_000_000, 1_000_000 + 599);
+        assert_eq!(progress.headers, 100);
+        assert_eq!(progress.progress, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_synced() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let checks = Arc::new(AtomicUsize::new(0));
+                let chain = syncing_chain(3, checks.clone());
+
+                wait_until_synced(&chain, Duration::from_millis(1), Duration::from_secs(5))
+                    .await
+                    .unwrap();
+                assert_eq!(checks.load(Ordering::SeqCst), 4);
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_synced_times_out() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let checks = Arc::new(AtomicUsize::new(0));
+                let chain = syncing_chain(usize::MAX, checks.clone());
+
+                let result =
+                    wait_until_synced(&chain, Duration::from_millis(5), Duration::from_millis(50))
+                        .await;
+                assert_eq!(
+                    result,
+                    Err(BlockTalkError::node_error("sync timeout".to_string(), -1))
+                );
+                assert!(checks.load(Ordering::SeqCst) > 1);
+            })
+            .await;
+    }
+}
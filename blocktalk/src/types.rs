@@ -0,0 +1,34 @@
+use bitcoin::{Amount, Block, BlockHash, Txid};
+
+/// A block with its place in the node's chain, from
+/// `ChainInterface::get_block_verbose`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockDetails {
+    pub block: Block,
+
+    /// Height of the block, whether or not it is in the active chain
+    pub height: i32,
+
+    /// Blocks from this one to the tip, counting both, or -1 if the block is
+    /// not in the active chain
+    pub confirmations: i32,
+
+    /// Total proof of work of the chain ending at this block, big-endian, if
+    /// already worked out by `ChainInterface::get_chain_work`
+    pub chain_work: Option<[u8; 32]>,
+
+    /// The block after this one in the active chain, `None` at the tip or off
+    /// the active chain
+    pub next_block_hash: Option<BlockHash>,
+}
+
+/// An output paying to one of the scripts passed to
+/// `Blockchain::scan_blocks_for_scripts`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptMatch {
+    /// Height of the block containing the transaction
+    pub height: i32,
+    pub txid: Txid,
+    pub vout: u32,
+    pub amount: Amount,
+}